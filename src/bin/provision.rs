@@ -0,0 +1,238 @@
+//! Bench tool that writes per-device provisioning blobs to ESP32-C3 tags.
+//!
+//! The blob is flashed to the start of the `nvs` partition with `espflash write-bin`, so
+//! `espflash` has to be installed and on the `PATH`.
+//!
+//! Usage:
+//!   provision write --name <NAME> --key <HEX> [--board <BOARD>] [--profile <PROFILE>]
+//!       [--port <PORT>]
+//!   provision batch <FILE> [--port <PORT>]
+//!   provision blob --name <NAME> --key <HEX> [--board <BOARD>] [--profile <PROFILE>]
+//!       --out <FILE>
+//!
+//! Boards are `display` and `headless`, profiles `anchor`, `tag`, `relay` and `gateway`. A batch
+//! file holds one device per line as `name,key,board` or `name,key,board,profile`. Empty lines
+//! and lines starting with `#` are skipped. The tool waits for the operator to connect each
+//! device in turn.
+
+use std::{
+    env, fs,
+    io::{self, BufRead, Write},
+    path::{Path, PathBuf},
+    process::{Command, ExitCode},
+};
+
+use esp_tag::logic::{
+    consts::NETWORK_KEY_LEN,
+    profile::StartupProfile,
+    provisioning::{BoardPreset, PROVISIONING_OFFSET, Provisioning, ProvisioningData},
+    wire::WireCodec,
+};
+use heapless::String;
+
+const DEFAULT_PROFILE: &str = "relay";
+
+fn main() -> ExitCode {
+    let args: Vec<std::string::String> = env::args().skip(1).collect();
+    let result = match args.first().map(|s| s.as_str()) {
+        Some("write") => write(&args[1..]),
+        Some("batch") => batch(&args[1..]),
+        Some("blob") => blob(&args[1..]),
+        _ => Err(usage()),
+    };
+    match result {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) => {
+            eprintln!("{}", e);
+            ExitCode::FAILURE
+        }
+    }
+}
+
+fn usage() -> std::string::String {
+    concat!(
+        "Usage:\n",
+        "  provision write",
+        " --name <NAME>",
+        " --key <HEX>",
+        " [--board <BOARD>]",
+        " [--profile <PROFILE>]",
+        " [--port <PORT>]\n",
+        "  provision batch <FILE>",
+        " [--port <PORT>]\n",
+        "  provision blob",
+        " --name <NAME>",
+        " --key <HEX>",
+        " [--board <BOARD>]",
+        " [--profile <PROFILE>]",
+        " --out <FILE>",
+    )
+    .into()
+}
+
+fn write(args: &[std::string::String]) -> Result<(), std::string::String> {
+    let provisioning = provisioning_from_args(args)?;
+    flash(&provisioning, option(args, "--port"))
+}
+
+fn blob(args: &[std::string::String]) -> Result<(), std::string::String> {
+    let provisioning = provisioning_from_args(args)?;
+    let out = option(args, "--out").ok_or("Missing --out")?;
+    fs::write(out, encode(&provisioning)?).map_err(|e| format!("Failed to write {}: {}", out, e))
+}
+
+fn batch(args: &[std::string::String]) -> Result<(), std::string::String> {
+    let file = args.first().ok_or_else(usage)?;
+    let port = option(args, "--port");
+    let content =
+        fs::read_to_string(file).map_err(|e| format!("Failed to read {}: {}", file, e))?;
+    let devices = content
+        .lines()
+        .enumerate()
+        .map(|(i, line)| (i + 1, line.trim()))
+        .filter(|(_, line)| !line.is_empty() && !line.starts_with('#'))
+        .map(|(number, line)| {
+            parse_batch_line(line).map_err(|e| format!("{}:{}: {}", file, number, e))
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+    let stdin = io::stdin();
+    for (i, provisioning) in devices.iter().enumerate() {
+        print!(
+            "[{}/{}] Connect {} and press enter ",
+            i + 1,
+            devices.len(),
+            provisioning.name
+        );
+        io::stdout().flush().map_err(|e| e.to_string())?;
+        stdin
+            .lock()
+            .read_line(&mut std::string::String::new())
+            .map_err(|e| e.to_string())?;
+        flash(provisioning, port)?;
+    }
+    Ok(())
+}
+
+fn parse_batch_line(line: &str) -> Result<Provisioning, std::string::String> {
+    let fields: Vec<&str> = line.split(',').map(str::trim).collect();
+    match fields.as_slice() {
+        [name, key, board] => build(name, key, board, DEFAULT_PROFILE),
+        [name, key, board, profile] => build(name, key, board, profile),
+        _ => Err("Expected `name,key,board` or `name,key,board,profile`".into()),
+    }
+}
+
+fn provisioning_from_args(
+    args: &[std::string::String],
+) -> Result<Provisioning, std::string::String> {
+    let name = option(args, "--name").ok_or("Missing --name")?;
+    let key = option(args, "--key").ok_or("Missing --key")?;
+    let board = option(args, "--board").unwrap_or("display");
+    let profile = option(args, "--profile").unwrap_or(DEFAULT_PROFILE);
+    build(name, key, board, profile)
+}
+
+fn build(
+    name: &str,
+    key: &str,
+    board: &str,
+    profile: &str,
+) -> Result<Provisioning, std::string::String> {
+    let mut heapless_name = String::new();
+    heapless_name
+        .push_str(name)
+        .map_err(|_| format!("Name `{}` is too long", name))?;
+    Ok(Provisioning {
+        network_key: parse_key(key)?,
+        name: heapless_name,
+        board: parse_board(board)?,
+        profile: parse_profile(profile)?,
+    })
+}
+
+fn parse_key(key: &str) -> Result<[u8; NETWORK_KEY_LEN], std::string::String> {
+    if key.len() != NETWORK_KEY_LEN * 2 {
+        return Err(format!(
+            "Key must be {} hex characters",
+            NETWORK_KEY_LEN * 2
+        ));
+    }
+    let mut out = [0; NETWORK_KEY_LEN];
+    for (i, byte) in out.iter_mut().enumerate() {
+        // `get` instead of indexing, a non-ASCII key can put a char boundary inside a pair.
+        *byte = key
+            .get(i * 2..i * 2 + 2)
+            .and_then(|pair| u8::from_str_radix(pair, 16).ok())
+            .ok_or_else(|| format!("Key `{}` is not valid hex", key))?;
+    }
+    Ok(out)
+}
+
+fn parse_board(board: &str) -> Result<BoardPreset, std::string::String> {
+    match board {
+        "display" => Ok(BoardPreset::Display),
+        "headless" => Ok(BoardPreset::Headless),
+        other => Err(format!("Unknown board preset `{}`", other)),
+    }
+}
+
+fn parse_profile(profile: &str) -> Result<StartupProfile, std::string::String> {
+    match profile {
+        "anchor" => Ok(StartupProfile::LeaderAnchor),
+        "tag" => Ok(StartupProfile::MobileTag),
+        "relay" => Ok(StartupProfile::Relay),
+        "gateway" => Ok(StartupProfile::Gateway),
+        other => Err(format!("Unknown startup profile `{}`", other)),
+    }
+}
+
+fn option<'a>(args: &'a [std::string::String], flag: &str) -> Option<&'a str> {
+    args.iter()
+        .position(|arg| arg == flag)
+        .and_then(|i| args.get(i + 1))
+        .map(|s| s.as_str())
+}
+
+fn encode(provisioning: &Provisioning) -> Result<ProvisioningData, std::string::String> {
+    let mut out = ProvisioningData::new();
+    provisioning
+        .encode(&mut out)
+        .map_err(|e| format!("Failed to encode provisioning blob:\n{}", e))?;
+    Ok(out)
+}
+
+fn flash(provisioning: &Provisioning, port: Option<&str>) -> Result<(), std::string::String> {
+    let path = blob_path(&provisioning.name);
+    fs::write(&path, encode(provisioning)?)
+        .map_err(|e| format!("Failed to write {}: {}", path.display(), e))?;
+    let mut command = Command::new("espflash");
+    command.args(["write-bin", "--chip", "esp32c3"]);
+    if let Some(port) = port {
+        command.args(["--port", port]);
+    }
+    command
+        .arg(format!("{:#x}", PROVISIONING_OFFSET))
+        .arg(&path);
+    let status = command
+        .status()
+        .map_err(|e| format!("Failed to run espflash: {}", e))?;
+    remove(&path);
+    if !status.success() {
+        return Err(format!(
+            "espflash failed for {}: {}",
+            provisioning.name, status
+        ));
+    }
+    println!("Provisioned {}", provisioning.name);
+    Ok(())
+}
+
+fn blob_path(name: &str) -> PathBuf {
+    env::temp_dir().join(format!("esp-tag-{}.bin", name))
+}
+
+fn remove(path: &Path) {
+    if let Err(e) = fs::remove_file(path) {
+        eprintln!("Failed to remove {}: {}", path.display(), e);
+    }
+}
@@ -0,0 +1,99 @@
+//! Plots a timeline of topology snapshots recorded by simulated meshes, see
+//! `logic::timeline`.
+//!
+//! Usage:
+//!   timeline <FILE> [--step <MS>]
+//!
+//! Prints one row per step with the number of nodes each node knows, marked with `*` once all
+//! nodes see the same tree, followed by the time the mesh converged and its final tree.
+
+use std::{collections::BTreeMap, env, fs, process::ExitCode};
+
+use esp_tag::logic::timeline::{Snapshot, converged};
+
+const DEFAULT_STEP_MS: u64 = 500;
+
+fn main() -> ExitCode {
+    match run(&env::args().skip(1).collect::<Vec<_>>()) {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) => {
+            eprintln!("{}", e);
+            ExitCode::FAILURE
+        }
+    }
+}
+
+fn usage() -> String {
+    "Usage:\n  timeline <FILE> [--step <MS>]".into()
+}
+
+fn run(args: &[String]) -> Result<(), String> {
+    let path = args.first().ok_or_else(usage)?;
+    let step_ms = match args.iter().position(|arg| arg == "--step") {
+        Some(i) => args
+            .get(i + 1)
+            .and_then(|step| step.parse().ok())
+            .filter(|step| *step > 0)
+            .ok_or("--step needs a positive number of milliseconds")?,
+        None => DEFAULT_STEP_MS,
+    };
+    let text = fs::read_to_string(path).map_err(|e| format!("Failed to read {}: {}", path, e))?;
+    let mut snapshots = Vec::new();
+    for (number, line) in text.lines().enumerate() {
+        if line.trim().is_empty() {
+            continue;
+        }
+        let snapshot =
+            Snapshot::parse(line).ok_or_else(|| format!("Line {} is no snapshot", number + 1))?;
+        snapshots.push(snapshot);
+    }
+    snapshots.sort_by_key(|snapshot| snapshot.at_ms);
+    let end_ms = snapshots.last().ok_or("The timeline is empty")?.at_ms;
+
+    let mut nodes = snapshots.iter().map(|s| s.node.mac).collect::<Vec<_>>();
+    nodes.sort();
+    nodes.dedup();
+    print!("{:>8}", "ms");
+    for mac in &nodes {
+        print!(" {:02x}{:02x}", mac[4], mac[5]);
+    }
+    println!();
+
+    let mut latest: BTreeMap<[u8; 6], &Snapshot> = BTreeMap::new();
+    let mut pending = snapshots.iter().peekable();
+    let mut converged_since = None;
+    let mut t = 0;
+    while t <= end_ms + step_ms {
+        while let Some(snapshot) = pending.next_if(|snapshot| snapshot.at_ms <= t) {
+            latest.insert(snapshot.node.mac, snapshot);
+        }
+        let views = latest.values().map(|s| (*s).clone()).collect::<Vec<_>>();
+        let agree = views.len() == nodes.len() && converged(&views);
+        converged_since = match (agree, converged_since) {
+            (true, None) => Some(t),
+            (true, since) => since,
+            (false, _) => None,
+        };
+        print!("{:>8}", t);
+        for mac in &nodes {
+            match latest.get(mac) {
+                Some(snapshot) => print!(" {:>4}", snapshot.edges.len() + 1),
+                None => print!(" {:>4}", "-"),
+            }
+        }
+        println!("{}", if agree { " *" } else { "" });
+        t += step_ms;
+    }
+
+    match converged_since {
+        Some(t) => println!("converged at {} ms", t),
+        None => println!("not converged"),
+    }
+    if let Some(last) = latest.values().max_by_key(|snapshot| snapshot.edges.len()) {
+        println!("tree of {}:", last.node);
+        for (child, parent) in &last.edges {
+            println!("  {} -> {}", child, parent);
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,41 @@
+//! Writes the canonical wire vectors of this build to a fixture, see `logic::vectors`.
+//!
+//! Usage:
+//!   vectors <FILE>
+//!
+//! Run it after an intended codec change and commit the fixture, then check the firmware
+//! against it with the `wire-selftest` feature.
+
+use std::{env, fmt::Write, fs, process::ExitCode};
+
+use esp_tag::logic::vectors::wire_vectors;
+
+fn main() -> ExitCode {
+    match run(&env::args().skip(1).collect::<Vec<_>>()) {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) => {
+            eprintln!("{}", e);
+            ExitCode::FAILURE
+        }
+    }
+}
+
+fn usage() -> String {
+    "Usage:\n  vectors <FILE>".into()
+}
+
+fn run(args: &[String]) -> Result<(), String> {
+    let path = args.first().ok_or_else(usage)?;
+    let mut fixture = String::new();
+    for (name, frame) in wire_vectors().map_err(|e| e.to_string())? {
+        fixture.push_str(name);
+        fixture.push(' ');
+        for byte in frame {
+            let _ = write!(fixture, "{:02x}", byte);
+        }
+        fixture.push('\n');
+    }
+    fs::write(path, fixture).map_err(|e| format!("Failed to write {}: {}", path, e))?;
+    println!("Wrote {}", path);
+    Ok(())
+}
@@ -1,8 +1,10 @@
 use embassy_executor::SpawnToken;
 use embassy_sync::blocking_mutex::raw::CriticalSectionRawMutex;
-pub use embassy_time::{Duration, Ticker};
+pub use embassy_time::Duration;
+use embassy_time::{Instant, Timer};
+use esp_hal::rng::{Rng, Trng};
 
-use crate::logic::error::AsyncError;
+use crate::logic::{error::AsyncError, util};
 
 pub type Spawner = embassy_executor::Spawner;
 
@@ -20,6 +22,7 @@ pub trait MyChannel<T> {
 
     fn my_try_send(&self, v: T) -> Result<(), ()>;
     fn my_recv(&self) -> Self::RecvFut<'_>;
+    fn my_len(&self) -> usize;
 }
 
 pub type Channel<T, const N: usize> = embassy_sync::channel::Channel<
@@ -41,6 +44,10 @@ impl<T, const N: usize> MyChannel<T> for Channel<T, N> {
     fn my_recv(&self) -> Self::RecvFut<'_> {
         self.receive()
     }
+
+    fn my_len(&self) -> usize {
+        self.len()
+    }
 }
 
 pub type Mutex<T> = embassy_sync::mutex::Mutex<CriticalSectionRawMutex, T>;
@@ -64,3 +71,42 @@ where
 pub fn after(duration: Duration) -> impl Future<Output = ()> {
     embassy_time::Timer::after(duration)
 }
+
+/// Periodic timer whose period is jittered by +-`util::JITTER_PERCENT` on every tick.
+pub struct Ticker {
+    period: Duration,
+    deadline: Instant,
+}
+
+impl Ticker {
+    pub fn every(period: Duration) -> Self {
+        Self {
+            period,
+            deadline: Instant::now() + jittered(period),
+        }
+    }
+
+    pub async fn next(&mut self) {
+        Timer::at(self.deadline).await;
+        self.deadline += jittered(self.period);
+    }
+}
+
+/// Spreads `period` over +-`util::JITTER_PERCENT`.
+pub fn jittered(period: Duration) -> Duration {
+    Duration::from_micros(util::jittered_micros(period.as_micros(), random_u32()))
+}
+
+/// Random number from the TRNG, which is fed by the entropy source `main` enables at boot and
+/// by the radio. Falls back to the pseudo-random generator if neither is running.
+pub fn random_u32() -> u32 {
+    match Trng::try_new() {
+        Ok(trng) => trng.random(),
+        Err(_) => Rng::new().random(),
+    }
+}
+
+/// Milliseconds since boot.
+pub fn now_ms() -> u64 {
+    Instant::now().as_millis()
+}
@@ -0,0 +1,116 @@
+use core::cell::Cell;
+
+use embassy_sync::blocking_mutex::{Mutex as BlockingMutex, raw::CriticalSectionRawMutex};
+use esp_hal::delay::Delay;
+use esp_rom_sys::rom::spiflash::{
+    ESP_ROM_SPIFLASH_RESULT_OK, esp_rom_spiflash_erase_sector, esp_rom_spiflash_read,
+    esp_rom_spiflash_unlock, esp_rom_spiflash_write,
+};
+
+use crate::{
+    hardware::{asynchronous::Mutex, error::CrashError, link},
+    logic::{
+        consts::{CRASH_RECORD_SIZE, LAST_GASP_WAIT_MS},
+        crash::{CRASH_LOG_OFFSET, CrashData, CrashRecord},
+        error::CodecError,
+        mesh::{self, MeshState},
+        node::Node,
+        tree::Tree,
+        wire::{Cursor, WireCodec},
+    },
+};
+
+const FLASH_SECTOR_SIZE: u32 = 4096;
+
+type MeshHandles = (&'static Mutex<Tree>, &'static Mutex<MeshState>);
+
+static LAST_GASP: BlockingMutex<CriticalSectionRawMutex, Cell<Option<MeshHandles>>> =
+    BlockingMutex::new(Cell::new(None));
+
+/// Lets the panic handler reach the routing tree and mesh state of the running mesh.
+pub fn arm_last_gasp(tree: &'static Mutex<Tree>, state: &'static Mutex<MeshState>) {
+    LAST_GASP.lock(|handles| handles.set(Some((tree, state))));
+}
+
+/// Tells the uplink that this node is going down. Only succeeds if neither the tree, the mesh
+/// state nor the radio were locked when the panic hit.
+pub fn send_last_gasp() -> Result<(), CrashError> {
+    let (tree, state) = LAST_GASP.lock(Cell::get).ok_or(CrashError::NotArmedError)?;
+    let tree = tree.try_lock().map_err(|_| CrashError::LockedError)?;
+    let mut state = state.try_lock().map_err(|_| CrashError::LockedError)?;
+    let node = Node::new(esp_radio::wifi::sta_mac());
+    let (uplink, data) =
+        mesh::crash_notice(&tree, &mut state, node).map_err(CrashError::MeshError)?;
+    link::send_now(&data, uplink).map_err(CrashError::LinkError)?;
+    Delay::new().delay_millis(LAST_GASP_WAIT_MS);
+    Ok(())
+}
+
+/// Overwrites the crash log sector with `record`.
+pub fn write_crash_record(record: &CrashRecord) -> Result<(), CrashError> {
+    let mut data = CrashData::new();
+    record.encode(&mut data).map_err(CrashError::EncodeError)?;
+    data.resize(CRASH_RECORD_SIZE, 0xFF)
+        .map_err(|_| CrashError::EncodeError(CodecError::CodecError))?;
+    let mut words = [0u32; CRASH_RECORD_SIZE / 4];
+    for (word, bytes) in words.iter_mut().zip(data.chunks_exact(4)) {
+        *word = u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]);
+    }
+    program_crash_log(&words)
+}
+
+/// Returns the record of the last crash, if any, and clears it so it is reported only once.
+pub fn take_crash_record() -> Result<Option<CrashRecord>, CrashError> {
+    let mut words = [0u32; CRASH_RECORD_SIZE / 4];
+    let result = unsafe {
+        esp_rom_spiflash_read(
+            CRASH_LOG_OFFSET,
+            words.as_mut_ptr(),
+            CRASH_RECORD_SIZE as u32,
+        )
+    };
+    if result != ESP_ROM_SPIFLASH_RESULT_OK {
+        return Err(CrashError::FlashReadError(result));
+    }
+    let mut data = CrashData::new();
+    for word in words {
+        let _ = data.extend_from_slice(&word.to_le_bytes());
+    }
+    match CrashRecord::decode(&mut Cursor::new(&data)) {
+        Ok(record) => {
+            erase_crash_log()?;
+            Ok(Some(record))
+        }
+        Err(CodecError::InvalidMagicError) => Ok(None),
+        Err(e) => {
+            erase_crash_log()?;
+            Err(CrashError::DecodeError(e))
+        }
+    }
+}
+
+/// Runs from RAM, as the flash cannot serve instructions while it is written.
+#[esp_hal::ram]
+fn program_crash_log(words: &[u32; CRASH_RECORD_SIZE / 4]) -> Result<(), CrashError> {
+    erase_crash_log()?;
+    let result = unsafe {
+        esp_rom_spiflash_write(CRASH_LOG_OFFSET, words.as_ptr(), CRASH_RECORD_SIZE as u32)
+    };
+    if result != ESP_ROM_SPIFLASH_RESULT_OK {
+        return Err(CrashError::FlashWriteError(result));
+    }
+    Ok(())
+}
+
+#[esp_hal::ram]
+fn erase_crash_log() -> Result<(), CrashError> {
+    let result = unsafe { esp_rom_spiflash_unlock() };
+    if result != ESP_ROM_SPIFLASH_RESULT_OK {
+        return Err(CrashError::FlashEraseError(result));
+    }
+    let result = unsafe { esp_rom_spiflash_erase_sector(CRASH_LOG_OFFSET / FLASH_SECTOR_SIZE) };
+    if result != ESP_ROM_SPIFLASH_RESULT_OK {
+        return Err(CrashError::FlashEraseError(result));
+    }
+    Ok(())
+}
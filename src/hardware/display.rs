@@ -1,4 +1,7 @@
-use crate::hardware::{bmp, error::DisplayError};
+use crate::{
+    hardware::{bmp, error::DisplayError},
+    logic::{node::Node, text, tree::LinkDirection},
+};
 use embedded_graphics::{
     image::{Image, ImageRaw},
     mono_font::{MonoTextStyle, MonoTextStyleBuilder, ascii::FONT_6X10, iso_8859_2::FONT_9X18},
@@ -7,11 +10,15 @@ use embedded_graphics::{
     text::{Alignment, Baseline, Text},
 };
 use embedded_hal_async::i2c::I2c;
+use heapless::String;
 use ssd1306::{I2CDisplayInterface, Ssd1306Async, mode::BufferedGraphicsModeAsync, prelude::*};
 
 const WIDTH: usize = 128;
 const HEIGHT: usize = 32;
 const BUFFER_SIZE: usize = WIDTH * HEIGHT / 8;
+const NEIGHBOR_COLUMNS: usize = 3;
+const NEIGHBOR_COLUMN_WIDTH: i32 = 42;
+const NEIGHBOR_ROW_HEIGHT: i32 = 11;
 
 pub struct Display<I2C> {
     display: Ssd1306Async<
@@ -87,6 +94,45 @@ where
             .map_err(|_| DisplayError::FlushError)
     }
 
+    /// Lists the direct neighbors with an arrow pointing towards the leader (`^`) or away
+    /// from it (`v`). The parent is drawn inverted so it stands out.
+    pub async fn show_neighbors(
+        &mut self,
+        neighbors: &[(Node, LinkDirection)],
+    ) -> Result<(), DisplayError> {
+        self.display
+            .clear(BinaryColor::Off)
+            .map_err(|_| DisplayError::ClearError)?;
+        let normal = MonoTextStyle::new(&FONT_6X10, BinaryColor::On);
+        let highlighted = MonoTextStyleBuilder::new()
+            .font(&FONT_6X10)
+            .text_color(BinaryColor::Off)
+            .background_color(BinaryColor::On)
+            .build();
+        for (idx, (node, direction)) in neighbors.iter().enumerate() {
+            let mut label: String<{ text::SHORT_MAC_LEN + 1 }> = String::new();
+            let (arrow, style) = match direction {
+                LinkDirection::Upstream => ('^', highlighted),
+                LinkDirection::Downstream => ('v', normal),
+            };
+            label.push(arrow).map_err(|_| DisplayError::DrawError)?;
+            label
+                .push_str(&text::short_mac(*node))
+                .map_err(|_| DisplayError::DrawError)?;
+            let position = Point::new(
+                (idx % NEIGHBOR_COLUMNS) as i32 * NEIGHBOR_COLUMN_WIDTH,
+                (idx / NEIGHBOR_COLUMNS) as i32 * NEIGHBOR_ROW_HEIGHT,
+            );
+            Text::with_baseline(&label, position, style, Baseline::Top)
+                .draw(&mut self.display)
+                .map_err(|_| DisplayError::DrawError)?;
+        }
+        self.display
+            .flush()
+            .await
+            .map_err(|_| DisplayError::FlushError)
+    }
+
     pub async fn clear(&mut self) -> Result<(), DisplayError> {
         self.display
             .clear(BinaryColor::Off)
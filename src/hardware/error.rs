@@ -1,5 +1,7 @@
 use core::fmt;
 
+use crate::logic::error::{CodecError, LinkError, MeshError};
+
 pub enum DisplayError {
     DrawError,
     FlushError,
@@ -17,3 +19,57 @@ impl fmt::Display for DisplayError {
         }
     }
 }
+
+pub enum ProvisioningError {
+    FlashReadError(i32),
+    DecodeError(CodecError),
+}
+
+impl fmt::Display for ProvisioningError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::FlashReadError(e) => write!(f, "failed to read provisioning from flash: {}\n", e),
+            Self::DecodeError(e) => write!(f, "failed to decode provisioning:\n{}", e),
+        }
+    }
+}
+
+pub enum OtaError {
+    PartitionError(esp_bootloader_esp_idf::partitions::Error),
+}
+
+impl fmt::Display for OtaError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::PartitionError(e) => write!(f, "failed to access ota data: {}\n", e),
+        }
+    }
+}
+
+pub enum CrashError {
+    NotArmedError,
+    LockedError,
+    MeshError(MeshError),
+    LinkError(LinkError),
+    EncodeError(CodecError),
+    DecodeError(CodecError),
+    FlashReadError(i32),
+    FlashEraseError(i32),
+    FlashWriteError(i32),
+}
+
+impl fmt::Display for CrashError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::NotArmedError => write!(f, "last gasp was not armed\n"),
+            Self::LockedError => write!(f, "mesh was locked while panicking\n"),
+            Self::MeshError(e) => write!(f, "failed to build crash notice:\n{}", e),
+            Self::LinkError(e) => write!(f, "failed to send crash notice:\n{}", e),
+            Self::EncodeError(e) => write!(f, "failed to encode crash record:\n{}", e),
+            Self::DecodeError(e) => write!(f, "failed to decode crash record:\n{}", e),
+            Self::FlashReadError(e) => write!(f, "failed to read crash log from flash: {}\n", e),
+            Self::FlashEraseError(e) => write!(f, "failed to erase crash log: {}\n", e),
+            Self::FlashWriteError(e) => write!(f, "failed to write crash log to flash: {}\n", e),
+        }
+    }
+}
@@ -1,25 +1,39 @@
+use core::cell::RefCell;
+
+use crate::hardware::asynchronous;
 use crate::logic::error::AsyncError;
-use crate::logic::message::MessageData;
+use crate::logic::message::{MessageData, Priority};
 use crate::logic::{
+    config::TxPowerPolicy,
+    congestion::ChannelMonitor,
+    consts::LINK_RECV_QUEUE_SIZE,
     error::LinkError,
-    link::{Link, RecvData, SendData},
+    link::{Link, RecvData, SendData, SendQueues},
     node::Node,
 };
 use embassy_executor::Spawner;
+use embassy_sync::blocking_mutex::Mutex as BlockingMutex;
 use embassy_sync::blocking_mutex::raw::{CriticalSectionRawMutex, NoopRawMutex};
 use embassy_sync::channel::Channel;
+use embassy_sync::mutex::Mutex;
+use embassy_sync::signal::Signal;
 use esp_println::println;
 use esp_radio::esp_now::{EspNowReceiver, EspNowSender};
 
-const SEND_QUEUE_SIZE: usize = 16;
-const RECV_QUEUE_SIZE: usize = 16;
+type SharedSendQueues = BlockingMutex<CriticalSectionRawMutex, RefCell<SendQueues>>;
 
-static SEND_QUEUE: Channel<CriticalSectionRawMutex, SendData, SEND_QUEUE_SIZE> = Channel::new();
-static RECV_QUEUE: Channel<CriticalSectionRawMutex, RecvData, RECV_QUEUE_SIZE> = Channel::new();
+static SEND_QUEUES: SharedSendQueues = BlockingMutex::new(RefCell::new(SendQueues::new()));
+/// Wakes the send task once a frame was queued.
+static SEND_READY: Signal<CriticalSectionRawMutex, ()> = Signal::new();
+static RECV_QUEUE: Channel<CriticalSectionRawMutex, RecvData, LINK_RECV_QUEUE_SIZE> =
+    Channel::new();
+static SENDER: Mutex<CriticalSectionRawMutex, Option<EspNowSender<'static>>> = Mutex::new(None);
 
 pub struct ESPNowLink {
-    send_queue: &'static Channel<CriticalSectionRawMutex, SendData, SEND_QUEUE_SIZE>,
-    recv_queue: &'static Channel<CriticalSectionRawMutex, RecvData, RECV_QUEUE_SIZE>,
+    send_queues: &'static SharedSendQueues,
+    send_ready: &'static Signal<CriticalSectionRawMutex, ()>,
+    recv_queue: &'static Channel<CriticalSectionRawMutex, RecvData, LINK_RECV_QUEUE_SIZE>,
+    shared_sender: &'static Mutex<CriticalSectionRawMutex, Option<EspNowSender<'static>>>,
     sender: Option<EspNowSender<'static>>,
     receiver: Option<EspNowReceiver<'static>>,
     spawner: Spawner,
@@ -32,8 +46,10 @@ impl ESPNowLink {
         receiver: EspNowReceiver<'static>,
     ) -> Self {
         ESPNowLink {
-            send_queue: &SEND_QUEUE,
+            send_queues: &SEND_QUEUES,
+            send_ready: &SEND_READY,
             recv_queue: &RECV_QUEUE,
+            shared_sender: &SENDER,
             sender: Some(sender),
             receiver: Some(receiver),
             spawner,
@@ -43,8 +59,12 @@ impl ESPNowLink {
     pub fn init(&mut self) -> Result<(), LinkError> {
         let sender = self.sender.take().ok_or(LinkError::AlreadyInitialized)?;
         let receiver = self.receiver.take().ok_or(LinkError::AlreadyInitialized)?;
+        *self
+            .shared_sender
+            .try_lock()
+            .map_err(|_| LinkError::AlreadyInitialized)? = Some(sender);
         self.spawner
-            .spawn(send_task(&SEND_QUEUE, sender))
+            .spawn(send_task(&SEND_QUEUES, &SEND_READY, &SENDER))
             .map_err(|_| LinkError::SpawnError)?;
         self.spawner
             .spawn(recv_task(&RECV_QUEUE, receiver))
@@ -54,22 +74,29 @@ impl ESPNowLink {
 }
 
 impl<'a> Link<'a> for ESPNowLink {
-    fn send(&'a self, data: MessageData, destination: Node) -> impl Future<Output = ()> {
-        async move {
-            let send_data = SendData { data, destination };
-            self.send_queue.send(send_data).await
-        }
+    fn send(
+        &'a self,
+        data: MessageData,
+        destination: Node,
+    ) -> impl Future<Output = Result<(), LinkError>> {
+        async move { deliver(self.shared_sender, &data, destination).await }
     }
 
     fn try_send(
         &self,
         data: MessageData,
         destination: Node,
+        priority: Priority,
     ) -> Result<(), crate::logic::error::LinkError> {
-        let send_data = SendData { data, destination };
-        self.send_queue
-            .try_send(send_data)
-            .map_err(|_| LinkError::QueueFullError())
+        let send_data = SendData {
+            data,
+            destination,
+            priority,
+        };
+        self.send_queues
+            .lock(|queues| queues.borrow_mut().push(send_data))?;
+        self.send_ready.signal(());
+        Ok(())
     }
 
     fn receive(&'a self) -> impl Future<Output = RecvData> {
@@ -81,24 +108,96 @@ impl<'a> Link<'a> for ESPNowLink {
             .try_receive()
             .map_err(|_| LinkError::QueueEmptyError())
     }
+
+    fn queued(&self) -> usize {
+        self.send_queues.lock(|queues| queues.borrow().len())
+    }
+
+    fn address(&self) -> Node {
+        Node::new(esp_radio::wifi::sta_mac())
+    }
+
+    fn set_tx_power(&self, policy: TxPowerPolicy) -> Result<(), LinkError> {
+        // Safety: only called once the radio was started for ESP-NOW.
+        let err = unsafe { esp_wifi_sys::include::esp_wifi_set_max_tx_power(policy.quarter_dbm()) };
+        match err {
+            0 => Ok(()),
+            e => Err(LinkError::TxPowerError(e)),
+        }
+    }
 }
 
 #[embassy_executor::task]
 async fn send_task(
-    send_queue: &'static Channel<CriticalSectionRawMutex, SendData, SEND_QUEUE_SIZE>,
-    mut sender: EspNowSender<'static>,
+    send_queues: &'static SharedSendQueues,
+    send_ready: &'static Signal<CriticalSectionRawMutex, ()>,
+    sender: &'static Mutex<CriticalSectionRawMutex, Option<EspNowSender<'static>>>,
 ) -> ! {
+    let mut channel = ChannelMonitor::new();
     loop {
-        let data = send_queue.receive().await;
-        if let Err(e) = sender.send_async(&data.destination.mac, &data.data).await {
+        // While the channel is busy only frames that may skip the deferral leave the queue.
+        let defer_ms = channel.defer_ms(Priority::Low, asynchronous::now_ms());
+        let next = send_queues.lock(|queues| {
+            let mut queues = queues.borrow_mut();
+            match defer_ms {
+                Some(_) => queues.pop_at_least(Priority::High),
+                None => queues.pop(),
+            }
+        });
+        let Some(data) = next else {
+            match defer_ms {
+                Some(ms) => {
+                    let timer = asynchronous::after(asynchronous::Duration::from_millis(ms));
+                    asynchronous::select(send_ready.wait(), timer).await;
+                }
+                None => send_ready.wait().await,
+            }
+            continue;
+        };
+        let started_ms = asynchronous::now_ms();
+        let result = deliver(sender, &data.data, data.destination).await;
+        let now_ms = asynchronous::now_ms();
+        channel.record(
+            now_ms - started_ms,
+            result.is_ok(),
+            now_ms,
+            asynchronous::random_u32(),
+        );
+        if let Err(e) = result {
             println!("Error while sending EspNow message:\n{}", e);
         }
     }
 }
 
+/// Hands `data` to the radio and waits for the ESP-NOW send status of the next hop.
+async fn deliver(
+    sender: &Mutex<CriticalSectionRawMutex, Option<EspNowSender<'static>>>,
+    data: &MessageData,
+    destination: Node,
+) -> Result<(), LinkError> {
+    let mut sender = sender.lock().await;
+    let sender = sender.as_mut().ok_or(LinkError::NotInitializedError)?;
+    sender
+        .send_async(&destination.mac, data)
+        .await
+        .map_err(|_| LinkError::DeliveryError(destination))
+}
+
+/// Hands `data` to the radio without waiting for the send status. Meant for the panic handler,
+/// which can neither await nor rely on the send callback still being delivered.
+pub fn send_now(data: &MessageData, destination: Node) -> Result<(), LinkError> {
+    let mut sender = SENDER.try_lock().map_err(|_| LinkError::SenderBusyError)?;
+    let sender = sender.as_mut().ok_or(LinkError::NotInitializedError)?;
+    let waiter = sender
+        .send(&destination.mac, data)
+        .map_err(|_| LinkError::DeliveryError(destination))?;
+    core::mem::forget(waiter);
+    Ok(())
+}
+
 #[embassy_executor::task]
 async fn recv_task(
-    recv_queue: &'static Channel<CriticalSectionRawMutex, RecvData, RECV_QUEUE_SIZE>,
+    recv_queue: &'static Channel<CriticalSectionRawMutex, RecvData, LINK_RECV_QUEUE_SIZE>,
     mut receiver: EspNowReceiver<'static>,
 ) -> ! {
     loop {
@@ -106,6 +205,7 @@ async fn recv_task(
         let mut data = MessageData::new();
         if let Err(e) = data.extend_from_slice(received_data.data()) {
             println!("Error while extending receive message:\n{}", e);
+            continue;
         }
         let source = Node::new(received_data.info.src_address);
         let destination = Node::new(received_data.info.dst_address);
@@ -116,6 +216,7 @@ async fn recv_task(
                 source,
                 destination,
                 rssi,
+                received_ms: asynchronous::now_ms(),
             })
             .await;
     }
@@ -1,7 +1,12 @@
 pub mod asynchronous;
 pub mod bmp;
 pub mod bus;
+pub mod crash;
 pub mod display;
 pub mod error;
 pub mod link;
+pub mod ota;
+pub mod provisioning;
+#[cfg(feature = "rig")]
+pub mod rig;
 pub mod util;
@@ -0,0 +1,143 @@
+use embedded_storage::{ReadStorage, Storage};
+use esp_bootloader_esp_idf::{
+    ota::OtaImageState,
+    ota_updater::OtaUpdater,
+    partitions::{self, PARTITION_TABLE_MAX_LEN},
+};
+use esp_rom_sys::rom::spiflash::{
+    ESP_ROM_SPIFLASH_RESULT_OK, esp_rom_spiflash_erase_sector, esp_rom_spiflash_read,
+    esp_rom_spiflash_unlock, esp_rom_spiflash_write,
+};
+
+use crate::{hardware::error::OtaError, logic::capabilities::ImageState};
+
+const FLASH_SECTOR_SIZE: usize = 4096;
+const FLASH_SIZE: usize = 4 * 1024 * 1024;
+
+/// The SPI flash accessed through the ROM functions, so the bootloader crate can read the
+/// partition table and flip the state of the running image. Writes erase and rewrite whole
+/// sectors, errors are the ROM result codes.
+pub struct RomFlash {
+    sector: [u32; FLASH_SECTOR_SIZE / 4],
+}
+
+impl RomFlash {
+    pub const fn new() -> Self {
+        Self {
+            sector: [0; FLASH_SECTOR_SIZE / 4],
+        }
+    }
+
+    fn read_sector(&mut self, base: u32) -> Result<(), i32> {
+        let result = unsafe {
+            esp_rom_spiflash_read(base, self.sector.as_mut_ptr(), FLASH_SECTOR_SIZE as u32)
+        };
+        if result != ESP_ROM_SPIFLASH_RESULT_OK {
+            return Err(result);
+        }
+        Ok(())
+    }
+
+    #[esp_hal::ram]
+    fn write_sector(&mut self, base: u32) -> Result<(), i32> {
+        let result = unsafe { esp_rom_spiflash_unlock() };
+        if result != ESP_ROM_SPIFLASH_RESULT_OK {
+            return Err(result);
+        }
+        let result = unsafe { esp_rom_spiflash_erase_sector(base / FLASH_SECTOR_SIZE as u32) };
+        if result != ESP_ROM_SPIFLASH_RESULT_OK {
+            return Err(result);
+        }
+        let result =
+            unsafe { esp_rom_spiflash_write(base, self.sector.as_ptr(), FLASH_SECTOR_SIZE as u32) };
+        if result != ESP_ROM_SPIFLASH_RESULT_OK {
+            return Err(result);
+        }
+        Ok(())
+    }
+
+    fn sector_bytes(&mut self) -> &mut [u8] {
+        let words = &mut self.sector;
+        // SAFETY: any bit pattern is a valid u8 and the length covers exactly the words.
+        unsafe { core::slice::from_raw_parts_mut(words.as_mut_ptr() as *mut u8, FLASH_SECTOR_SIZE) }
+    }
+}
+
+impl Default for RomFlash {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl ReadStorage for RomFlash {
+    type Error = i32;
+
+    fn read(&mut self, offset: u32, bytes: &mut [u8]) -> Result<(), Self::Error> {
+        let mut done = 0;
+        while done < bytes.len() {
+            let address = offset as usize + done;
+            let base = address - address % FLASH_SECTOR_SIZE;
+            self.read_sector(base as u32)?;
+            let start = address - base;
+            let len = (FLASH_SECTOR_SIZE - start).min(bytes.len() - done);
+            bytes[done..done + len].copy_from_slice(&self.sector_bytes()[start..start + len]);
+            done += len;
+        }
+        Ok(())
+    }
+
+    fn capacity(&self) -> usize {
+        FLASH_SIZE
+    }
+}
+
+impl Storage for RomFlash {
+    fn write(&mut self, offset: u32, bytes: &[u8]) -> Result<(), Self::Error> {
+        let mut done = 0;
+        while done < bytes.len() {
+            let address = offset as usize + done;
+            let base = address - address % FLASH_SECTOR_SIZE;
+            self.read_sector(base as u32)?;
+            let start = address - base;
+            let len = (FLASH_SECTOR_SIZE - start).min(bytes.len() - done);
+            self.sector_bytes()[start..start + len].copy_from_slice(&bytes[done..done + len]);
+            self.write_sector(base as u32)?;
+            done += len;
+        }
+        Ok(())
+    }
+}
+
+fn with_updater<T>(
+    update: impl FnOnce(&mut OtaUpdater<'_, RomFlash>) -> Result<T, partitions::Error>,
+) -> Result<T, OtaError> {
+    let mut flash = RomFlash::new();
+    let mut buffer = [0u8; PARTITION_TABLE_MAX_LEN];
+    let mut updater = OtaUpdater::new(&mut flash, &mut buffer).map_err(OtaError::PartitionError)?;
+    update(&mut updater).map_err(OtaError::PartitionError)
+}
+
+/// State of the running image as the bootloader left it. Images the bootloader marked as
+/// failed count as rolled back, since the previous image is running instead.
+pub fn boot_image_state() -> Result<ImageState, OtaError> {
+    let state = with_updater(|updater| updater.current_ota_state())?;
+    Ok(match state {
+        OtaImageState::New | OtaImageState::PendingVerify => ImageState::PendingVerify,
+        OtaImageState::Invalid | OtaImageState::Aborted => ImageState::RolledBack,
+        _ => ImageState::Confirmed,
+    })
+}
+
+/// Marks the running image as working, so the bootloader keeps booting it.
+pub fn confirm_image() -> Result<(), OtaError> {
+    with_updater(|updater| updater.set_current_ota_state(OtaImageState::Valid))
+}
+
+/// Marks the running image as broken and resets, so the bootloader boots the previous one.
+/// Requires a bootloader built with app rollback enabled.
+pub fn roll_back() -> ! {
+    if let Err(e) = with_updater(|updater| updater.set_current_ota_state(OtaImageState::Invalid)) {
+        esp_println::println!("{}", e);
+    }
+    esp_hal::system::software_reset()
+}
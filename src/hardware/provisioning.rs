@@ -0,0 +1,29 @@
+use crate::{
+    hardware::error::ProvisioningError,
+    logic::{
+        consts::PROVISIONING_BLOB_SIZE,
+        provisioning::{PROVISIONING_OFFSET, Provisioning, ProvisioningData},
+        wire::{Cursor, WireCodec},
+    },
+};
+use esp_rom_sys::rom::spiflash::{ESP_ROM_SPIFLASH_RESULT_OK, esp_rom_spiflash_read};
+
+/// Reads the blob the `provision` bench tool wrote to the start of the `nvs` partition.
+pub fn read_provisioning() -> Result<Provisioning, ProvisioningError> {
+    let mut words = [0u32; PROVISIONING_BLOB_SIZE / 4];
+    let result = unsafe {
+        esp_rom_spiflash_read(
+            PROVISIONING_OFFSET,
+            words.as_mut_ptr(),
+            PROVISIONING_BLOB_SIZE as u32,
+        )
+    };
+    if result != ESP_ROM_SPIFLASH_RESULT_OK {
+        return Err(ProvisioningError::FlashReadError(result));
+    }
+    let mut blob = ProvisioningData::new();
+    for word in words {
+        let _ = blob.extend_from_slice(&word.to_le_bytes());
+    }
+    Provisioning::decode(&mut Cursor::new(&blob)).map_err(ProvisioningError::DecodeError)
+}
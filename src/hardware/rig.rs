@@ -0,0 +1,73 @@
+use core::fmt;
+
+use embassy_time::{Duration, Instant, Timer};
+use esp_println::{Printer, println};
+
+use crate::{
+    hardware::asynchronous::Mutex,
+    logic::{
+        mesh::{Mesh, MeshHandle},
+        rig::Tap,
+        tree::Tree,
+    },
+};
+
+/// Steps of `run_script`.
+const RIG_TESTS: u16 = 2;
+/// How long the board waits to join a mesh before failing the step.
+const RIG_JOIN_TIMEOUT_MS: u64 = 60_000;
+/// How often the board checks whether it joined.
+const RIG_POLL_INTERVAL_MS: u64 = 1000;
+
+/// Runs the scripted checks of a regression rig once and reports them over serial, see
+/// `logic::rig`. Meant for a rig of two boards flashed with the `rig` feature.
+#[embassy_executor::task]
+pub async fn rig_task(mesh: MeshHandle, tree: &'static Mutex<Tree>) {
+    if let Err(e) = run_script(&mesh, tree).await {
+        println!("Failed to write rig report: {}", e);
+    }
+}
+
+/// Joins the mesh and pings a neighbor in the tree.
+async fn run_script(mesh: &Mesh, tree: &Mutex<Tree>) -> fmt::Result {
+    let mut tap = Tap::new(Printer, RIG_TESTS)?;
+    if wait_for_join(mesh).await {
+        tap.pass("join mesh")?;
+        tap.diagnostic(format_args!("{} nodes", mesh.node_count().await))?;
+    } else {
+        tap.fail(
+            "join mesh",
+            format_args!("still alone after {} ms", RIG_JOIN_TIMEOUT_MS),
+        )?;
+    }
+
+    let neighbor = tree
+        .lock()
+        .await
+        .neighbors()
+        .ok()
+        .and_then(|neighbors| neighbors.first().map(|(node, _)| *node));
+    match neighbor {
+        None => tap.skip("ping neighbor", "no neighbor")?,
+        Some(node) => match mesh.ping(node).await {
+            Ok(rtt) => {
+                tap.pass("ping neighbor")?;
+                tap.diagnostic(format_args!("{} answered in {} ms", node, rtt.as_millis()))?;
+            }
+            Err(e) => tap.fail("ping neighbor", e)?,
+        },
+    }
+    Ok(())
+}
+
+/// Waits until this node knows another one, or `RIG_JOIN_TIMEOUT_MS` passed.
+async fn wait_for_join(mesh: &Mesh) -> bool {
+    let deadline = Instant::now() + Duration::from_millis(RIG_JOIN_TIMEOUT_MS);
+    while mesh.node_count().await < 2 {
+        if Instant::now() >= deadline {
+            return false;
+        }
+        Timer::after(Duration::from_millis(RIG_POLL_INTERVAL_MS)).await;
+    }
+    true
+}
@@ -2,7 +2,13 @@ use crate::logic::error::ArenaError;
 use core::{cell::RefCell, option::Option, result::Result};
 use heapless::Vec;
 
+/// Index of an arena slot, which bounds the capacity of an arena. Builds with the `wide-slots`
+/// feature use 16-bit IDs so larger routing tables fit, at the cost of twice the memory per
+/// stored ID.
+#[cfg(not(feature = "wide-slots"))]
 pub type SlotId = u8;
+#[cfg(feature = "wide-slots")]
+pub type SlotId = u16;
 
 pub struct Arena<T, const N: usize> {
     free: Vec<SlotId, N>,
@@ -11,8 +17,9 @@ pub struct Arena<T, const N: usize> {
 
 impl<T, const N: usize> Arena<T, N> {
     pub fn new() -> Self {
+        const { assert!(N <= SlotId::MAX as usize + 1, "Every slot needs a SlotId") };
         Arena {
-            free: (0..N as SlotId).collect(),
+            free: (0..N).map(|id| id as SlotId).collect(),
             slots: [(); N].map(|_| None),
         }
     }
@@ -36,6 +43,39 @@ impl<T, const N: usize> Arena<T, N> {
             .as_ref()
             .ok_or(ArenaError::SlotEmptyError(id))
     }
+
+    /// Empties every slot in place, so a large arena is never rebuilt on the stack.
+    pub fn clear(&mut self) {
+        self.slots.iter_mut().for_each(|slot| *slot = None);
+        self.free.clear();
+        self.free.extend((0..N).map(|id| id as SlotId));
+    }
+
+    /// Occupied slots in the order of their IDs.
+    pub fn iter(&self) -> impl Iterator<Item = (SlotId, &RefCell<T>)> {
+        self.slots
+            .iter()
+            .enumerate()
+            .filter_map(|(id, slot)| slot.as_ref().map(|val| (id as SlotId, val)))
+    }
+
+    /// Number of occupied slots.
+    pub fn len(&self) -> usize {
+        N - self.free.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.free.len() == N
+    }
+
+    pub const fn capacity(&self) -> usize {
+        N
+    }
+
+    /// Returns true if the next `alloc` fails.
+    pub fn is_full(&self) -> bool {
+        self.free.is_empty()
+    }
 }
 
 #[cfg(test)]
@@ -83,6 +123,38 @@ mod tests {
         assert!(id3.is_none());
     }
 
+    #[test]
+    fn test_alloc_uses_every_slot_id() {
+        let mut arena: Arena<(), 256> = Arena::new();
+
+        for _ in 0..256 {
+            assert!(arena.alloc(()).is_some());
+        }
+        assert!(arena.alloc(()).is_none());
+    }
+
+    #[test]
+    fn test_occupancy_and_iter() {
+        let mut arena: Arena<i32, 3> = Arena::new();
+        assert!(arena.is_empty());
+        assert_eq!(arena.capacity(), 3);
+
+        let id1 = arena.alloc(1).unwrap();
+        let id2 = arena.alloc(2).unwrap();
+        let id3 = arena.alloc(3).unwrap();
+        assert!(arena.is_full());
+        unwrap_print!(arena.remove(id2));
+        assert_eq!(arena.len(), 2);
+        assert!(!arena.is_full());
+
+        let mut occupied: Vec<(SlotId, i32), 3> =
+            arena.iter().map(|(id, val)| (id, *val.borrow())).collect();
+        occupied.sort_unstable();
+        let mut expected: Vec<(SlotId, i32), 3> = [(id1, 1), (id3, 3)].into_iter().collect();
+        expected.sort_unstable();
+        assert_eq!(occupied, expected);
+    }
+
     #[test]
     fn test_remove_invalid() {
         let mut arena: Arena<i32, 1> = Arena::new();
@@ -1,8 +1,13 @@
 #![cfg(feature = "std")]
+use std::collections::hash_map::RandomState;
+use std::hash::{BuildHasher, Hasher};
+use std::sync::OnceLock;
+use std::sync::atomic::{AtomicU64, Ordering};
 pub use std::time::Duration;
 use tokio::sync::{futures, mpsc};
+use tokio::time::Instant;
 
-use crate::logic::error::AsyncError;
+use crate::logic::{error::AsyncError, util};
 
 pub type Spawner = ();
 
@@ -34,6 +39,10 @@ impl<T, const N: usize> StdChannel<T, N> {
     pub async fn my_recv(&self) -> T {
         self.rx.lock().await.recv().await.expect("channel closed")
     }
+
+    pub fn my_len(&self) -> usize {
+        self.tx.max_capacity() - self.tx.capacity()
+    }
 }
 
 pub type Mutex<T> = tokio::sync::Mutex<T>;
@@ -58,18 +67,59 @@ pub fn after(duration: Duration) -> impl Future<Output = ()> {
     tokio::time::sleep(duration)
 }
 
+/// Periodic timer whose period is jittered by +-`util::JITTER_PERCENT` on every tick.
 pub struct Ticker {
-    interval: tokio::time::Interval,
+    period: Duration,
+    deadline: Instant,
 }
 
 impl Ticker {
-    pub fn every(duration: Duration) -> Self {
+    pub fn every(period: Duration) -> Self {
         Self {
-            interval: tokio::time::interval(duration),
+            period,
+            deadline: Instant::now(),
         }
     }
 
     pub async fn next(&mut self) {
-        self.interval.tick().await;
+        tokio::time::sleep_until(self.deadline).await;
+        self.deadline += jittered(self.period);
     }
 }
+
+/// Spreads `period` over +-`util::JITTER_PERCENT`.
+pub fn jittered(period: Duration) -> Duration {
+    Duration::from_micros(util::jittered_micros(
+        period.as_micros() as u64,
+        random_u32(),
+    ))
+}
+
+const SPLITMIX_GAMMA: u64 = 0x9E37_79B9_7F4A_7C15;
+
+/// State of the SplitMix64 generator behind `random_u32`. Seeded from the OS unless
+/// `seed_random` is called.
+fn random_state() -> &'static AtomicU64 {
+    static STATE: OnceLock<AtomicU64> = OnceLock::new();
+    STATE.get_or_init(|| AtomicU64::new(RandomState::new().build_hasher().finish()))
+}
+
+/// Restarts the random stream at `seed`, so a simulation can be replayed.
+pub fn seed_random(seed: u64) {
+    random_state().store(seed, Ordering::Relaxed);
+}
+
+pub fn random_u32() -> u32 {
+    let mut z = random_state()
+        .fetch_add(SPLITMIX_GAMMA, Ordering::Relaxed)
+        .wrapping_add(SPLITMIX_GAMMA);
+    z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+    ((z ^ (z >> 31)) >> 32) as u32
+}
+
+/// Milliseconds since the first call. Stands in for the time since boot of the hardware build.
+pub fn now_ms() -> u64 {
+    static START: OnceLock<Instant> = OnceLock::new();
+    START.get_or_init(Instant::now).elapsed().as_millis() as u64
+}
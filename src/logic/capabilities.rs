@@ -0,0 +1,188 @@
+use crate::logic::{
+    consts::{FIRMWARE_VERSION, MESSAGE_SIZE},
+    error::CodecError,
+    wire::{Cursor, WireCodec, WriteBytes},
+};
+use crate::wire_codec;
+use heapless::Vec;
+
+/// Hardware a node offers beyond the radio. Bits this firmware does not know are kept, so a
+/// leader running older firmware passes them on unchanged.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub struct Features(u8);
+
+impl Features {
+    /// Runs off a steady supply, so it never drains its battery by forwarding for others.
+    pub const MAINS_POWERED: Self = Self(0x01);
+    /// Has the SSD1306 display of `BoardPreset::Display`.
+    pub const DISPLAY: Self = Self(0x02);
+    /// Has a BMP pressure and temperature sensor.
+    pub const PRESSURE_SENSOR: Self = Self(0x04);
+
+    pub const fn empty() -> Self {
+        Self(0)
+    }
+
+    pub const fn from_bits(bits: u8) -> Self {
+        Self(bits)
+    }
+
+    pub const fn bits(self) -> u8 {
+        self.0
+    }
+
+    pub const fn contains(self, other: Self) -> bool {
+        self.0 & other.0 == other.0
+    }
+
+    pub fn insert(&mut self, other: Self) {
+        self.0 |= other.0;
+    }
+}
+
+impl<const N: usize> WireCodec<N> for Features {
+    fn encode(&self, out: &mut Vec<u8, N>) -> Result<(), CodecError> {
+        out.write_u8(self.0)
+    }
+
+    fn decode(cursor: &mut Cursor<'_>) -> Result<Self, CodecError> {
+        Ok(Self(cursor.read_u8().map_err(CodecError::CursorReadError)?))
+    }
+}
+
+/// Whether the firmware a node runs has proven itself, see `Mesh::confirm_boot`.
+#[repr(u8)]
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub enum ImageState {
+    /// Runs fine, or was never updated over the air.
+    #[default]
+    Confirmed = 0x00,
+    /// Freshly updated and waiting for the leader to confirm its boot.
+    PendingVerify = 0x01,
+    /// An update failed to get confirmed, so the node went back to its previous firmware.
+    RolledBack = 0x02,
+}
+
+impl TryFrom<u8> for ImageState {
+    type Error = CodecError;
+
+    fn try_from(value: u8) -> Result<Self, Self::Error> {
+        match value {
+            0x00 => Ok(ImageState::Confirmed),
+            0x01 => Ok(ImageState::PendingVerify),
+            0x02 => Ok(ImageState::RolledBack),
+            v => Err(CodecError::InvalidImageStateError(v)),
+        }
+    }
+}
+
+impl<const N: usize> WireCodec<N> for ImageState {
+    fn encode(&self, out: &mut Vec<u8, N>) -> Result<(), CodecError> {
+        out.write_u8(*self as u8)
+    }
+
+    fn decode(cursor: &mut Cursor<'_>) -> Result<Self, CodecError> {
+        ImageState::try_from(cursor.read_u8().map_err(CodecError::CursorReadError)?)
+    }
+}
+
+/// What a node advertises about itself in its Discovery, so the leader can take it into account
+/// when admitting and placing the node, and the application when handing out game roles.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct Capabilities {
+    pub firmware: u16,
+    pub image: ImageState,
+    /// Remaining charge in percent, `None` if the node cannot measure it.
+    pub battery: Option<u8>,
+    pub features: Features,
+    /// Rank of this node when newcomers elect a leader, higher wins. Ties go to the lower
+    /// address, so the same fleet elects the same leader on every boot.
+    pub priority: u8,
+}
+
+impl Capabilities {
+    /// Capabilities of this firmware on a board without any known extras.
+    pub const fn new() -> Self {
+        Self {
+            firmware: FIRMWARE_VERSION,
+            image: ImageState::Confirmed,
+            battery: None,
+            features: Features::empty(),
+            priority: 0,
+        }
+    }
+
+    pub fn with_battery(self, percent: u8) -> Self {
+        Self {
+            battery: Some(percent.min(100)),
+            ..self
+        }
+    }
+
+    pub fn with_features(self, features: Features) -> Self {
+        Self { features, ..self }
+    }
+
+    pub fn with_image(self, image: ImageState) -> Self {
+        Self { image, ..self }
+    }
+
+    pub fn with_priority(self, priority: u8) -> Self {
+        Self { priority, ..self }
+    }
+}
+
+impl Default for Capabilities {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+wire_codec! {
+    Capabilities: MESSAGE_SIZE {
+        firmware: u16,
+        image: ImageState,
+        battery: Option<u8>,
+        features: Features,
+        priority: u8,
+    }
+    round_trip_test capabilities_round_trip = Capabilities {
+        firmware: 3,
+        image: ImageState::RolledBack,
+        battery: Some(42),
+        features: Features::from_bits(0x85),
+        priority: 7,
+    };
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn features_keep_unknown_bits() {
+        let mut features = Features::from_bits(0x80);
+        features.insert(Features::DISPLAY);
+        assert!(features.contains(Features::DISPLAY));
+        assert!(!features.contains(Features::MAINS_POWERED));
+        assert_eq!(features.bits(), 0x82);
+
+        let tag = Capabilities::new()
+            .with_battery(150)
+            .with_features(features);
+        assert_eq!(tag.battery, Some(100));
+        assert_eq!(tag.firmware, FIRMWARE_VERSION);
+    }
+
+    #[test]
+    fn unknown_image_state_is_rejected() {
+        assert!(matches!(
+            ImageState::try_from(0x01),
+            Ok(ImageState::PendingVerify)
+        ));
+        assert!(matches!(
+            ImageState::try_from(0x03),
+            Err(CodecError::InvalidImageStateError(0x03))
+        ));
+    }
+}
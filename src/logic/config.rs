@@ -0,0 +1,276 @@
+use crate::logic::{
+    capabilities::Capabilities,
+    consts::{
+        HEARTBEAT_INTERVAL_MS, MAX_NEWS_INTERVAL_MS, MESSAGE_SIZE, MIN_KEEPALIVE_INTERVAL_MS,
+        MIN_NEWS_INTERVAL_MS, ROUTE_COST_PER_HOP_DB, STALE_NODE_TIMEOUT_MS,
+    },
+    crypto::NetworkKey,
+    error::CodecError,
+    placement::{FanOutLimit, ParentSelectionPolicy, RouteCost},
+    wire::{Cursor, WireCodec, WriteBytes},
+};
+use crate::wire_codec;
+use heapless::Vec;
+
+/// How a node takes part in the mesh.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum NodeRole {
+    /// Regular member that may lead, forward traffic and act as a parent.
+    Member,
+    /// Read-only member that receives topology and can be addressed, but never leads, forwards
+    /// or reports newcomers, so no other node is ever attached below it.
+    Monitor,
+}
+
+/// Whether a `NodeRole::Member` takes the lead of the mesh.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum LeadPolicy {
+    /// Searches for a mesh first and leads once elected, see `MeshConfig::lead_after_rounds`.
+    Elected,
+    /// Leads right after boot without searching, e.g. the powered base station of an
+    /// installation that wants a predictable root. Two such nodes in range lead two meshes.
+    Always,
+    /// Searches until a mesh admits it and never leads, neither elected nor by failing over or
+    /// leading a partition.
+    Never,
+}
+
+#[derive(Copy, Clone, Debug)]
+pub struct MeshConfig {
+    /// Weakest RSSI the leader accepts when attaching a newcomer to a parent.
+    pub min_join_rssi: i32,
+    /// Number of news rounds a newcomer is held back so that stronger Discovery reports from
+    /// other members can still arrive before a parent is chosen.
+    pub join_grace_rounds: u8,
+    pub role: NodeRole,
+    /// Pins this node as the leader or as a follower instead of electing, see `LeadPolicy`.
+    pub lead: LeadPolicy,
+    /// Search rounds this node ignores the Discovery of other newcomers instead of taking the
+    /// lead, so the node meant to lead wins when a deployment powers up at once.
+    pub lead_after_rounds: u8,
+    /// Frames carrying a different network ID are dropped, so independent deployments on the
+    /// same channel never join each other's tree.
+    pub network_id: u16,
+    /// Stamps every frame this node originates with its local time, so receivers can measure
+    /// latency. Costs five bytes per frame.
+    pub timestamps: bool,
+    /// Lets a follower answer the Discovery of a node still known below it, e.g. a sleepy node
+    /// waking up, with the initial topology right away instead of waiting for the next news
+    /// round. The leader confirms or moves the node in the following round.
+    pub fast_rejoin: bool,
+    /// Key application payloads are encrypted with end to end. Without a key they are sent in
+    /// the clear.
+    pub network_key: Option<NetworkKey>,
+    /// Picks the parent of each newcomer while this node leads the mesh. The default
+    /// `RouteCost` weighs signal against hops, `StrongestSignal` only looks at the signal.
+    pub parent_selection: &'static dyn ParentSelectionPolicy,
+    /// Caps the children the leader gives a member by how well it hears its own uplink and how
+    /// much battery it has left. `FanOutLimit::UNLIMITED` fills every parent up.
+    pub fan_out: FanOutLimit,
+    /// Advertised in every Discovery of this node, see `Mesh::set_capabilities`.
+    pub capabilities: Capabilities,
+    /// Name this node introduces itself to the leader with after joining, see `Mesh::roster`.
+    /// Longer names are cut to `MAX_NAME_LEN` bytes.
+    pub name: &'static str,
+    /// Oldest firmware the leader admits. Newcomers advertising an older one are ignored.
+    pub min_firmware: u16,
+    /// Sends application payloads of every traffic class straight to destinations heard
+    /// directly with a strong signal, not only realtime ones. See `ShortcutTable`.
+    pub shortcuts: bool,
+    /// How long the leader waits for any frame from or through a member before it prunes the
+    /// member and tells the others, so a crashed relay does not swallow the traffic routed
+    /// through it. `u64::MAX` keeps silent members.
+    pub stale_after_ms: u64,
+}
+
+impl Default for MeshConfig {
+    fn default() -> Self {
+        Self {
+            min_join_rssi: -90,
+            join_grace_rounds: 0,
+            role: NodeRole::Member,
+            lead: LeadPolicy::Elected,
+            lead_after_rounds: 0,
+            network_id: 0,
+            timestamps: false,
+            fast_rejoin: true,
+            network_key: None,
+            parent_selection: &RouteCost {
+                rssi_per_hop: ROUTE_COST_PER_HOP_DB,
+            },
+            fan_out: FanOutLimit::default(),
+            capabilities: Capabilities::new(),
+            name: "",
+            min_firmware: 0,
+            shortcuts: false,
+            stale_after_ms: STALE_NODE_TIMEOUT_MS,
+        }
+    }
+}
+
+impl MeshConfig {
+    /// Whether this node may ever lead the mesh.
+    pub fn may_lead(&self) -> bool {
+        self.role == NodeRole::Member && self.lead != LeadPolicy::Never
+    }
+}
+
+#[repr(u8)]
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum TxPowerPolicy {
+    Maximum = 0x00,
+    Balanced = 0x01,
+    LowPower = 0x02,
+}
+
+impl TxPowerPolicy {
+    /// Transmit power limit in quarters of a dBm, the unit the radio takes.
+    pub const fn quarter_dbm(self) -> i8 {
+        match self {
+            TxPowerPolicy::Maximum => 80,
+            TxPowerPolicy::Balanced => 60,
+            TxPowerPolicy::LowPower => 34,
+        }
+    }
+}
+
+impl TryFrom<u8> for TxPowerPolicy {
+    type Error = CodecError;
+
+    fn try_from(value: u8) -> Result<Self, Self::Error> {
+        match value {
+            0x00 => Ok(TxPowerPolicy::Maximum),
+            0x01 => Ok(TxPowerPolicy::Balanced),
+            0x02 => Ok(TxPowerPolicy::LowPower),
+            v => Err(CodecError::InvalidTxPowerPolicyError(v)),
+        }
+    }
+}
+
+impl<const N: usize> WireCodec<N> for TxPowerPolicy {
+    fn encode(&self, out: &mut Vec<u8, N>) -> Result<(), CodecError> {
+        out.write_u8(*self as u8)
+    }
+
+    fn decode(cursor: &mut Cursor<'_>) -> Result<Self, CodecError> {
+        TxPowerPolicy::try_from(cursor.read_u8().map_err(CodecError::CursorReadError)?)
+    }
+}
+
+/// Operational parameters the leader distributes to every member at runtime. A member only
+/// applies a config with a newer `version` than the one it already has.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct NetworkConfig {
+    pub version: u16,
+    /// Time between two news rounds of the leader, see `news_round_interval_ms`.
+    pub news_interval_ms: u32,
+    /// Time between two heartbeats to the tree neighbors, see `heartbeat_interval_ms`.
+    pub keepalive_interval_ms: u32,
+    /// Set on the link of every member once the config arrives, see `Link::set_tx_power`.
+    pub tx_power: TxPowerPolicy,
+}
+
+impl NetworkConfig {
+    pub const fn new() -> Self {
+        Self {
+            version: 0,
+            news_interval_ms: 3000,
+            keepalive_interval_ms: HEARTBEAT_INTERVAL_MS as u32,
+            tx_power: TxPowerPolicy::Maximum,
+        }
+    }
+
+    /// `keepalive_interval_ms` within what failure detection allows. Silent neighbors are found
+    /// after a few missed `HEARTBEAT_INTERVAL_MS`, so heartbeats may only come more often, down
+    /// to `MIN_KEEPALIVE_INTERVAL_MS`.
+    pub fn heartbeat_interval_ms(&self) -> u64 {
+        (self.keepalive_interval_ms as u64).clamp(MIN_KEEPALIVE_INTERVAL_MS, HEARTBEAT_INTERVAL_MS)
+    }
+
+    /// `news_interval_ms` within `MIN_NEWS_INTERVAL_MS` and `MAX_NEWS_INTERVAL_MS`.
+    pub fn news_round_interval_ms(&self) -> u64 {
+        (self.news_interval_ms as u64).clamp(MIN_NEWS_INTERVAL_MS, MAX_NEWS_INTERVAL_MS)
+    }
+}
+
+impl Default for NetworkConfig {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+wire_codec! {
+    NetworkConfig: MESSAGE_SIZE {
+        version: u16,
+        news_interval_ms: u32,
+        keepalive_interval_ms: u32,
+        tx_power: TxPowerPolicy,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::logic::message::MessageData;
+    use crate::unwrap_print;
+
+    #[test]
+    fn network_config_encode_decode() {
+        let config = NetworkConfig {
+            version: 3,
+            news_interval_ms: 5000,
+            keepalive_interval_ms: 250,
+            tx_power: TxPowerPolicy::LowPower,
+        };
+        let mut out = MessageData::new();
+        unwrap_print!(config.encode(&mut out));
+
+        let mut cursor = Cursor::new(&out);
+        let decoded = unwrap_print!(NetworkConfig::decode(&mut cursor));
+        assert_eq!(decoded, config);
+    }
+
+    #[test]
+    fn network_config_bounds_heartbeat_interval() {
+        let config = |keepalive_interval_ms| NetworkConfig {
+            keepalive_interval_ms,
+            ..NetworkConfig::new()
+        };
+        assert_eq!(
+            NetworkConfig::new().heartbeat_interval_ms(),
+            HEARTBEAT_INTERVAL_MS
+        );
+        assert_eq!(config(500).heartbeat_interval_ms(), 500);
+        assert_eq!(config(0).heartbeat_interval_ms(), MIN_KEEPALIVE_INTERVAL_MS);
+        assert_eq!(
+            config(u32::MAX).heartbeat_interval_ms(),
+            HEARTBEAT_INTERVAL_MS
+        );
+    }
+
+    #[test]
+    fn network_config_bounds_news_interval() {
+        let config = |news_interval_ms| NetworkConfig {
+            news_interval_ms,
+            ..NetworkConfig::new()
+        };
+        assert_eq!(config(5000).news_round_interval_ms(), 5000);
+        assert_eq!(config(0).news_round_interval_ms(), MIN_NEWS_INTERVAL_MS);
+        assert_eq!(
+            config(u32::MAX).news_round_interval_ms(),
+            MAX_NEWS_INTERVAL_MS
+        );
+    }
+
+    #[test]
+    fn network_config_rejects_unknown_tx_power_policy() {
+        let mut out = MessageData::new();
+        unwrap_print!(NetworkConfig::new().encode(&mut out));
+        let last = out.len() - 1;
+        out[last] = 0x7f;
+
+        let mut cursor = Cursor::new(&out);
+        let err = NetworkConfig::decode(&mut cursor).unwrap_err();
+        assert!(matches!(err, CodecError::InvalidTxPowerPolicyError(0x7f)));
+    }
+}
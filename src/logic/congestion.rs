@@ -0,0 +1,182 @@
+use crate::logic::{
+    consts::{
+        BUSY_DEFER_MS, BUSY_SEND_MS, BUSY_SEND_STREAK, CONGESTION_ECHO_INTERVAL_MS,
+        CONGESTION_HOLD_MS, CONGESTION_QUEUE_THRESHOLD, MAX_LEAFS,
+    },
+    message::Priority,
+    node::Node,
+    util,
+};
+use heapless::LinearMap;
+
+/// Watches the send queue of a relay. The relay counts as congested once the queue held at
+/// least `CONGESTION_QUEUE_THRESHOLD` frames at every sample for `CONGESTION_HOLD_MS`, so a
+/// single burst does not slow down the sources.
+pub struct CongestionDetector {
+    above_since_ms: Option<u64>,
+}
+
+impl CongestionDetector {
+    pub const fn new() -> Self {
+        Self {
+            above_since_ms: None,
+        }
+    }
+
+    /// Records that `queued` frames were waiting for the radio and tells whether the relay is
+    /// congested.
+    pub fn sample(&mut self, queued: usize, now_ms: u64) -> bool {
+        if queued < CONGESTION_QUEUE_THRESHOLD {
+            self.above_since_ms = None;
+            return false;
+        }
+        let since = *self.above_since_ms.get_or_insert(now_ms);
+        now_ms.saturating_sub(since) >= CONGESTION_HOLD_MS
+    }
+}
+
+impl Default for CongestionDetector {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// When a congested relay last told each source about it, so a stream of frames through the
+/// relay gets one `MessageContent::Congested` per `CONGESTION_ECHO_INTERVAL_MS`.
+pub struct CongestionEchoes {
+    last_echo_ms: LinearMap<Node, u64, MAX_LEAFS>,
+}
+
+impl CongestionEchoes {
+    pub const fn new() -> Self {
+        Self {
+            last_echo_ms: LinearMap::new(),
+        }
+    }
+
+    /// True if `source` should be told now, in which case the echo is recorded. Once full, the
+    /// stalest source is forgotten.
+    pub fn should_echo(&mut self, source: Node, now_ms: u64) -> bool {
+        if let Some(last) = self.last_echo_ms.get_mut(&source) {
+            if now_ms.saturating_sub(*last) < CONGESTION_ECHO_INTERVAL_MS {
+                return false;
+            }
+            *last = now_ms;
+            return true;
+        }
+        if self.last_echo_ms.is_full() {
+            let stalest = self
+                .last_echo_ms
+                .iter()
+                .min_by_key(|(_, last)| **last)
+                .map(|(node, _)| *node);
+            if let Some(stalest) = stalest {
+                self.last_echo_ms.remove(&stalest);
+            }
+        }
+        let _ = self.last_echo_ms.insert(source, now_ms);
+        true
+    }
+}
+
+impl Default for CongestionEchoes {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Judges how busy the channel is from the send status ESP-NOW reports for every frame. After
+/// `BUSY_SEND_STREAK` slow or failed sends in a row, frames below `Priority::High` are held back
+/// for a random time from a window that doubles while the channel stays busy, so neighbors in
+/// a noisy venue stop retrying into each other.
+pub struct ChannelMonitor {
+    streak: u8,
+    hold_until_ms: u64,
+}
+
+impl ChannelMonitor {
+    pub const fn new() -> Self {
+        Self {
+            streak: 0,
+            hold_until_ms: 0,
+        }
+    }
+
+    /// Records that a send took `elapsed_ms` until its status arrived and whether it was
+    /// delivered.
+    pub fn record(&mut self, elapsed_ms: u64, delivered: bool, now_ms: u64, random: u32) {
+        if delivered && elapsed_ms < BUSY_SEND_MS {
+            self.streak = 0;
+            return;
+        }
+        self.streak = self.streak.saturating_add(1);
+        if self.streak >= BUSY_SEND_STREAK {
+            let window = util::backoff_ms(BUSY_DEFER_MS, self.streak - BUSY_SEND_STREAK, random);
+            self.hold_until_ms = now_ms + BUSY_DEFER_MS / 2 + window;
+        }
+    }
+
+    /// Time a frame of `priority` still has to wait at `now_ms`, None if it may go out.
+    pub fn defer_ms(&self, priority: Priority, now_ms: u64) -> Option<u64> {
+        if priority >= Priority::High || now_ms >= self.hold_until_ms {
+            return None;
+        }
+        Some(self.hold_until_ms - now_ms)
+    }
+}
+
+impl Default for ChannelMonitor {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn congestion_needs_a_queue_that_stays_long() {
+        let mut detector = CongestionDetector::new();
+        assert!(!detector.sample(CONGESTION_QUEUE_THRESHOLD, 0));
+        assert!(!detector.sample(CONGESTION_QUEUE_THRESHOLD, CONGESTION_HOLD_MS - 1));
+        assert!(detector.sample(CONGESTION_QUEUE_THRESHOLD, CONGESTION_HOLD_MS));
+
+        assert!(!detector.sample(CONGESTION_QUEUE_THRESHOLD - 1, CONGESTION_HOLD_MS + 1));
+        assert!(!detector.sample(CONGESTION_QUEUE_THRESHOLD, CONGESTION_HOLD_MS + 2));
+    }
+
+    #[test]
+    fn busy_channel_holds_back_non_urgent_frames() {
+        let mut channel = ChannelMonitor::new();
+        for _ in 0..BUSY_SEND_STREAK - 1 {
+            channel.record(BUSY_SEND_MS, true, 0, 0);
+        }
+        channel.record(1, true, 0, 0);
+        channel.record(1, false, 0, 0);
+        assert_eq!(channel.defer_ms(Priority::Normal, 0), None);
+
+        for _ in 0..BUSY_SEND_STREAK - 1 {
+            channel.record(1, false, 100, 0);
+        }
+        let held = channel.defer_ms(Priority::Normal, 100).unwrap();
+        assert!(held >= BUSY_DEFER_MS / 2);
+        assert_eq!(channel.defer_ms(Priority::High, 100), None);
+        assert_eq!(channel.defer_ms(Priority::Low, 100 + held), None);
+
+        channel.record(1, true, 200, 0);
+        channel.record(1, false, 200, u32::MAX);
+        assert_eq!(channel.defer_ms(Priority::Low, 200), None);
+    }
+
+    #[test]
+    fn echoes_are_limited_per_source() {
+        let mut echoes = CongestionEchoes::new();
+        let a = Node::new([0, 0, 0, 0, 0, 1]);
+        let b = Node::new([0, 0, 0, 0, 0, 2]);
+        assert!(echoes.should_echo(a, 0));
+        assert!(echoes.should_echo(b, 1));
+        assert!(!echoes.should_echo(a, CONGESTION_ECHO_INTERVAL_MS - 1));
+        assert!(echoes.should_echo(a, CONGESTION_ECHO_INTERVAL_MS));
+    }
+}
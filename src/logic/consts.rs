@@ -0,0 +1,302 @@
+//! Sizes and timings shared by every layer of the protocol. Values that depend on each other
+//! are tied together by the compile-time assertions at the bottom of this file.
+
+/// Largest payload ESP-NOW accepts in a single frame.
+pub const ESP_NOW_MAX_PAYLOAD: usize = 250;
+/// Capacity of a serialized message. A message must fit into one ESP-NOW frame. Builds with the
+/// `small-frames` feature use about half of it, which shrinks every queue and buffer holding a
+/// message at the cost of shorter application payloads. All nodes of a mesh must agree on it,
+/// since longer frames are dropped on receipt.
+#[cfg(not(feature = "small-frames"))]
+pub const MESSAGE_SIZE: usize = ESP_NOW_MAX_PAYLOAD;
+#[cfg(feature = "small-frames")]
+pub const MESSAGE_SIZE: usize = 128;
+
+/// Number of nodes a routing tree can hold, including this node. Builds with the `wide-slots`
+/// feature hold more, which takes wider arena slot IDs and a larger chunk bitmap in
+/// `RequestTopologyChunks`. All nodes of a mesh must agree on it, since a tree sent to a
+/// newcomer has to fit.
+#[cfg(not(feature = "wide-slots"))]
+pub const MAX_LEAFS: usize = 32;
+#[cfg(feature = "wide-slots")]
+pub const MAX_LEAFS: usize = 512;
+/// Number of children a single node in the routing tree can have.
+pub const MAX_CHILD_LEAFS: usize = 8;
+/// Number of newcomers the leader considers per news round.
+pub const MAX_NEWS: usize = 16;
+/// Members whose report of one newcomer the leader keeps as parent candidates.
+pub const MAX_PARENT_REPORTS: usize = 4;
+/// Signal in dB one more hop to the leader is worth to `RouteCost`, the default policy of
+/// `MeshConfig::parent_selection`.
+pub const ROUTE_COST_PER_HOP_DB: i32 = 6;
+/// Number of news rounds a candidate may go unreported before the leader forgets it.
+pub const MAX_SILENT_ROUNDS: u8 = 3;
+/// Hops a message may travel before it is dropped.
+pub const DEFAULT_HOP_LIMIT: u16 = MAX_LEAFS as u16;
+/// Largest hop limit that fits next to the two priority bits of its 16-bit field in the header.
+pub const MAX_HOP_LIMIT: u16 = u16::MAX >> 2;
+/// Number of priorities the two priority bits in the header can express.
+pub const PRIORITY_LEVELS: usize = 4;
+/// Number of `(final_source, sequence)` pairs the dispatcher remembers.
+pub const DUPLICATE_CACHE_SIZE: usize = 32;
+/// Number of sequence numbers below the highest one seen that the replay window still
+/// accepts once. One bit each in a `u64`.
+pub const REPLAY_WINDOW_SIZE: u16 = 64;
+/// Sessions the replay filter keeps a window for, the current and the previous one of every
+/// node.
+pub const REPLAY_SESSIONS: usize = 2 * MAX_LEAFS;
+
+/// Application messages waiting for `Mesh::receive`.
+pub const RECV_QUEUE_SIZE: usize = 16;
+/// Organization messages waiting for the searcher, leader or follower task.
+pub const ORGANIZE_QUEUE_SIZE: usize = 16;
+/// Frames of one priority waiting to be handed to the radio.
+pub const LINK_SEND_QUEUE_SIZE: usize = 16;
+/// Frames received by the radio waiting for the dispatcher.
+pub const LINK_RECV_QUEUE_SIZE: usize = 16;
+/// Application frames waiting for the next heartbeat to their next hop, see
+/// `TrafficClass::Piggyback`.
+pub const PIGGYBACK_QUEUE_SIZE: usize = 8;
+/// Mesh events kept for applications that start listening late.
+pub const MESH_EVENT_HISTORY: usize = 16;
+/// Peer and port pairs whose received application traffic is counted.
+pub const MAX_TRAFFIC_ENTRIES: usize = 16;
+
+/// Capacity of the metadata entries sent in front of an application payload.
+pub const MAX_METADATA_LEN: usize = 32;
+/// Length of the tag that authenticates organization messages.
+pub const MESSAGE_TAG_LEN: usize = 8;
+/// Longest run of optional fields behind the body a frame may carry, see
+/// `MessageFlags::EXTENDED`. Relays keep them verbatim, frames with more are dropped.
+pub const MAX_EXTENSION_LEN: usize = 16;
+/// Longest header, type, flags, session, tag and checksum a frame can carry next to its body:
+/// version 1, network ID 3, final destination 6, final source 7, sequence 3, priority and hop
+/// limit 3, timestamp 5, type 1, flags 1, session 4, tag and a CRC-16 of 2.
+pub const MAX_FRAME_OVERHEAD: usize = 1 + 3 + 6 + 7 + 3 + 3 + 5 + 1 + 1 + 4 + MESSAGE_TAG_LEN + 2;
+/// Largest body of an organization message, so it fits into one frame behind any header.
+/// Content built at runtime is split to stay within it, see `TopologyChunks`.
+pub const MAX_ORGANIZATION_BODY_LEN: usize = MESSAGE_SIZE - MAX_FRAME_OVERHEAD;
+/// Firmware version nodes advertise in their Discovery. Bumped with every release.
+pub const FIRMWARE_VERSION: u16 = 1;
+/// Length of the network key written during provisioning.
+pub const NETWORK_KEY_LEN: usize = 16;
+/// Longest device name written during provisioning.
+pub const MAX_NAME_LEN: usize = 16;
+/// Capacity of a serialized provisioning blob.
+pub const PROVISIONING_BLOB_SIZE: usize = 64;
+/// Longest panic message kept in the crash record.
+pub const MAX_CRASH_MESSAGE_LEN: usize = 120;
+/// Capacity of a serialized crash record.
+pub const CRASH_RECORD_SIZE: usize = 128;
+
+/// Times a frame is resent to a neighbor that did not acknowledge it.
+pub const LINK_SEND_RETRIES: u8 = 2;
+/// Backoff window before the first resend. Doubles with every further attempt.
+pub const LINK_RETRY_BACKOFF_MS: u64 = 10;
+/// Failed sends in a row after which a destination counts as unreachable.
+pub const UNREACHABLE_AFTER_FAILURES: u8 = 3;
+/// How long sends to an unreachable destination fail fast before the route is probed again.
+pub const UNREACHABLE_HOLD_MS: u64 = 5000;
+/// Nodes after which the leader refuses newcomers with `JoinRejection::MeshFull`. Kept below
+/// `MAX_LEAFS` so members whose tree still holds a node the leader already moved or dropped do
+/// not overflow.
+pub const MESH_FULL_WATERMARK: usize = MAX_LEAFS - 2;
+/// How long a node refused by a full mesh waits before it searches again.
+pub const JOIN_REFUSED_BACKOFF_MS: u64 = 10_000;
+/// How long a node refused for its outdated firmware waits before it searches again. Only an
+/// update or a leader with a lower `MeshConfig::min_firmware` lets it in.
+pub const OUTDATED_FIRMWARE_BACKOFF_MS: u64 = 60_000;
+/// Time between two heartbeats a node sends to each of its neighbors in the tree.
+pub const HEARTBEAT_INTERVAL_MS: u64 = 2000;
+/// Shortest heartbeat interval a `NetworkConfig` can set, so a bad config cannot flood the
+/// channel.
+pub const MIN_KEEPALIVE_INTERVAL_MS: u64 = 250;
+/// Weakest signal a destination has to be heard with to become a shortcut, so frames to it go
+/// there directly instead of following the tree.
+pub const SHORTCUT_MIN_RSSI: i32 = -65;
+/// Signal below which a shortcut is dropped and frames follow the tree again. Kept below
+/// `SHORTCUT_MIN_RSSI` so a link at the edge does not flip between both routes.
+pub const SHORTCUT_DROP_RSSI: i32 = -75;
+/// Longest a destination may have been silent to stay a shortcut.
+pub const SHORTCUT_MAX_AGE_MS: u64 = 2 * HEARTBEAT_INTERVAL_MS;
+/// Direct neighbors remembered as shortcuts.
+pub const MAX_SHORTCUTS: usize = 16;
+/// Time between two link reports of a member, see `MessageContent::LinkReport`. Every node also
+/// broadcasts a heartbeat this often, so nodes in range that are not its tree neighbors hear it.
+pub const LINK_REPORT_INTERVAL_MS: u64 = 5 * HEARTBEAT_INTERVAL_MS;
+/// Neighbors a link report holds, the strongest ones. Fewer with small frames, so a full report
+/// still fits.
+pub const MAX_LINK_REPORT: usize = at_most(8, (MAX_ORGANIZATION_BODY_LEN - 6 - 1) / (6 + 4));
+/// Default of `MeshConfig::stale_after_ms`. Every member reports to the leader at least once
+/// per `LINK_REPORT_INTERVAL_MS`, so this allows two reports in a row to get lost.
+pub const STALE_NODE_TIMEOUT_MS: u64 = 3 * LINK_REPORT_INTERVAL_MS;
+/// Time between two `MessageContent::LeaderHeartbeat` the leader sends to every member.
+pub const LEADER_HEARTBEAT_INTERVAL_MS: u64 = LINK_REPORT_INTERVAL_MS;
+/// Silence of the leader after which a follower elects its successor, see `Tree::successor`.
+pub const LEADER_LOST_MS: u64 = 3 * LEADER_HEARTBEAT_INTERVAL_MS;
+/// Silence of the uplink after which a follower considers the path to the leader broken and
+/// leads the nodes below it, see `MeshEvent::Partitioned`.
+pub const UPLINK_LOST_MS: u64 = 4 * HEARTBEAT_INTERVAL_MS;
+/// Weight of a new frame in the smoothed RSSI of a neighbor, see `Ewma`. 1 averages every frame
+/// with the previous value.
+pub const NEIGHBOR_RSSI_SHIFT: u8 = 1;
+/// Longest a neighbor may have been silent to still appear in the link report.
+pub const NEIGHBOR_MAX_AGE_MS: u64 = 2 * LINK_REPORT_INTERVAL_MS;
+/// Signal of the uplink below which the leader looks for a better parent for a member.
+pub const REPARENT_BELOW_RSSI: i32 = -80;
+/// How much stronger another parent has to be heard before the leader moves a member to it, so
+/// members at the edge do not move back and forth.
+pub const REPARENT_MARGIN: i32 = 10;
+/// Signal of its own uplink below which a parent gets at most `FAN_OUT_WEAK_UPLINK_CHILDREN`
+/// children, since everything they send has to pass that uplink.
+pub const FAN_OUT_WEAK_UPLINK_RSSI: i32 = -75;
+pub const FAN_OUT_WEAK_UPLINK_CHILDREN: usize = 2;
+/// Battery in percent below which a parent gets at most `FAN_OUT_LOW_BATTERY_CHILDREN`
+/// children, relaying for them drains it.
+pub const FAN_OUT_LOW_BATTERY: u8 = 20;
+pub const FAN_OUT_LOW_BATTERY_CHILDREN: usize = 1;
+/// Frames waiting in the send queue of a relay from which on it may be congested.
+pub const CONGESTION_QUEUE_THRESHOLD: usize = LINK_SEND_QUEUE_SIZE / 2;
+/// How long the send queue has to stay at `CONGESTION_QUEUE_THRESHOLD` before a relay tells the
+/// sources of the frames it forwards with `MessageContent::Congested`.
+pub const CONGESTION_HOLD_MS: u64 = 500;
+/// Shortest time between two congestion notices a relay sends to the same source.
+pub const CONGESTION_ECHO_INTERVAL_MS: u64 = 1000;
+/// Send status delay from which on a frame counts as evidence of a busy channel. ESP-NOW
+/// reports within a few milliseconds on a quiet channel, retries and backoff stretch that.
+pub const BUSY_SEND_MS: u64 = 20;
+/// Slow or failed sends in a row after which the channel counts as busy.
+pub const BUSY_SEND_STREAK: u8 = 3;
+/// Base of the random window non-urgent frames are held back for while the channel is busy.
+pub const BUSY_DEFER_MS: u64 = 30;
+/// Most a telemetry interval is stretched while the mesh reports congestion.
+pub const TELEMETRY_MAX_BACKOFF: u32 = 8;
+/// How long `Mesh::ping` waits for the pong before giving up.
+pub const PING_TIMEOUT_MS: u64 = 2000;
+/// Tasks that can wait for the role of this node to change at the same time, see
+/// `Mesh::role_receiver`.
+pub const MAX_ROLE_RECEIVERS: usize = 4;
+/// Pings and trace routes that can wait for their answer at the same time.
+pub const MAX_PENDING_PINGS: usize = 4;
+/// Hops a trace route records. Relays beyond that forward it without adding themselves. Fewer
+/// with small frames, so a full trace route still fits.
+pub const MAX_TRACE_HOPS: usize = at_most(16, (MAX_ORGANIZATION_BODY_LEN - 3 - 1) / 6);
+/// How long a node running freshly updated firmware has to get its boot confirmed by the
+/// leader before it rolls back to the previous firmware.
+pub const BOOT_CONFIRM_WINDOW_MS: u64 = 60_000;
+/// Time between two boot reports of a node waiting for its confirmation.
+pub const BOOT_REPORT_INTERVAL_MS: u64 = 2000;
+/// How long a panicking node gives the radio to get its crash notice out before resetting.
+pub const LAST_GASP_WAIT_MS: u32 = 20;
+
+/// Edges sent in one initial topology chunk. Chosen so that a chunk of edges with both nodes
+/// set still fits into one frame.
+pub const MAX_TOPOLOGY_CHUNK_EDGES: usize = at_most(12, (MAX_ORGANIZATION_BODY_LEN - 3) / (2 * 7));
+/// How long a joining node waits for the next initial topology chunk before requesting the
+/// missing ones.
+pub const TOPOLOGY_CHUNK_TIMEOUT_MS: u64 = 500;
+/// Times a joining node requests missing topology chunks before it gives up.
+pub const TOPOLOGY_REQUEST_RETRIES: u8 = 3;
+/// Shortest time between two full initial topologies a parent sends to the same child.
+pub const INIT_TOPOLOGY_COOLDOWN_MS: u64 = 2000;
+/// How long a searching node waits for an invitation before sending the next Discovery. Jittered,
+/// so that nodes booted together do not keep sending their Discovery at the same time.
+pub const SEARCH_ROUND_MS: u64 = 1000;
+/// Search rounds a node that dropped its tree after a partition holds back from leading, so the
+/// former members of the partition join the mesh again instead of leading each other.
+pub const REJOIN_SEARCH_ROUNDS: u8 = 20;
+/// Search rounds past `MeshConfig::lead_after_rounds` a node leaves the lead to a higher ranked
+/// newcomer, see `Capabilities::priority`. Bounded, as that newcomer may never lead, e.g. as a
+/// `NodeRole::Monitor`.
+pub const MAX_OUTRANKED_ROUNDS: u8 = 5;
+/// How long the leader waits for the next reply of a member while collecting news.
+pub const NEWS_RESPONSE_TIMEOUT_MS: u64 = 500;
+/// Shortest news interval a `NetworkConfig` can set, so a bad config cannot keep the leader
+/// collecting news without a break.
+pub const MIN_NEWS_INTERVAL_MS: u64 = 1000;
+/// Longest news interval a `NetworkConfig` can set, so newcomers are still admitted.
+pub const MAX_NEWS_INTERVAL_MS: u64 = 60_000;
+
+const fn at_most(limit: usize, value: usize) -> usize {
+    if value < limit { value } else { limit }
+}
+
+const _: () = assert!(MESSAGE_SIZE <= ESP_NOW_MAX_PAYLOAD);
+const _: () = assert!(MAX_CHILD_LEAFS < MAX_LEAFS);
+const _: () = assert!(
+    REPLAY_SESSIONS > MAX_LEAFS,
+    "The replay filter keeps the latest window of every member and needs room beyond them"
+);
+const _: () = assert!(
+    PROVISIONING_BLOB_SIZE >= 4 + 1 + 1 + 1 + NETWORK_KEY_LEN + 1 + MAX_NAME_LEN + 2,
+    "A provisioning blob with the longest name must fit"
+);
+const _: () = assert!(
+    PROVISIONING_BLOB_SIZE.is_multiple_of(4),
+    "Flash is read in whole 32-bit words"
+);
+const _: () = assert!(
+    CRASH_RECORD_SIZE >= 4 + 1 + 1 + MAX_CRASH_MESSAGE_LEN + 2,
+    "A crash record with the longest message must fit"
+);
+const _: () = assert!(
+    CRASH_RECORD_SIZE.is_multiple_of(4),
+    "Flash is written in whole 32-bit words"
+);
+const _: () = assert!(
+    MAX_METADATA_LEN <= u8::MAX as usize,
+    "Metadata value lengths are encoded in a single byte"
+);
+const _: () = assert!(
+    MAX_CRASH_MESSAGE_LEN <= u8::MAX as usize,
+    "Crash message length is encoded in a single byte"
+);
+const _: () = assert!(
+    MESH_FULL_WATERMARK > 1 && MESH_FULL_WATERMARK <= MAX_LEAFS,
+    "The leader must be able to admit at least one node and never more than fit"
+);
+const _: () = assert!(
+    MAX_NEWS <= MAX_LEAFS,
+    "Every admitted newcomer needs a leaf"
+);
+const _: () = assert!(
+    MAX_LEAFS.div_ceil(MAX_TOPOLOGY_CHUNK_EDGES)
+        <= crate::logic::topology::ChunkBitmap::BITS as usize,
+    "Every initial topology chunk needs a bit in the request bitmap"
+);
+const _: () = assert!(
+    3 + MAX_TOPOLOGY_CHUNK_EDGES * 2 * 7 <= MAX_ORGANIZATION_BODY_LEN,
+    "A full topology chunk must fit into one frame next to the longest header"
+);
+const _: () = assert!(
+    3 + 1 + MAX_TRACE_HOPS * 6 <= MAX_ORGANIZATION_BODY_LEN,
+    "A full trace route must fit into one frame next to the longest header"
+);
+const _: () = assert!(
+    6 + 1 + MAX_LINK_REPORT * (6 + 4) <= MAX_ORGANIZATION_BODY_LEN,
+    "A full link report must fit into one frame next to the longest header"
+);
+const _: () = assert!(
+    DEFAULT_HOP_LIMIT as usize >= MAX_LEAFS,
+    "A message must be able to cross the deepest possible tree"
+);
+const _: () = assert!(
+    DEFAULT_HOP_LIMIT <= MAX_HOP_LIMIT,
+    "The hop limit shares its field with the priority"
+);
+const _: () = assert!(
+    REPLAY_WINDOW_SIZE as u32 <= u64::BITS,
+    "The replay window is a u64 bitmap"
+);
+const _: () = assert!(
+    DUPLICATE_CACHE_SIZE >= LINK_RECV_QUEUE_SIZE,
+    "Duplicates within a full receive queue must still be recognized"
+);
+const _: () = assert!(
+    ORGANIZE_QUEUE_SIZE >= MAX_NEWS,
+    "A full round of news replies must fit into the organize queue"
+);
+const _: () = assert!(
+    LEADER_HEARTBEAT_INTERVAL_MS * 2 < LEADER_LOST_MS,
+    "Followers must hear the leader at least twice before they consider it lost"
+);
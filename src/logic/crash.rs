@@ -0,0 +1,128 @@
+use crate::logic::{
+    consts::{CRASH_RECORD_SIZE, MAX_CRASH_MESSAGE_LEN},
+    error::CodecError,
+    wire::{Cursor, WireCodec, WriteBytes, crc16},
+};
+use core::fmt::{self, Write};
+use heapless::{String, Vec};
+
+/// Marks the start of a crash record so an erased sector is not mistaken for one.
+pub const CRASH_MAGIC: [u8; 4] = *b"ECRS";
+/// Layout version of the crash record.
+pub const CRASH_FORMAT: u8 = 1;
+/// Last sector of the `nvs` partition in the default ESP-IDF partition table. The provisioning
+/// blob lives at the start of the partition, so both can be erased independently.
+pub const CRASH_LOG_OFFSET: u32 = 0xE000;
+
+pub type CrashData = Vec<u8, CRASH_RECORD_SIZE>;
+
+/// Panic message the firmware writes to flash before resetting, so the cause of a crash can
+/// be read after the next boot.
+///
+/// Layout: magic | format | message length | message | CRC-16 (LE)
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct CrashRecord {
+    pub message: String<MAX_CRASH_MESSAGE_LEN>,
+}
+
+impl CrashRecord {
+    /// Formats `info` into a record. Messages longer than `MAX_CRASH_MESSAGE_LEN` are cut off.
+    pub fn new(info: &impl fmt::Display) -> Self {
+        let mut writer = TruncatingWriter {
+            message: String::new(),
+        };
+        let _ = write!(writer, "{}", info);
+        Self {
+            message: writer.message,
+        }
+    }
+}
+
+struct TruncatingWriter {
+    message: String<MAX_CRASH_MESSAGE_LEN>,
+}
+
+impl Write for TruncatingWriter {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        for c in s.chars() {
+            self.message.push(c).map_err(|_| fmt::Error)?;
+        }
+        Ok(())
+    }
+}
+
+impl WireCodec<CRASH_RECORD_SIZE> for CrashRecord {
+    fn encode(&self, out: &mut CrashData) -> Result<(), CodecError> {
+        let start = out.len();
+        out.write_bytes(&CRASH_MAGIC)?;
+        out.write_u8(CRASH_FORMAT)?;
+        out.write_u8(self.message.len() as u8)?;
+        out.write_bytes(self.message.as_bytes())?;
+        let checksum = crc16(&out[start..]);
+        out.write_u16_le(checksum)
+    }
+
+    fn decode(cursor: &mut Cursor<'_>) -> Result<Self, CodecError> {
+        let record = cursor.remaining();
+        let magic: [u8; 4] = cursor.read_array().map_err(CodecError::CursorReadError)?;
+        if magic != CRASH_MAGIC {
+            return Err(CodecError::InvalidMagicError);
+        }
+        let format = cursor.read_u8().map_err(CodecError::CursorReadError)?;
+        if format != CRASH_FORMAT {
+            return Err(CodecError::UnsupportedFormatError(format));
+        }
+        let message_len = cursor.read_u8().map_err(CodecError::CursorReadError)? as usize;
+        let message_bytes = cursor
+            .take(message_len)
+            .map_err(CodecError::CursorReadError)?;
+        let body_len = record.len() - cursor.remaining().len();
+        let expected = cursor.read_u16_le().map_err(CodecError::CursorReadError)?;
+        let actual = crc16(&record[..body_len]);
+        if expected != actual {
+            return Err(CodecError::ChecksumMismatch(expected, actual));
+        }
+        let mut message = String::new();
+        message
+            .push_str(core::str::from_utf8(message_bytes).map_err(|_| CodecError::CodecError)?)
+            .map_err(CodecError::BufferCapacityError)?;
+        Ok(CrashRecord { message })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::unwrap_print;
+
+    #[test]
+    fn crash_record_encode_decode() {
+        let record = CrashRecord::new(&"panicked at src/logic/tree.rs:42:9");
+        let mut out = CrashData::new();
+        unwrap_print!(record.encode(&mut out));
+
+        let mut cursor = Cursor::new(&out);
+        let decoded = unwrap_print!(CrashRecord::decode(&mut cursor));
+        assert_eq!(decoded, record);
+    }
+
+    #[test]
+    fn crash_record_truncates_long_messages() {
+        let long = "ä".repeat(MAX_CRASH_MESSAGE_LEN);
+        let record = CrashRecord::new(&long);
+        assert_eq!(record.message.len(), MAX_CRASH_MESSAGE_LEN);
+
+        let mut out = CrashData::new();
+        unwrap_print!(record.encode(&mut out));
+        let mut cursor = Cursor::new(&out);
+        assert_eq!(unwrap_print!(CrashRecord::decode(&mut cursor)), record);
+    }
+
+    #[test]
+    fn crash_record_rejects_erased_flash() {
+        let erased = [0xFF; CRASH_RECORD_SIZE];
+        let mut cursor = Cursor::new(&erased);
+        let err = CrashRecord::decode(&mut cursor).unwrap_err();
+        assert!(matches!(err, CodecError::InvalidMagicError));
+    }
+}
@@ -0,0 +1,279 @@
+use aes::Aes128;
+use ccm::{
+    AeadInPlace, Ccm, KeyInit,
+    consts::{U8, U13},
+};
+use cmac::{Cmac, Mac};
+
+use crate::logic::{
+    consts::{MESSAGE_TAG_LEN, NETWORK_KEY_LEN},
+    error::CryptoError,
+    message::{MessageData, MessageTag},
+    node::Node,
+};
+
+pub type NetworkKey = [u8; NETWORK_KEY_LEN];
+
+/// Length of the CCM authentication tag appended to every encrypted payload.
+pub const CCM_TAG_LEN: usize = 8;
+/// Width of the CCM length field. Two bytes are enough for any frame.
+const CCM_L: usize = 2;
+pub const CCM_NONCE_LEN: usize = 15 - CCM_L;
+/// Bytes an encrypted application payload is longer than the plaintext.
+pub const SEAL_OVERHEAD: usize = 4 + CCM_TAG_LEN;
+
+/// AES-CCM (RFC 3610) with the tag and nonce lengths above.
+type Aes128Ccm = Ccm<Aes128, U8, U13>;
+
+/// Labels the subkeys are derived with, so sealing and tagging never share a key.
+const SEAL_LABEL: &[u8] = b"seal";
+const TAG_LABEL: &[u8] = b"tag";
+
+/// Derives the subkey for `label` from the network key, CMAC(key, label).
+fn subkey(key: &NetworkKey, label: &[u8]) -> [u8; 16] {
+    cmac(key, label)
+}
+
+/// AES-CMAC (RFC 4493) over `data`.
+fn cmac(key: &[u8; 16], data: &[u8]) -> [u8; 16] {
+    tag_mac(key, data).finalize().into_bytes().into()
+}
+
+fn tag_mac(key: &[u8; 16], data: &[u8]) -> Cmac<Aes128> {
+    let mut mac = <Cmac<Aes128> as KeyInit>::new(key.into());
+    mac.update(data);
+    mac
+}
+
+/// Truncated CMAC that authenticates an organization message, keyed with the tagging subkey of
+/// `key`.
+pub fn message_tag(key: &NetworkKey, data: &[u8]) -> MessageTag {
+    let mac = cmac(&subkey(key, TAG_LABEL), data);
+    let mut tag = [0; MESSAGE_TAG_LEN];
+    tag.copy_from_slice(&mac[..MESSAGE_TAG_LEN]);
+    tag
+}
+
+/// Checks `tag` against `data` in constant time.
+pub fn verify_message_tag(
+    key: &NetworkKey,
+    data: &[u8],
+    tag: &MessageTag,
+) -> Result<(), CryptoError> {
+    tag_mac(&subkey(key, TAG_LABEL), data)
+        .verify_truncated_left(tag)
+        .map_err(|_| CryptoError::AuthenticationError)
+}
+
+/// Header fields an encrypted application payload is bound to. They make up the associated
+/// data, so a relay cannot redirect or replay a payload under a different header.
+pub struct AuthenticatedHeader {
+    pub final_source: Node,
+    pub final_destination: Node,
+    pub sequence: u16,
+}
+
+impl AuthenticatedHeader {
+    fn nonce(&self, session: u32) -> [u8; CCM_NONCE_LEN] {
+        let mut nonce = [0; CCM_NONCE_LEN];
+        nonce[..6].copy_from_slice(&self.final_source.mac);
+        nonce[6..10].copy_from_slice(&session.to_le_bytes());
+        nonce[10..12].copy_from_slice(&self.sequence.to_le_bytes());
+        nonce
+    }
+
+    fn aad(&self) -> [u8; 14] {
+        let mut aad = [0; 14];
+        aad[..6].copy_from_slice(&self.final_destination.mac);
+        aad[6..12].copy_from_slice(&self.final_source.mac);
+        aad[12..].copy_from_slice(&self.sequence.to_le_bytes());
+        aad
+    }
+}
+
+/// Encrypts an application payload end to end.
+///
+/// Layout: session (LE) | ciphertext | tag
+///
+/// `session` must be random per boot and change whenever the sequence number wraps, so that
+/// no nonce is used twice under the same key.
+pub fn seal_application(
+    key: &NetworkKey,
+    header: &AuthenticatedHeader,
+    session: u32,
+    plaintext: &[u8],
+) -> Result<MessageData, CryptoError> {
+    let mut out = MessageData::new();
+    out.extend_from_slice(&session.to_le_bytes())
+        .map_err(CryptoError::BufferCapacityError)?;
+    out.extend_from_slice(plaintext)
+        .map_err(CryptoError::BufferCapacityError)?;
+    let cipher = Aes128Ccm::new(&subkey(key, SEAL_LABEL).into());
+    let tag = cipher
+        .encrypt_in_place_detached(&header.nonce(session).into(), &header.aad(), &mut out[4..])
+        .map_err(|_| CryptoError::AuthenticationError)?;
+    out.extend_from_slice(&tag)
+        .map_err(CryptoError::BufferCapacityError)?;
+    Ok(out)
+}
+
+/// Reverses `seal_application`, failing if the payload or its header was tampered with. Returns
+/// the session the payload was sealed in along with the plaintext.
+pub fn open_application(
+    key: &NetworkKey,
+    header: &AuthenticatedHeader,
+    sealed: &[u8],
+) -> Result<(u32, MessageData), CryptoError> {
+    if sealed.len() < SEAL_OVERHEAD {
+        return Err(CryptoError::TruncatedError);
+    }
+    let session = u32::from_le_bytes([sealed[0], sealed[1], sealed[2], sealed[3]]);
+    let (ciphertext, tag_bytes) = sealed[4..].split_at(sealed.len() - SEAL_OVERHEAD);
+    let mut out = MessageData::new();
+    out.extend_from_slice(ciphertext)
+        .map_err(CryptoError::BufferCapacityError)?;
+    let cipher = Aes128Ccm::new(&subkey(key, SEAL_LABEL).into());
+    cipher
+        .decrypt_in_place_detached(
+            &header.nonce(session).into(),
+            &header.aad(),
+            &mut out,
+            tag_bytes.into(),
+        )
+        .map_err(|_| CryptoError::AuthenticationError)?;
+    Ok((session, out))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::unwrap_print;
+    use aes::cipher::BlockEncrypt;
+
+    fn bytes<const N: usize>(range: core::ops::RangeInclusive<u8>) -> [u8; N] {
+        let mut out = [0; N];
+        for (o, b) in out.iter_mut().zip(range) {
+            *o = b;
+        }
+        out
+    }
+
+    #[test]
+    fn aes128_matches_fips_197_vector() {
+        let cipher = <Aes128 as KeyInit>::new(&bytes::<16>(0x00..=0x0f).into());
+        let mut block = [
+            0x00, 0x11, 0x22, 0x33, 0x44, 0x55, 0x66, 0x77, 0x88, 0x99, 0xaa, 0xbb, 0xcc, 0xdd,
+            0xee, 0xff,
+        ]
+        .into();
+        cipher.encrypt_block(&mut block);
+        assert_eq!(
+            block,
+            [
+                0x69, 0xc4, 0xe0, 0xd8, 0x6a, 0x7b, 0x04, 0x30, 0xd8, 0xcd, 0xb7, 0x80, 0x70, 0xb4,
+                0xc5, 0x5a
+            ]
+            .into()
+        );
+    }
+
+    #[test]
+    fn ccm_matches_rfc_3610_packet_vector_1() {
+        let cipher = Aes128Ccm::new(&bytes::<16>(0xc0..=0xcf).into());
+        let nonce = [
+            0x00, 0x00, 0x00, 0x03, 0x02, 0x01, 0x00, 0xa0, 0xa1, 0xa2, 0xa3, 0xa4, 0xa5,
+        ];
+        let aad: [u8; 8] = bytes(0x00..=0x07);
+        let mut data: [u8; 23] = bytes(0x08..=0x1e);
+
+        let tag = unwrap_print!(cipher.encrypt_in_place_detached(&nonce.into(), &aad, &mut data));
+        assert_eq!(
+            data,
+            [
+                0x58, 0x8c, 0x97, 0x9a, 0x61, 0xc6, 0x63, 0xd2, 0xf0, 0x66, 0xd0, 0xc2, 0xc0, 0xf9,
+                0x89, 0x80, 0x6d, 0x5f, 0x6b, 0x61, 0xda, 0xc3, 0x84
+            ]
+        );
+        assert_eq!(tag[..], [0x17, 0xe8, 0xd1, 0x2c, 0xfd, 0xf9, 0x26, 0xe0]);
+
+        unwrap_print!(cipher.decrypt_in_place_detached(&nonce.into(), &aad, &mut data, &tag));
+        assert_eq!(data, bytes::<23>(0x08..=0x1e));
+    }
+
+    #[test]
+    fn cmac_matches_rfc_4493_vectors() {
+        let key = [
+            0x2b, 0x7e, 0x15, 0x16, 0x28, 0xae, 0xd2, 0xa6, 0xab, 0xf7, 0x15, 0x88, 0x09, 0xcf,
+            0x4f, 0x3c,
+        ];
+        let message = [
+            0x6b, 0xc1, 0xbe, 0xe2, 0x2e, 0x40, 0x9f, 0x96, 0xe9, 0x3d, 0x7e, 0x11, 0x73, 0x93,
+            0x17, 0x2a, 0xae, 0x2d, 0x8a, 0x57, 0x1e, 0x03, 0xac, 0x9c, 0x9e, 0xb7, 0x6f, 0xac,
+            0x45, 0xaf, 0x8e, 0x51, 0x30, 0xc8, 0x1c, 0x46, 0xa3, 0x5c, 0xe4, 0x11,
+        ];
+        assert_eq!(
+            cmac(&key, &[]),
+            [
+                0xbb, 0x1d, 0x69, 0x29, 0xe9, 0x59, 0x37, 0x28, 0x7f, 0xa3, 0x7d, 0x12, 0x9b, 0x75,
+                0x67, 0x46
+            ]
+        );
+        assert_eq!(
+            cmac(&key, &message[..16]),
+            [
+                0x07, 0x0a, 0x16, 0xb4, 0x6b, 0x4d, 0x41, 0x44, 0xf7, 0x9b, 0xdd, 0x9d, 0xd0, 0x4a,
+                0x28, 0x7c
+            ]
+        );
+        assert_eq!(
+            cmac(&key, &message),
+            [
+                0xdf, 0xa6, 0x67, 0x47, 0xde, 0x9a, 0xe6, 0x30, 0x30, 0xca, 0x32, 0x61, 0x14, 0x97,
+                0xc8, 0x27
+            ]
+        );
+    }
+
+    #[test]
+    fn sealing_and_tagging_use_separate_subkeys() {
+        let key = [0x42; NETWORK_KEY_LEN];
+        let seal = subkey(&key, SEAL_LABEL);
+        let tag = subkey(&key, TAG_LABEL);
+        assert_ne!(seal, tag);
+        assert_ne!(seal, key);
+        assert_ne!(tag, key);
+
+        let tagged = message_tag(&key, b"frame");
+        assert_eq!(tagged[..], cmac(&tag, b"frame")[..MESSAGE_TAG_LEN]);
+        unwrap_print!(verify_message_tag(&key, b"frame", &tagged));
+        assert!(matches!(
+            verify_message_tag(&key, b"other", &tagged),
+            Err(CryptoError::AuthenticationError)
+        ));
+    }
+
+    #[test]
+    fn sealed_payload_is_bound_to_its_header() {
+        let key = [0x42; NETWORK_KEY_LEN];
+        let header = AuthenticatedHeader {
+            final_source: Node::new([0, 0, 0, 0, 0, 1]),
+            final_destination: Node::new([0, 0, 0, 0, 0, 2]),
+            sequence: 9,
+        };
+        let sealed = unwrap_print!(seal_application(&key, &header, 0xdead_beef, &[1, 2, 3]));
+        assert_eq!(sealed.len(), 3 + SEAL_OVERHEAD);
+
+        let (session, opened) = unwrap_print!(open_application(&key, &header, &sealed));
+        assert_eq!(session, 0xdead_beef);
+        assert_eq!(&opened[..], &[1, 2, 3]);
+
+        let redirected = AuthenticatedHeader {
+            final_destination: Node::new([0, 0, 0, 0, 0, 3]),
+            ..header
+        };
+        assert!(matches!(
+            open_application(&key, &redirected, &sealed),
+            Err(CryptoError::AuthenticationError)
+        ));
+    }
+}
@@ -0,0 +1,251 @@
+use crate::logic::{
+    consts::{DUPLICATE_CACHE_SIZE, REPLAY_SESSIONS, REPLAY_WINDOW_SIZE},
+    node::Node,
+};
+use heapless::{Deque, LinearMap};
+
+/// Remembers the most recent `(final_source, sequence)` pairs seen by the dispatcher so a
+/// retransmitted or rebroadcast frame is only forwarded or delivered once.
+pub struct DuplicateFilter {
+    seen: Deque<(Node, u16), DUPLICATE_CACHE_SIZE>,
+}
+
+impl DuplicateFilter {
+    pub const fn new() -> Self {
+        Self { seen: Deque::new() }
+    }
+
+    /// Returns true if the pair was seen before, otherwise records it and returns false.
+    pub fn is_duplicate(&mut self, source: Node, sequence: u16) -> bool {
+        if self.seen.iter().any(|&entry| entry == (source, sequence)) {
+            return true;
+        }
+        if self.seen.is_full() {
+            self.seen.pop_front();
+        }
+        let _ = self.seen.push_back((source, sequence));
+        false
+    }
+}
+
+impl Default for DuplicateFilter {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Sliding window over the sequence numbers of one source. Bit `i` of `seen` stands for
+/// `highest - i`.
+struct ReplayWindow {
+    highest: u16,
+    seen: u64,
+    last_accepted_ms: u64,
+}
+
+impl ReplayWindow {
+    fn new(sequence: u16, now_ms: u64) -> Self {
+        Self {
+            highest: sequence,
+            seen: 1,
+            last_accepted_ms: now_ms,
+        }
+    }
+
+    /// Records `sequence` and returns true if it is newer than the window or inside it and not
+    /// seen yet. Sequence numbers are compared with wrap around.
+    fn accept(&mut self, sequence: u16, now_ms: u64) -> bool {
+        let ahead = sequence.wrapping_sub(self.highest) as i16;
+        if ahead > 0 {
+            self.seen = self.seen.checked_shl(ahead as u32).unwrap_or(0) | 1;
+            self.highest = sequence;
+        } else {
+            let behind = self.highest.wrapping_sub(sequence);
+            if behind >= REPLAY_WINDOW_SIZE || self.seen & (1 << behind) != 0 {
+                return false;
+            }
+            self.seen |= 1 << behind;
+        }
+        self.last_accepted_ms = now_ms;
+        true
+    }
+}
+
+/// Rejects authenticated frames that were captured and sent again. Unlike `DuplicateFilter`
+/// it keeps a window per source and session, so old frames stay rejected no matter how much
+/// other traffic arrived in between or how long the source was quiet, as long as their window
+/// is kept. A rebooted source starts a new session with a window of its own. Once full, the
+/// window accepted from least recently is forgotten, but never the latest one of a source the
+/// caller still knows, so the current session of every member stays protected. Sessions are
+/// random and carry no order, so the frames of an older, forgotten session are accepted again,
+/// e.g. those recorded two reboots ago.
+pub struct ReplayFilter {
+    windows: LinearMap<(Node, u32), ReplayWindow, REPLAY_SESSIONS>,
+}
+
+impl ReplayFilter {
+    pub const fn new() -> Self {
+        Self {
+            windows: LinearMap::new(),
+        }
+    }
+
+    /// Returns true if `sequence` from `source` in `session` must be dropped as a replay,
+    /// otherwise records it and returns false. `known` tells the sources whose latest window
+    /// must not make room for another one.
+    pub fn is_replay(
+        &mut self,
+        source: Node,
+        session: u32,
+        sequence: u16,
+        now_ms: u64,
+        known: impl Fn(Node) -> bool,
+    ) -> bool {
+        let key = (source, session);
+        if let Some(window) = self.windows.get_mut(&key) {
+            return !window.accept(sequence, now_ms);
+        }
+        let window = ReplayWindow::new(sequence, now_ms);
+        if let Err((key, window)) = self.windows.insert(key, window) {
+            let stalest = self
+                .windows
+                .iter()
+                .filter(|(key, _)| !known(key.0) || self.latest(key.0) != Some(**key))
+                .min_by_key(|(_, w)| w.last_accepted_ms)
+                .map(|(key, _)| *key);
+            if let Some(stalest) = stalest {
+                self.windows.remove(&stalest);
+            }
+            let _ = self.windows.insert(key, window);
+        }
+        false
+    }
+
+    /// The window of `source` that accepted a frame most recently.
+    fn latest(&self, source: Node) -> Option<(Node, u32)> {
+        self.windows
+            .iter()
+            .filter(|((node, _), _)| *node == source)
+            .max_by_key(|(_, w)| w.last_accepted_ms)
+            .map(|(key, _)| *key)
+    }
+}
+
+impl Default for ReplayFilter {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn n(mac_last_byte: u8) -> Node {
+        Node::new([0, 0, 0, 0, 0, mac_last_byte])
+    }
+
+    #[test]
+    fn second_copy_is_duplicate() {
+        let mut filter = DuplicateFilter::new();
+
+        assert!(!filter.is_duplicate(n(1), 5));
+        assert!(filter.is_duplicate(n(1), 5));
+    }
+
+    #[test]
+    fn sequence_is_tracked_per_source() {
+        let mut filter = DuplicateFilter::new();
+
+        assert!(!filter.is_duplicate(n(1), 5));
+        assert!(!filter.is_duplicate(n(2), 5));
+        assert!(!filter.is_duplicate(n(1), 6));
+    }
+
+    #[test]
+    fn replayed_sequence_is_rejected() {
+        let mut filter = ReplayFilter::new();
+
+        assert!(!filter.is_replay(n(1), 7, 10, 0, |_| false));
+        assert!(!filter.is_replay(n(1), 7, 12, 0, |_| false));
+        assert!(!filter.is_replay(n(1), 7, 11, 0, |_| false));
+        assert!(filter.is_replay(n(1), 7, 11, 0, |_| false));
+        assert!(filter.is_replay(n(1), 7, 10, 0, |_| false));
+        assert!(!filter.is_replay(n(2), 7, 10, 0, |_| false));
+    }
+
+    #[test]
+    fn sequence_older_than_window_is_rejected() {
+        let mut filter = ReplayFilter::new();
+
+        assert!(!filter.is_replay(n(1), 7, 100, 0, |_| false));
+        assert!(filter.is_replay(n(1), 7, 100 - REPLAY_WINDOW_SIZE, 0, |_| false));
+        assert!(!filter.is_replay(n(1), 7, 100 - REPLAY_WINDOW_SIZE + 1, 0, |_| false));
+    }
+
+    #[test]
+    fn replay_window_follows_wrap_around() {
+        let mut filter = ReplayFilter::new();
+
+        assert!(!filter.is_replay(n(1), 7, u16::MAX, 0, |_| false));
+        assert!(!filter.is_replay(n(1), 7, 1, 0, |_| false));
+        assert!(filter.is_replay(n(1), 7, u16::MAX, 0, |_| false));
+        assert!(!filter.is_replay(n(1), 7, 0, 0, |_| false));
+    }
+
+    #[test]
+    fn rebooted_source_gets_a_new_window() {
+        let mut filter = ReplayFilter::new();
+
+        assert!(!filter.is_replay(n(1), 7, 500, 0, |_| false));
+        assert!(filter.is_replay(n(1), 7, 1, 1000, |_| false));
+        assert!(!filter.is_replay(n(1), 8, 1, 1000, |_| false));
+        assert!(filter.is_replay(n(1), 8, 1, 1000, |_| false));
+    }
+
+    #[test]
+    fn quiet_source_stays_protected() {
+        let mut filter = ReplayFilter::new();
+
+        assert!(!filter.is_replay(n(1), 7, 500, 0, |_| false));
+        assert!(filter.is_replay(n(1), 7, 500, u64::MAX, |_| false));
+        assert!(filter.is_replay(n(1), 7, 1, u64::MAX, |_| false));
+    }
+
+    #[test]
+    fn least_recently_accepted_session_is_forgotten_when_full() {
+        let mut filter = ReplayFilter::new();
+
+        for session in 0..=REPLAY_SESSIONS as u32 {
+            assert!(!filter.is_replay(n(1), session, 1, session as u64, |_| false));
+        }
+
+        assert!(!filter.is_replay(n(1), 0, 1, 100, |_| false));
+        assert!(filter.is_replay(n(1), REPLAY_SESSIONS as u32, 1, 100, |_| false));
+    }
+
+    #[test]
+    fn latest_session_of_known_source_is_kept_when_full() {
+        let mut filter = ReplayFilter::new();
+        let known = |node| node == n(1);
+
+        assert!(!filter.is_replay(n(1), 0, 1, 0, known));
+        for session in 1..=REPLAY_SESSIONS as u32 {
+            assert!(!filter.is_replay(n(2), session, 1, session as u64, known));
+        }
+
+        assert!(filter.is_replay(n(1), 0, 1, 100, known));
+        assert!(!filter.is_replay(n(2), 1, 1, 100, known));
+    }
+
+    #[test]
+    fn oldest_entry_is_forgotten_when_full() {
+        let mut filter = DuplicateFilter::new();
+
+        for sequence in 0..=DUPLICATE_CACHE_SIZE as u16 {
+            assert!(!filter.is_duplicate(n(1), sequence));
+        }
+
+        assert!(!filter.is_duplicate(n(1), 0));
+        assert!(filter.is_duplicate(n(1), DUPLICATE_CACHE_SIZE as u16));
+    }
+}
@@ -25,7 +25,11 @@ pub enum LinkError {
     QueueFullError(),
     QueueEmptyError(),
     AlreadyInitialized,
+    NotInitializedError,
+    DeliveryError(Node),
+    SenderBusyError,
     SpawnError,
+    TxPowerError(i32),
     MockError,
 }
 
@@ -41,7 +45,11 @@ impl fmt::Display for LinkError {
                 "Failed to receive new message since message queue was empty:\n"
             ),
             Self::AlreadyInitialized => write!(f, "Link has already been initialized"),
+            Self::NotInitializedError => write!(f, "Link has not been initialized yet"),
+            Self::DeliveryError(n) => write!(f, "Neighbor {} did not acknowledge the frame", n),
+            Self::SenderBusyError => write!(f, "Radio is in use by another task"),
             Self::SpawnError => write!(f, "Failed to spawn task"),
+            Self::TxPowerError(e) => write!(f, "Radio refused the transmit power: {}", e),
             Self::MockError => write!(f, "Nothing failed this is just a test"),
         }
     }
@@ -56,6 +64,18 @@ pub enum MeshError {
     OrganizeQueueSendError(),
     OrganizeQueueRecvError(),
     ReceiveQueueSendError(),
+    HopLimitExceededError(Node),
+    CryptoError(CryptoError),
+    MetadataError(CodecError),
+    #[cfg(feature = "postcard")]
+    PayloadCodecError(postcard::Error),
+    UnreachableError(Node),
+    DestinationUnreachableError(Node),
+    PingTimeoutError(Node),
+    NotUnicastError(Node),
+    BootConfirmTimeoutError,
+    NotLeaderError,
+    StateLockedError,
     SpawnError,
 }
 
@@ -75,11 +95,71 @@ impl fmt::Display for MeshError {
             Self::ReceiveQueueSendError() => {
                 write!(f, "Failed to send receive message to channel:\n")
             }
+            Self::HopLimitExceededError(n) => {
+                write!(f, "Dropped message from {} after reaching hop limit", n)
+            }
+            Self::CryptoError(e) => write!(f, "Failed to protect message:\n{}", e),
+            Self::MetadataError(e) => write!(f, "Failed to process message metadata:\n{}", e),
+            #[cfg(feature = "postcard")]
+            Self::PayloadCodecError(e) => write!(f, "Failed to convert typed payload:\n{}", e),
+            Self::UnreachableError(n) => {
+                write!(f, "{} was unreachable recently, not sending", n)
+            }
+            Self::DestinationUnreachableError(n) => {
+                write!(
+                    f,
+                    "A node on the route reported {} unreachable, not sending",
+                    n
+                )
+            }
+            Self::PingTimeoutError(n) => {
+                write!(f, "{} did not answer the ping or trace route in time", n)
+            }
+            Self::NotUnicastError(n) => {
+                write!(f, "{} is not the address of a single node, not sending", n)
+            }
+            Self::NotLeaderError => write!(f, "Only the leader can hand over the lead"),
+            Self::BootConfirmTimeoutError => {
+                write!(
+                    f,
+                    "The leader did not confirm the boot of this firmware in time"
+                )
+            }
+            Self::StateLockedError => write!(f, "Mesh state is locked by another task"),
             Self::SpawnError => write!(f, "Failed to spawn task"),
         }
     }
 }
 
+#[derive(Debug)]
+pub enum CryptoError {
+    AuthenticationError,
+    MissingTagError,
+    TruncatedError,
+    BufferCapacityError(CapacityError),
+    AuthenticatedDataError(CodecError),
+    EncryptionMismatchError,
+}
+
+impl fmt::Display for CryptoError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::AuthenticationError => write!(f, "Payload failed authentication"),
+            Self::MissingTagError => write!(f, "Organization message carries no tag"),
+            Self::TruncatedError => write!(f, "Payload is too short to be encrypted"),
+            Self::BufferCapacityError(e) => {
+                write!(f, "Encrypted payload exceeds buffer capacity:\n{}", e)
+            }
+            Self::AuthenticatedDataError(e) => {
+                write!(f, "Failed to encode authenticated data:\n{}", e)
+            }
+            Self::EncryptionMismatchError => {
+                write!(f, "Payload encryption does not match the network key")
+            }
+        }
+    }
+}
+
 #[derive(Debug)]
 pub enum TreeError {
     LeafAllocationError,
@@ -87,6 +167,9 @@ pub enum TreeError {
     LeafNotFoundError(ArenaError),
     RootIsDestinationError,
     UninitializedError,
+    NoUplinkError,
+    StaleEdgeError(Node),
+    RenderOverflowError,
 }
 
 impl fmt::Display for TreeError {
@@ -97,6 +180,11 @@ impl fmt::Display for TreeError {
             Self::LeafNotFoundError(e) => write!(f, "Could not find Leaf:\n{}", e),
             Self::RootIsDestinationError => write!(f, "The root of this tree is the destination"),
             Self::UninitializedError => write!(f, "Tree is uninitialized"),
+            Self::NoUplinkError => write!(f, "This node has no uplink"),
+            Self::StaleEdgeError(node) => {
+                write!(f, "Ignored an edge of {} older than the known one", node)
+            }
+            Self::RenderOverflowError => write!(f, "The rendered tree does not fit the buffer"),
         }
     }
 }
@@ -136,6 +224,19 @@ pub enum CodecError {
     BufferCapacityError(CapacityError),
     BufferOverflowError(u8),
     InvalidOptionFlagError(u8),
+    InvalidTxPowerPolicyError(u8),
+    ChecksumMismatch(u16, u16),
+    InvalidBoardPresetError(u8),
+    InvalidImageStateError(u8),
+    InvalidStartupProfileError(u8),
+    InvalidJoinRejectionError(u8),
+    InvalidMagicError,
+    UnsupportedFormatError(u8),
+    InvalidMetadataError,
+    VarintOverflowError,
+    LengthExceededError(u32),
+    /// An encoded tree refers to a parent before listing it, or lists a node twice.
+    InvalidTreeError,
     CodecError,
 }
 
@@ -151,6 +252,24 @@ impl fmt::Display for CodecError {
                 write!(f, "Buffer is full; cannot push more bytes:\n{}", e)
             }
             Self::InvalidOptionFlagError(e) => write!(f, "Flag {} is not supported for option", e),
+            Self::InvalidTxPowerPolicyError(e) => write!(f, "Unknown tx power policy: {}", e),
+            Self::ChecksumMismatch(expected, actual) => write!(
+                f,
+                "Checksum mismatch: expected {:#06x}, got {:#06x}",
+                expected, actual
+            ),
+            Self::InvalidBoardPresetError(e) => write!(f, "Unknown board preset: {}", e),
+            Self::InvalidImageStateError(e) => write!(f, "Unknown firmware image state: {}", e),
+            Self::InvalidStartupProfileError(e) => write!(f, "Unknown startup profile: {}", e),
+            Self::InvalidJoinRejectionError(e) => write!(f, "Unknown join rejection: {}", e),
+            Self::InvalidMagicError => write!(f, "Data does not start with the expected magic"),
+            Self::UnsupportedFormatError(e) => write!(f, "Unsupported format version: {}", e),
+            Self::InvalidMetadataError => write!(f, "Metadata entry exceeds its section"),
+            Self::VarintOverflowError => write!(f, "Varint exceeds the range of its field"),
+            Self::LengthExceededError(len) => {
+                write!(f, "{} elements exceed the capacity of the list", len)
+            }
+            Self::InvalidTreeError => write!(f, "Encoded tree is no valid edge list"),
             Self::CodecError => write!(f, "Failed to encode component:\n"),
         }
     }
@@ -171,15 +290,24 @@ impl fmt::Display for MessageTypeError {
 
 #[derive(Debug)]
 pub enum SendMessageError {
+    VersionEncodeError(CodecError),
+    NetworkIdEncodeError(CodecError),
     MessageTypeEncodeError(CodecError),
     FinalDestinationEncodeError(CodecError),
     FinalSourceEncodeError(CodecError),
+    SequenceEncodeError(CodecError),
+    HopLimitEncodeError(CodecError),
+    TimestampEncodeError(CodecError),
+    FlagsEncodeError(CodecError),
+    ChecksumEncodeError(CodecError),
     MessageTooLargeError(CapacityError),
 }
 
 impl fmt::Display for SendMessageError {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         match self {
+            Self::VersionEncodeError(e) => write!(f, "Failed to encode protocol version:\n{}", e),
+            Self::NetworkIdEncodeError(e) => write!(f, "Failed to encode network ID:\n{}", e),
             Self::MessageTypeEncodeError(e) => write!(f, "Failed to encode MessageType:\n{}", e),
             Self::FinalDestinationEncodeError(e) => {
                 write!(f, "Failed to encode final destination:\n{}", e)
@@ -187,6 +315,21 @@ impl fmt::Display for SendMessageError {
             Self::FinalSourceEncodeError(e) => {
                 write!(f, "Failed to encode final source:\n{}", e)
             }
+            Self::SequenceEncodeError(e) => {
+                write!(f, "Failed to encode sequence number:\n{}", e)
+            }
+            Self::HopLimitEncodeError(e) => {
+                write!(f, "Failed to encode hop limit:\n{}", e)
+            }
+            Self::TimestampEncodeError(e) => {
+                write!(f, "Failed to encode timestamp:\n{}", e)
+            }
+            Self::FlagsEncodeError(e) => {
+                write!(f, "Failed to encode message flags:\n{}", e)
+            }
+            Self::ChecksumEncodeError(e) => {
+                write!(f, "Failed to encode checksum:\n{}", e)
+            }
             Self::MessageTooLargeError(e) => {
                 write!(f, "Message size exceeds buffer capacity:\n{}", e)
             }
@@ -194,17 +337,65 @@ impl fmt::Display for SendMessageError {
     }
 }
 
+#[cfg(any(feature = "std", feature = "wire-selftest"))]
+#[derive(Debug)]
+pub enum WireVectorError {
+    SerializationError(SendMessageError),
+    CryptoError(CryptoError),
+    CodecError(CodecError),
+    TooManyVectorsError,
+    /// The line of the fixture with this number is no vector.
+    MalformedLineError(usize),
+    MissingVectorError(&'static str),
+    MismatchError(&'static str),
+}
+
+#[cfg(any(feature = "std", feature = "wire-selftest"))]
+impl fmt::Display for WireVectorError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::SerializationError(e) => write!(f, "Failed to encode wire vector:\n{}", e),
+            Self::CryptoError(e) => write!(f, "Failed to seal wire vector:\n{}", e),
+            Self::CodecError(e) => write!(f, "Failed to build wire vector:\n{}", e),
+            Self::TooManyVectorsError => write!(f, "More wire vectors than MAX_WIRE_VECTORS"),
+            Self::MalformedLineError(line) => {
+                write!(f, "Line {} of the fixture is no vector", line)
+            }
+            Self::MissingVectorError(name) => write!(f, "Fixture lacks wire vector {}", name),
+            Self::MismatchError(name) => {
+                write!(f, "Wire vector {} differs from the fixture", name)
+            }
+        }
+    }
+}
+
 #[derive(Debug)]
 pub enum ReceiveMessageError {
+    VersionDecodeError(CodecError),
+    UnsupportedVersionError(u8),
+    NetworkIdDecodeError(CodecError),
     MessageTypeDecodeError(CodecError),
     FinalDestinationDecodeError(CodecError),
     FinalSourceDecodeError(CodecError),
+    SequenceDecodeError(CodecError),
+    HopLimitDecodeError(CodecError),
+    TimestampDecodeError(CodecError),
+    FlagsDecodeError(CodecError),
+    UnsupportedFlagsError(u8),
+    TagDecodeError(CodecError),
+    TrailingBytesError(usize),
+    ChecksumDecodeError(CodecError),
     BufferOverflowError(CapacityError),
 }
 
 impl fmt::Display for ReceiveMessageError {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         match self {
+            Self::VersionDecodeError(e) => write!(f, "Failed to decode protocol version:\n{}", e),
+            Self::UnsupportedVersionError(v) => {
+                write!(f, "Cannot decode content of protocol version {}", v)
+            }
+            Self::NetworkIdDecodeError(e) => write!(f, "Failed to decode network ID:\n{}", e),
             Self::MessageTypeDecodeError(e) => write!(f, "Failed to decode message type:\n{}", e),
             Self::FinalDestinationDecodeError(e) => {
                 write!(f, "Failed to decode final destination:\n{}", e)
@@ -212,6 +403,30 @@ impl fmt::Display for ReceiveMessageError {
             Self::FinalSourceDecodeError(e) => {
                 write!(f, "Failed to decode final source:\n{}", e)
             }
+            Self::SequenceDecodeError(e) => {
+                write!(f, "Failed to decode sequence number:\n{}", e)
+            }
+            Self::HopLimitDecodeError(e) => {
+                write!(f, "Failed to decode hop limit:\n{}", e)
+            }
+            Self::TimestampDecodeError(e) => {
+                write!(f, "Failed to decode timestamp:\n{}", e)
+            }
+            Self::FlagsDecodeError(e) => {
+                write!(f, "Failed to decode message flags:\n{}", e)
+            }
+            Self::UnsupportedFlagsError(flags) => {
+                write!(f, "Cannot decode content with flags {:#04x}", flags)
+            }
+            Self::TagDecodeError(e) => {
+                write!(f, "Failed to decode message tag:\n{}", e)
+            }
+            Self::TrailingBytesError(n) => {
+                write!(f, "Frame has {} bytes left after its content", n)
+            }
+            Self::ChecksumDecodeError(e) => {
+                write!(f, "Failed to verify checksum:\n{}", e)
+            }
             Self::BufferOverflowError(e) => {
                 write!(f, "Failed to extend data from cursor buffer:\n{}", e)
             }
@@ -0,0 +1,105 @@
+use crate::logic::{consts::MESH_EVENT_HISTORY, message::JoinRejection, node::Node};
+use heapless::{HistoryBuf, Vec};
+
+/// Changes of the mesh an application may want to show, e.g. on the display.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum MeshEvent {
+    /// No mesh answered the discoveries of this node, so it leads a new one.
+    BecameLeader,
+    /// This node joined an existing mesh below the given uplink.
+    Joined(Node),
+    /// A node was attached to the mesh or moved to another parent.
+    NodeAttached(Node),
+    /// A newer network config with the given version was applied.
+    ConfigUpdated(u16),
+    /// A node reported that it crashed.
+    NodeCrashed(Node),
+    /// The leader refused the given newcomer.
+    JoinRejected(Node, JoinRejection),
+    /// The mesh refused to admit this node. Holds the member that passed on the refusal.
+    JoinRefused(Node, JoinRejection),
+    /// A node announced that it powers down and was pruned from the tree.
+    NodeLeft(Node),
+    /// The leader pruned a node it did not hear from for `MeshConfig::stale_after_ms`.
+    NodeSilent(Node),
+    /// The leader confirmed that a node runs its freshly updated firmware fine.
+    BootConfirmed(Node),
+    /// The given uplink went silent, so this node leads the nodes below it until it hears the
+    /// rest of the mesh again.
+    Partitioned(Node),
+    /// This node heard the rest of the mesh again and dissolved the partition it led.
+    Remerged,
+    /// The given uplink went silent and this node may not lead, so it searches for the mesh
+    /// again while it keeps passing frames between the nodes below it.
+    Detached(Node),
+    /// The given leader went silent and its successor took over its tree.
+    LeaderLost(Node),
+    /// The leader handed the lead over to the given member, see `Mesh::hand_over_leadership`.
+    LeaderHandedOver(Node),
+    /// The given node asked this node to show where it is, see `Mesh::identify`.
+    IdentifyRequested(Node),
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct EventRecord {
+    /// Local time in milliseconds when the event happened.
+    pub at_ms: u64,
+    pub event: MeshEvent,
+}
+
+/// The most recent mesh events. Once full, every new event replaces the oldest one.
+pub struct EventLog {
+    events: HistoryBuf<EventRecord, MESH_EVENT_HISTORY>,
+}
+
+impl EventLog {
+    pub const fn new() -> Self {
+        Self {
+            events: HistoryBuf::new(),
+        }
+    }
+
+    pub fn record(&mut self, event: MeshEvent, at_ms: u64) {
+        self.events.write(EventRecord { at_ms, event });
+    }
+
+    /// Returns the kept events, oldest first.
+    pub fn recent(&self) -> Vec<EventRecord, MESH_EVENT_HISTORY> {
+        self.events.oldest_ordered().copied().collect()
+    }
+}
+
+impl Default for EventLog {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn event_log_returns_events_oldest_first() {
+        let mut log = EventLog::new();
+        log.record(MeshEvent::BecameLeader, 1);
+        log.record(MeshEvent::ConfigUpdated(1), 2);
+
+        let events = log.recent();
+        assert_eq!(events.len(), 2);
+        assert_eq!(events[0].event, MeshEvent::BecameLeader);
+        assert_eq!(events[1].at_ms, 2);
+    }
+
+    #[test]
+    fn event_log_drops_oldest_when_full() {
+        let mut log = EventLog::new();
+        for version in 0..=MESH_EVENT_HISTORY as u16 {
+            log.record(MeshEvent::ConfigUpdated(version), version as u64);
+        }
+
+        let events = log.recent();
+        assert_eq!(events.len(), MESH_EVENT_HISTORY);
+        assert_eq!(events[0].event, MeshEvent::ConfigUpdated(1));
+    }
+}
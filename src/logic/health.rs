@@ -0,0 +1,119 @@
+use crate::logic::{
+    consts::{MAX_CHILD_LEAFS, MESSAGE_SIZE},
+    node::Node,
+};
+use crate::wire_codec;
+use heapless::LinearMap;
+
+/// Status a node piggybacks on every heartbeat, so its parent keeps fresh health data about it
+/// without sending a frame more.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub struct Health {
+    /// Remaining charge in percent, `None` if the node cannot measure it.
+    pub battery: Option<u8>,
+    /// Frames waiting in the send queue, saturated at `u8::MAX`.
+    pub queued: u8,
+    /// RSSI the node hears its uplink with, `None` without uplink or before it heard one.
+    pub uplink_rssi: Option<i8>,
+}
+
+impl Health {
+    pub fn new(battery: Option<u8>, queued: usize, uplink_rssi: Option<i32>) -> Self {
+        Self {
+            battery,
+            queued: queued.min(u8::MAX as usize) as u8,
+            uplink_rssi: uplink_rssi.map(|rssi| rssi.clamp(i8::MIN as i32, i8::MAX as i32) as i8),
+        }
+    }
+}
+
+wire_codec! {
+    Health: MESSAGE_SIZE {
+        battery: Option<u8>,
+        queued: u8,
+        uplink_rssi: Option<i8>,
+    }
+    round_trip_test health_round_trip = Health::new(Some(64), 300, Some(-200));
+}
+
+/// What a child last piggybacked on a heartbeat.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct HealthReport {
+    pub health: Health,
+    /// Local time in milliseconds the heartbeat arrived.
+    pub received_ms: u64,
+}
+
+/// Latest health of every child of this node. Once full, the child heard from least recently
+/// is forgotten.
+pub struct ChildHealth {
+    reports: LinearMap<Node, HealthReport, MAX_CHILD_LEAFS>,
+}
+
+impl ChildHealth {
+    pub const fn new() -> Self {
+        Self {
+            reports: LinearMap::new(),
+        }
+    }
+
+    pub fn record(&mut self, child: Node, health: Health, now_ms: u64) {
+        if self.reports.is_full() && !self.reports.contains_key(&child) {
+            let stalest = self
+                .reports
+                .iter()
+                .min_by_key(|(_, report)| report.received_ms)
+                .map(|(node, _)| *node);
+            if let Some(stalest) = stalest {
+                self.reports.remove(&stalest);
+            }
+        }
+        let report = HealthReport {
+            health,
+            received_ms: now_ms,
+        };
+        let _ = self.reports.insert(child, report);
+    }
+
+    pub fn get(&self, child: Node) -> Option<HealthReport> {
+        self.reports.get(&child).copied()
+    }
+}
+
+impl Default for ChildHealth {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn n(mac_last_byte: u8) -> Node {
+        Node::new([0, 0, 0, 0, 0, mac_last_byte])
+    }
+
+    #[test]
+    fn health_saturates_into_its_fields() {
+        let health = Health::new(None, 300, Some(-200));
+        assert_eq!(health.queued, u8::MAX);
+        assert_eq!(health.uplink_rssi, Some(i8::MIN));
+    }
+
+    #[test]
+    fn full_table_forgets_stalest_child() {
+        let mut table = ChildHealth::new();
+        for i in 0..MAX_CHILD_LEAFS as u8 {
+            table.record(n(i), Health::default(), i as u64);
+        }
+        table.record(n(0), Health::new(Some(50), 0, None), 100);
+        table.record(n(200), Health::default(), 101);
+
+        assert!(table.get(n(1)).is_none());
+        let report = table.get(n(0)).unwrap();
+        assert_eq!(report.health.battery, Some(50));
+        assert_eq!(report.received_ms, 100);
+        assert!(table.get(n(200)).is_some());
+    }
+}
@@ -0,0 +1,338 @@
+#![cfg(feature = "std")]
+//! History of a mesh kept by a std gateway, so who was where and when the mesh degraded can be
+//! analyzed after an event without any other infrastructure. A history file is CSV with one
+//! record per row:
+//!
+//! `at_ms,source,kind,node,parent,battery,queued,uplink_rssi,detail`
+//!
+//! `source` is the gateway that recorded the row. A `topology` row holds a node of its tree and
+//! the parent. Whenever the tree changes, all of its rows are written with the same time,
+//! starting with the gateway itself without parent. A `health` row holds what a child of the
+//! gateway piggybacked on its heartbeat, an `event` row a mesh event of the gateway and the node
+//! it is about. Columns that do not apply stay empty.
+//!
+//! `record_history` appends to a file while the gateway runs. The simulated meshes of the tests
+//! record one when `HISTORY_ENV` names a path.
+
+use std::{
+    collections::BTreeMap,
+    fs::{File, OpenOptions},
+    io::{self, Write},
+    path::Path,
+};
+
+use crate::logic::{
+    asynchronous,
+    event::{EventRecord, MeshEvent},
+    health::HealthReport,
+    mesh::MeshHandle,
+    node::Node,
+};
+
+/// Environment variable holding the path simulated meshes append their history to.
+pub const HISTORY_ENV: &str = "ESP_TAG_HISTORY";
+
+/// First row of every history file.
+pub const HISTORY_HEADER: &str = "at_ms,source,kind,node,parent,battery,queued,uplink_rssi,detail";
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Record {
+    /// `node` hangs below `parent` in the tree of the gateway, which itself has no parent.
+    Topology {
+        at_ms: u64,
+        node: Node,
+        parent: Option<Node>,
+    },
+    Health {
+        node: Node,
+        report: HealthReport,
+    },
+    Event(EventRecord),
+}
+
+impl Record {
+    /// Formats this record as a row of the history of `source`.
+    pub fn to_csv(&self, source: Node) -> String {
+        let opt = |value: Option<String>| value.unwrap_or_default();
+        match self {
+            Self::Topology {
+                at_ms,
+                node,
+                parent,
+            } => format!(
+                "{},{},topology,{},{},,,,",
+                at_ms,
+                source,
+                node,
+                opt(parent.map(|parent| parent.to_string()))
+            ),
+            Self::Health { node, report } => format!(
+                "{},{},health,{},,{},{},{},",
+                report.received_ms,
+                source,
+                node,
+                opt(report.health.battery.map(|battery| battery.to_string())),
+                report.health.queued,
+                opt(report.health.uplink_rssi.map(|rssi| rssi.to_string())),
+            ),
+            Self::Event(record) => {
+                let (node, detail) = describe(record.event);
+                format!(
+                    "{},{},event,{},,,,,{}",
+                    record.at_ms,
+                    source,
+                    opt(node.map(|node| node.to_string())),
+                    detail
+                )
+            }
+        }
+    }
+}
+
+/// The node `event` is about and its name, with the config version where there is one.
+fn describe(event: MeshEvent) -> (Option<Node>, String) {
+    match event {
+        MeshEvent::BecameLeader => (None, "BecameLeader".into()),
+        MeshEvent::Joined(node) => (Some(node), "Joined".into()),
+        MeshEvent::NodeAttached(node) => (Some(node), "NodeAttached".into()),
+        MeshEvent::ConfigUpdated(version) => (None, format!("ConfigUpdated {}", version)),
+        MeshEvent::NodeCrashed(node) => (Some(node), "NodeCrashed".into()),
+        MeshEvent::JoinRejected(node, reason) => (Some(node), format!("JoinRejected {:?}", reason)),
+        MeshEvent::JoinRefused(node, reason) => (Some(node), format!("JoinRefused {:?}", reason)),
+        MeshEvent::NodeLeft(node) => (Some(node), "NodeLeft".into()),
+        MeshEvent::NodeSilent(node) => (Some(node), "NodeSilent".into()),
+        MeshEvent::BootConfirmed(node) => (Some(node), "BootConfirmed".into()),
+        MeshEvent::Partitioned(node) => (Some(node), "Partitioned".into()),
+        MeshEvent::Remerged => (None, "Remerged".into()),
+        MeshEvent::Detached(node) => (Some(node), "Detached".into()),
+        MeshEvent::LeaderLost(node) => (Some(node), "LeaderLost".into()),
+        MeshEvent::LeaderHandedOver(node) => (Some(node), "LeaderHandedOver".into()),
+        MeshEvent::IdentifyRequested(node) => (Some(node), "IdentifyRequested".into()),
+    }
+}
+
+/// What was already written, so every sample only adds what changed since the previous one.
+#[derive(Default)]
+pub struct Recorder {
+    /// `None` until the first sample, so even an empty tree is written once.
+    edges: Option<Vec<(Node, Option<Node>)>>,
+    health: BTreeMap<[u8; 6], u64>,
+    last_event: Option<EventRecord>,
+}
+
+impl Recorder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Records sampled at `at_ms` from the gateway `own`: its tree if it changed, health
+    /// reports newer than the written ones and events after the last written one. `events`
+    /// are the recent events of the gateway, oldest first.
+    pub fn sample(
+        &mut self,
+        at_ms: u64,
+        own: Node,
+        edges: &[(Node, Option<Node>)],
+        health: &[(Node, HealthReport)],
+        events: &[EventRecord],
+    ) -> Vec<Record> {
+        let mut records = Vec::new();
+        let mut sorted = edges.to_vec();
+        sorted.sort_by_key(|(node, _)| node.mac);
+        if self.edges.as_ref() != Some(&sorted) {
+            records.push(Record::Topology {
+                at_ms,
+                node: own,
+                parent: None,
+            });
+            records.extend(sorted.iter().map(|&(node, parent)| Record::Topology {
+                at_ms,
+                node,
+                parent: Some(parent.unwrap_or(own)),
+            }));
+            self.edges = Some(sorted);
+        }
+        for &(node, report) in health {
+            let written = self.health.insert(node.mac, report.received_ms);
+            if written != Some(report.received_ms) {
+                records.push(Record::Health { node, report });
+            }
+        }
+        let start = self
+            .last_event
+            .and_then(|last| events.iter().rposition(|record| *record == last))
+            .map_or(0, |i| i + 1);
+        records.extend(events[start..].iter().copied().map(Record::Event));
+        if let Some(&last) = events.last() {
+            self.last_event = Some(last);
+        }
+        records
+    }
+}
+
+/// History file the records of one gateway are appended to.
+pub struct History {
+    file: File,
+    source: Node,
+}
+
+impl History {
+    /// Opens the file at `path` for appending the records of `source`, writing
+    /// `HISTORY_HEADER` if the file is new.
+    pub fn append(path: impl AsRef<Path>, source: Node) -> io::Result<Self> {
+        let mut file = OpenOptions::new().create(true).append(true).open(path)?;
+        if file.metadata()?.len() == 0 {
+            file.write_all(format!("{}\n", HISTORY_HEADER).as_bytes())?;
+        }
+        Ok(Self { file, source })
+    }
+
+    /// Writes `records` with a single write, so meshes sharing a file never interleave them.
+    pub fn record(&mut self, records: &[Record]) -> io::Result<()> {
+        if records.is_empty() {
+            return Ok(());
+        }
+        let rows = records
+            .iter()
+            .map(|record| format!("{}\n", record.to_csv(self.source)))
+            .collect::<String>();
+        self.file.write_all(rows.as_bytes())
+    }
+}
+
+/// Appends what changed in `mesh` to the history at `path` every `interval`.
+pub async fn record_history(mesh: MeshHandle, path: String, interval: asynchronous::Duration) {
+    let mut history = match History::append(&path, mesh.address()) {
+        Ok(history) => history,
+        Err(e) => {
+            println!("Failed to open history {}: {}", path, e);
+            return;
+        }
+    };
+    let mut recorder = Recorder::new();
+    let mut ticker = asynchronous::Ticker::every(interval);
+    loop {
+        ticker.next().await;
+        let edges = mesh.edges().await;
+        let mut health = Vec::new();
+        for &(node, parent) in &edges {
+            if parent.is_none()
+                && let Some(report) = mesh.child_health(node).await
+            {
+                health.push((node, report));
+            }
+        }
+        let events = mesh.recent_events().await;
+        let records = recorder.sample(
+            asynchronous::now_ms(),
+            mesh.address(),
+            &edges,
+            &health,
+            &events,
+        );
+        if let Err(e) = history.record(&records) {
+            println!("Failed to write history {}: {}", path, e);
+            return;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::logic::{health::Health, message::JoinRejection};
+
+    fn n(mac_last_byte: u8) -> Node {
+        Node::new([0, 0, 0, 0, 0xab, mac_last_byte])
+    }
+
+    fn report(received_ms: u64) -> HealthReport {
+        HealthReport {
+            health: Health::new(Some(80), 2, Some(-60)),
+            received_ms,
+        }
+    }
+
+    #[test]
+    fn records_become_csv_rows() {
+        let topology = Record::Topology {
+            at_ms: 5,
+            node: n(2),
+            parent: Some(n(1)),
+        };
+        assert_eq!(
+            topology.to_csv(n(1)),
+            "5,00:00:00:00:ab:01,topology,00:00:00:00:ab:02,00:00:00:00:ab:01,,,,"
+        );
+        let health = Record::Health {
+            node: n(2),
+            report: report(7),
+        };
+        assert_eq!(
+            health.to_csv(n(1)),
+            "7,00:00:00:00:ab:01,health,00:00:00:00:ab:02,,80,2,-60,"
+        );
+        let event = Record::Event(EventRecord {
+            at_ms: 9,
+            event: MeshEvent::JoinRejected(n(3), JoinRejection::MeshFull),
+        });
+        assert_eq!(
+            event.to_csv(n(1)),
+            "9,00:00:00:00:ab:01,event,00:00:00:00:ab:03,,,,,JoinRejected MeshFull"
+        );
+        for record in [topology, health, event] {
+            assert_eq!(
+                record.to_csv(n(1)).split(',').count(),
+                HISTORY_HEADER.split(',').count()
+            );
+        }
+    }
+
+    #[test]
+    fn recorder_only_writes_changes() {
+        let mut recorder = Recorder::new();
+        let edges = [(n(3), Some(n(2))), (n(2), None)];
+        let health = [(n(2), report(10))];
+        let joined = EventRecord {
+            at_ms: 10,
+            event: MeshEvent::NodeAttached(n(3)),
+        };
+
+        let records = recorder.sample(20, n(1), &edges, &health, &[joined]);
+        assert_eq!(records.len(), 5);
+        assert_eq!(
+            records[1],
+            Record::Topology {
+                at_ms: 20,
+                node: n(2),
+                parent: Some(n(1)),
+            }
+        );
+        assert!(
+            recorder
+                .sample(30, n(1), &edges, &health, &[joined])
+                .is_empty()
+        );
+
+        let partitioned = EventRecord {
+            at_ms: 40,
+            event: MeshEvent::Partitioned(n(2)),
+        };
+        let records = recorder.sample(50, n(1), &[], &[(n(2), report(35))], &[joined, partitioned]);
+        assert_eq!(
+            records,
+            [
+                Record::Topology {
+                    at_ms: 50,
+                    node: n(1),
+                    parent: None,
+                },
+                Record::Health {
+                    node: n(2),
+                    report: report(35),
+                },
+                Record::Event(partitioned),
+            ]
+        );
+    }
+}
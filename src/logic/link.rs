@@ -1,16 +1,169 @@
-use crate::logic::{error::LinkError, message::MessageData, node::Node};
-use core::future::Future;
+use crate::logic::{
+    config::TxPowerPolicy,
+    consts::{LINK_SEND_QUEUE_SIZE, PRIORITY_LEVELS},
+    error::LinkError,
+    message::{MessageData, Priority},
+    node::Node,
+};
+use core::{
+    future::{Future, poll_fn},
+    pin::pin,
+    task::Poll,
+};
+use heapless::Deque;
 
 #[cfg(feature = "hardware")]
-pub type ActiveLink = crate::hardware::link::ESPNowLink;
+pub type RadioLink = crate::hardware::link::ESPNowLink;
 
-#[cfg(not(feature = "hardware"))]
-pub type ActiveLink = crate::logic::link::mock::MockLink;
+#[cfg(all(feature = "std", not(feature = "hardware")))]
+pub type RadioLink = crate::logic::link::mock::MockLink;
+
+/// Second link of a gateway running on a host, next to the radio.
+#[cfg(feature = "std")]
+pub type HostLink = crate::logic::udp::UdpLink;
+
+/// Picks the destinations a gateway reaches through its second link, see `SplitLink`.
+pub type Selector = &'static (dyn Fn(Node) -> bool + Sync);
+
+/// The link the mesh runs on. The tasks of the mesh cannot be generic, so every kind of link a
+/// node may use is a variant here and the `Link` methods dispatch on it.
+#[cfg(any(feature = "hardware", feature = "std"))]
+#[derive(Copy, Clone)]
+pub enum ActiveLink {
+    Radio(&'static RadioLink),
+    /// The radio and the UDP link of a gateway running on a host. Only built with `std`, where
+    /// the radio is a `MockLink`, so this runs gateways in simulations and tests but not on a
+    /// board.
+    #[cfg(feature = "std")]
+    Split(&'static SplitLink<RadioLink, &'static HostLink, Selector>),
+}
+
+#[cfg(any(feature = "hardware", feature = "std"))]
+impl<'a> Link<'a> for ActiveLink {
+    async fn send(&'a self, data: MessageData, destination: Node) -> Result<(), LinkError> {
+        match *self {
+            Self::Radio(link) => link.send(data, destination).await,
+            #[cfg(feature = "std")]
+            Self::Split(link) => link.send(data, destination).await,
+        }
+    }
+
+    fn try_send(
+        &self,
+        data: MessageData,
+        destination: Node,
+        priority: Priority,
+    ) -> Result<(), LinkError> {
+        match self {
+            Self::Radio(link) => link.try_send(data, destination, priority),
+            #[cfg(feature = "std")]
+            Self::Split(link) => link.try_send(data, destination, priority),
+        }
+    }
+
+    async fn receive(&'a self) -> RecvData {
+        match *self {
+            Self::Radio(link) => link.receive().await,
+            #[cfg(feature = "std")]
+            Self::Split(link) => link.receive().await,
+        }
+    }
+
+    fn try_receive(&self) -> Result<RecvData, LinkError> {
+        match self {
+            Self::Radio(link) => link.try_receive(),
+            #[cfg(feature = "std")]
+            Self::Split(link) => link.try_receive(),
+        }
+    }
+
+    fn queued(&self) -> usize {
+        match self {
+            Self::Radio(link) => link.queued(),
+            #[cfg(feature = "std")]
+            Self::Split(link) => link.queued(),
+        }
+    }
+
+    fn address(&self) -> Node {
+        match self {
+            Self::Radio(link) => link.address(),
+            #[cfg(feature = "std")]
+            Self::Split(link) => link.address(),
+        }
+    }
+
+    fn set_tx_power(&self, policy: TxPowerPolicy) -> Result<(), LinkError> {
+        match self {
+            Self::Radio(link) => link.set_tx_power(policy),
+            #[cfg(feature = "std")]
+            Self::Split(link) => link.set_tx_power(policy),
+        }
+    }
+
+    fn trust_source(&self, source: Node) {
+        match self {
+            Self::Radio(link) => link.trust_source(source),
+            #[cfg(feature = "std")]
+            Self::Split(link) => link.trust_source(source),
+        }
+    }
+}
 
 #[derive(Debug)]
 pub struct SendData {
     pub data: MessageData,
     pub destination: Node,
+    pub priority: Priority,
+}
+
+/// Frames waiting for the radio with one FIFO per priority, so organization traffic never
+/// waits behind a burst of application frames.
+pub struct SendQueues {
+    queues: [Deque<SendData, LINK_SEND_QUEUE_SIZE>; PRIORITY_LEVELS],
+}
+
+impl SendQueues {
+    pub const fn new() -> Self {
+        Self {
+            queues: [const { Deque::new() }; PRIORITY_LEVELS],
+        }
+    }
+
+    /// Queues `data` behind the frames of the same priority. Fails if that queue is full.
+    pub fn push(&mut self, data: SendData) -> Result<(), LinkError> {
+        self.queues[data.priority as usize]
+            .push_back(data)
+            .map_err(|_| LinkError::QueueFullError())
+    }
+
+    /// Takes the oldest frame of the highest priority.
+    pub fn pop(&mut self) -> Option<SendData> {
+        self.pop_at_least(Priority::Low)
+    }
+
+    /// Takes the oldest frame of the highest priority, if that is at least `min`.
+    pub fn pop_at_least(&mut self, min: Priority) -> Option<SendData> {
+        self.queues[min as usize..]
+            .iter_mut()
+            .rev()
+            .find_map(Deque::pop_front)
+    }
+
+    /// Frames waiting across all priorities.
+    pub fn len(&self) -> usize {
+        self.queues.iter().map(Deque::len).sum()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.queues.iter().all(Deque::is_empty)
+    }
+}
+
+impl Default for SendQueues {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 pub struct RecvData {
@@ -18,29 +171,263 @@ pub struct RecvData {
     pub source: Node,
     pub destination: Node,
     pub rssi: i32,
+    /// Local time in milliseconds when the radio received the frame.
+    pub received_ms: u64,
 }
 
 pub trait Link<'a> {
-    fn send(&'a self, data: MessageData, destination: Node) -> impl Future<Output = ()>;
-    fn try_send(&self, data: MessageData, destination: Node) -> Result<(), LinkError>;
+    /// Sends `data` to the neighbor `destination` and resolves once the radio reports whether
+    /// the neighbor acknowledged the frame. Broadcasts are never acknowledged and always succeed.
+    fn send(
+        &'a self,
+        data: MessageData,
+        destination: Node,
+    ) -> impl Future<Output = Result<(), LinkError>>;
+    /// Queues `data` for `destination` without waiting. Frames of a higher `priority` leave the
+    /// queue first.
+    fn try_send(
+        &self,
+        data: MessageData,
+        destination: Node,
+        priority: Priority,
+    ) -> Result<(), LinkError>;
     fn receive(&'a self) -> impl Future<Output = RecvData>;
     fn try_receive(&self) -> Result<RecvData, LinkError>;
+    /// Frames queued by `try_send` that did not reach the radio yet.
+    fn queued(&self) -> usize;
+    /// Address other nodes see as the link source of frames sent by this node.
+    fn address(&self) -> Node;
+    /// Limits the transmit power of the radio, see `NetworkConfig::tx_power`.
+    fn set_tx_power(&self, policy: TxPowerPolicy) -> Result<(), LinkError>;
+    /// Called once the last frame received from the neighbor `source` passed the tag and replay
+    /// checks, so a link that learns its peers from their frames may trust where it came from.
+    fn trust_source(&self, _source: Node) {}
+}
+
+/// A shared link, e.g. the secondary of a `SplitLink` whose selector asks it for its peers.
+impl<'a, 'b: 'a, L: Link<'a>> Link<'a> for &'b L {
+    async fn send(&'a self, data: MessageData, destination: Node) -> Result<(), LinkError> {
+        (*self).send(data, destination).await
+    }
+
+    fn try_send(
+        &self,
+        data: MessageData,
+        destination: Node,
+        priority: Priority,
+    ) -> Result<(), LinkError> {
+        (*self).try_send(data, destination, priority)
+    }
+
+    async fn receive(&'a self) -> RecvData {
+        (*self).receive().await
+    }
+
+    fn try_receive(&self) -> Result<RecvData, LinkError> {
+        (*self).try_receive()
+    }
+
+    fn queued(&self) -> usize {
+        (*self).queued()
+    }
+
+    fn address(&self) -> Node {
+        (*self).address()
+    }
+
+    fn set_tx_power(&self, policy: TxPowerPolicy) -> Result<(), LinkError> {
+        (*self).set_tx_power(policy)
+    }
+
+    fn trust_source(&self, source: Node) {
+        (*self).trust_source(source)
+    }
+}
+
+/// Two links behind one `Link`, e.g. the radio and a UDP link of a gateway. Each frame goes
+/// out through the link `via_secondary` picks for its destination, broadcasts through both.
+/// Frames of both links are received. The `Link` methods return `impl Future`, so links cannot
+/// be trait objects; this dispatches statically instead and needs no allocator. The mesh runs
+/// on it through `ActiveLink::Split`.
+pub struct SplitLink<A, B, F> {
+    primary: A,
+    secondary: B,
+    /// Returns true for destinations reached through `secondary`. Asked on every send, so it may
+    /// look up state that changes at runtime, like the peers a gateway has learned.
+    via_secondary: F,
+}
+
+impl<A, B, F> SplitLink<A, B, F> {
+    pub const fn new(primary: A, secondary: B, via_secondary: F) -> Self {
+        Self {
+            primary,
+            secondary,
+            via_secondary,
+        }
+    }
+
+    pub fn primary(&self) -> &A {
+        &self.primary
+    }
+
+    pub fn secondary(&self) -> &B {
+        &self.secondary
+    }
+}
+
+impl<'a, A: Link<'a>, B: Link<'a>, F: Fn(Node) -> bool> Link<'a> for SplitLink<A, B, F> {
+    async fn send(&'a self, data: MessageData, destination: Node) -> Result<(), LinkError> {
+        if destination.is_broadcast() {
+            let primary = self.primary.send(data.clone(), destination).await;
+            let secondary = self.secondary.send(data, destination).await;
+            return primary.and(secondary);
+        }
+        if (self.via_secondary)(destination) {
+            self.secondary.send(data, destination).await
+        } else {
+            self.primary.send(data, destination).await
+        }
+    }
+
+    fn try_send(
+        &self,
+        data: MessageData,
+        destination: Node,
+        priority: Priority,
+    ) -> Result<(), LinkError> {
+        if destination.is_broadcast() {
+            let primary = self.primary.try_send(data.clone(), destination, priority);
+            let secondary = self.secondary.try_send(data, destination, priority);
+            return primary.and(secondary);
+        }
+        if (self.via_secondary)(destination) {
+            self.secondary.try_send(data, destination, priority)
+        } else {
+            self.primary.try_send(data, destination, priority)
+        }
+    }
+
+    /// Resolves with the next frame of either link, the primary one first if both have one.
+    async fn receive(&'a self) -> RecvData {
+        let mut primary = pin!(self.primary.receive());
+        let mut secondary = pin!(self.secondary.receive());
+        poll_fn(|cx| match primary.as_mut().poll(cx) {
+            Poll::Ready(recv) => Poll::Ready(recv),
+            Poll::Pending => secondary.as_mut().poll(cx),
+        })
+        .await
+    }
+
+    fn try_receive(&self) -> Result<RecvData, LinkError> {
+        self.primary
+            .try_receive()
+            .or_else(|_| self.secondary.try_receive())
+    }
+
+    fn queued(&self) -> usize {
+        self.primary.queued() + self.secondary.queued()
+    }
+
+    /// The address of the primary link, which the mesh knows this node by.
+    fn address(&self) -> Node {
+        self.primary.address()
+    }
+
+    fn set_tx_power(&self, policy: TxPowerPolicy) -> Result<(), LinkError> {
+        let primary = self.primary.set_tx_power(policy);
+        let secondary = self.secondary.set_tx_power(policy);
+        primary.and(secondary)
+    }
+
+    fn trust_source(&self, source: Node) {
+        self.primary.trust_source(source);
+        self.secondary.trust_source(source);
+    }
 }
 
 #[cfg(feature = "std")]
 pub mod mock {
-    use crate::logic::message::BROADCAST_NODE;
+    use crate::logic::consts::LINK_RECV_QUEUE_SIZE;
 
     use super::*;
     use std::collections::hash_map::HashMap;
+    use std::sync::atomic::{AtomicU8, AtomicU64, AtomicUsize, Ordering};
+    use std::time::Duration;
     use tokio::sync::Mutex;
     use tokio::sync::mpsc::{Receiver, Sender, channel};
+    use tokio::time::Instant;
+
+    /// RSSI frames arrive with unless a connection sets another one.
+    const MOCK_RSSI: i32 = 255;
+
+    /// How a node hears the frames of a link.
+    struct Connection {
+        sender: Sender<MockMessage>,
+        rssi: i32,
+        /// Share of frames lost on the way, from 0.0 to 1.0.
+        loss: f64,
+    }
+
+    impl Connection {
+        fn drops_frame(&self) -> bool {
+            self.loss > 0.0
+                && (crate::logic::asynchronous::random_u32() as f64) < self.loss * u32::MAX as f64
+        }
+    }
 
     pub struct MockLink {
-        foreign_senders: Mutex<HashMap<Node, Sender<MockMessage>>>,
+        /// Nodes this link reaches and how they hear its frames.
+        foreign_senders: Mutex<HashMap<Node, Connection>>,
         receiver: Mutex<Receiver<MockMessage>>,
         sender: Sender<MockMessage>,
         node: Node,
+        energy_model: EnergyModel,
+        tx_frames: AtomicU64,
+        rx_frames: AtomicU64,
+        started: Instant,
+        /// What `queued` reports. Frames never wait in the mock, so tests set it.
+        backlog: AtomicUsize,
+        /// Last `TxPowerPolicy` set, it does not change how other links hear this one.
+        tx_power: AtomicU8,
+    }
+
+    /// Energy cost of the radio in millijoules, used to estimate battery life of a simulated
+    /// node. The defaults roughly match an ESP32-C3 sending short ESP-NOW frames.
+    #[derive(Copy, Clone, Debug)]
+    pub struct EnergyModel {
+        pub tx_cost: f64,
+        pub rx_cost: f64,
+        pub listen_cost_per_sec: f64,
+    }
+
+    impl Default for EnergyModel {
+        fn default() -> Self {
+            Self {
+                tx_cost: 0.6,
+                rx_cost: 0.2,
+                listen_cost_per_sec: 300.0,
+            }
+        }
+    }
+
+    #[derive(Copy, Clone, Debug)]
+    pub struct EnergyReport {
+        pub tx_frames: u64,
+        pub rx_frames: u64,
+        pub listen_time: Duration,
+        pub consumed: f64,
+    }
+
+    impl EnergyReport {
+        /// Extrapolates the consumption so far to a battery of `capacity` millijoules. `None` if
+        /// nothing was consumed yet or the capacity gives no representable duration.
+        pub fn estimated_battery_life(&self, capacity: f64) -> Option<Duration> {
+            if self.consumed <= 0.0 {
+                return None;
+            }
+            let rate = self.consumed / self.listen_time.as_secs_f64();
+            Duration::try_from_secs_f64(capacity / rate).ok()
+        }
     }
 
     struct MockMessage {
@@ -52,71 +439,196 @@ pub mod mock {
 
     impl MockLink {
         pub fn new(node: Node) -> Self {
-            let foreign_senders: Mutex<HashMap<Node, Sender<MockMessage>>> =
-                Mutex::new(HashMap::new());
-            let (sender, receiver) = channel(32);
+            let foreign_senders: Mutex<HashMap<Node, Connection>> = Mutex::new(HashMap::new());
+            let (sender, receiver) = channel(LINK_RECV_QUEUE_SIZE);
             return MockLink {
                 foreign_senders,
                 receiver: Mutex::new(receiver),
                 sender,
                 node,
+                energy_model: EnergyModel::default(),
+                tx_frames: AtomicU64::new(0),
+                rx_frames: AtomicU64::new(0),
+                started: Instant::now(),
+                backlog: AtomicUsize::new(0),
+                tx_power: AtomicU8::new(TxPowerPolicy::Maximum as u8),
             };
         }
 
+        pub fn with_energy_model(mut self, energy_model: EnergyModel) -> Self {
+            self.energy_model = energy_model;
+            self
+        }
+
+        pub fn energy(&self) -> EnergyReport {
+            let tx_frames = self.tx_frames.load(Ordering::Relaxed);
+            let rx_frames = self.rx_frames.load(Ordering::Relaxed);
+            let listen_time = self.started.elapsed();
+            let consumed = tx_frames as f64 * self.energy_model.tx_cost
+                + rx_frames as f64 * self.energy_model.rx_cost
+                + listen_time.as_secs_f64() * self.energy_model.listen_cost_per_sec;
+            EnergyReport {
+                tx_frames,
+                rx_frames,
+                listen_time,
+                consumed,
+            }
+        }
+
         pub async fn connect(&self, link: &MockLink) {
+            self.connect_with_rssi(link, MOCK_RSSI).await;
+        }
+
+        /// Pretends `frames` are waiting in the send queue, to simulate a congested node.
+        pub fn set_backlog(&self, frames: usize) {
+            self.backlog.store(frames, Ordering::Relaxed);
+        }
+
+        pub fn tx_power(&self) -> TxPowerPolicy {
+            TxPowerPolicy::try_from(self.tx_power.load(Ordering::Relaxed))
+                .unwrap_or(TxPowerPolicy::Maximum)
+        }
+
+        /// Lets `link` hear the frames of this link with `rssi`. Connecting again replaces the
+        /// RSSI, so a test can weaken a link while the meshes run.
+        pub async fn connect_with_rssi(&self, link: &MockLink, rssi: i32) {
+            self.connect_with_quality(link, rssi, 0.0).await;
+        }
+
+        /// Lets `link` hear the frames of this link with `rssi`, losing the share `loss` of
+        /// them. A lost unicast fails like a frame the neighbor never acknowledged.
+        pub async fn connect_with_quality(&self, link: &MockLink, rssi: i32, loss: f64) {
+            let connection = Connection {
+                sender: link.sender.clone(),
+                rssi,
+                loss,
+            };
             self.foreign_senders
                 .lock()
                 .await
-                .insert(link.node, link.sender.clone());
+                .insert(link.node, connection);
+        }
+
+        /// Stops `node` from hearing the frames of this link.
+        pub async fn disconnect(&self, node: Node) {
+            self.foreign_senders.lock().await.remove(&node);
+        }
+    }
+
+    /// The links of a simulated deployment, so a test can move nodes around while their meshes
+    /// run. Every change applies to both directions of a link.
+    #[derive(Default)]
+    pub struct MockNetwork {
+        links: Vec<&'static MockLink>,
+    }
+
+    impl MockNetwork {
+        pub fn new() -> Self {
+            Self::default()
+        }
+
+        /// Creates the link of `node`, which lives as long as the test.
+        pub fn add(&mut self, node: Node) -> &'static MockLink {
+            let link: &'static MockLink = Box::leak(Box::new(MockLink::new(node)));
+            self.links.push(link);
+            link
+        }
+
+        fn link(&self, node: Node) -> &'static MockLink {
+            self.links
+                .iter()
+                .find(|link| link.node == node)
+                .copied()
+                .expect("node was not added to the network")
+        }
+
+        pub async fn connect(&self, a: Node, b: Node) {
+            self.set_link_quality(a, b, MOCK_RSSI, 0.0).await;
+        }
+
+        /// Lets `a` and `b` hear each other with `rssi`, losing the share `loss` of frames in
+        /// each direction.
+        pub async fn set_link_quality(&self, a: Node, b: Node, rssi: i32, loss: f64) {
+            let (link_a, link_b) = (self.link(a), self.link(b));
+            link_a.connect_with_quality(link_b, rssi, loss).await;
+            link_b.connect_with_quality(link_a, rssi, loss).await;
+        }
+
+        pub async fn disconnect(&self, a: Node, b: Node) {
+            self.link(a).disconnect(b).await;
+            self.link(b).disconnect(a).await;
         }
     }
 
     impl<'a> Link<'a> for MockLink {
-        fn send(&'a self, data: MessageData, destination: Node) -> impl Future<Output = ()> {
+        fn send(
+            &'a self,
+            data: MessageData,
+            destination: Node,
+        ) -> impl Future<Output = Result<(), LinkError>> {
             async move {
-                let message = |destination| MockMessage {
+                self.tx_frames.fetch_add(1, Ordering::Relaxed);
+                let message = |destination, rssi| MockMessage {
                     data: data.clone(),
                     source: self.node,
                     destination,
-                    rssi: 255,
+                    rssi,
                 };
-                if destination == BROADCAST_NODE {
-                    for (node, sender) in self.foreign_senders.lock().await.iter() {
-                        if let Err(e) = sender.send(message(*node)).await {
+                if destination.is_broadcast() {
+                    for (node, connection) in self.foreign_senders.lock().await.iter() {
+                        if connection.drops_frame() {
+                            continue;
+                        }
+                        if let Err(e) = connection
+                            .sender
+                            .send(message(*node, connection.rssi))
+                            .await
+                        {
                             println!("failed to send broadcast to {}: {:?}", node, e);
                         }
                     }
-                    return;
+                    return Ok(());
                 }
                 match self.foreign_senders.lock().await.get(&destination) {
-                    Some(sender) => {
-                        if let Err(e) = sender.send(message(destination)).await {
-                            println!("failed to send to {}: {:?}", destination, e);
-                        }
-                    }
-                    None => {
-                        println!("not connected to {}", destination);
+                    Some(connection) if connection.drops_frame() => {
+                        Err(LinkError::DeliveryError(destination))
                     }
+                    Some(connection) => connection
+                        .sender
+                        .send(message(destination, connection.rssi))
+                        .await
+                        .map_err(|_| LinkError::DeliveryError(destination)),
+                    None => Err(LinkError::DeliveryError(destination)),
                 }
             }
         }
 
-        fn try_send(&self, data: MessageData, destination: Node) -> Result<(), LinkError> {
-            let message = |destination| MockMessage {
+        fn try_send(
+            &self,
+            data: MessageData,
+            destination: Node,
+            _priority: Priority,
+        ) -> Result<(), LinkError> {
+            self.tx_frames.fetch_add(1, Ordering::Relaxed);
+            let message = |destination, rssi| MockMessage {
                 data: data.clone(),
                 source: self.node,
                 destination,
-                rssi: 255,
+                rssi,
             };
-            if destination == BROADCAST_NODE {
-                for (node, sender) in self
+            if destination.is_broadcast() {
+                for (node, connection) in self
                     .foreign_senders
                     .try_lock()
                     .map_err(|_| LinkError::MockError)?
                     .iter()
                 {
-                    if let Err(e) = sender
-                        .try_send(message(*node))
+                    if connection.drops_frame() {
+                        continue;
+                    }
+                    if let Err(e) = connection
+                        .sender
+                        .try_send(message(*node, connection.rssi))
                         .map_err(|_| LinkError::MockError)
                     {
                         println!("failed to send broadcast to {}: {:?}", node, e);
@@ -130,9 +642,11 @@ pub mod mock {
                 .map_err(|_| LinkError::MockError)?
                 .get(&destination)
             {
-                Some(sender) => {
-                    if let Err(e) = sender
-                        .try_send(message(destination))
+                Some(connection) if connection.drops_frame() => {}
+                Some(connection) => {
+                    if let Err(e) = connection
+                        .sender
+                        .try_send(message(destination, connection.rssi))
                         .map_err(|_| LinkError::MockError)
                     {
                         println!("failed to send to {}: {:?}", destination, e);
@@ -148,11 +662,13 @@ pub mod mock {
         fn receive(&'a self) -> impl Future<Output = RecvData> {
             async {
                 let message = self.receiver.lock().await.recv().await.unwrap();
+                self.rx_frames.fetch_add(1, Ordering::Relaxed);
                 RecvData {
                     rssi: message.rssi,
                     data: message.data,
                     source: message.source,
                     destination: message.destination,
+                    received_ms: crate::logic::asynchronous::now_ms(),
                 }
             }
         }
@@ -164,12 +680,211 @@ pub mod mock {
                 .map_err(|_| LinkError::MockError)?
                 .try_recv()
                 .map_err(|_| LinkError::MockError)?;
+            self.rx_frames.fetch_add(1, Ordering::Relaxed);
             Ok(RecvData {
                 rssi: message.rssi,
                 data: message.data,
                 source: message.source,
                 destination: message.destination,
+                received_ms: crate::logic::asynchronous::now_ms(),
             })
         }
+
+        fn queued(&self) -> usize {
+            self.backlog.load(Ordering::Relaxed)
+        }
+
+        fn address(&self) -> Node {
+            self.node
+        }
+
+        fn set_tx_power(&self, policy: TxPowerPolicy) -> Result<(), LinkError> {
+            self.tx_power.store(policy as u8, Ordering::Relaxed);
+            Ok(())
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::mock::{EnergyModel, EnergyReport, MockLink, MockNetwork};
+    use super::*;
+    use std::time::Duration;
+
+    #[tokio::test(flavor = "current_thread")]
+    async fn mock_link_accounts_energy_per_frame() {
+        let a = Node::new([0, 0, 0, 0, 0, 1]);
+        let b = Node::new([0, 0, 0, 0, 0, 2]);
+        let model = EnergyModel {
+            tx_cost: 2.0,
+            rx_cost: 1.0,
+            listen_cost_per_sec: 0.0,
+        };
+        let link_a = MockLink::new(a).with_energy_model(model);
+        let link_b = MockLink::new(b).with_energy_model(model);
+        link_a.connect(&link_b).await;
+
+        link_a.send(MessageData::from([1]), b).await.unwrap();
+        link_a.send(MessageData::from([2]), b).await.unwrap();
+        link_b.receive().await;
+
+        let sent = link_a.energy();
+        assert_eq!(sent.tx_frames, 2);
+        assert_eq!(sent.consumed, 4.0);
+        let received = link_b.energy();
+        assert_eq!(received.rx_frames, 1);
+        assert_eq!(received.consumed, 1.0);
+    }
+
+    #[tokio::test(flavor = "current_thread")]
+    async fn mock_link_reports_unreachable_neighbor() {
+        let a = Node::new([0, 0, 0, 0, 0, 1]);
+        let b = Node::new([0, 0, 0, 0, 0, 2]);
+        let link_a = MockLink::new(a);
+
+        let result = link_a.send(MessageData::from([1]), b).await;
+        assert!(matches!(result, Err(LinkError::DeliveryError(n)) if n == b));
+    }
+
+    #[tokio::test(flavor = "current_thread")]
+    async fn mock_network_changes_links_of_running_nodes() {
+        let a = Node::new([0, 0, 0, 0, 0, 1]);
+        let b = Node::new([0, 0, 0, 0, 0, 2]);
+        let mut network = MockNetwork::new();
+        let link_a = network.add(a);
+        let link_b = network.add(b);
+
+        network.set_link_quality(a, b, -70, 0.0).await;
+        link_a.send(MessageData::from([1]), b).await.unwrap();
+        assert_eq!(link_b.receive().await.rssi, -70);
+        link_b.send(MessageData::from([2]), a).await.unwrap();
+        assert_eq!(link_a.receive().await.rssi, -70);
+
+        network.set_link_quality(a, b, -80, 1.0).await;
+        assert!(link_a.send(MessageData::from([3]), b).await.is_err());
+        link_a
+            .send(MessageData::from([4]), Node::BROADCAST)
+            .await
+            .unwrap();
+        assert!(link_b.try_receive().is_err());
+
+        network.disconnect(a, b).await;
+        let result = link_b.send(MessageData::from([5]), a).await;
+        assert!(matches!(result, Err(LinkError::DeliveryError(n)) if n == a));
+    }
+
+    #[tokio::test(flavor = "current_thread")]
+    async fn split_link_picks_link_per_destination() {
+        let gateway = Node::new([0, 0, 0, 0, 0, 1]);
+        let radio_peer = Node::new([0, 0, 0, 0, 0, 2]);
+        let udp_peer = Node::new([0, 0, 0, 0, 0, 3]);
+        let link_radio_peer = MockLink::new(radio_peer);
+        let link_udp_peer = MockLink::new(udp_peer);
+        let wired = core::cell::Cell::new(Some(udp_peer));
+        let split = SplitLink::new(MockLink::new(gateway), MockLink::new(gateway), |node| {
+            wired.get() == Some(node)
+        });
+        split.primary().connect(&link_radio_peer).await;
+        link_radio_peer.connect(split.primary()).await;
+        split.secondary().connect(&link_udp_peer).await;
+        link_udp_peer.connect(split.secondary()).await;
+
+        split
+            .send(MessageData::from([1]), radio_peer)
+            .await
+            .unwrap();
+        split.send(MessageData::from([2]), udp_peer).await.unwrap();
+        assert_eq!(link_radio_peer.receive().await.data, MessageData::from([1]));
+        assert_eq!(link_udp_peer.receive().await.data, MessageData::from([2]));
+        assert!(link_radio_peer.try_receive().is_err());
+
+        split
+            .send(MessageData::from([3]), Node::BROADCAST)
+            .await
+            .unwrap();
+        assert_eq!(link_radio_peer.receive().await.data, MessageData::from([3]));
+        assert_eq!(link_udp_peer.receive().await.data, MessageData::from([3]));
+
+        link_udp_peer
+            .send(MessageData::from([4]), gateway)
+            .await
+            .unwrap();
+        assert_eq!(split.receive().await.source, udp_peer);
+        assert_eq!(split.address(), gateway);
+
+        // The radio does not reach the UDP peer.
+        wired.set(None);
+        assert!(split.send(MessageData::from([5]), udp_peer).await.is_err());
+    }
+
+    #[test]
+    fn send_queues_prefer_higher_priority() {
+        let node = Node::new([0, 0, 0, 0, 0, 1]);
+        let frame = |byte, priority| SendData {
+            data: MessageData::from([byte]),
+            destination: node,
+            priority,
+        };
+        let mut queues = SendQueues::new();
+        queues.push(frame(1, Priority::Normal)).unwrap();
+        queues.push(frame(2, Priority::High)).unwrap();
+        queues.push(frame(3, Priority::Normal)).unwrap();
+        queues.push(frame(4, Priority::High)).unwrap();
+        assert_eq!(queues.len(), 4);
+
+        let order: Vec<u8> = core::iter::from_fn(|| queues.pop())
+            .map(|frame| frame.data[0])
+            .collect();
+        assert_eq!(order, [2, 4, 1, 3]);
+    }
+
+    #[test]
+    fn send_queues_full_priority_does_not_block_others() {
+        let node = Node::new([0, 0, 0, 0, 0, 1]);
+        let frame = |priority| SendData {
+            data: MessageData::new(),
+            destination: node,
+            priority,
+        };
+        let mut queues = SendQueues::new();
+        for _ in 0..LINK_SEND_QUEUE_SIZE {
+            queues.push(frame(Priority::Normal)).unwrap();
+        }
+
+        assert!(queues.push(frame(Priority::Normal)).is_err());
+        assert!(queues.push(frame(Priority::High)).is_ok());
+    }
+
+    #[test]
+    fn send_queues_hold_frames_below_minimum() {
+        let node = Node::new([0, 0, 0, 0, 0, 1]);
+        let frame = |priority| SendData {
+            data: MessageData::new(),
+            destination: node,
+            priority,
+        };
+        let mut queues = SendQueues::new();
+        queues.push(frame(Priority::Normal)).unwrap();
+        assert!(queues.pop_at_least(Priority::High).is_none());
+
+        queues.push(frame(Priority::Critical)).unwrap();
+        let urgent = queues.pop_at_least(Priority::High).unwrap();
+        assert_eq!(urgent.priority, Priority::Critical);
+        assert_eq!(queues.len(), 1);
+    }
+
+    #[test]
+    fn energy_report_estimates_battery_life() {
+        let report = EnergyReport {
+            tx_frames: 0,
+            rx_frames: 0,
+            listen_time: Duration::from_secs(2),
+            consumed: 20.0,
+        };
+        let life = report.estimated_battery_life(1000.0).unwrap();
+        assert_eq!(life.as_secs(), 100);
+        assert!(report.estimated_battery_life(-1.0).is_none());
+        assert!(report.estimated_battery_life(f64::NAN).is_none());
+        assert!(report.estimated_battery_life(f64::MAX).is_none());
     }
 }
@@ -0,0 +1,111 @@
+use crate::logic::{consts::MAX_LEAFS, node::Node};
+use heapless::LinearMap;
+
+/// Local time in milliseconds a frame was last received from each node, either from the node
+/// in radio range or originated by it and passed on by relays. Any frame that passed the tag
+/// and replay checks counts, heartbeats only make sure that quiet neighbors are heard at least
+/// every `HEARTBEAT_INTERVAL_MS`. Once full, the node heard from least recently is forgotten.
+pub struct LivenessTable {
+    last_seen: LinearMap<Node, u64, MAX_LEAFS>,
+}
+
+impl LivenessTable {
+    pub const fn new() -> Self {
+        Self {
+            last_seen: LinearMap::new(),
+        }
+    }
+
+    pub fn record(&mut self, node: Node, now_ms: u64) {
+        if let Some(last_seen) = self.last_seen.get_mut(&node) {
+            *last_seen = now_ms;
+            return;
+        }
+        if self.last_seen.is_full() {
+            let stalest = self
+                .last_seen
+                .iter()
+                .min_by_key(|(_, last_seen)| **last_seen)
+                .map(|(node, _)| *node);
+            if let Some(stalest) = stalest {
+                self.last_seen.remove(&stalest);
+            }
+        }
+        let _ = self.last_seen.insert(node, now_ms);
+    }
+
+    /// Like `record`, but only for a node that is known already. Frames this node relays carry
+    /// no tag it checks, so they keep a trusted neighbor alive but never add one.
+    pub fn refresh(&mut self, node: Node, now_ms: u64) {
+        if let Some(last_seen) = self.last_seen.get_mut(&node) {
+            *last_seen = now_ms;
+        }
+    }
+
+    pub fn forget(&mut self, node: Node) {
+        self.last_seen.remove(&node);
+    }
+
+    pub fn last_seen(&self, node: Node) -> Option<u64> {
+        self.last_seen.get(&node).copied()
+    }
+
+    /// Milliseconds since `node` was last heard, `None` if it never was.
+    pub fn silent_for(&self, node: Node, now_ms: u64) -> Option<u64> {
+        self.last_seen(node)
+            .map(|last_seen| now_ms.saturating_sub(last_seen))
+    }
+}
+
+impl Default for LivenessTable {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn n(id: u16) -> Node {
+        let [high, low] = id.to_be_bytes();
+        Node::new([0, 0, 0, 0, high, low])
+    }
+
+    #[test]
+    fn records_latest_frame_per_node() {
+        let mut table = LivenessTable::new();
+        table.record(n(1), 100);
+        table.record(n(1), 250);
+
+        assert_eq!(table.last_seen(n(1)), Some(250));
+        assert_eq!(table.silent_for(n(1), 400), Some(150));
+        assert_eq!(table.silent_for(n(2), 400), None);
+    }
+
+    #[test]
+    fn refresh_only_updates_known_nodes() {
+        let mut table = LivenessTable::new();
+        table.refresh(n(1), 100);
+        assert_eq!(table.last_seen(n(1)), None);
+
+        table.record(n(1), 100);
+        table.refresh(n(1), 300);
+        assert_eq!(table.last_seen(n(1)), Some(300));
+    }
+
+    #[test]
+    fn full_table_forgets_stalest_node() {
+        let mut table = LivenessTable::new();
+        for i in 0..MAX_LEAFS as u16 {
+            table.record(n(i), 100 + i as u64);
+        }
+        table.record(n(0), 500);
+
+        table.record(n(MAX_LEAFS as u16), 600);
+
+        assert!(table.last_seen(n(1)).is_none());
+        assert!(table.last_seen(n(0)).is_some());
+        assert!(table.last_seen(n(MAX_LEAFS as u16)).is_some());
+    }
+}
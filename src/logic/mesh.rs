@@ -8,27 +8,425 @@ use crate::hardware::asynchronous::{self, MyChannel};
 #[cfg(feature = "std")]
 use crate::logic::asynchronous;
 
+#[cfg(feature = "postcard")]
+use crate::logic::consts::MESSAGE_SIZE;
+
+use core::{
+    future::poll_fn,
+    task::{Context, Poll},
+};
 use heapless::{LinearMap, Vec};
 
 use crate::logic::{
-    error::{MeshError, TreeError},
-    link::{ActiveLink, Link},
+    capabilities::{Capabilities, ImageState},
+    config::{LeadPolicy, MeshConfig, NetworkConfig, NodeRole},
+    congestion::{CongestionDetector, CongestionEchoes},
+    consts::{
+        BOOT_CONFIRM_WINDOW_MS, BOOT_REPORT_INTERVAL_MS, LEADER_HEARTBEAT_INTERVAL_MS,
+        LEADER_LOST_MS, LINK_REPORT_INTERVAL_MS, LINK_RETRY_BACKOFF_MS, LINK_SEND_RETRIES,
+        MAX_LEAFS, MAX_NEWS, MAX_OUTRANKED_ROUNDS, MAX_PARENT_REPORTS, MAX_ROLE_RECEIVERS,
+        MAX_SILENT_ROUNDS, MAX_TRAFFIC_ENTRIES, MESH_EVENT_HISTORY, MESH_FULL_WATERMARK,
+        NEWS_RESPONSE_TIMEOUT_MS, ORGANIZE_QUEUE_SIZE, PIGGYBACK_QUEUE_SIZE, PING_TIMEOUT_MS,
+        RECV_QUEUE_SIZE, REJOIN_SEARCH_ROUNDS, REPARENT_BELOW_RSSI, REPARENT_MARGIN,
+        SEARCH_ROUND_MS, TOPOLOGY_CHUNK_TIMEOUT_MS, UPLINK_LOST_MS,
+    },
+    crypto::{self, AuthenticatedHeader, NetworkKey},
+    dedup::{DuplicateFilter, ReplayFilter},
+    error::{CryptoError, LinkError, MeshError, ReceiveMessageError, TreeError},
+    event::{EventLog, EventRecord, MeshEvent},
+    health::{ChildHealth, Health, HealthReport},
+    link::{ActiveLink, Link, RecvData},
+    liveness::LivenessTable,
     message::{
-        BROADCAST_NODE, MessageContent, MessageData, MessageType, ReceiveMessage, SendMessage,
+        Frame, JoinRejection, MessageContent, MessageData, MessageFlags, OpaqueMessage,
+        ReceiveMessage, SendMessage, TraceHops, TrafficClass,
     },
+    metadata::{self, Metadata},
+    neighbor::{LinkReport, NeighborTable},
     node::Node,
-    tree::{self, Tree},
+    ping::PingTracker,
+    placement::{FanOutLimit, ParentCandidate, ParentReports, ParentSelectionPolicy},
+    reachability::{Reachability, ReachabilityCache},
+    roster::{NodeName, Roster, RosterEntry},
+    shortcut::ShortcutTable,
+    stats::{ForwardCounters, MeshStats},
+    topology::{
+        ChunkBitmap, Edge, PendingEdges, TopologyChunk, TopologyChunks, TopologyRateLimit,
+        TopologyTransfer,
+    },
+    traffic::{PortTraffic, TrafficTable},
+    tree::{OrphanPolicy, Orphans, Tree},
+    util,
+    watch::Watched,
 };
-pub const RECV_QUEUE_SIZE: usize = 16;
-pub const ORGANIZE_QUEUE_SIZE: usize = 16;
-const MAX_NEWS: usize = 16;
 
 pub struct Mesh {
     link: &'static ActiveLink,
     tree: &'static asynchronous::Mutex<Tree>,
-    recv_queue: &'static asynchronous::Channel<(MessageData, Node), RECV_QUEUE_SIZE>,
+    state: &'static asynchronous::Mutex<MeshState>,
+    recv_queue: &'static asynchronous::Channel<Delivery, RECV_QUEUE_SIZE>,
     organize_queue: &'static asynchronous::Channel<ReceiveMessage, ORGANIZE_QUEUE_SIZE>,
     spawner: asynchronous::Spawner,
+    config: MeshConfig,
+}
+
+/// A copy of a `Mesh` for another task, made with `Mesh::handle`. Every handle works on the
+/// same tree, state and queues, so the display, game and telemetry tasks can each own one.
+/// Received messages go to whichever handle asks first. Only the `Mesh` itself should call
+/// `init`.
+pub struct MeshHandle {
+    mesh: Mesh,
+}
+
+impl Clone for MeshHandle {
+    fn clone(&self) -> Self {
+        self.mesh.handle()
+    }
+}
+
+impl core::ops::Deref for MeshHandle {
+    type Target = Mesh;
+
+    fn deref(&self) -> &Mesh {
+        &self.mesh
+    }
+}
+
+/// An application message handed to `Mesh::receive_delivery`.
+#[derive(Debug)]
+pub struct Delivery {
+    pub data: MessageData,
+    pub metadata: Metadata,
+    pub source: Node,
+    /// Time of the final source in milliseconds when it sent the message, if it stamps frames.
+    pub sent_ms: Option<u32>,
+    /// Local time in milliseconds when the last hop delivered the frame.
+    pub received_ms: u64,
+}
+
+impl Delivery {
+    /// Time the message spent travelling from its final source to this node. Only meaningful
+    /// once the clocks of both nodes are synchronized.
+    pub fn latency_ms(&self) -> Option<u32> {
+        self.sent_ms
+            .map(|sent| (self.received_ms as u32).wrapping_sub(sent))
+    }
+}
+
+/// Application frame waiting for the next heartbeat to `next`, see `TrafficClass::Piggyback`.
+struct PendingPiggyback {
+    next: Node,
+    destination: Node,
+    frame: MessageData,
+}
+
+/// How this node takes part in the mesh at the moment, see `Mesh::role`.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum MeshRole {
+    /// Sends Discovery until a mesh admits it or it leads one.
+    Searching,
+    Follower,
+    Leader,
+}
+
+/// Waits for the role of this node to change, see `Mesh::role_receiver`.
+pub struct RoleReceiver {
+    state: &'static asynchronous::Mutex<MeshState>,
+    seen: u32,
+}
+
+impl RoleReceiver {
+    /// Resolves with the role once it differs from the one this receiver returned last, or from
+    /// the one at its creation.
+    pub async fn changed(&mut self) -> MeshRole {
+        let seen = self.seen;
+        let (role, version) =
+            wait_on_state(self.state, |state, cx| state.role.poll_changed(seen, cx)).await;
+        self.seen = version;
+        role
+    }
+}
+
+/// Resolves once `poll` is ready on the state. `poll` registers the task with whatever it waits
+/// for, e.g. a `Watched`, and the lock is released while the task sleeps so the change it waits
+/// for can be made.
+async fn wait_on_state<T>(
+    state: &asynchronous::Mutex<MeshState>,
+    mut poll: impl FnMut(&mut MeshState, &mut Context<'_>) -> Poll<T>,
+) -> T {
+    loop {
+        {
+            let mut state = state.lock().await;
+            if let Poll::Ready(value) = poll_fn(|cx| Poll::Ready(poll(&mut state, cx))).await {
+                return value;
+            }
+        }
+        let mut woken = false;
+        poll_fn(|_| match core::mem::replace(&mut woken, true) {
+            true => Poll::Ready(()),
+            false => Poll::Pending,
+        })
+        .await;
+    }
+}
+
+/// Mutable state shared between the mesh tasks.
+pub struct MeshState {
+    sequence: u16,
+    session: Option<u32>,
+    frame_options: FrameOptions,
+    topology: Option<TopologyTransfer>,
+    network_config: NetworkConfig,
+    config_acks: LinearMap<Node, u16, MAX_LEAFS>,
+    /// Placements the leader is still waiting for each member to acknowledge.
+    edge_acks: LinearMap<Node, PendingEdges, MAX_LEAFS>,
+    events: EventLog,
+    reachability: ReachabilityCache,
+    liveness: LivenessTable,
+    shortcuts: ShortcutTable,
+    neighbors: NeighborTable,
+    child_health: ChildHealth,
+    /// Latest link report of each member, while this node leads the mesh. Kept once
+    /// `reparent_degraded` looked at it, so the children of a relay that left can be placed
+    /// again from it, see `adopt_orphans`.
+    link_reports: LinearMap<Node, LinkReport, MAX_LEAFS>,
+    /// Members whose link report `reparent_degraded` did not look at yet.
+    fresh_reports: Vec<Node, MAX_LEAFS>,
+    traffic: TrafficTable,
+    pings: PingTracker,
+    congestion: CongestionDetector,
+    congestion_echoes: CongestionEchoes,
+    /// Set when a relay reported congestion on the way, until
+    /// `Mesh::take_congestion` reads it.
+    congested: bool,
+    /// Advertised in the Discovery of this node.
+    capabilities: Capabilities,
+    /// What the nodes the leader placed advertised when they joined.
+    member_capabilities: LinearMap<Node, Capabilities, MAX_LEAFS>,
+    /// Members the leader attached, with their join time and name.
+    roster: Roster,
+    /// Set once the leader confirmed the boot of this node, or this node as the leader placed
+    /// its first member.
+    boot_confirmed: Watched<bool, 1>,
+    /// Uplink this node lost while it leads the nodes that were below it, see `lead_partition`,
+    /// or the leader it took over from, see `fail_over`.
+    partitioned_from: Option<Node>,
+    /// Uplink this node lost while it could not lead the nodes below it, see `search_detached`.
+    detached_from: Option<Node>,
+    /// Leader of the mesh while this node follows, known from its `LeaderHeartbeat`.
+    leader: Option<Node>,
+    /// Member the leader hands the lead over to after its next news round, see
+    /// `Mesh::hand_over_leadership`.
+    handover: Option<Node>,
+    /// Set once this node announced that it powers down, so the silence of its former uplink
+    /// is expected.
+    leaving: bool,
+    /// Local time until which this node shows where it is, see `Mesh::identify`.
+    identify_until_ms: Option<u64>,
+    role: Watched<MeshRole, MAX_ROLE_RECEIVERS>,
+    forwards: ForwardCounters,
+    piggyback: Vec<PendingPiggyback, PIGGYBACK_QUEUE_SIZE>,
+}
+
+impl MeshState {
+    pub const fn new() -> Self {
+        Self {
+            sequence: 0,
+            session: None,
+            frame_options: FrameOptions {
+                network_id: 0,
+                key: None,
+                timestamps: false,
+                session: 0,
+            },
+            topology: None,
+            network_config: NetworkConfig::new(),
+            config_acks: LinearMap::new(),
+            edge_acks: LinearMap::new(),
+            events: EventLog::new(),
+            reachability: ReachabilityCache::new(),
+            liveness: LivenessTable::new(),
+            shortcuts: ShortcutTable::new(),
+            neighbors: NeighborTable::new(),
+            child_health: ChildHealth::new(),
+            link_reports: LinearMap::new(),
+            fresh_reports: Vec::new(),
+            traffic: TrafficTable::new(),
+            pings: PingTracker::new(),
+            congestion: CongestionDetector::new(),
+            congestion_echoes: CongestionEchoes::new(),
+            congested: false,
+            capabilities: Capabilities::new(),
+            member_capabilities: LinearMap::new(),
+            roster: Roster::new(),
+            boot_confirmed: Watched::new(false),
+            partitioned_from: None,
+            detached_from: None,
+            leader: None,
+            handover: None,
+            leaving: false,
+            identify_until_ms: None,
+            role: Watched::new(MeshRole::Searching),
+            forwards: ForwardCounters::new(),
+            piggyback: Vec::new(),
+        }
+    }
+
+    fn next_sequence(&mut self) -> u16 {
+        self.sequence = self.sequence.wrapping_add(1);
+        if self.sequence == 0 {
+            self.session = None;
+        }
+        self.sequence
+    }
+
+    /// Sequence number and options of the next frame originating at this node.
+    fn next_frame(&mut self) -> (u16, FrameOptions) {
+        let sequence = self.next_sequence();
+        let options = FrameOptions {
+            session: self.session(),
+            ..self.frame_options
+        };
+        (sequence, options)
+    }
+
+    /// Takes the oldest frame waiting for a heartbeat to `next`.
+    fn take_piggyback(&mut self, next: Node) -> Option<PendingPiggyback> {
+        let index = self.piggyback.iter().position(|p| p.next == next)?;
+        Some(self.piggyback.remove(index))
+    }
+
+    /// Random per boot and renewed whenever the sequence number wraps, so that encrypted
+    /// payloads never reuse a nonce.
+    fn session(&mut self) -> u32 {
+        *self.session.get_or_insert_with(asynchronous::random_u32)
+    }
+
+    /// Takes over `config` if it is newer than the current one. Returns true if it did.
+    fn apply_network_config(&mut self, config: NetworkConfig) -> bool {
+        // Versions wrap, see `Mesh::set_network_config`.
+        if config.version.wrapping_sub(self.network_config.version) as i16 <= 0 {
+            return false;
+        }
+        self.network_config = config;
+        self.record_event(MeshEvent::ConfigUpdated(config.version));
+        true
+    }
+
+    fn record_event(&mut self, event: MeshEvent) {
+        self.events.record(event, asynchronous::now_ms());
+    }
+
+    fn record_config_ack(&mut self, node: Node, version: u16) {
+        if let Err((n, _)) = self.config_acks.insert(node, version) {
+            println!("dropping config ack of {}", n);
+        }
+    }
+
+    fn has_acked_config(&self, node: Node) -> bool {
+        self.config_acks.get(&node) == Some(&self.network_config.version)
+    }
+
+    fn record_edge_sent(&mut self, node: Node, epoch: u32) {
+        if let Some(pending) = self.edge_acks.get_mut(&node) {
+            pending.sent(epoch);
+            return;
+        }
+        let mut pending = PendingEdges::new();
+        pending.sent(epoch);
+        if let Err((n, _)) = self.edge_acks.insert(node, pending) {
+            println!("dropping pending edges of {}", n);
+        }
+    }
+
+    /// Remembers what `node` advertised. Once full, nodes that are no longer in `tree` make room.
+    fn record_capabilities(&mut self, tree: &Tree, node: Node, capabilities: Capabilities) {
+        if self.member_capabilities.is_full() && !self.member_capabilities.contains_key(&node) {
+            self.member_capabilities
+                .retain(|member, _| tree.is_downstream(*member));
+        }
+        if let Err((n, _)) = self.member_capabilities.insert(node, capabilities) {
+            println!("dropping capabilities of {}", n);
+        }
+    }
+
+    /// Keeps the latest link report of `node`. Once full, nodes that are no longer in `tree`
+    /// make room.
+    fn record_link_report(&mut self, tree: &Tree, node: Node, report: LinkReport) {
+        if self.link_reports.is_full() && !self.link_reports.contains_key(&node) {
+            self.link_reports
+                .retain(|member, _| tree.is_downstream(*member));
+        }
+        if let Err((n, _)) = self.link_reports.insert(node, report) {
+            println!("dropping link report of {}", n);
+            return;
+        }
+        if !self.fresh_reports.contains(&node) {
+            let _ = self.fresh_reports.push(node);
+        }
+    }
+
+    /// RSSI `parent` hears its own uplink with and its battery, as far as the leader knows.
+    /// Children of the leader piggyback both on their heartbeats, deeper members report their
+    /// uplink in their link report and their battery when they joined.
+    fn link_budget(&self, tree: &Tree, parent: Node) -> (Option<i32>, Option<u8>) {
+        if let Some(report) = self.child_health.get(parent) {
+            let health = report.health;
+            return (health.uplink_rssi.map(i32::from), health.battery);
+        }
+        let uplink = tree.parent(parent).ok().flatten();
+        let uplink_rssi = self.link_reports.get(&parent).and_then(|report| {
+            report
+                .iter()
+                .find(|(neighbor, _)| Some(*neighbor) == uplink)
+                .map(|(_, rssi)| *rssi)
+        });
+        let battery = self
+            .capabilities_of(Some(parent))
+            .and_then(|capabilities| capabilities.battery);
+        (uplink_rssi, battery)
+    }
+
+    /// Capabilities of `node`, `None` standing for this node itself.
+    fn capabilities_of(&self, node: Option<Node>) -> Option<Capabilities> {
+        match node {
+            None => Some(self.capabilities),
+            Some(node) => self.member_capabilities.get(&node).copied(),
+        }
+    }
+
+    fn record_edge_ack(&mut self, node: Node, epoch: u32) {
+        if let Some(pending) = self.edge_acks.get_mut(&node) {
+            pending.acked(epoch);
+        }
+    }
+}
+
+/// How frames originating at this node are serialized.
+#[derive(Copy, Clone)]
+struct FrameOptions {
+    network_id: u16,
+    key: Option<NetworkKey>,
+    timestamps: bool,
+    /// Sent with the tag of organization messages, filled in by `MeshState::next_frame`.
+    session: u32,
+}
+
+impl From<&MeshConfig> for FrameOptions {
+    fn from(config: &MeshConfig) -> Self {
+        Self {
+            network_id: config.network_id,
+            key: config.network_key,
+            timestamps: config.timestamps,
+            session: 0,
+        }
+    }
+}
+
+impl Default for MeshState {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl Mesh {
@@ -36,465 +434,5919 @@ impl Mesh {
         spawner: asynchronous::Spawner,
         link: &'static ActiveLink,
         tree: &'static asynchronous::Mutex<Tree>,
-        recv_queue: &'static asynchronous::Channel<(MessageData, Node), RECV_QUEUE_SIZE>,
+        state: &'static asynchronous::Mutex<MeshState>,
+        recv_queue: &'static asynchronous::Channel<Delivery, RECV_QUEUE_SIZE>,
         organize_queue: &'static asynchronous::Channel<ReceiveMessage, ORGANIZE_QUEUE_SIZE>,
+        config: MeshConfig,
     ) -> Self {
         Self {
             link,
             tree,
+            state,
             recv_queue,
             organize_queue,
             spawner,
+            config,
+        }
+    }
+
+    /// A handle other tasks can own, see `MeshHandle`. Cheap, it only copies references.
+    pub fn handle(&self) -> MeshHandle {
+        MeshHandle {
+            mesh: Mesh {
+                link: self.link,
+                tree: self.tree,
+                state: self.state,
+                recv_queue: self.recv_queue,
+                organize_queue: self.organize_queue,
+                spawner: self.spawner,
+                config: self.config,
+            },
         }
     }
 
     pub fn init(&self) -> Result<(), MeshError> {
+        {
+            let mut state = self
+                .state
+                .try_lock()
+                .map_err(|_| MeshError::StateLockedError)?;
+            state.frame_options = FrameOptions::from(&self.config);
+            state.capabilities = self.config.capabilities;
+        }
+        asynchronous::spawn(
+            &self.spawner,
+            searcher_task(
+                self.spawner,
+                self.tree,
+                self.state,
+                self.link,
+                self.organize_queue,
+                self.config,
+            ),
+        )
+        .map_err(|_| MeshError::SpawnError)?;
         asynchronous::spawn(
             &self.spawner,
-            searcher_task(self.spawner, self.tree, self.link, self.organize_queue),
+            heartbeat_task(self.link, self.tree, self.state, self.config.role),
         )
         .map_err(|_| MeshError::SpawnError)?;
         asynchronous::spawn(
             &self.spawner,
-            dispatcher_task(self.link, self.tree, self.recv_queue, self.organize_queue),
+            dispatcher_task(
+                self.link,
+                self.tree,
+                self.state,
+                self.recv_queue,
+                self.organize_queue,
+                self.config,
+            ),
         )
         .map_err(|_| MeshError::SpawnError)
     }
 
+    /// Sends `data` to `destination`, which has to be the address of a single node. Broadcasts
+    /// are reserved for discovery and heartbeats and fail with `MeshError::NotUnicastError`.
     pub async fn send(&self, data: MessageData, destination: Node) -> Result<(), MeshError> {
-        let content = MessageContent::Application(data);
-        Self::send_content(self.link, self.tree, content, destination).await
-    }
-
-    pub async fn receive(&self) -> (MessageData, Node) {
-        self.recv_queue.my_recv().await
+        self.send_with_metadata(data, &Metadata::new(), destination)
+            .await
     }
 
-    async fn send_content(
-        link: &'static ActiveLink,
-        tree: &'static asynchronous::Mutex<Tree>,
-        content: MessageContent,
+    /// Sends `data` together with `metadata`, which the receiver gets from
+    /// `receive_with_metadata`.
+    pub async fn send_with_metadata(
+        &self,
+        data: MessageData,
+        metadata: &Metadata,
         destination: Node,
     ) -> Result<(), MeshError> {
-        let msg = SendMessage::new(destination, content, None);
-        let next = tree
-            .lock()
+        self.send_with_class(data, metadata, TrafficClass::Standard, destination)
             .await
-            .next_hop(destination)
-            .map_err(|e| MeshError::TreeError(e))?;
-        Ok(link
-            .send(
-                msg.serialize()
-                    .map_err(|e| MeshError::SerializationError(e))?,
-                next,
-            )
-            .await)
     }
-}
 
-#[cfg_attr(feature = "hardware", embassy_executor::task)]
-async fn searcher_task(
-    spawner: asynchronous::Spawner,
-    tree: &'static asynchronous::Mutex<Tree>,
-    link: &'static ActiveLink,
-    organize_queue: &'static asynchronous::Channel<ReceiveMessage, ORGANIZE_QUEUE_SIZE>,
-) {
-    loop {
-        match run_search_round(spawner, tree, link, organize_queue).await {
-            Ok(RoleDecision::Leader) => {
-                println!("leader");
-                asynchronous::spawn(&spawner, leader_task(spawner, tree, link, organize_queue));
-                break;
+    /// Like `send_with_metadata`, but routed according to `class`.
+    pub async fn send_with_class(
+        &self,
+        data: MessageData,
+        metadata: &Metadata,
+        class: TrafficClass,
+        destination: Node,
+    ) -> Result<(), MeshError> {
+        if !destination.is_unicast() {
+            return Err(MeshError::NotUnicastError(destination));
+        }
+        let data = metadata::attach(metadata, &data).map_err(MeshError::MetadataError)?;
+        let (sequence, session) = {
+            let mut state = self.state.lock().await;
+            let now_ms = asynchronous::now_ms();
+            if state
+                .reachability
+                .is_reported_unreachable(destination, now_ms)
+            {
+                return Err(MeshError::DestinationUnreachableError(destination));
             }
-            Ok(RoleDecision::Follower) => {
-                println!("follower");
-                asynchronous::spawn(&spawner, follower_task(spawner, tree, link, organize_queue));
-                break;
+            if state.reachability.is_unreachable(destination, now_ms) {
+                return Err(MeshError::UnreachableError(destination));
             }
-            Ok(RoleDecision::Timeout) => {}
-            Err(e) => println!("{}", e),
+            (state.next_sequence(), state.session())
+        };
+        let data = match self.config.network_key {
+            Some(key) => {
+                let header = AuthenticatedHeader {
+                    final_source: self.link.address(),
+                    final_destination: destination,
+                    sequence,
+                };
+                crypto::seal_application(&key, &header, session, &data)
+                    .map_err(MeshError::CryptoError)?
+            }
+            None => data,
+        };
+        let mut msg = SendMessage::new(
+            destination,
+            MessageContent::Application(data),
+            None,
+            sequence,
+        );
+        if class == TrafficClass::Realtime {
+            msg.flags.insert(MessageFlags::REALTIME);
         }
+        let shortcut = may_shortcut(&msg, &self.config);
+        let result = match route(self.tree, self.state, destination, shortcut).await {
+            Ok(next) => {
+                let options = FrameOptions::from(&self.config);
+                match serialize_signed(msg, self.link.address(), options) {
+                    Ok(frame) => {
+                        if class == TrafficClass::Piggyback
+                            && self.queue_piggyback(&frame, next, destination).await
+                        {
+                            // Recorded once the heartbeat carrying it is sent.
+                            return Ok(());
+                        }
+                        Self::send_frame(self.link, self.tree, &frame, next, destination).await
+                    }
+                    Err(e) => Err(e),
+                }
+            }
+            Err(e) => Err(MeshError::TreeError(e)),
+        };
+        record_send(self.state, destination, &result).await;
+        result
     }
-}
 
-enum RoleDecision {
-    Leader,
-    Follower,
-    Timeout,
-}
+    /// Queues `frame` for the next heartbeat to `next`. Returns false if the queue is full and the
+    /// frame has to be sent on its own.
+    async fn queue_piggyback(&self, frame: &MessageData, next: Node, destination: Node) -> bool {
+        let pending = PendingPiggyback {
+            next,
+            destination,
+            frame: frame.clone(),
+        };
+        self.state.lock().await.piggyback.push(pending).is_ok()
+    }
 
-async fn run_search_round(
-    spawner: asynchronous::Spawner,
-    tree: &'static asynchronous::Mutex<Tree>,
-    link: &'static ActiveLink,
-    organize_queue: &'static asynchronous::Channel<ReceiveMessage, ORGANIZE_QUEUE_SIZE>,
-) -> Result<RoleDecision, MeshError> {
-    send_discovery(link).await?;
-    match asynchronous::select(
-        asynchronous::after(asynchronous::Duration::from_secs(1)),
-        wait_for_invitation(organize_queue, tree),
-    )
-    .await
-    {
-        asynchronous::Either::First(_) => Ok(RoleDecision::Timeout),
-        asynchronous::Either::Second(role) => Ok(role),
+    /// Encodes `value` with postcard and sends it as the application payload.
+    #[cfg(feature = "postcard")]
+    pub async fn send_typed<T: serde::Serialize>(
+        &self,
+        value: &T,
+        destination: Node,
+    ) -> Result<(), MeshError> {
+        let mut buffer = [0; MESSAGE_SIZE];
+        let encoded =
+            postcard::to_slice(value, &mut buffer).map_err(MeshError::PayloadCodecError)?;
+        let data = MessageData::from_slice(encoded)
+            .map_err(|_| MeshError::PayloadCodecError(postcard::Error::SerializeBufferFull))?;
+        self.send(data, destination).await
     }
-}
 
-async fn send_discovery(link: &ActiveLink) -> Result<(), MeshError> {
-    println!("discovery");
-    let msg = SendMessage::new(BROADCAST_NODE, MessageContent::Discovery, None);
-    let data = msg.serialize().map_err(MeshError::SerializationError)?;
-    link.send(data, BROADCAST_NODE).await;
-    Ok(())
-}
+    /// Waits for the next application message and decodes it as `T` with postcard. A message
+    /// that does not decode is consumed and reported as `MeshError::PayloadCodecError`.
+    #[cfg(feature = "postcard")]
+    pub async fn receive_typed<T: serde::de::DeserializeOwned>(
+        &self,
+    ) -> Result<(T, Node), MeshError> {
+        let (data, source) = self.receive().await;
+        let value = postcard::from_bytes(&data).map_err(MeshError::PayloadCodecError)?;
+        Ok((value, source))
+    }
 
-async fn wait_for_invitation(
-    organize_queue: &'static asynchronous::Channel<ReceiveMessage, ORGANIZE_QUEUE_SIZE>,
-    tree: &'static asynchronous::Mutex<Tree>,
-) -> RoleDecision {
-    loop {
-        let recv_msg = organize_queue.my_recv().await;
-        match recv_msg.data {
-            MessageContent::Discovery => {
-                return RoleDecision::Leader;
-            }
-            MessageContent::UpsertEdge((n, p)) => {
-                let parent = match p {
-                    None => Some(recv_msg.final_source),
-                    Some(node) if node == recv_msg.final_destination => None,
-                    Some(node) => Some(node),
-                };
-                let new = match n {
-                    None => recv_msg.final_source,
-                    Some(node) => node,
-                };
-                match tree.lock().await.upsert_edge(parent, new) {
-                    Err(e) => println!("{}", e),
-                    _ => (),
-                }
-                return RoleDecision::Follower;
-            }
-            _ => {}
-        }
+    pub async fn receive(&self) -> (MessageData, Node) {
+        let (data, _, source) = self.receive_with_metadata().await;
+        (data, source)
     }
-}
 
-#[cfg_attr(feature = "hardware", embassy_executor::task)]
-async fn leader_task(
-    spawner: asynchronous::Spawner,
-    tree: &'static asynchronous::Mutex<Tree>,
-    link: &'static ActiveLink,
-    organize_queue: &'static asynchronous::Channel<ReceiveMessage, ORGANIZE_QUEUE_SIZE>,
-) {
-    let mut news: Vec<(Node, i32), MAX_NEWS> = Vec::new();
-    let mut ticker = asynchronous::Ticker::every(asynchronous::Duration::from_secs(3));
+    pub async fn receive_with_metadata(&self) -> (MessageData, Metadata, Node) {
+        let delivery = self.receive_delivery().await;
+        (delivery.data, delivery.metadata, delivery.source)
+    }
 
-    loop {
-        match asynchronous::select(organize_queue.my_recv(), ticker.next()).await {
-            asynchronous::Either::First(msg) => handle_leader_message(&mut news, msg),
-            asynchronous::Either::Second(_) => {
-                process_news_round(&news, tree, link, organize_queue).await;
-                news.clear();
-            }
+    /// Like `receive`, but also returns the metadata and timestamps of the message.
+    pub async fn receive_delivery(&self) -> Delivery {
+        self.recv_queue.my_recv().await
+    }
+
+    /// Hands every application message to `handler` as soon as it arrives, together with its
+    /// final source.
+    pub async fn run_app(&self, mut handler: impl FnMut(MessageData, Node)) -> ! {
+        loop {
+            let (data, source) = self.receive().await;
+            handler(data, source);
         }
     }
-}
 
-fn handle_leader_message(news: &mut Vec<(Node, i32), MAX_NEWS>, msg: ReceiveMessage) {
-    if let MessageContent::Discovery = msg.data {
-        if let Err((e, _)) = news.push((msg.final_source, msg.rssi)) {
+    pub async fn network_config(&self) -> NetworkConfig {
+        self.state.lock().await.network_config
+    }
+
+    /// Replaces the runtime network config with the next version. On the leader the new config
+    /// is sent to every member after each news round until the member acknowledges it.
+    pub async fn set_network_config(&self, config: NetworkConfig) {
+        let mut state = self.state.lock().await;
+        let version = state.network_config.version.wrapping_add(1);
+        state.network_config = NetworkConfig { version, ..config };
+        state.record_event(MeshEvent::ConfigUpdated(version));
+        if let Err(e) = self.link.set_tx_power(config.tx_power) {
             println!("{}", e);
         }
     }
-}
 
-async fn process_news_round(
-    news: &Vec<(Node, i32), MAX_NEWS>,
-    tree: &'static asynchronous::Mutex<Tree>,
-    link: &'static ActiveLink,
-    organize_queue: &'static asynchronous::Channel<ReceiveMessage, ORGANIZE_QUEUE_SIZE>,
-) {
-    let mut all_news = LinearMap::new();
-    collect_local_news(news, &mut all_news);
-    collect_remote_news(&mut all_news, tree, link, organize_queue).await;
-    send_topology_updates(all_news, tree, link).await;
-}
+    /// Returns the send statistics of `destination`, or `None` if nothing was sent to it yet.
+    /// Sends fail with `MeshError::UnreachableError` while it is considered unreachable, and
+    /// with `MeshError::DestinationUnreachableError` while a node on the route reports that it
+    /// cannot forward to it.
+    pub async fn reachability(&self, destination: Node) -> Option<Reachability> {
+        self.state.lock().await.reachability.get(destination)
+    }
 
-fn collect_local_news(
-    news: &Vec<(Node, i32), MAX_NEWS>,
-    all_news: &mut LinearMap<Node, (Option<Node>, i32), MAX_NEWS>,
-) {
-    for (node, rssi) in news {
-        if let Err(e) = all_news.insert(*node, (None, *rssi)) {
-            println!("{:?}", e);
-        }
+    /// Whether this node searches for a mesh, follows or leads it.
+    pub async fn role(&self) -> MeshRole {
+        self.state.lock().await.role.get()
     }
-}
 
-async fn collect_remote_news(
-    all_news: &mut LinearMap<Node, (Option<Node>, i32), MAX_NEWS>,
-    tree: &'static asynchronous::Mutex<Tree>,
-    link: &'static ActiveLink,
-    organize_queue: &'static asynchronous::Channel<ReceiveMessage, ORGANIZE_QUEUE_SIZE>,
-) {
-    let nodes = {
-        let t = tree.lock().await;
-        t.into_iter().collect::<Vec<_, { tree::MAX_LEAFS }>>()
-    };
-    for (node, parent) in nodes {
-        Mesh::send_content(link, tree, MessageContent::RequestNews, node).await;
-        loop {
-            match asynchronous::select(
-                organize_queue.my_recv(),
-                asynchronous::after(asynchronous::Duration::from_millis(500)),
-            )
-            .await
-            {
-                asynchronous::Either::First(response) => {
-                    if !handle_news_response(all_news, node, response) {
-                        break;
-                    }
-                }
-                asynchronous::Either::Second(_) => break,
-            }
+    /// Whether this node shares a mesh with at least one other node.
+    pub async fn is_connected(&self) -> bool {
+        let role = self.state.lock().await.role.get();
+        role != MeshRole::Searching && self.tree.lock().await.node_count() > 1
+    }
+
+    /// A receiver for every change of `role`, e.g. to update the display.
+    pub async fn role_receiver(&self) -> RoleReceiver {
+        RoleReceiver {
+            state: self.state,
+            seen: self.state.lock().await.role.version(),
         }
     }
-}
 
-fn handle_news_response(
-    all_news: &mut LinearMap<Node, (Option<Node>, i32), MAX_NEWS>,
-    parent: Node,
-    response: ReceiveMessage,
-) -> bool {
-    match response.data {
-        MessageContent::SendNew((node, rssi)) => {
-            match all_news.get_mut(&node) {
-                Some((best_parent, best_rssi)) if *best_rssi < rssi => {
-                    *best_parent = Some(parent);
-                    *best_rssi = rssi;
-                }
-                None => {
-                    if let Err(e) = all_news.insert(node, (Some(parent), rssi)) {
-                        println!("{:?}", e);
-                    }
-                }
-                _ => {}
+    /// Address of this node.
+    pub fn address(&self) -> Node {
+        self.link.address()
+    }
+
+    /// Every other node of the tree of this node together with its parent, `None` standing for
+    /// this node.
+    pub async fn edges(&self) -> Vec<(Node, Option<Node>), MAX_LEAFS> {
+        self.tree.lock().await.into_iter().collect()
+    }
+
+    /// Number of nodes in the mesh as far as this node knows, including itself.
+    pub async fn node_count(&self) -> usize {
+        self.tree.lock().await.node_count()
+    }
+
+    /// Most nodes the tree of this node can hold, see `node_count`.
+    pub async fn capacity(&self) -> usize {
+        self.tree.lock().await.capacity()
+    }
+
+    /// Shape of the tree of this node, how full its queues are and how many frames it relayed.
+    pub async fn stats(&self) -> MeshStats {
+        let (node_count, height, subtrees) = {
+            let tree = self.tree.lock().await;
+            let uplink = tree.uplink();
+            let subtrees = tree
+                .children(None)
+                .unwrap_or_default()
+                .into_iter()
+                .filter(|child| Some(*child) != uplink)
+                .filter_map(|child| Some((child, tree.descendants(Some(child)).ok()? + 1)))
+                .collect();
+            (tree.node_count(), tree.height(), subtrees)
+        };
+        MeshStats {
+            node_count,
+            height,
+            subtrees,
+            send_queue: self.link.queued(),
+            receive_queue: self.recv_queue.my_len(),
+            organize_queue: self.organize_queue.my_len(),
+            forwards: self.state.lock().await.forwards,
+        }
+    }
+
+    /// What the child `node` piggybacked on its latest heartbeat, `None` if it is not a child
+    /// of this node or did not send one yet. The leader only knows about its own children.
+    pub async fn child_health(&self, node: Node) -> Option<HealthReport> {
+        self.state.lock().await.child_health.get(node)
+    }
+
+    /// Local time in milliseconds this node last received a frame from `node`, directly or over
+    /// relays, `None` if it never did. Neighbors in the tree are heard at least every
+    /// `HEARTBEAT_INTERVAL_MS`.
+    pub async fn last_seen(&self, node: Node) -> Option<u64> {
+        self.state.lock().await.liveness.last_seen(node)
+    }
+
+    /// Application traffic this node received, per peer and port with the most bytes first. Use
+    /// `Metadata::for_port` when sending to tell application features apart here.
+    pub async fn port_traffic(&self) -> Vec<PortTraffic, MAX_TRAFFIC_ENTRIES> {
+        self.state.lock().await.traffic.entries()
+    }
+
+    /// Replaces what this node advertises in its next Discovery, e.g. after its battery level
+    /// dropped.
+    pub async fn set_capabilities(&self, capabilities: Capabilities) {
+        self.state.lock().await.capabilities = capabilities;
+    }
+
+    /// What `node` advertised when it joined. Only the leader learns this about other nodes,
+    /// members just know their own.
+    pub async fn capabilities(&self, node: Node) -> Option<Capabilities> {
+        let state = self.state.lock().await;
+        if node == self.link.address() {
+            return state.capabilities_of(None);
+        }
+        state.capabilities_of(Some(node))
+    }
+
+    /// Whether a relay reported congestion on the path of the application messages of
+    /// this node since the last call. Sources should send less while it keeps returning true,
+    /// see `TelemetryRate`.
+    pub async fn take_congestion(&self) -> bool {
+        core::mem::take(&mut self.state.lock().await.congested)
+    }
+
+    /// Members of the mesh in the order they joined, with their name and what they advertised.
+    /// Only the leader admits nodes, so the roster of every other node is empty. Unlike the
+    /// routing tree it keeps the join time of nodes that moved to another parent.
+    pub async fn roster(&self) -> Vec<RosterEntry, MAX_LEAFS> {
+        let tree = self.tree.lock().await;
+        let state = self.state.lock().await;
+        state
+            .roster
+            .entries(&tree, |node| state.capabilities_of(Some(node)))
+    }
+
+    /// Announces that this node is about to power down. Its uplink and the leader prune it and
+    /// attach its children to its parent, and the leader tells every other member to do the
+    /// same. The leader itself has no uplink to announce this to and gets `NoUplinkError`.
+    pub async fn leave(&self) -> Result<(), MeshError> {
+        let uplink = self
+            .tree
+            .lock()
+            .await
+            .uplink()
+            .ok_or(MeshError::TreeError(TreeError::NoUplinkError))?;
+        self.state.lock().await.leaving = true;
+        let content = MessageContent::Leave(self.link.address());
+        Self::send_content(self.link, self.tree, self.state, content, uplink).await
+    }
+
+    /// Hands the lead of the mesh over to the member `target`, e.g. a mains-powered gateway,
+    /// without a new election. After its next news round the leader brings the tree of `target`
+    /// up to date and tells every member, which then follow `target`, and follows it itself.
+    /// Fails with `MeshError::NotLeaderError` on a follower and with `NodeNotFoundError` if
+    /// `target` is no member. A target that may not lead, see `MeshConfig::may_lead`, searches
+    /// for the mesh again instead and its neighbors fail over.
+    pub async fn hand_over_leadership(&self, target: Node) -> Result<(), MeshError> {
+        if !target.is_unicast() {
+            return Err(MeshError::NotUnicastError(target));
+        }
+        let t = self.tree.lock().await;
+        if t.uplink().is_some() {
+            return Err(MeshError::NotLeaderError);
+        }
+        if !t.contains(target) {
+            return Err(MeshError::TreeError(TreeError::NodeNotFoundError));
+        }
+        self.state.lock().await.handover = Some(target);
+        Ok(())
+    }
+
+    /// Sends a ping to `node` and returns the time until its pong arrived. Fails with
+    /// `MeshError::PingTimeoutError` if no pong arrives within `PING_TIMEOUT_MS`.
+    pub async fn ping(&self, node: Node) -> Result<asynchronous::Duration, MeshError> {
+        self.probe(node, MessageContent::Ping, PingTracker::rtt_ms)
+            .await
+            .map(asynchronous::Duration::from_millis)
+    }
+
+    /// Sends a trace route to `node` and returns the nodes it passed, ending with `node`
+    /// itself. Routes longer than `MAX_TRACE_HOPS` are cut off at the end. Fails like `ping`.
+    pub async fn trace_route(&self, node: Node) -> Result<TraceHops, MeshError> {
+        let content = |id| MessageContent::TraceRoute(id, TraceHops::new());
+        self.probe(node, content, PingTracker::route).await
+    }
+
+    /// Makes `node` show where it is for `duration`, e.g. blink and show "HERE" on its display,
+    /// to find one tag in a box of them. Resolves once `node` acknowledged the request, fails
+    /// like `ping` otherwise.
+    pub async fn identify(
+        &self,
+        node: Node,
+        duration: asynchronous::Duration,
+    ) -> Result<(), MeshError> {
+        let duration_ms = u32::try_from(duration.as_millis()).unwrap_or(u32::MAX);
+        let content = |id| MessageContent::Identify(id, duration_ms);
+        self.probe(node, content, PingTracker::rtt_ms)
+            .await
+            .map(|_| ())
+    }
+
+    /// Whether another node asked this one to show where it is within the requested duration,
+    /// see `identify`.
+    pub async fn identifying(&self) -> bool {
+        self.state
+            .lock()
+            .await
+            .identify_until_ms
+            .is_some_and(|until_ms| asynchronous::now_ms() < until_ms)
+    }
+
+    /// Sends the content `request` builds for a fresh correlation ID to `node` and waits up to
+    /// `PING_TIMEOUT_MS` until `answer` finds the reply in the ping tracker, which wakes this
+    /// task when it arrives.
+    async fn probe<T>(
+        &self,
+        node: Node,
+        request: impl FnOnce(u16) -> MessageContent,
+        answer: fn(&PingTracker, u16) -> Option<T>,
+    ) -> Result<T, MeshError> {
+        if !node.is_unicast() {
+            return Err(MeshError::NotUnicastError(node));
+        }
+        let sent_ms = asynchronous::now_ms();
+        let id = self.state.lock().await.pings.start(node, sent_ms);
+        let content = request(id);
+        if let Err(e) = Self::send_content(self.link, self.tree, self.state, content, node).await {
+            self.state.lock().await.pings.finish(id);
+            return Err(e);
+        }
+        let answered = wait_on_state(self.state, |state, cx| match answer(&state.pings, id) {
+            Some(value) => Poll::Ready(value),
+            None => {
+                state.pings.wake_on_answer(id, cx.waker());
+                Poll::Pending
+            }
+        });
+        let left_ms =
+            PING_TIMEOUT_MS.saturating_sub(asynchronous::now_ms().saturating_sub(sent_ms));
+        let timeout = asynchronous::after(asynchronous::Duration::from_millis(left_ms));
+        let result = match asynchronous::select(answered, timeout).await {
+            asynchronous::Either::First(value) => Ok(value),
+            asynchronous::Either::Second(_) => Err(MeshError::PingTimeoutError(node)),
+        };
+        self.state.lock().await.pings.finish(id);
+        result
+    }
+
+    /// Waits until the leader confirms that this node, running freshly updated firmware, reached
+    /// the mesh. Reports this node to its uplink every `BOOT_REPORT_INTERVAL_MS` until the
+    /// confirmation arrives. The leader confirms itself once it placed its first member. Fails
+    /// with `MeshError::BootConfirmTimeoutError` after `BOOT_CONFIRM_WINDOW_MS`, in which case
+    /// the firmware should be rolled back.
+    pub async fn confirm_boot(&self) -> Result<(), MeshError> {
+        let started_ms = asynchronous::now_ms();
+        let mut reported_ms = None;
+        let (mut boot_seen, mut role_seen) = {
+            let state = self.state.lock().await;
+            (state.boot_confirmed.version(), state.role.version())
+        };
+        loop {
+            {
+                let mut state = self.state.lock().await;
+                if state.boot_confirmed.get() {
+                    state.capabilities.image = ImageState::Confirmed;
+                    return Ok(());
+                }
+            }
+            let now_ms = asynchronous::now_ms();
+            let elapsed_ms = now_ms.saturating_sub(started_ms);
+            if elapsed_ms >= BOOT_CONFIRM_WINDOW_MS {
+                return Err(MeshError::BootConfirmTimeoutError);
+            }
+            let due = reported_ms
+                .is_none_or(|ms: u64| now_ms.saturating_sub(ms) >= BOOT_REPORT_INTERVAL_MS);
+            let uplink = self.tree.lock().await.uplink();
+            if let (true, Some(uplink)) = (due, uplink) {
+                reported_ms = Some(now_ms);
+                let capabilities = self.state.lock().await.capabilities;
+                let content = MessageContent::BootHealthy(
+                    self.link.address(),
+                    capabilities.with_image(ImageState::Confirmed),
+                );
+                if let Err(e) =
+                    Self::send_content(self.link, self.tree, self.state, content, uplink).await
+                {
+                    println!("{}", e);
+                }
+            }
+            // Wakes for the confirmation, for the adoption that gives this node an uplink to
+            // report to, or when the next report is due.
+            let next_report_ms = reported_ms.map_or(BOOT_REPORT_INTERVAL_MS, |ms: u64| {
+                (ms + BOOT_REPORT_INTERVAL_MS).saturating_sub(now_ms)
+            });
+            let wait_ms = next_report_ms.min(BOOT_CONFIRM_WINDOW_MS - elapsed_ms);
+            let changed = wait_on_state(self.state, |state, cx| {
+                let boot = state.boot_confirmed.poll_changed(boot_seen, cx);
+                let role = state.role.poll_changed(role_seen, cx);
+                match (boot, role) {
+                    (Poll::Pending, Poll::Pending) => Poll::Pending,
+                    _ => Poll::Ready((state.boot_confirmed.version(), state.role.version())),
+                }
+            });
+            let timeout = asynchronous::after(asynchronous::Duration::from_millis(wait_ms));
+            if let asynchronous::Either::First(seen) = asynchronous::select(changed, timeout).await
+            {
+                (boot_seen, role_seen) = seen;
+            }
+        }
+    }
+
+    /// Returns the most recent mesh events, oldest first, so that a task that starts late can
+    /// still show the current state.
+    pub async fn recent_events(&self) -> Vec<EventRecord, MESH_EVENT_HISTORY> {
+        self.state.lock().await.events.recent()
+    }
+
+    async fn send_content(
+        link: &'static ActiveLink,
+        tree: &'static asynchronous::Mutex<Tree>,
+        state: &'static asynchronous::Mutex<MeshState>,
+        content: MessageContent,
+        destination: Node,
+    ) -> Result<(), MeshError> {
+        let (sequence, options) = {
+            let mut state = state.lock().await;
+            state.next_frame()
+        };
+        let msg = SendMessage::new(destination, content, None, sequence);
+        let next = tree.lock().await.next_hop(destination);
+        let result = match next {
+            Ok(next) => Self::send_sequenced(link, tree, msg, next, options).await,
+            Err(e) => Err(MeshError::TreeError(e)),
+        };
+        record_send(state, destination, &result).await;
+        result
+    }
+
+    /// Sends `msg` to `next`, retrying while it does not acknowledge. If it never does, the edge
+    /// to it becomes suspect and the frame tries the node behind it once, see
+    /// `Tree::hop_beyond`.
+    async fn send_sequenced(
+        link: &'static ActiveLink,
+        tree: &'static asynchronous::Mutex<Tree>,
+        msg: SendMessage,
+        next: Node,
+        options: FrameOptions,
+    ) -> Result<(), MeshError> {
+        let destination = msg.final_destination;
+        let data = serialize_signed(msg, link.address(), options)?;
+        Self::send_frame(link, tree, &data, next, destination).await
+    }
+
+    /// Sends the serialized `data` like `send_sequenced`.
+    async fn send_frame(
+        link: &'static ActiveLink,
+        tree: &'static asynchronous::Mutex<Tree>,
+        data: &MessageData,
+        next: Node,
+        destination: Node,
+    ) -> Result<(), MeshError> {
+        let result = Self::send_with_retries(link, data, next).await;
+        if !matches!(result, Err(LinkError::DeliveryError(_))) {
+            return result.map_err(MeshError::LinkError);
+        }
+        let beyond = {
+            let mut t = tree.lock().await;
+            t.mark_suspect(next);
+            t.hop_beyond(next, destination)
+        };
+        match beyond {
+            Some(beyond) => Self::send_with_retries(link, data, beyond).await,
+            None => result,
+        }
+        .map_err(MeshError::LinkError)
+    }
+
+    async fn send_with_retries(
+        link: &'static ActiveLink,
+        data: &MessageData,
+        next: Node,
+    ) -> Result<(), LinkError> {
+        let mut retries = 0;
+        loop {
+            match link.send(data.clone(), next).await {
+                Err(LinkError::DeliveryError(_)) if retries < LINK_SEND_RETRIES => {
+                    let backoff = util::backoff_ms(
+                        LINK_RETRY_BACKOFF_MS,
+                        retries,
+                        asynchronous::random_u32(),
+                    );
+                    asynchronous::after(asynchronous::Duration::from_millis(backoff)).await;
+                    retries += 1;
+                }
+                result => return result,
             }
-            true
         }
-        MessageContent::FinSendNew => false,
-        _ => true,
     }
 }
 
-async fn send_topology_updates(
-    all_news: LinearMap<Node, (Option<Node>, i32), MAX_NEWS>,
+/// Returns true if `msg` may skip the tree when its destination is a shortcut. Organization
+/// messages always follow the tree, the leader relies on it to place nodes.
+fn may_shortcut(msg: &SendMessage, config: &MeshConfig) -> bool {
+    !msg.is_organization() && (config.shortcuts || msg.flags.contains(MessageFlags::REALTIME))
+}
+
+/// Next hop towards `destination`. With `shortcut`, frames go straight to a destination this
+/// node hears directly with a strong signal, even if the tree routes them through another node.
+/// A suspect hop is skipped for the node behind it, see `Tree::mark_suspect`.
+async fn route(
+    tree: &'static asynchronous::Mutex<Tree>,
+    state: &'static asynchronous::Mutex<MeshState>,
+    destination: Node,
+    shortcut: bool,
+) -> Result<Node, TreeError> {
+    if shortcut
+        && state
+            .lock()
+            .await
+            .shortcuts
+            .is_shortcut(destination, asynchronous::now_ms())
+    {
+        return Ok(destination);
+    }
+    let t = tree.lock().await;
+    let next = t.next_hop(destination)?;
+    if t.is_suspect(next)
+        && let Some(beyond) = t.hop_beyond(next, destination)
+    {
+        return Ok(beyond);
+    }
+    Ok(next)
+}
+
+/// Counts the outcome of a send to `destination` towards its reachability.
+async fn record_send(
+    state: &asynchronous::Mutex<MeshState>,
+    destination: Node,
+    result: &Result<(), MeshError>,
+) {
+    state
+        .lock()
+        .await
+        .reachability
+        .record(destination, result.is_ok(), asynchronous::now_ms());
+}
+
+/// Builds the crash notice a panicking node sends to its uplink. Returns the uplink together
+/// with the serialized frame. Takes the locked state directly since the panic handler cannot
+/// await.
+pub fn crash_notice(
+    tree: &Tree,
+    state: &mut MeshState,
+    node: Node,
+) -> Result<(Node, MessageData), MeshError> {
+    let uplink = tree
+        .uplink()
+        .ok_or(MeshError::TreeError(TreeError::NoUplinkError))?;
+    let (sequence, options) = state.next_frame();
+    let msg = SendMessage::new(uplink, MessageContent::Crash(node), None, sequence);
+    let data = serialize_signed(msg, node, options)?;
+    Ok((uplink, data))
+}
+
+/// Serializes `msg` with `options`, tagging organization messages when a network key is set.
+/// `source` is the address of this node.
+fn serialize_signed(
+    mut msg: SendMessage,
+    source: Node,
+    options: FrameOptions,
+) -> Result<MessageData, MeshError> {
+    msg.network_id = options.network_id;
+    if options.timestamps {
+        msg.timestamp = Some(asynchronous::now_ms() as u32);
+    }
+    if let Some(key) = options.key {
+        if msg.is_organization() {
+            msg.session = options.session;
+            let data = msg
+                .authenticated_data(source)
+                .map_err(|e| MeshError::CryptoError(CryptoError::AuthenticatedDataError(e)))?;
+            msg.set_tag(crypto::message_tag(&key, &data));
+        } else {
+            msg.flags.insert(MessageFlags::ENCRYPTED);
+        }
+    }
+    msg.serialize().map_err(MeshError::SerializationError)
+}
+
+/// Rejects organization messages without a valid tag when a network key is set.
+fn verify_organization(msg: &ReceiveMessage, key: Option<NetworkKey>) -> Result<(), MeshError> {
+    let Some(key) = key else {
+        return Ok(());
+    };
+    let tag = msg
+        .tag
+        .ok_or(MeshError::CryptoError(CryptoError::MissingTagError))?;
+    let data = msg
+        .authenticated_data()
+        .map_err(|e| MeshError::CryptoError(CryptoError::AuthenticatedDataError(e)))?;
+    crypto::verify_message_tag(&key, &data, &tag).map_err(MeshError::CryptoError)
+}
+
+#[cfg_attr(feature = "hardware", embassy_executor::task)]
+async fn searcher_task(
+    spawner: asynchronous::Spawner,
     tree: &'static asynchronous::Mutex<Tree>,
+    state: &'static asynchronous::Mutex<MeshState>,
     link: &'static ActiveLink,
+    organize_queue: &'static asynchronous::Channel<ReceiveMessage, ORGANIZE_QUEUE_SIZE>,
+    config: MeshConfig,
 ) {
-    for (new_node, (parent, _)) in all_news {
-        let nodes = {
-            let t = tree.lock().await;
-            t.into_iter().collect::<Vec<_, { tree::MAX_LEAFS }>>()
-        };
-        for (node, parent) in nodes {
-            let content = MessageContent::UpsertEdge((Some(new_node), parent));
-            Mesh::send_content(link, tree, content, node).await;
+    if config.lead == LeadPolicy::Always && config.may_lead() {
+        take_lead(spawner, tree, state, link, organize_queue, config).await;
+        return;
+    }
+    let mut rounds = 0u8;
+    let mut election = Election::default();
+    loop {
+        election.may_lead = config.may_lead() && rounds >= config.lead_after_rounds;
+        election.defer = rounds
+            < config
+                .lead_after_rounds
+                .saturating_add(MAX_OUTRANKED_ROUNDS);
+        rounds = rounds.saturating_add(1);
+        let decision =
+            run_search_round(spawner, tree, state, link, organize_queue, &mut election).await;
+        match decision {
+            Ok(RoleDecision::Leader) => {
+                take_lead(spawner, tree, state, link, organize_queue, config).await;
+                break;
+            }
+            Ok(RoleDecision::Follower) => {
+                println!("follower");
+                state.lock().await.role.set(MeshRole::Follower);
+                release_kept_members(tree, state, link).await;
+                let uplink = tree.lock().await.uplink();
+                if let Some(uplink) = uplink {
+                    state.lock().await.record_event(MeshEvent::Joined(uplink));
+                    introduce(config.name, uplink, tree, state, link).await;
+                }
+                asynchronous::spawn(
+                    &spawner,
+                    follower_task(spawner, tree, state, link, organize_queue, config),
+                );
+                break;
+            }
+            Ok(RoleDecision::Refused(member, reason)) => {
+                println!("refused by mesh: {:?}", reason);
+                state
+                    .lock()
+                    .await
+                    .record_event(MeshEvent::JoinRefused(member, reason));
+                asynchronous::after(asynchronous::Duration::from_millis(reason.backoff_ms())).await;
+            }
+            Ok(RoleDecision::Timeout) => {}
+            Err(e) => println!("{}", e),
         }
-        if let Err(e) = tree.lock().await.upsert_edge(None, new_node) {
-            println!("{:?}", e);
-            continue;
+    }
+}
+
+/// Tells the leader through `uplink` which name this node was provisioned with.
+async fn introduce(
+    name: &str,
+    uplink: Node,
+    tree: &'static asynchronous::Mutex<Tree>,
+    state: &'static asynchronous::Mutex<MeshState>,
+    link: &'static ActiveLink,
+) {
+    if name.is_empty() {
+        return;
+    }
+    let mut short = NodeName::new();
+    for c in name.chars() {
+        if short.push(c).is_err() {
+            break;
         }
-        match parent {
-            None => {
-                send_initial_topology(new_node, tree, link).await;
+    }
+    let content = MessageContent::Introduce(link.address(), short);
+    if let Err(e) = Mesh::send_content(link, tree, state, content, uplink).await {
+        println!("{}", e);
+    }
+}
+
+/// Starts leading the mesh with the tree this node has, after winning the election of a search
+/// round, right after boot with `LeadPolicy::Always`, or when succeeding the leader.
+async fn take_lead(
+    spawner: asynchronous::Spawner,
+    tree: &'static asynchronous::Mutex<Tree>,
+    state: &'static asynchronous::Mutex<MeshState>,
+    link: &'static ActiveLink,
+    organize_queue: &'static asynchronous::Channel<ReceiveMessage, ORGANIZE_QUEUE_SIZE>,
+    config: MeshConfig,
+) {
+    println!("leader");
+    {
+        let mut state = state.lock().await;
+        state.leader = None;
+        state.role.set(MeshRole::Leader);
+        state.record_event(MeshEvent::BecameLeader);
+    }
+    if let Err(e) = asynchronous::spawn(
+        &spawner,
+        leader_task(spawner, tree, state, link, organize_queue, config),
+    ) {
+        println!("{}", e);
+    }
+}
+
+enum RoleDecision {
+    Leader,
+    Follower,
+    /// The mesh refused this node for the given reason, told by the given member.
+    Refused(Node, JoinRejection),
+    Timeout,
+}
+
+/// Whether a searching node leads once it hears other newcomers, and which it heard.
+#[derive(Default)]
+struct Election {
+    may_lead: bool,
+    /// Leaves the lead to higher ranked newcomers instead of taking it on the first Discovery.
+    defer: bool,
+    /// Set once another newcomer was heard in the current search round.
+    contested: bool,
+    /// Set once one outranked this node, see `outranks`. Kept for the whole search, as that one
+    /// stops sending its Discovery once it leads.
+    outranked: bool,
+}
+
+impl Election {
+    fn won(&self) -> bool {
+        self.contested && !self.outranked
+    }
+}
+
+async fn run_search_round(
+    spawner: asynchronous::Spawner,
+    tree: &'static asynchronous::Mutex<Tree>,
+    state: &'static asynchronous::Mutex<MeshState>,
+    link: &'static ActiveLink,
+    organize_queue: &'static asynchronous::Channel<ReceiveMessage, ORGANIZE_QUEUE_SIZE>,
+    election: &mut Election,
+) -> Result<RoleDecision, MeshError> {
+    send_discovery(link, state).await?;
+    election.contested = false;
+    let own = link.address();
+    match asynchronous::select(
+        asynchronous::after(asynchronous::jittered(asynchronous::Duration::from_millis(
+            SEARCH_ROUND_MS,
+        ))),
+        wait_for_invitation(organize_queue, tree, state, own, election),
+    )
+    .await
+    {
+        asynchronous::Either::First(_) if election.won() => Ok(RoleDecision::Leader),
+        asynchronous::Either::First(_) => Ok(RoleDecision::Timeout),
+        asynchronous::Either::Second(role) => Ok(role),
+    }
+}
+
+async fn send_discovery(
+    link: &ActiveLink,
+    state: &asynchronous::Mutex<MeshState>,
+) -> Result<(), MeshError> {
+    println!("discovery");
+    let ((sequence, options), capabilities) = {
+        let mut state = state.lock().await;
+        (state.next_frame(), state.capabilities)
+    };
+    let content = MessageContent::Discovery(capabilities);
+    let msg = SendMessage::new(Node::BROADCAST, content, None, sequence);
+    let data = serialize_signed(msg, link.address(), options)?;
+    link.send(data, Node::BROADCAST)
+        .await
+        .map_err(MeshError::LinkError)
+}
+
+/// Waits for the mesh to invite this node `own`. If it may lead, the Discovery of another
+/// newcomer makes this node lead right away, unless it has to defer to higher ranked ones.
+/// Then the Discovery is only recorded in `election`, which decides at the end of the round.
+async fn wait_for_invitation(
+    organize_queue: &'static asynchronous::Channel<ReceiveMessage, ORGANIZE_QUEUE_SIZE>,
+    tree: &'static asynchronous::Mutex<Tree>,
+    state: &'static asynchronous::Mutex<MeshState>,
+    own: Node,
+    election: &mut Election,
+) -> RoleDecision {
+    loop {
+        let recv_msg = organize_queue.my_recv().await;
+        match recv_msg.data {
+            MessageContent::Discovery(_) if election.may_lead && !election.defer => {
+                return RoleDecision::Leader;
             }
-            Some(p) => {
-                let content = MessageContent::RequestInitTopology(new_node);
-                Mesh::send_content(link, tree, content, p).await;
+            MessageContent::Discovery(capabilities) if election.may_lead => {
+                let priority = state.lock().await.capabilities.priority;
+                let other = (capabilities.priority, recv_msg.final_source);
+                election.contested = true;
+                election.outranked |= !outranks((priority, own), other);
+            }
+            MessageContent::UpsertEdge(edge, epoch) => {
+                apply_edge(
+                    tree,
+                    recv_msg.final_source,
+                    recv_msg.final_destination,
+                    edge,
+                    epoch,
+                )
+                .await;
+                // Placements a leader still sends to this node after it dropped its tree, e.g.
+                // when a partition merged back, attach nothing and are no invitation.
+                if tree.lock().await.uplink().is_some() {
+                    return RoleDecision::Follower;
+                }
+            }
+            MessageContent::Topology(chunk) => {
+                apply_topology_chunk(
+                    tree,
+                    state,
+                    recv_msg.final_source,
+                    recv_msg.final_destination,
+                    chunk,
+                )
+                .await;
+                return RoleDecision::Follower;
             }
+            MessageContent::JoinRejected(node, reason) if node == recv_msg.final_destination => {
+                return RoleDecision::Refused(recv_msg.final_source, reason);
+            }
+            _ => {}
         }
     }
 }
 
-async fn send_initial_topology(
-    new: Node,
+/// Whether a newcomer ranked `own` takes the lead over another one ranked `other`. The higher
+/// priority wins, then the lower address, like `Tree::successor`.
+fn outranks((priority, own): (u8, Node), (other_priority, other): (u8, Node)) -> bool {
+    priority > other_priority || (priority == other_priority && own.mac < other.mac)
+}
+
+/// Applies an edge sent by `source` to this node `own`. A missing node stands for the sender,
+/// a missing parent for the root of the sender and a parent equal to `own` attaches the sender
+/// as uplink. An edge older than the known placement of its node is dropped, see
+/// `Tree::upsert_edge_with_epoch`.
+async fn apply_edge(
     tree: &'static asynchronous::Mutex<Tree>,
-    link: &'static ActiveLink,
+    source: Node,
+    own: Node,
+    edge: Edge,
+    epoch: u32,
 ) {
-    let self_content = MessageContent::UpsertEdge((None, Some(new)));
-    Mesh::send_content(link, tree, self_content, new).await;
-    let nodes = {
-        let t = tree.lock().await;
-        t.into_iter().collect::<Vec<_, { tree::MAX_LEAFS }>>()
+    upsert_received_edge(&mut *tree.lock().await, source, own, edge, Some(epoch));
+}
+
+fn upsert_received_edge(
+    tree: &mut Tree,
+    source: Node,
+    own: Node,
+    (n, p): Edge,
+    epoch: Option<u32>,
+) {
+    let parent = match p {
+        None => Some(source),
+        Some(node) if node == own => None,
+        Some(node) => Some(node),
     };
-    for (node, parent) in nodes {
-        if node == new {
-            continue;
+    let new = match n {
+        None => source,
+        Some(node) => node,
+    };
+    if new == own {
+        // The leader moved this node, see `Tree::attach_below`.
+        if let Some(parent) = parent
+            && let Err(e) = tree.attach_below(parent)
+        {
+            println!("{}", e);
+        }
+        return;
+    }
+    let result = match epoch {
+        Some(epoch) => tree.upsert_edge_with_epoch(parent, new, epoch),
+        None => tree.upsert_edge(parent, new),
+    };
+    if let Err(e) = result {
+        println!("{}", e);
+    }
+    if n.is_none() && parent.is_none() {
+        tree.set_uplink(new);
+    }
+}
+
+/// Applies all edges of one chunk of the initial topology under a single lock, so the tree never
+/// holds half a chunk. Only the parent of a joining node sends chunks, so the sender becomes the
+/// uplink even if chunk 0, which attaches it, got lost.
+async fn apply_topology_chunk(
+    tree: &'static asynchronous::Mutex<Tree>,
+    state: &'static asynchronous::Mutex<MeshState>,
+    source: Node,
+    own: Node,
+    chunk: TopologyChunk,
+) {
+    let attach = (None, Some(own));
+    {
+        let mut tree = tree.lock().await;
+        if tree.uplink().is_none() && !chunk.edges.contains(&attach) {
+            upsert_received_edge(&mut tree, source, own, attach, None);
+        }
+        for edge in chunk.edges {
+            upsert_received_edge(&mut tree, source, own, edge, None);
+        }
+    }
+    state
+        .lock()
+        .await
+        .topology
+        .get_or_insert_with(|| TopologyTransfer::new(source))
+        .record(chunk.index, chunk.total);
+}
+
+/// Asks the parent to resend the initial topology chunks that have not arrived yet.
+async fn request_missing_topology(
+    tree: &'static asynchronous::Mutex<Tree>,
+    state: &'static asynchronous::Mutex<MeshState>,
+    link: &'static ActiveLink,
+) {
+    let (source, missing) = {
+        let mut state = state.lock().await;
+        let Some(transfer) = state.topology.as_mut() else {
+            return;
         };
-        let foreign_content = MessageContent::UpsertEdge((Some(node), parent));
-        Mesh::send_content(link, tree, foreign_content, new).await;
+        match transfer.next_request() {
+            Some(missing) => (transfer.source(), missing),
+            None => {
+                state.topology = None;
+                drop(state);
+                // Whatever the leader did not place again left the mesh meanwhile.
+                tree.lock().await.drop_detached();
+                return;
+            }
+        }
+    };
+    let content = MessageContent::RequestTopologyChunks(missing);
+    if let Err(e) = Mesh::send_content(link, tree, state, content, source).await {
+        println!("{}", e);
     }
 }
 
-#[cfg_attr(feature = "hardware", embassy_executor::task)]
-async fn follower_task(
-    spawner: asynchronous::Spawner,
-    tree: &'static asynchronous::Mutex<Tree>,
-    link: &'static ActiveLink,
-    organize_queue: &'static asynchronous::Channel<ReceiveMessage, ORGANIZE_QUEUE_SIZE>,
-) {
-    let mut news: Vec<(Node, i32), MAX_NEWS> = Vec::new();
-    loop {
-        let msg = organize_queue.my_recv().await;
-        match msg.data {
-            MessageContent::Discovery => match news.push((msg.final_source, msg.rssi)) {
-                Ok(_) => (),
-                Err((n, r)) => println!("{}", n),
-            },
-            MessageContent::RequestNews => {
-                for new in news.iter() {
-                    let content = MessageContent::SendNew(new.clone());
-                    Mesh::send_content(link, tree, content, msg.final_source).await;
-                }
-                Mesh::send_content(link, tree, MessageContent::FinSendNew, msg.final_source).await;
-            }
-            MessageContent::UpsertEdge((n, p)) => {
-                let parent = match p {
-                    None => Some(msg.final_source),
-                    Some(node) if node == msg.final_destination => None,
-                    Some(node) => Some(node),
-                };
-                let new = match n {
-                    None => msg.final_source,
-                    Some(node) => node,
-                };
-                tree.lock().await.upsert_edge(parent, new);
-            }
-            MessageContent::RequestInitTopology(n) => {
-                send_initial_topology(n, tree, link).await;
-            }
-            _ => (),
-        }
+#[cfg_attr(feature = "hardware", embassy_executor::task)]
+async fn leader_task(
+    spawner: asynchronous::Spawner,
+    tree: &'static asynchronous::Mutex<Tree>,
+    state: &'static asynchronous::Mutex<MeshState>,
+    link: &'static ActiveLink,
+    organize_queue: &'static asynchronous::Channel<ReceiveMessage, ORGANIZE_QUEUE_SIZE>,
+    config: MeshConfig,
+) {
+    let mut news: LinearMap<Node, Discovered, MAX_NEWS> = LinearMap::new();
+    let mut candidates: LinearMap<Node, Candidate, MAX_NEWS> = LinearMap::new();
+    let mut resync_limit = TopologyRateLimit::new();
+    let mut orphans: Vec<Node, MAX_LEAFS> = Vec::new();
+    let mut news_interval_ms = state.lock().await.network_config.news_round_interval_ms();
+    let mut ticker =
+        asynchronous::Ticker::every(asynchronous::Duration::from_millis(news_interval_ms));
+    // Beats on its own, as a news round may take longer than followers wait for the leader.
+    let mut beat_ticker = asynchronous::Ticker::every(asynchronous::Duration::from_millis(
+        LEADER_HEARTBEAT_INTERVAL_MS,
+    ));
+    send_leader_heartbeat(tree, state, link).await;
+
+    loop {
+        let ticks = asynchronous::select(ticker.next(), beat_ticker.next());
+        match asynchronous::select(organize_queue.my_recv(), ticks).await {
+            asynchronous::Either::First(msg) => match msg.data {
+                MessageContent::ConfigAck(version) => {
+                    state
+                        .lock()
+                        .await
+                        .record_config_ack(msg.final_source, version);
+                }
+                MessageContent::Crash(node) => {
+                    println!("{} crashed", node);
+                    state
+                        .lock()
+                        .await
+                        .record_event(MeshEvent::NodeCrashed(node));
+                }
+                MessageContent::RequestTopologyChunks(missing) => {
+                    send_initial_topology(msg.final_source, missing, tree, state, link).await;
+                }
+                MessageContent::EdgeAck(epoch) => {
+                    state.lock().await.record_edge_ack(msg.final_source, epoch);
+                }
+                MessageContent::RequestResync => {
+                    if resync_limit.allow(msg.final_source, asynchronous::now_ms()) {
+                        resync_member(msg.final_source, tree, state, link).await;
+                    }
+                }
+                MessageContent::Leave(node) => {
+                    let removed = prune_leaving(node, tree, state).await;
+                    adopt_later(&mut orphans, &removed);
+                    announce_leave(node, tree, state, link).await;
+                }
+                MessageContent::Introduce(node, name) => {
+                    let t = tree.lock().await;
+                    let now_ms = asynchronous::now_ms();
+                    state
+                        .lock()
+                        .await
+                        .roster
+                        .record_name(&t, node, name, now_ms);
+                }
+                MessageContent::LinkReport(node, report) => {
+                    let t = tree.lock().await;
+                    state.lock().await.record_link_report(&t, node, report);
+                }
+                MessageContent::BootHealthy(node, capabilities) => {
+                    {
+                        let t = tree.lock().await;
+                        let mut s = state.lock().await;
+                        s.record_capabilities(&t, node, capabilities);
+                        s.record_event(MeshEvent::BootConfirmed(node));
+                    }
+                    let content = MessageContent::BootConfirmed;
+                    if let Err(e) = Mesh::send_content(link, tree, state, content, node).await {
+                        println!("{}", e);
+                    }
+                }
+                // Only handed over while this node leads a partition, see `dispatch`.
+                MessageContent::Heartbeat(..) => {
+                    remerge(
+                        msg.final_source,
+                        spawner,
+                        tree,
+                        state,
+                        link,
+                        organize_queue,
+                        config,
+                    )
+                    .await;
+                    return;
+                }
+                _ => handle_leader_message(&mut news, msg),
+            },
+            asynchronous::Either::Second(asynchronous::Either::First(_)) => {
+                process_news_round(
+                    &news,
+                    &mut candidates,
+                    &config,
+                    tree,
+                    state,
+                    link,
+                    organize_queue,
+                )
+                .await;
+                news.clear();
+                distribute_network_config(tree, state, link).await;
+                resend_unacked_edges(tree, state, link).await;
+                reparent_degraded(tree, state, link).await;
+                prune_stale(tree, state, link, config.stale_after_ms, &mut orphans).await;
+                adopt_orphans(&mut orphans, &config, tree, state, link).await;
+                let handover = state.lock().await.handover.take();
+                if let Some(target) = handover
+                    && hand_over_lead(target, spawner, tree, state, link, organize_queue, config)
+                        .await
+                {
+                    return;
+                }
+                let interval_ms = state.lock().await.network_config.news_round_interval_ms();
+                if interval_ms != news_interval_ms {
+                    news_interval_ms = interval_ms;
+                    ticker = asynchronous::Ticker::every(asynchronous::Duration::from_millis(
+                        news_interval_ms,
+                    ));
+                }
+            }
+            asynchronous::Either::Second(asynchronous::Either::Second(_)) => {
+                send_leader_heartbeat(tree, state, link).await;
+            }
+        }
+    }
+}
+
+/// Hands the lead over to the member `target`, see `Mesh::hand_over_leadership`. The target gets
+/// every placement again first, so it leads with the tree of this node, then every member is
+/// told, the target last. Returns true if this node follows the target from now on.
+async fn hand_over_lead(
+    target: Node,
+    spawner: asynchronous::Spawner,
+    tree: &'static asynchronous::Mutex<Tree>,
+    state: &'static asynchronous::Mutex<MeshState>,
+    link: &'static ActiveLink,
+    organize_queue: &'static asynchronous::Channel<ReceiveMessage, ORGANIZE_QUEUE_SIZE>,
+    config: MeshConfig,
+) -> bool {
+    if !tree.lock().await.contains(target) {
+        println!("{} left before the lead was handed over", target);
+        return false;
+    }
+    println!("handing the lead over to {}", target);
+    resync_member(target, tree, state, link).await;
+    let (mut members, epoch) = {
+        let t = tree.lock().await;
+        let members = t
+            .into_iter()
+            .map(|(member, _)| member)
+            .filter(|member| *member != target)
+            .collect::<Vec<_, MAX_LEAFS>>();
+        (members, t.version())
+    };
+    let _ = members.push(target);
+    for member in members {
+        let content = MessageContent::LeaderHandover(target, epoch);
+        if let Err(e) = Mesh::send_content(link, tree, state, content, member).await {
+            println!("{}", e);
+        }
+    }
+    if let Err(e) = tree.lock().await.turn_towards(Some(target)) {
+        println!("{}", e);
+        return false;
+    }
+    {
+        let mut state = state.lock().await;
+        state.partitioned_from = None;
+        state.role.set(MeshRole::Follower);
+        follow(&mut state, target);
+    }
+    if let Err(e) = asynchronous::spawn(
+        &spawner,
+        follower_task(spawner, tree, state, link, organize_queue, config),
+    ) {
+        println!("{}", e);
+    }
+    true
+}
+
+/// Follows `leader`, which the lead was handed over to.
+fn follow(state: &mut MeshState, leader: Node) {
+    state.leader = Some(leader);
+    // The new leader may never have been heard directly, its heartbeats start the clock.
+    state.liveness.record(leader, asynchronous::now_ms());
+    state.record_event(MeshEvent::LeaderHandedOver(leader));
+}
+
+/// Sends the current network config to every member that has not acknowledged it yet.
+async fn distribute_network_config(
+    tree: &'static asynchronous::Mutex<Tree>,
+    state: &'static asynchronous::Mutex<MeshState>,
+    link: &'static ActiveLink,
+) {
+    let config = state.lock().await.network_config;
+    if config.version == 0 {
+        return;
+    }
+    let nodes = {
+        let t = tree.lock().await;
+        t.into_iter().collect::<Vec<_, MAX_LEAFS>>()
+    };
+    for (node, _) in nodes {
+        if state.lock().await.has_acked_config(node) {
+            continue;
+        }
+        let content = MessageContent::Config(config);
+        if let Err(e) = Mesh::send_content(link, tree, state, content, node).await {
+            println!("{}", e);
+        }
+    }
+}
+
+/// Sends every placement a member has not acknowledged again, so a lost `UpsertEdge` leaves
+/// the member with a stale tree for at most one news round. A placement that was replaced by a
+/// newer one in the meantime is dropped, the newer one is pending itself.
+async fn resend_unacked_edges(
+    tree: &'static asynchronous::Mutex<Tree>,
+    state: &'static asynchronous::Mutex<MeshState>,
+    link: &'static ActiveLink,
+) {
+    let members = {
+        let t = tree.lock().await;
+        t.into_iter()
+            .map(|(node, _)| node)
+            .collect::<Vec<_, MAX_LEAFS>>()
+    };
+    let pending = {
+        let mut state = state.lock().await;
+        state
+            .edge_acks
+            .retain(|node, pending| !pending.is_empty() && members.contains(node));
+        state
+            .edge_acks
+            .iter()
+            .map(|(node, pending)| (*node, *pending))
+            .collect::<Vec<_, MAX_LEAFS>>()
+    };
+    for (member, pending) in pending {
+        for epoch in pending.missing() {
+            let placement = {
+                let t = tree.lock().await;
+                t.into_iter()
+                    .find(|(node, _)| *node != member && t.epoch(*node) == Some(epoch))
+            };
+            let Some((node, parent)) = placement else {
+                state.lock().await.record_edge_ack(member, epoch);
+                continue;
+            };
+            let content = MessageContent::UpsertEdge((Some(node), parent), epoch);
+            if let Err(e) = Mesh::send_content(link, tree, state, content, member).await {
+                println!("{}", e);
+            }
+        }
+    }
+}
+
+/// A node that has been reported by at least one member but has not been attached yet.
+struct Candidate {
+    reports: ParentReports,
+    rounds: u8,
+    silent_rounds: u8,
+}
+
+/// Inserts `node` into `map`. If the map is full the entry with the weakest RSSI is evicted,
+/// provided it is weaker than the new one. Returns the node that did not make it, if any.
+fn insert_evicting_weakest<V, const N: usize>(
+    map: &mut LinearMap<Node, V, N>,
+    node: Node,
+    value: V,
+    rssi: fn(&V) -> i32,
+) -> Option<Node> {
+    let (node, value) = match map.insert(node, value) {
+        Ok(_) => return None,
+        Err(rejected) => rejected,
+    };
+    let weakest = map
+        .iter()
+        .min_by_key(|(_, v)| rssi(v))
+        .map(|(n, v)| (*n, rssi(v)));
+    match weakest {
+        Some((weakest, weakest_rssi)) if weakest_rssi < rssi(&value) => {
+            map.remove(&weakest);
+            let _ = map.insert(node, value);
+            Some(weakest)
+        }
+        _ => Some(node),
+    }
+}
+
+fn handle_leader_message(news: &mut LinearMap<Node, Discovered, MAX_NEWS>, msg: ReceiveMessage) {
+    if let MessageContent::Discovery(capabilities) = msg.data {
+        record_discovery(news, msg.final_source, msg.rssi, capabilities);
+    }
+}
+
+async fn process_news_round(
+    news: &LinearMap<Node, Discovered, MAX_NEWS>,
+    candidates: &mut LinearMap<Node, Candidate, MAX_NEWS>,
+    config: &MeshConfig,
+    tree: &'static asynchronous::Mutex<Tree>,
+    state: &'static asynchronous::Mutex<MeshState>,
+    link: &'static ActiveLink,
+    organize_queue: &'static asynchronous::Channel<ReceiveMessage, ORGANIZE_QUEUE_SIZE>,
+) {
+    let mut all_news = LinearMap::new();
+    collect_local_news(news, &mut all_news);
+    collect_remote_news(&mut all_news, tree, state, link, organize_queue).await;
+    for (node, reports) in &all_news {
+        if reports.capabilities.firmware < config.min_firmware {
+            let reporter = reports.strongest().and_then(|(parent, _)| parent);
+            let reason = JoinRejection::OutdatedFirmware;
+            refuse_join(*node, reporter, reason, tree, state, link).await;
+        }
+    }
+    let admitted = select_admissions(all_news, candidates, config);
+    send_topology_updates(admitted, config, tree, state, link).await;
+}
+
+/// Merges this round's reports into the candidate list and returns the candidates that have
+/// waited out the grace period with a link at least as strong as `min_join_rssi`. Candidates
+/// that were not heard this round are carried over and only forgotten after
+/// `MAX_SILENT_ROUNDS`, so a joiner evicted from one round's news still gets its turn.
+fn select_admissions(
+    all_news: LinearMap<Node, ParentReports, MAX_NEWS>,
+    candidates: &mut LinearMap<Node, Candidate, MAX_NEWS>,
+    config: &MeshConfig,
+) -> LinearMap<Node, ParentReports, MAX_NEWS> {
+    for (node, candidate) in candidates.iter_mut() {
+        candidate.rounds = candidate.rounds.saturating_add(1);
+        if !all_news.contains_key(node) {
+            candidate.silent_rounds = candidate.silent_rounds.saturating_add(1);
+        }
+    }
+    candidates.retain(|_, candidate| candidate.silent_rounds <= MAX_SILENT_ROUNDS);
+    for (node, reports) in all_news {
+        match candidates.get_mut(&node) {
+            Some(candidate) => {
+                candidate.reports.merge(&reports);
+                candidate.silent_rounds = 0;
+            }
+            None => {
+                let candidate = Candidate {
+                    reports,
+                    rounds: 0,
+                    silent_rounds: 0,
+                };
+                if let Some(n) = insert_evicting_weakest(candidates, node, candidate, |c| {
+                    c.reports.strongest_rssi()
+                }) {
+                    println!("evicted candidate {}", n);
+                }
+            }
+        }
+    }
+    candidates
+        .retain(|_, candidate| candidate.reports.capabilities.firmware >= config.min_firmware);
+    let mut admitted = LinearMap::new();
+    for (node, candidate) in candidates.iter() {
+        if candidate.rounds >= config.join_grace_rounds
+            && candidate.reports.strongest_rssi() >= config.min_join_rssi
+        {
+            let _ = admitted.insert(*node, candidate.reports.clone());
+        }
+    }
+    candidates.retain(|node, _| !admitted.contains_key(node));
+    admitted
+}
+
+fn collect_local_news(
+    news: &LinearMap<Node, Discovered, MAX_NEWS>,
+    all_news: &mut LinearMap<Node, ParentReports, MAX_NEWS>,
+) {
+    for (node, discovered) in news {
+        let mut reports = ParentReports::new().with_capabilities(discovered.capabilities);
+        reports.record_heard(None, discovered.rssi, discovered.heard);
+        if let Some(n) = insert_evicting_weakest(all_news, *node, reports, |r| r.strongest_rssi()) {
+            println!("evicted news of {}", n);
+        }
+    }
+}
+
+async fn collect_remote_news(
+    all_news: &mut LinearMap<Node, ParentReports, MAX_NEWS>,
+    tree: &'static asynchronous::Mutex<Tree>,
+    state: &'static asynchronous::Mutex<MeshState>,
+    link: &'static ActiveLink,
+    organize_queue: &'static asynchronous::Channel<ReceiveMessage, ORGANIZE_QUEUE_SIZE>,
+) {
+    let nodes = {
+        let t = tree.lock().await;
+        t.into_iter().collect::<Vec<_, MAX_LEAFS>>()
+    };
+    let mut deferred = Vec::<_, ORGANIZE_QUEUE_SIZE>::new();
+    for (node, parent) in nodes {
+        Mesh::send_content(link, tree, state, MessageContent::RequestNews, node).await;
+        loop {
+            match asynchronous::select(
+                organize_queue.my_recv(),
+                asynchronous::after(asynchronous::Duration::from_millis(
+                    NEWS_RESPONSE_TIMEOUT_MS,
+                )),
+            )
+            .await
+            {
+                asynchronous::Either::First(response) => {
+                    if !handle_news_response(all_news, node, response, &mut deferred) {
+                        break;
+                    }
+                }
+                asynchronous::Either::Second(_) => break,
+            }
+        }
+    }
+    for msg in deferred {
+        if organize_queue.my_try_send(msg).is_err() {
+            println!("dropping organization message deferred during news");
+        }
+    }
+}
+
+/// Handles one message that arrived while waiting for the news of `parent`. A Discovery the
+/// leader hears itself in the meantime counts as local news, otherwise collecting from many
+/// members would swallow every newcomer in direct range. Anything else is `deferred` until the
+/// news are collected.
+fn handle_news_response(
+    all_news: &mut LinearMap<Node, ParentReports, MAX_NEWS>,
+    parent: Node,
+    response: ReceiveMessage,
+    deferred: &mut Vec<ReceiveMessage, ORGANIZE_QUEUE_SIZE>,
+) -> bool {
+    match response.data {
+        MessageContent::SendNew((node, rssi), heard, capabilities) => {
+            merge_news(all_news, node, Some(parent), rssi, heard, capabilities);
+            true
+        }
+        MessageContent::Discovery(capabilities) => {
+            let node = response.final_source;
+            merge_news(all_news, node, None, response.rssi, 1, capabilities);
+            true
+        }
+        MessageContent::FinSendNew => false,
+        _ => {
+            if deferred.push(response).is_err() {
+                println!("dropping organization message during news");
+            }
+            true
+        }
+    }
+}
+
+/// Adds the report of `node` by `parent`, which heard `heard` of its Discoveries, to the news of
+/// this round.
+fn merge_news(
+    all_news: &mut LinearMap<Node, ParentReports, MAX_NEWS>,
+    node: Node,
+    parent: Option<Node>,
+    rssi: i32,
+    heard: u16,
+    capabilities: Capabilities,
+) {
+    match all_news.get_mut(&node) {
+        Some(reports) => {
+            reports.record_heard(parent, rssi, heard);
+            reports.capabilities = capabilities;
+        }
+        None => {
+            let mut reports = ParentReports::new().with_capabilities(capabilities);
+            reports.record_heard(parent, rssi, heard);
+            if let Some(n) =
+                insert_evicting_weakest(all_news, node, reports, |r| r.strongest_rssi())
+            {
+                println!("evicted news of {}", n);
+            }
+        }
+    }
+}
+
+async fn send_topology_updates(
+    admitted: LinearMap<Node, ParentReports, MAX_NEWS>,
+    config: &MeshConfig,
+    tree: &'static asynchronous::Mutex<Tree>,
+    state: &'static asynchronous::Mutex<MeshState>,
+    link: &'static ActiveLink,
+) {
+    for (new_node, reports) in admitted {
+        let placement = place(
+            &*tree.lock().await,
+            &*state.lock().await,
+            new_node,
+            &reports,
+            config.parent_selection,
+            &config.fan_out,
+        );
+        let parent = match placement {
+            Placement::Below(parent) => parent,
+            Placement::Refused(reporter, reason) => {
+                refuse_join(new_node, reporter, reason, tree, state, link).await;
+                continue;
+            }
+            Placement::Deferred => {
+                println!("no parent chosen for {}", new_node);
+                continue;
+            }
+        };
+        let epoch = tree.lock().await.next_version();
+        announce_edge(new_node, parent, epoch, tree, state, link).await;
+        let result = tree
+            .lock()
+            .await
+            .upsert_edge_with_epoch(parent, new_node, epoch);
+        if let Err(e) = result {
+            println!("{:?}", e);
+            continue;
+        }
+        {
+            let t = tree.lock().await;
+            let mut s = state.lock().await;
+            s.record_capabilities(&t, new_node, reports.capabilities);
+            s.roster.record_join(&t, new_node, asynchronous::now_ms());
+            s.record_event(MeshEvent::NodeAttached(new_node));
+            s.boot_confirmed.set(true);
+        }
+        match parent {
+            None => {
+                send_initial_topology(new_node, ChunkBitmap::MAX, tree, state, link).await;
+            }
+            Some(p) => {
+                let content = MessageContent::RequestInitTopology(new_node);
+                Mesh::send_content(link, tree, state, content, p).await;
+            }
+        }
+    }
+}
+
+/// Tells every member that `node` now hangs below `parent` since `epoch`, and waits for each of
+/// them to acknowledge it.
+async fn announce_edge(
+    node: Node,
+    parent: Option<Node>,
+    epoch: u32,
+    tree: &'static asynchronous::Mutex<Tree>,
+    state: &'static asynchronous::Mutex<MeshState>,
+    link: &'static ActiveLink,
+) {
+    let members = {
+        let t = tree.lock().await;
+        t.into_iter()
+            .map(|(member, _)| member)
+            .collect::<Vec<_, MAX_LEAFS>>()
+    };
+    for member in members {
+        let content = MessageContent::UpsertEdge((Some(node), parent), epoch);
+        if let Err(e) = Mesh::send_content(link, tree, state, content, member).await {
+            println!("{}", e);
+        }
+        state.lock().await.record_edge_sent(member, epoch);
+    }
+}
+
+/// Sends `member` every placement of the leader's tree again, stamped with the epoch it was
+/// made at, parents before their children. Placements the member already has are rejected as
+/// stale on its side, the ones it missed are applied.
+async fn resync_member(
+    member: Node,
+    tree: &'static asynchronous::Mutex<Tree>,
+    state: &'static asynchronous::Mutex<MeshState>,
+    link: &'static ActiveLink,
+) {
+    let placements = {
+        let t = tree.lock().await;
+        t.into_iter()
+            .filter(|(node, _)| *node != link.address())
+            .map(|(node, parent)| (node, parent, t.epoch(node).unwrap_or(0)))
+            .collect::<Vec<_, MAX_LEAFS>>()
+    };
+    println!("resyncing {} with {} placements", member, placements.len());
+    for (node, parent, epoch) in placements {
+        let content = MessageContent::UpsertEdge((Some(node), parent), epoch);
+        if let Err(e) = Mesh::send_content(link, tree, state, content, member).await {
+            println!("{}", e);
+        }
+    }
+}
+
+/// Moves every member whose latest link report shows a degraded uplink to a better parent, see
+/// `better_parent`. Each report is used once, the member reports again from its new place.
+async fn reparent_degraded(
+    tree: &'static asynchronous::Mutex<Tree>,
+    state: &'static asynchronous::Mutex<MeshState>,
+    link: &'static ActiveLink,
+) {
+    let reports = {
+        let mut state = state.lock().await;
+        let fresh = core::mem::take(&mut state.fresh_reports);
+        fresh
+            .into_iter()
+            .filter_map(|node| Some((node, state.link_reports.get(&node)?.clone())))
+            .collect::<Vec<_, MAX_LEAFS>>()
+    };
+    for (node, report) in reports.iter() {
+        let parent = better_parent(&*tree.lock().await, link.address(), *node, report);
+        let Some(parent) = parent else {
+            continue;
+        };
+        println!("moving {} below {:?}", node, parent);
+        let epoch = tree.lock().await.next_version();
+        announce_edge(*node, parent, epoch, tree, state, link).await;
+        let result = tree
+            .lock()
+            .await
+            .upsert_edge_with_epoch(parent, *node, epoch);
+        match result {
+            Ok(()) => state
+                .lock()
+                .await
+                .record_event(MeshEvent::NodeAttached(*node)),
+            Err(e) => println!("{}", e),
+        }
+    }
+}
+
+/// Parent the leader moves `node` to, `None` if it stays. Only a node that hears its uplink
+/// below `REPARENT_BELOW_RSSI` moves, to the strongest member in its `report` that is heard at
+/// least `REPARENT_MARGIN` better, does not hang below `node` and has room left. `leader` is
+/// the address of this node, which stands for `None` in the tree.
+fn better_parent(
+    tree: &Tree,
+    leader: Node,
+    node: Node,
+    report: &LinkReport,
+) -> Option<Option<Node>> {
+    let current = tree.parent(node).ok()?;
+    let uplink = current.unwrap_or(leader);
+    let uplink_rssi = report
+        .iter()
+        .find(|(neighbor, _)| *neighbor == uplink)
+        .map_or(i32::MIN, |(_, rssi)| *rssi);
+    if uplink_rssi >= REPARENT_BELOW_RSSI {
+        return None;
+    }
+    report
+        .iter()
+        .filter(|(_, rssi)| *rssi >= uplink_rssi.saturating_add(REPARENT_MARGIN))
+        .map(|(neighbor, _)| (*neighbor != leader).then_some(*neighbor))
+        .find(|parent| {
+            *parent != current
+                && parent
+                    .is_none_or(|p| p != node && tree.is_downstream(p) && !tree.is_below(node, p))
+                && tree.has_room_below(*parent)
+        })
+}
+
+/// Queues the direct children of a removed relay for `adopt_orphans`. Their subtrees move with
+/// them.
+fn adopt_later(orphans: &mut Vec<Node, MAX_LEAFS>, removed: &Orphans) {
+    for (node, _) in removed.iter().filter(|(_, parent)| parent.is_none()) {
+        if !orphans.contains(node) {
+            let _ = orphans.push(*node);
+        }
+    }
+}
+
+/// Places every orphan again instead of leaving it to time out and search on its own. Its
+/// latest link report offers the members it hears to the parent selection of `config`, like the
+/// reports of a newcomer, see `adoption_reports`. Without a usable report it stays where
+/// `OrphanPolicy::Promote` put it. Either way the placement is announced with a new epoch, so
+/// the orphan and its new parent learn about it.
+async fn adopt_orphans(
+    orphans: &mut Vec<Node, MAX_LEAFS>,
+    config: &MeshConfig,
+    tree: &'static asynchronous::Mutex<Tree>,
+    state: &'static asynchronous::Mutex<MeshState>,
+    link: &'static ActiveLink,
+) {
+    for orphan in core::mem::take(orphans) {
+        let parent = {
+            let t = tree.lock().await;
+            let s = state.lock().await;
+            let Ok(promoted) = t.parent(orphan) else {
+                continue;
+            };
+            let reports = s.link_reports.get(&orphan).map(|report| {
+                let capabilities = s.capabilities_of(Some(orphan)).unwrap_or_default();
+                adoption_reports(&t, link.address(), orphan, report).with_capabilities(capabilities)
+            });
+            let placement = reports.map(|reports| {
+                place(
+                    &t,
+                    &s,
+                    orphan,
+                    &reports,
+                    config.parent_selection,
+                    &config.fan_out,
+                )
+            });
+            match placement {
+                Some(Placement::Below(parent)) => parent,
+                _ => promoted,
+            }
+        };
+        println!("adopting {} below {:?}", orphan, parent);
+        // Placed before it is announced, unlike `reparent_degraded`, the old parent is gone and
+        // the announcement has to reach the orphan through the new one.
+        let epoch = tree.lock().await.next_version();
+        let result = tree
+            .lock()
+            .await
+            .upsert_edge_with_epoch(parent, orphan, epoch);
+        if let Err(e) = result {
+            println!("{}", e);
+            continue;
+        }
+        state
+            .lock()
+            .await
+            .record_event(MeshEvent::NodeAttached(orphan));
+        announce_edge(orphan, parent, epoch, tree, state, link).await;
+        // The new parent may still route the announcement through the old one, the topology it
+        // sends like to a newcomer makes it the uplink of the orphan either way.
+        match parent {
+            None => send_initial_topology(orphan, ChunkBitmap::MAX, tree, state, link).await,
+            Some(p) => {
+                let content = MessageContent::RequestInitTopology(orphan);
+                if let Err(e) = Mesh::send_content(link, tree, state, content, p).await {
+                    println!("{}", e);
+                }
+            }
+        }
+    }
+}
+
+/// The members `orphan` reported hearing, as parent reports for `place`. Nodes that left the
+/// tree or hang below `orphan` are skipped. `leader` is the address of this node, which stands
+/// for `None` in the tree.
+fn adoption_reports(tree: &Tree, leader: Node, orphan: Node, report: &LinkReport) -> ParentReports {
+    let mut reports = ParentReports::new();
+    for &(neighbor, rssi) in report {
+        if neighbor == leader {
+            reports.record(None, rssi);
+        } else if tree.is_downstream(neighbor) && !tree.is_below(orphan, neighbor) {
+            reports.record(Some(neighbor), rssi);
+        }
+    }
+    reports
+}
+
+/// Where the leader attaches an admitted node.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+enum Placement {
+    /// Below the given parent, `None` for the leader.
+    Below(Option<Node>),
+    /// The mesh has no room for the newcomer, refused through the member that heard it the
+    /// loudest.
+    Refused(Option<Node>, JoinRejection),
+    /// The policy passed on every candidate, or none of them can take a known node that moves.
+    Deferred,
+}
+
+/// Offers every reporting member that can take another child to `policy`, together with what
+/// the newcomer advertised. Members that reached their `fan_out` are only offered if no other
+/// one is left. A newcomer is refused once the tree reached `MESH_FULL_WATERMARK` or no
+/// reporting member has room left. Known nodes may always move.
+fn place(
+    tree: &Tree,
+    state: &MeshState,
+    node: Node,
+    reports: &ParentReports,
+    policy: &dyn ParentSelectionPolicy,
+    fan_out: &FanOutLimit,
+) -> Placement {
+    let moving = tree.is_downstream(node);
+    let reporter = reports.strongest().and_then(|(parent, _)| parent);
+    if !moving && tree.node_count() >= MESH_FULL_WATERMARK {
+        return Placement::Refused(reporter, JoinRejection::MeshFull);
+    }
+    let candidates = reports
+        .iter()
+        .filter(|(parent, _)| *parent != Some(node) && tree.has_room_below(*parent))
+        .map(|(parent, rssi)| ParentCandidate {
+            parent,
+            rssi,
+            load: tree.descendants(parent).unwrap_or(0),
+            depth: tree.depth(parent).unwrap_or(0),
+            heard: reports.heard(parent),
+            capabilities: state.capabilities_of(parent),
+        })
+        .collect::<Vec<_, MAX_PARENT_REPORTS>>();
+    let below_fan_out = |candidate: &&ParentCandidate| {
+        candidate.parent.is_none_or(|parent| {
+            let (uplink_rssi, battery) = state.link_budget(tree, parent);
+            let children = tree.children(Some(parent)).map_or(0, |children| {
+                children.iter().filter(|c| **c != node).count()
+            });
+            children < fan_out.max_children(uplink_rssi, battery)
+        })
+    };
+    let spilled = candidates
+        .iter()
+        .filter(below_fan_out)
+        .copied()
+        .collect::<Vec<_, MAX_PARENT_REPORTS>>();
+    let offered = if spilled.is_empty() {
+        &candidates
+    } else {
+        &spilled
+    };
+    match (candidates.is_empty(), moving) {
+        (true, false) => Placement::Refused(reporter, JoinRejection::NoRoom),
+        (true, true) => Placement::Deferred,
+        (false, _) => policy
+            .select(node, &reports.capabilities, offered)
+            .map_or(Placement::Deferred, |chosen| {
+                Placement::Below(chosen.parent)
+            }),
+    }
+}
+
+/// Tells `joiner` why the mesh does not admit it, so it can back off or look for another mesh
+/// instead of waiting for a topology that never comes. A joiner reported by a member is told by
+/// that member, since only it can reach the joiner.
+async fn refuse_join(
+    joiner: Node,
+    parent: Option<Node>,
+    reason: JoinRejection,
+    tree: &'static asynchronous::Mutex<Tree>,
+    state: &'static asynchronous::Mutex<MeshState>,
+    link: &'static ActiveLink,
+) {
+    println!("refusing {}: {:?}", joiner, reason);
+    state
+        .lock()
+        .await
+        .record_event(MeshEvent::JoinRejected(joiner, reason));
+    let result = match parent {
+        None => send_join_rejected(joiner, reason, state, link).await,
+        Some(p) => {
+            let content = MessageContent::JoinRejected(joiner, reason);
+            Mesh::send_content(link, tree, state, content, p).await
+        }
+    };
+    if let Err(e) = result {
+        println!("{}", e);
+    }
+}
+
+/// Sends the refusal straight to `joiner`, a neighbor that is not part of the tree.
+async fn send_join_rejected(
+    joiner: Node,
+    reason: JoinRejection,
+    state: &'static asynchronous::Mutex<MeshState>,
+    link: &'static ActiveLink,
+) -> Result<(), MeshError> {
+    let (sequence, options) = {
+        let mut state = state.lock().await;
+        state.next_frame()
+    };
+    let content = MessageContent::JoinRejected(joiner, reason);
+    let msg = SendMessage::new(joiner, content, None, sequence);
+    let data = serialize_signed(msg, link.address(), options)?;
+    link.send(data, joiner).await.map_err(MeshError::LinkError)
+}
+
+/// Sends the chunks of the initial topology whose bit is set in `missing` to the newly
+/// attached child `new`, split by `TopologyChunks`.
+async fn send_initial_topology(
+    new: Node,
+    missing: ChunkBitmap,
+    tree: &'static asynchronous::Mutex<Tree>,
+    state: &'static asynchronous::Mutex<MeshState>,
+    link: &'static ActiveLink,
+) {
+    let mut edges: Vec<Edge, MAX_LEAFS> = Vec::new();
+    let _ = edges.push((None, Some(new)));
+    {
+        let t = tree.lock().await;
+        for (node, parent) in t.into_iter().filter(|(node, _)| *node != new) {
+            let _ = edges.push((Some(node), parent));
+        }
+    }
+    for chunk in TopologyChunks::new(&edges) {
+        if missing & (1 << chunk.index) == 0 {
+            continue;
+        }
+        let content = MessageContent::Topology(chunk);
+        if let Err(e) = Mesh::send_content(link, tree, state, content, new).await {
+            println!("{}", e);
+        }
+    }
+}
+
+#[cfg_attr(feature = "hardware", embassy_executor::task)]
+async fn follower_task(
+    spawner: asynchronous::Spawner,
+    tree: &'static asynchronous::Mutex<Tree>,
+    state: &'static asynchronous::Mutex<MeshState>,
+    link: &'static ActiveLink,
+    organize_queue: &'static asynchronous::Channel<ReceiveMessage, ORGANIZE_QUEUE_SIZE>,
+    config: MeshConfig,
+) {
+    let role = config.role;
+    let mut news: LinearMap<Node, Discovered, MAX_NEWS> = LinearMap::new();
+    let mut topology_limit = TopologyRateLimit::new();
+    loop {
+        if let Some(uplink) = lost_uplink(tree, state).await {
+            let leader = state.lock().await.leader;
+            if leader != Some(uplink) {
+                lead_partition(uplink, spawner, tree, state, link, organize_queue, config).await;
+                return;
+            }
+            if fail_over(uplink, spawner, tree, state, link, organize_queue, config).await {
+                return;
+            }
+        }
+        if let Some(leader) = lost_leader(state).await
+            && fail_over(leader, spawner, tree, state, link, organize_queue, config).await
+        {
+            return;
+        }
+        let msg = match asynchronous::select(
+            organize_queue.my_recv(),
+            asynchronous::after(asynchronous::Duration::from_millis(
+                TOPOLOGY_CHUNK_TIMEOUT_MS,
+            )),
+        )
+        .await
+        {
+            asynchronous::Either::First(msg) => msg,
+            asynchronous::Either::Second(_) => {
+                request_missing_topology(tree, state, link).await;
+                continue;
+            }
+        };
+        match msg.data {
+            MessageContent::Discovery(capabilities) if role == NodeRole::Member => {
+                record_discovery(&mut news, msg.final_source, msg.rssi, capabilities);
+                let node = msg.final_source;
+                if config.fast_rejoin
+                    && tree.lock().await.is_downstream(node)
+                    && topology_limit.allow(node, asynchronous::now_ms())
+                {
+                    readmit(node, tree, state, link).await;
+                }
+            }
+            MessageContent::RequestNews => {
+                for (node, discovered) in news.iter() {
+                    let news = (*node, discovered.rssi);
+                    let content =
+                        MessageContent::SendNew(news, discovered.heard, discovered.capabilities);
+                    Mesh::send_content(link, tree, state, content, msg.final_source).await;
+                }
+                Mesh::send_content(
+                    link,
+                    tree,
+                    state,
+                    MessageContent::FinSendNew,
+                    msg.final_source,
+                )
+                .await;
+                news.clear();
+            }
+            MessageContent::UpsertEdge(edge, epoch) => {
+                let in_sync = tree.lock().await.observe_version(epoch);
+                apply_edge(tree, msg.final_source, msg.final_destination, edge, epoch).await;
+                if let (Some(node), _) = edge {
+                    state
+                        .lock()
+                        .await
+                        .record_event(MeshEvent::NodeAttached(node));
+                }
+                let content = MessageContent::EdgeAck(epoch);
+                if let Err(e) =
+                    Mesh::send_content(link, tree, state, content, msg.final_source).await
+                {
+                    println!("{}", e);
+                }
+                if !in_sync {
+                    println!(
+                        "Missed placements before epoch {}, requesting resync",
+                        epoch
+                    );
+                    let content = MessageContent::RequestResync;
+                    if let Err(e) =
+                        Mesh::send_content(link, tree, state, content, msg.final_source).await
+                    {
+                        println!("{}", e);
+                    }
+                }
+            }
+            MessageContent::Topology(chunk) => {
+                apply_topology_chunk(tree, state, msg.final_source, msg.final_destination, chunk)
+                    .await;
+            }
+            MessageContent::RequestInitTopology(n) => {
+                if !tree.lock().await.is_child(n) {
+                    println!("ignored initial topology request for {}", n);
+                } else if topology_limit.allow(n, asynchronous::now_ms()) {
+                    send_initial_topology(n, ChunkBitmap::MAX, tree, state, link).await;
+                }
+            }
+            MessageContent::RequestTopologyChunks(missing) => {
+                send_initial_topology(msg.final_source, missing, tree, state, link).await;
+            }
+            MessageContent::JoinRejected(joiner, reason) => {
+                if let Err(e) = send_join_rejected(joiner, reason, state, link).await {
+                    println!("{}", e);
+                }
+            }
+            MessageContent::Config(config) => {
+                let applied = state.lock().await.apply_network_config(config);
+                if applied && let Err(e) = link.set_tx_power(config.tx_power) {
+                    println!("{}", e);
+                }
+                let content = MessageContent::ConfigAck(config.version);
+                if let Err(e) =
+                    Mesh::send_content(link, tree, state, content, msg.final_source).await
+                {
+                    println!("{}", e);
+                }
+            }
+            MessageContent::Crash(node) => {
+                state
+                    .lock()
+                    .await
+                    .record_event(MeshEvent::NodeCrashed(node));
+                let uplink = tree.lock().await.uplink();
+                if let Some(uplink) = uplink {
+                    let content = MessageContent::Crash(node);
+                    if let Err(e) = Mesh::send_content(link, tree, state, content, uplink).await {
+                        println!("{}", e);
+                    }
+                }
+            }
+            MessageContent::BootHealthy(..)
+            | MessageContent::Introduce(..)
+            | MessageContent::LinkReport(..) => {
+                let uplink = tree.lock().await.uplink();
+                if let Some(uplink) = uplink
+                    && let Err(e) = Mesh::send_content(link, tree, state, msg.data, uplink).await
+                {
+                    println!("{}", e);
+                }
+            }
+            MessageContent::Leave(node) => {
+                // Announcements from below go on to the leader, the leader's own stop here.
+                let from_below = tree.lock().await.is_downstream(msg.final_source);
+                prune_leaving(node, tree, state).await;
+                let uplink = tree.lock().await.uplink();
+                if let (true, Some(uplink)) = (from_below, uplink) {
+                    let content = MessageContent::Leave(node);
+                    if let Err(e) = Mesh::send_content(link, tree, state, content, uplink).await {
+                        println!("{}", e);
+                    }
+                }
+            }
+            MessageContent::LeaderHeartbeat(hash) => {
+                let leader = msg.final_source;
+                let previous = state.lock().await.leader;
+                let succeeded = match previous {
+                    Some(previous) if previous != leader => {
+                        let t = tree.lock().await;
+                        t.contains(previous)
+                            .then(|| t.successor(previous, link.address()))
+                    }
+                    _ => None,
+                };
+                match (previous, succeeded) {
+                    // Members further down learn about a successor from its first heartbeat.
+                    (Some(previous), Some(Ok(successor))) if successor == Some(leader) => {
+                        println!("{} took over from {}", leader, previous);
+                        let result = tree.lock().await.hand_over(previous, successor);
+                        match result {
+                            Ok(()) => {
+                                let mut state = state.lock().await;
+                                state.leader = successor;
+                                state.liveness.forget(previous);
+                                state.record_event(MeshEvent::LeaderLost(previous));
+                            }
+                            Err(e) => println!("{}", e),
+                        }
+                    }
+                    // A leader this node does not follow, e.g. the one a partition was cut off
+                    // from, that still knows this node.
+                    (Some(previous), Some(_)) => {
+                        println!("ignoring leader {}, following {}", leader, previous);
+                    }
+                    _ => state.lock().await.leader = Some(leader),
+                }
+                // Catches placements lost without a gap in the epochs, e.g. the last one.
+                let follows = state.lock().await.leader == Some(leader);
+                let own_hash = tree.lock().await.topology_hash(link.address());
+                if follows && hash.is_some_and(|hash| hash != own_hash) {
+                    println!("Topology diverged from {}, requesting resync", leader);
+                    let content = MessageContent::RequestResync;
+                    if let Err(e) = Mesh::send_content(link, tree, state, content, leader).await {
+                        println!("{}", e);
+                    }
+                }
+            }
+            MessageContent::LeaderHandover(leader, epoch) => {
+                let previous = state.lock().await.leader;
+                if let Some(previous) = previous.filter(|&p| p != msg.final_source) {
+                    println!(
+                        "ignoring handover by {}, following {}",
+                        msg.final_source, previous
+                    );
+                    continue;
+                }
+                tree.lock().await.observe_version(epoch);
+                if leader != link.address() {
+                    match tree.lock().await.turn_towards(Some(leader)) {
+                        Ok(()) => follow(&mut *state.lock().await, leader),
+                        Err(e) => println!("{}", e),
+                    }
+                    continue;
+                }
+                println!("{} handed the lead over", msg.final_source);
+                if config.may_lead() {
+                    if let Err(e) = tree.lock().await.turn_towards(None) {
+                        println!("{}", e);
+                    }
+                    take_lead(spawner, tree, state, link, organize_queue, config).await;
+                } else {
+                    restart_search(spawner, tree, state, link, organize_queue, config).await;
+                }
+                return;
+            }
+            MessageContent::Remerge if !tree.lock().await.is_downstream(msg.final_source) => {
+                println!("partition dissolved by {}", msg.final_source);
+                restart_search(spawner, tree, state, link, organize_queue, config).await;
+                return;
+            }
+            _ => (),
+        }
+    }
+}
+
+/// Returns the uplink if it was silent for longer than `UPLINK_LOST_MS`. An uplink never heard
+/// from does not count, neither does the one this node told that it leaves.
+async fn lost_uplink(
+    tree: &'static asynchronous::Mutex<Tree>,
+    state: &'static asynchronous::Mutex<MeshState>,
+) -> Option<Node> {
+    let uplink = tree.lock().await.uplink()?;
+    let state = state.lock().await;
+    if state.leaving {
+        return None;
+    }
+    let silent_ms = state.liveness.silent_for(uplink, asynchronous::now_ms())?;
+    (silent_ms > UPLINK_LOST_MS).then_some(uplink)
+}
+
+/// Returns the leader if it was silent for longer than `LEADER_LOST_MS`.
+async fn lost_leader(state: &'static asynchronous::Mutex<MeshState>) -> Option<Node> {
+    let state = state.lock().await;
+    let leader = state.leader.filter(|_| !state.leaving)?;
+    let silent_ms = state.liveness.silent_for(leader, asynchronous::now_ms())?;
+    (silent_ms > LEADER_LOST_MS).then_some(leader)
+}
+
+/// Hands the tree of the silent `leader` over to its successor, see `Tree::successor`. If that
+/// is this node, it leads from now on and merges back once it hears the old leader's mesh
+/// again, like the leader of a partition, or searches for it while keeping its tree if it may
+/// not lead. Without a usable tree, it searches for the mesh again. Returns true if this node
+/// no longer follows.
+async fn fail_over(
+    leader: Node,
+    spawner: asynchronous::Spawner,
+    tree: &'static asynchronous::Mutex<Tree>,
+    state: &'static asynchronous::Mutex<MeshState>,
+    link: &'static ActiveLink,
+    organize_queue: &'static asynchronous::Channel<ReceiveMessage, ORGANIZE_QUEUE_SIZE>,
+    config: MeshConfig,
+) -> bool {
+    println!("leader {} went silent", leader);
+    let handed_over = {
+        let mut t = tree.lock().await;
+        t.successor(leader, link.address())
+            .and_then(|successor| t.hand_over(leader, successor).map(|_| successor))
+    };
+    let successor = match handed_over {
+        Ok(successor) => successor,
+        Err(e) => {
+            println!("{}", e);
+            restart_search(spawner, tree, state, link, organize_queue, config).await;
+            return true;
+        }
+    };
+    {
+        let mut state = state.lock().await;
+        state.liveness.forget(leader);
+        state.record_event(MeshEvent::LeaderLost(leader));
+        state.leader = successor;
+        // The successor may never have been heard directly, its heartbeats start the clock.
+        if let Some(successor) = successor {
+            state.liveness.record(successor, asynchronous::now_ms());
+        }
+    }
+    match successor {
+        Some(_) => false,
+        None if config.may_lead() => {
+            state.lock().await.partitioned_from = Some(leader);
+            take_lead(spawner, tree, state, link, organize_queue, config).await;
+            true
+        }
+        None => {
+            search_detached(leader, spawner, tree, state, link, organize_queue, config).await;
+            true
+        }
+    }
+}
+
+/// Cuts off the lost `uplink` and everything behind it. The nodes below this node lost their
+/// path to the leader as well, so this node leads them until it hears the rest of the mesh
+/// again, see `remerge`. Without nodes below it, it simply searches for the mesh again, and
+/// if it may not lead, it searches while it keeps them, see `search_detached`.
+async fn lead_partition(
+    uplink: Node,
+    spawner: asynchronous::Spawner,
+    tree: &'static asynchronous::Mutex<Tree>,
+    state: &'static asynchronous::Mutex<MeshState>,
+    link: &'static ActiveLink,
+    organize_queue: &'static asynchronous::Channel<ReceiveMessage, ORGANIZE_QUEUE_SIZE>,
+    config: MeshConfig,
+) {
+    println!("lost uplink {}", uplink);
+    if !config.may_lead() {
+        search_detached(uplink, spawner, tree, state, link, organize_queue, config).await;
+        return;
+    }
+    let mut removed: Vec<Node, MAX_LEAFS> = Vec::new();
+    let has_members = {
+        let mut t = tree.lock().await;
+        let _ = removed.push(uplink);
+        match t.remove_node(uplink, OrphanPolicy::Rediscover) {
+            Ok(orphans) => {
+                for (node, _) in orphans {
+                    let _ = removed.push(node);
+                }
+            }
+            Err(e) => println!("{}", e),
+        }
+        t.node_count() > 1
+    };
+    if !has_members {
+        restart_search(spawner, tree, state, link, organize_queue, config).await;
+        return;
+    }
+    {
+        let mut state = state.lock().await;
+        state.partitioned_from = Some(uplink);
+        state.leader = None;
+        state.role.set(MeshRole::Leader);
+        state.record_event(MeshEvent::Partitioned(uplink));
+    }
+    for node in removed {
+        announce_leave(node, tree, state, link).await;
+    }
+    if let Err(e) = asynchronous::spawn(
+        &spawner,
+        leader_task(spawner, tree, state, link, organize_queue, config),
+    ) {
+        println!("{}", e);
+    }
+}
+
+/// Searches for the mesh again after losing `uplink`, for a node that may not lead the nodes
+/// below it. Unlike `restart_search` it keeps them, so frames between them still pass this
+/// node, and only detaches the rest of the mesh, see `Tree::detach`, which the placements of
+/// the leader bring back once this node joined again. Without nodes below it, it simply
+/// searches again.
+async fn search_detached(
+    uplink: Node,
+    spawner: asynchronous::Spawner,
+    tree: &'static asynchronous::Mutex<Tree>,
+    state: &'static asynchronous::Mutex<MeshState>,
+    link: &'static ActiveLink,
+    organize_queue: &'static asynchronous::Channel<ReceiveMessage, ORGANIZE_QUEUE_SIZE>,
+    config: MeshConfig,
+) {
+    let has_members = {
+        let mut t = tree.lock().await;
+        match t.detach(uplink) {
+            // A silent leader was pruned by the fail-over already.
+            Ok(()) | Err(TreeError::NodeNotFoundError) => t.node_count() > 1,
+            Err(e) => {
+                println!("{}", e);
+                false
+            }
+        }
+    };
+    if !has_members {
+        restart_search(spawner, tree, state, link, organize_queue, config).await;
+        return;
+    }
+    {
+        let mut state = state.lock().await;
+        state.detached_from = Some(uplink);
+        state.leader = None;
+        state.role.set(MeshRole::Searching);
+        state.record_event(MeshEvent::Detached(uplink));
+    }
+    if let Err(e) = asynchronous::spawn(
+        &spawner,
+        searcher_task(spawner, tree, state, link, organize_queue, config),
+    ) {
+        println!("{}", e);
+    }
+}
+
+/// Tells the nodes this node kept below it while it searched, see `search_detached`, to search
+/// as well, the deepest first so relays still pass the word on. The leader did not place them
+/// below this node, so they join again like newcomers.
+async fn release_kept_members(
+    tree: &'static asynchronous::Mutex<Tree>,
+    state: &'static asynchronous::Mutex<MeshState>,
+    link: &'static ActiveLink,
+) {
+    if state.lock().await.detached_from.take().is_none() {
+        return;
+    }
+    let kept = {
+        let t = tree.lock().await;
+        t.into_iter()
+            .map(|(member, _)| member)
+            .filter(|member| t.is_downstream(*member))
+            .collect::<Vec<_, MAX_LEAFS>>()
+    };
+    for member in kept.iter().rev() {
+        let content = MessageContent::Remerge;
+        if let Err(e) = Mesh::send_content(link, tree, state, content, *member).await {
+            println!("{}", e);
+        }
+    }
+    let mut t = tree.lock().await;
+    for member in kept {
+        let _ = t.remove_node(member, OrphanPolicy::Rediscover);
+    }
+}
+
+/// Dissolves the partition this node leads after it heard `node` from the rest of the mesh.
+/// Every member is told to search again, the deepest first so relays still pass the word on,
+/// and then this node searches itself.
+async fn remerge(
+    node: Node,
+    spawner: asynchronous::Spawner,
+    tree: &'static asynchronous::Mutex<Tree>,
+    state: &'static asynchronous::Mutex<MeshState>,
+    link: &'static ActiveLink,
+    organize_queue: &'static asynchronous::Channel<ReceiveMessage, ORGANIZE_QUEUE_SIZE>,
+    config: MeshConfig,
+) {
+    println!("heard {}, merging the partition back", node);
+    let members = {
+        let t = tree.lock().await;
+        t.into_iter()
+            .map(|(member, _)| member)
+            .collect::<Vec<_, MAX_LEAFS>>()
+    };
+    for member in members.iter().rev() {
+        let content = MessageContent::Remerge;
+        if let Err(e) = Mesh::send_content(link, tree, state, content, *member).await {
+            println!("{}", e);
+        }
+    }
+    state.lock().await.record_event(MeshEvent::Remerged);
+    restart_search(spawner, tree, state, link, organize_queue, config).await;
+}
+
+/// Forgets the tree and searches for a mesh to join like a freshly booted node, but holds back
+/// from leading for `REJOIN_SEARCH_ROUNDS`.
+async fn restart_search(
+    spawner: asynchronous::Spawner,
+    tree: &'static asynchronous::Mutex<Tree>,
+    state: &'static asynchronous::Mutex<MeshState>,
+    link: &'static ActiveLink,
+    organize_queue: &'static asynchronous::Channel<ReceiveMessage, ORGANIZE_QUEUE_SIZE>,
+    config: MeshConfig,
+) {
+    if let Err(e) = tree.lock().await.reset() {
+        println!("{}", e);
+    }
+    {
+        let mut state = state.lock().await;
+        state.partitioned_from = None;
+        state.detached_from = None;
+        state.leader = None;
+        state.role.set(MeshRole::Searching);
+    }
+    let config = MeshConfig {
+        lead_after_rounds: config.lead_after_rounds.max(REJOIN_SEARCH_ROUNDS),
+        ..config
+    };
+    if let Err(e) = asynchronous::spawn(
+        &spawner,
+        searcher_task(spawner, tree, state, link, organize_queue, config),
+    ) {
+        println!("{}", e);
+    }
+}
+
+/// Removes `node`, which announced that it powers down, from the tree. Relays and the leader
+/// prune it before the leader's announcement arrives, so the event is only recorded once.
+/// Returns what hung below `node`, see `Tree::remove_node`.
+async fn prune_leaving(
+    node: Node,
+    tree: &'static asynchronous::Mutex<Tree>,
+    state: &'static asynchronous::Mutex<MeshState>,
+) -> Orphans {
+    let removed = tree.lock().await.remove_node(node, OrphanPolicy::Promote);
+    match removed {
+        Ok(orphans) => {
+            println!("{} left", node);
+            state.lock().await.record_event(MeshEvent::NodeLeft(node));
+            orphans
+        }
+        Err(_) => Orphans::new(),
+    }
+}
+
+/// Prunes every member the leader has not received a frame from or through for
+/// `stale_after_ms` and tells the others it left. Members without any record yet, e.g. placed
+/// after joining through a relay, start their clock now. The children of pruned relays are
+/// added to `orphans`.
+async fn prune_stale(
+    tree: &'static asynchronous::Mutex<Tree>,
+    state: &'static asynchronous::Mutex<MeshState>,
+    link: &'static ActiveLink,
+    stale_after_ms: u64,
+    orphans: &mut Vec<Node, MAX_LEAFS>,
+) {
+    let now_ms = asynchronous::now_ms();
+    let mut stale: Vec<Node, MAX_LEAFS> = Vec::new();
+    {
+        let t = tree.lock().await;
+        let mut state = state.lock().await;
+        for (node, _) in &*t {
+            match state.liveness.silent_for(node, now_ms) {
+                Some(silent_ms) if silent_ms > stale_after_ms => {
+                    let _ = stale.push(node);
+                }
+                Some(_) => {}
+                None => state.liveness.record(node, now_ms),
+            }
+        }
+    }
+    for node in stale {
+        let removed = tree.lock().await.remove_node(node, OrphanPolicy::Promote);
+        let Ok(removed) = removed else {
+            continue;
+        };
+        adopt_later(orphans, &removed);
+        println!("{} went silent", node);
+        {
+            let mut state = state.lock().await;
+            state.liveness.forget(node);
+            state.record_event(MeshEvent::NodeSilent(node));
+        }
+        announce_leave(node, tree, state, link).await;
+    }
+}
+
+/// Tells every member that this node still leads, see `LEADER_LOST_MS`, and how its tree hashes.
+/// Members whose path still has relays that did not acknowledge all placements are skipped,
+/// those relays would only report them unreachable and hold back sends to them.
+async fn send_leader_heartbeat(
+    tree: &'static asynchronous::Mutex<Tree>,
+    state: &'static asynchronous::Mutex<MeshState>,
+    link: &'static ActiveLink,
+) {
+    let (hash, members) = {
+        let t = tree.lock().await;
+        let state = state.lock().await;
+        let settled = |node: &Node| {
+            state
+                .edge_acks
+                .get(node)
+                .is_none_or(|pending| pending.is_empty())
+        };
+        let members = t
+            .into_iter()
+            .map(|(member, _)| member)
+            .filter(|&member| {
+                let mut relay = t.parent(member).ok().flatten();
+                while let Some(node) = relay {
+                    if !settled(&node) {
+                        return false;
+                    }
+                    relay = t.parent(node).ok().flatten();
+                }
+                true
+            })
+            .collect::<Vec<_, MAX_LEAFS>>();
+        (t.topology_hash(link.address()), members)
+    };
+    for member in members {
+        let content = MessageContent::LeaderHeartbeat(Some(hash));
+        if let Err(e) = Mesh::send_content(link, tree, state, content, member).await {
+            println!("{}", e);
+        }
+    }
+}
+
+/// Tells every member that `node` left, so their trees match the leader's again.
+async fn announce_leave(
+    node: Node,
+    tree: &'static asynchronous::Mutex<Tree>,
+    state: &'static asynchronous::Mutex<MeshState>,
+    link: &'static ActiveLink,
+) {
+    let members = {
+        let t = tree.lock().await;
+        t.into_iter()
+            .map(|(member, _)| member)
+            .collect::<Vec<_, MAX_LEAFS>>()
+    };
+    for member in members {
+        let content = MessageContent::Leave(node);
+        if let Err(e) = Mesh::send_content(link, tree, state, content, member).await {
+            println!("{}", e);
+        }
+    }
+}
+
+/// Attaches `node`, which sent a Discovery although it is still known below this node, directly
+/// below this node and sends it the initial topology without waiting for the leader. The
+/// Discovery still goes into the news, so the leader confirms or moves the node next round.
+async fn readmit(
+    node: Node,
+    tree: &'static asynchronous::Mutex<Tree>,
+    state: &'static asynchronous::Mutex<MeshState>,
+    link: &'static ActiveLink,
+) {
+    println!("readmitting {}", node);
+    if let Err(e) = tree.lock().await.upsert_edge(None, node) {
+        println!("{}", e);
+        return;
+    }
+    send_initial_topology(node, ChunkBitmap::MAX, tree, state, link).await;
+}
+
+/// Discovery reports a node collected for one newcomer since the last news round, merged into
+/// the strongest one so a newcomer heard many times is still reported once, with how often.
+struct Discovered {
+    rssi: i32,
+    heard: u16,
+    /// Advertised in the latest Discovery.
+    capabilities: Capabilities,
+}
+
+fn record_discovery(
+    news: &mut LinearMap<Node, Discovered, MAX_NEWS>,
+    node: Node,
+    rssi: i32,
+    capabilities: Capabilities,
+) {
+    match news.get_mut(&node) {
+        Some(discovered) => {
+            discovered.rssi = discovered.rssi.max(rssi);
+            discovered.heard = discovered.heard.saturating_add(1);
+            discovered.capabilities = capabilities;
+        }
+        None => {
+            let discovered = Discovered {
+                rssi,
+                heard: 1,
+                capabilities,
+            };
+            if let Some(n) = insert_evicting_weakest(news, node, discovered, |d| d.rssi) {
+                println!("evicted discovery of {}", n);
+            }
+        }
+    }
+}
+
+/// Sends a heartbeat to the uplink and every child once per
+/// `NetworkConfig::heartbeat_interval_ms`, following the config as it changes. Once per
+/// `LINK_REPORT_INTERVAL_MS`, members broadcast it to every node in range instead, so nodes
+/// that are not their tree neighbors learn how well they hear them, and every follower reports
+/// the neighbors it hears to the leader. Monitors never become parents, so they do not
+/// broadcast. A unicast heartbeat carries the oldest frame queued for its neighbor if it fits,
+/// see `TrafficClass::Piggyback`, and the rest are sent on their own.
+#[cfg_attr(feature = "hardware", embassy_executor::task)]
+async fn heartbeat_task(
+    link: &'static ActiveLink,
+    tree: &'static asynchronous::Mutex<Tree>,
+    state: &'static asynchronous::Mutex<MeshState>,
+    role: NodeRole,
+) {
+    let mut last_report_ms = asynchronous::now_ms();
+    let mut interval_ms = state.lock().await.network_config.heartbeat_interval_ms();
+    let mut ticker = asynchronous::Ticker::every(asynchronous::Duration::from_millis(interval_ms));
+    loop {
+        ticker.next().await;
+        let now_ms = asynchronous::now_ms();
+        let report = now_ms - last_report_ms >= LINK_REPORT_INTERVAL_MS;
+        if report {
+            last_report_ms = now_ms;
+        }
+        let health = own_health(link, tree, state).await;
+        if report && role == NodeRole::Member {
+            if let Err(e) = broadcast_heartbeat(link, state, health).await {
+                println!("{}", e);
+            }
+        } else {
+            let neighbors = tree.lock().await.neighbors().unwrap_or_default();
+            for (neighbor, _) in neighbors {
+                let pending = state.lock().await.take_piggyback(neighbor);
+                if let Err(e) = send_heartbeat(link, tree, state, neighbor, health, pending).await {
+                    println!("{}", e);
+                }
+            }
+        }
+        let uplink = tree.lock().await.uplink();
+        if let (true, Some(uplink)) = (report, uplink) {
+            let neighbors = state.lock().await.neighbors.report(asynchronous::now_ms());
+            let content = MessageContent::LinkReport(link.address(), neighbors);
+            if let Err(e) = Mesh::send_content(link, tree, state, content, uplink).await {
+                println!("{}", e);
+            }
+        }
+        // Frames that found no unicast heartbeat, or whose next hop left the tree.
+        let leftovers = core::mem::take(&mut state.lock().await.piggyback);
+        for p in leftovers {
+            send_piggyback_alone(link, tree, state, p).await;
+        }
+        let configured_ms = state.lock().await.network_config.heartbeat_interval_ms();
+        if configured_ms != interval_ms {
+            interval_ms = configured_ms;
+            ticker = asynchronous::Ticker::every(asynchronous::Duration::from_millis(interval_ms));
+        }
+    }
+}
+
+async fn send_heartbeat(
+    link: &'static ActiveLink,
+    tree: &'static asynchronous::Mutex<Tree>,
+    state: &'static asynchronous::Mutex<MeshState>,
+    neighbor: Node,
+    health: Health,
+    pending: Option<PendingPiggyback>,
+) -> Result<(), MeshError> {
+    let bundle = pending.as_ref().map(|p| p.frame.clone());
+    let content = MessageContent::Heartbeat(health, bundle);
+    match (
+        Mesh::send_content(link, tree, state, content, neighbor).await,
+        pending,
+    ) {
+        // No room next to the heartbeat.
+        (Err(MeshError::SerializationError(_)), Some(p)) => {
+            send_piggyback_alone(link, tree, state, p).await;
+            let content = MessageContent::Heartbeat(health, None);
+            Mesh::send_content(link, tree, state, content, neighbor).await
+        }
+        (result, Some(p)) => {
+            record_send(state, p.destination, &result).await;
+            result
+        }
+        (result, None) => result,
+    }
+}
+
+/// Sends a frame that found no heartbeat to ride on by itself.
+async fn send_piggyback_alone(
+    link: &'static ActiveLink,
+    tree: &'static asynchronous::Mutex<Tree>,
+    state: &'static asynchronous::Mutex<MeshState>,
+    pending: PendingPiggyback,
+) {
+    let result = Mesh::send_frame(
+        link,
+        tree,
+        &pending.frame,
+        pending.next,
+        pending.destination,
+    )
+    .await;
+    record_send(state, pending.destination, &result).await;
+    if let Err(e) = result {
+        println!("{}", e);
+    }
+}
+
+/// What this node piggybacks on its heartbeats.
+async fn own_health(
+    link: &ActiveLink,
+    tree: &asynchronous::Mutex<Tree>,
+    state: &asynchronous::Mutex<MeshState>,
+) -> Health {
+    let uplink = tree.lock().await.uplink();
+    let state = state.lock().await;
+    let uplink_rssi = uplink.and_then(|uplink| state.neighbors.rssi(uplink));
+    Health::new(state.capabilities.battery, link.queued(), uplink_rssi)
+}
+
+async fn broadcast_heartbeat(
+    link: &ActiveLink,
+    state: &asynchronous::Mutex<MeshState>,
+    health: Health,
+) -> Result<(), MeshError> {
+    let (sequence, options) = {
+        let mut state = state.lock().await;
+        state.next_frame()
+    };
+    let content = MessageContent::Heartbeat(health, None);
+    let msg = SendMessage::new(Node::BROADCAST, content, None, sequence);
+    let data = serialize_signed(msg, link.address(), options)?;
+    link.send(data, Node::BROADCAST)
+        .await
+        .map_err(MeshError::LinkError)
+}
+
+#[cfg_attr(feature = "hardware", embassy_executor::task)]
+async fn dispatcher_task(
+    link: &'static ActiveLink,
+    tree: &'static asynchronous::Mutex<Tree>,
+    state: &'static asynchronous::Mutex<MeshState>,
+    recv_queue: &'static asynchronous::Channel<Delivery, RECV_QUEUE_SIZE>,
+    organize_queue: &'static asynchronous::Channel<ReceiveMessage, ORGANIZE_QUEUE_SIZE>,
+    config: MeshConfig,
+) {
+    let mut filters = FrameFilters {
+        duplicates: DuplicateFilter::new(),
+        replays: ReplayFilter::new(),
+        bundled: None,
+    };
+    let queues = DispatchQueues {
+        recv: recv_queue,
+        organize: organize_queue,
+    };
+    loop {
+        let mut data = Some(link.receive().await);
+        while let Some(frame) = data.take() {
+            let result = dispatch(frame, &mut filters, link, tree, state, &queues, &config).await;
+            if let Err(e) = result {
+                println!("{}", e);
+            }
+            data = filters.bundled.take();
+        }
+    }
+}
+
+/// Frames the dispatcher has seen so far, and the one it handles next.
+struct FrameFilters {
+    duplicates: DuplicateFilter,
+    replays: ReplayFilter,
+    /// Frame the last heartbeat carried, dispatched next as if it arrived on its own.
+    bundled: Option<RecvData>,
+}
+
+/// Where the dispatcher hands over frames addressed to this node.
+struct DispatchQueues {
+    recv: &'static asynchronous::Channel<Delivery, RECV_QUEUE_SIZE>,
+    organize: &'static asynchronous::Channel<ReceiveMessage, ORGANIZE_QUEUE_SIZE>,
+}
+
+async fn dispatch(
+    data: RecvData,
+    filters: &mut FrameFilters,
+    link: &'static ActiveLink,
+    tree: &'static asynchronous::Mutex<Tree>,
+    state: &'static asynchronous::Mutex<MeshState>,
+    queues: &DispatchQueues,
+    config: &MeshConfig,
+) -> Result<(), MeshError> {
+    let received_ms = data.received_ms;
+    let last_hop = data.source;
+    let rssi = data.rssi;
+    // The radio tags every frame with the address of the node that put it on air, so a node
+    // recognizes the echo of its own frames without a tag of its own in the header.
+    if last_hop == link.address() {
+        return Ok(());
+    }
+    let frame = Frame::parse(data.data, data.destination, data.source, data.rssi)
+        .map_err(MeshError::ReceiveMessageError)?;
+    let (network_id, final_source) = match &frame {
+        Frame::Message(msg) => (msg.network_id, msg.final_source),
+        Frame::Opaque(msg) => (msg.network_id(), msg.final_source()),
+    };
+    if network_id != config.network_id {
+        return Ok(());
+    }
+    // A frame this node sent came back over a relay, forwarding it again would start a loop.
+    // Checked before the duplicate filter, which never saw the frames sent from here.
+    if final_source == link.address() {
+        return Ok(());
+    }
+    let mut msg = match frame {
+        Frame::Message(msg) => msg,
+        Frame::Opaque(msg) => {
+            state.lock().await.liveness.refresh(last_hop, received_ms);
+            return relay_opaque(msg, &mut filters.duplicates, link, tree, state, config.role)
+                .await;
+        }
+    };
+    msg.received_ms = received_ms;
+    // Broadcasts only travel one hop.
+    if !msg.is_final_destination() && !msg.final_destination.is_broadcast() {
+        if filters
+            .duplicates
+            .is_duplicate(msg.final_source, msg.sequence)
+        {
+            return Ok(());
+        }
+        state.lock().await.liveness.refresh(last_hop, received_ms);
+        if config.role == NodeRole::Monitor {
+            return Ok(());
+        }
+        let result = forward(msg, link, tree, state, config).await;
+        state.lock().await.forwards.record(&result);
+        return result;
+    }
+    if msg.is_organization() {
+        verify_organization(&msg, config.network_key)?;
+        if is_replay(
+            &mut filters.replays,
+            tree,
+            msg.final_source,
+            msg.session,
+            msg.sequence,
+            config,
+        )
+        .await
+        {
+            return Ok(());
+        }
+        // Frames for this node count as seen once they are authentic, so a forged frame cannot
+        // use up the sequence of a genuine one.
+        if filters
+            .duplicates
+            .is_duplicate(msg.final_source, msg.sequence)
+        {
+            return Ok(());
+        }
+        record_trusted(link, tree, state, last_hop, final_source, rssi, received_ms).await;
+        match msg.data {
+            MessageContent::Unreachable(destination) => {
+                state
+                    .lock()
+                    .await
+                    .reachability
+                    .record_report(destination, asynchronous::now_ms());
+                return Ok(());
+            }
+            MessageContent::Congested(_) => {
+                state.lock().await.congested = true;
+                return Ok(());
+            }
+            // The frame itself was recorded as a sign of life above.
+            MessageContent::Heartbeat(health, ref mut bundle) => {
+                filters.bundled = bundle.take().map(|frame| RecvData {
+                    data: frame,
+                    source: msg.source,
+                    destination: msg.destination,
+                    rssi: msg.rssi,
+                    received_ms: msg.received_ms,
+                });
+                let child = msg.final_source;
+                let (is_child, known) = {
+                    let t = tree.lock().await;
+                    let is_child = t.uplink() != Some(child)
+                        && t.children(None)
+                            .is_ok_and(|children| children.contains(&child));
+                    (is_child, t.contains(child))
+                };
+                let mut state = state.lock().await;
+                if is_child {
+                    state.child_health.record(child, health, received_ms);
+                }
+                // A partition merges back once it hears its lost uplink or any node outside it
+                // that has an uplink of its own, i.e. belongs to a mesh rather than searching.
+                let rejoins = state
+                    .partitioned_from
+                    .is_some_and(|lost| !known && (child == lost || health.uplink_rssi.is_some()));
+                if !rejoins {
+                    return Ok(());
+                }
+            }
+            MessageContent::Ping(id) => {
+                let content = MessageContent::Pong(id);
+                return send_without_waiting(link, tree, state, content, msg.final_source).await;
+            }
+            MessageContent::Identify(id, duration_ms) => {
+                {
+                    let mut state = state.lock().await;
+                    state.identify_until_ms = Some(received_ms + duration_ms as u64);
+                    state.record_event(MeshEvent::IdentifyRequested(msg.final_source));
+                }
+                let content = MessageContent::Pong(id);
+                return send_without_waiting(link, tree, state, content, msg.final_source).await;
+            }
+            MessageContent::Pong(id) => {
+                state
+                    .lock()
+                    .await
+                    .pings
+                    .record_pong(msg.final_source, id, received_ms);
+                return Ok(());
+            }
+            MessageContent::TraceRoute(id, mut hops) => {
+                let _ = hops.push(link.address());
+                let content = MessageContent::TraceRouteReply(id, hops);
+                return send_without_waiting(link, tree, state, content, msg.final_source).await;
+            }
+            MessageContent::TraceRouteReply(id, hops) => {
+                state
+                    .lock()
+                    .await
+                    .pings
+                    .record_route(msg.final_source, id, received_ms, hops);
+                return Ok(());
+            }
+            MessageContent::BootConfirmed => {
+                state.lock().await.boot_confirmed.set(true);
+                return Ok(());
+            }
+            _ => {}
+        }
+        queues
+            .organize
+            .my_try_send(msg)
+            .map_err(|_| MeshError::OrganizeQueueSendError())?;
+        return Ok(());
+    }
+    if let MessageContent::Application(d) = msg.data {
+        let sealed_len = d.len();
+        let (session, d) = match (
+            config.network_key,
+            msg.flags.contains(MessageFlags::ENCRYPTED),
+        ) {
+            (Some(key), true) => {
+                let header = AuthenticatedHeader {
+                    final_source: msg.final_source,
+                    final_destination: msg.final_destination,
+                    sequence: msg.sequence,
+                };
+                crypto::open_application(&key, &header, &d).map_err(MeshError::CryptoError)?
+            }
+            (None, false) => (0, d),
+            _ => {
+                return Err(MeshError::CryptoError(CryptoError::EncryptionMismatchError));
+            }
+        };
+        if is_replay(
+            &mut filters.replays,
+            tree,
+            msg.final_source,
+            session,
+            msg.sequence,
+            config,
+        )
+        .await
+        {
+            return Ok(());
+        }
+        if filters
+            .duplicates
+            .is_duplicate(msg.final_source, msg.sequence)
+        {
+            return Ok(());
+        }
+        record_trusted(link, tree, state, last_hop, final_source, rssi, received_ms).await;
+        let (metadata, d) = metadata::detach(&d).map_err(MeshError::MetadataError)?;
+        state.lock().await.traffic.record(
+            msg.final_source,
+            metadata.port(),
+            sealed_len,
+            received_ms,
+        );
+        queues
+            .recv
+            .my_try_send(Delivery {
+                data: d,
+                metadata,
+                source: msg.final_source,
+                sent_ms: msg.timestamp,
+                received_ms: msg.received_ms,
+            })
+            .map_err(|_| MeshError::ReceiveQueueSendError())?;
+    }
+    Ok(())
+}
+
+/// Records a frame for this node that passed the tag and replay checks as a sign of life of the
+/// neighbor that sent it and of its final source. Only then is the neighbor trusted, so a forged
+/// or replayed frame cannot keep a silent node alive, take back its suspect mark or pull traffic
+/// onto a shortcut to itself.
+async fn record_trusted(
+    link: &'static ActiveLink,
+    tree: &'static asynchronous::Mutex<Tree>,
+    state: &'static asynchronous::Mutex<MeshState>,
+    last_hop: Node,
+    final_source: Node,
+    rssi: i32,
+    received_ms: u64,
+) {
+    link.trust_source(last_hop);
+    {
+        let mut state = state.lock().await;
+        state.liveness.record(last_hop, received_ms);
+        state.liveness.record(final_source, received_ms);
+        state.shortcuts.record(last_hop, rssi, received_ms);
+        state.neighbors.record(last_hop, rssi, received_ms);
+    }
+    tree.lock().await.clear_suspect(last_hop);
+}
+
+/// Sends a frame addressed to another node on towards its next hop.
+async fn forward(
+    mut msg: ReceiveMessage,
+    link: &'static ActiveLink,
+    tree: &'static asynchronous::Mutex<Tree>,
+    state: &'static asynchronous::Mutex<MeshState>,
+    config: &MeshConfig,
+) -> Result<(), MeshError> {
+    let received_ms = msg.received_ms;
+    let final_source = msg.final_source;
+    let is_report = matches!(msg.data, MessageContent::Unreachable(_));
+    if let MessageContent::TraceRoute(_, hops) = &mut msg.data {
+        let _ = hops.push(link.address());
+    }
+    let mut send_msg: SendMessage = msg.into();
+    if !send_msg.consume_hop() {
+        return Err(MeshError::HopLimitExceededError(final_source));
+    }
+    // Told in a tagged message of its own, as a mark on the frame could be set by anyone.
+    if !send_msg.is_organization() {
+        let congested = {
+            let mut state = state.lock().await;
+            state.congestion.sample(link.queued(), received_ms)
+                && state
+                    .congestion_echoes
+                    .should_echo(final_source, received_ms)
+        };
+        if congested {
+            let content = MessageContent::Congested(send_msg.final_destination);
+            let _ = send_without_waiting(link, tree, state, content, final_source).await;
+        }
+    }
+    let shortcut = may_shortcut(&send_msg, config);
+    let hop = route(tree, state, send_msg.final_destination, shortcut).await;
+    let next = match hop {
+        Ok(next) => next,
+        Err(e) => {
+            if !is_report {
+                let destination = send_msg.final_destination;
+                let _ = report_unreachable(link, tree, state, final_source, destination).await;
+            }
+            return Err(MeshError::TreeError(e));
+        }
+    };
+    link.try_send(
+        send_msg
+            .serialize()
+            .map_err(MeshError::SerializationError)?,
+        next,
+        send_msg.priority,
+    )
+    .map_err(MeshError::LinkError)?;
+    Ok(())
+}
+
+/// Checks an authenticated frame against the replay window of its source and session. Without a
+/// network key anyone can forge fresh frames, so no window is kept. The latest window of every
+/// node in the tree is kept however many other sessions arrive.
+async fn is_replay(
+    replays: &mut ReplayFilter,
+    tree: &'static asynchronous::Mutex<Tree>,
+    source: Node,
+    session: u32,
+    sequence: u16,
+    config: &MeshConfig,
+) -> bool {
+    if config.network_key.is_none() {
+        return false;
+    }
+    let tree = tree.lock().await;
+    let now_ms = asynchronous::now_ms();
+    replays.is_replay(source, session, sequence, now_ms, |node| {
+        tree.contains(node)
+    })
+}
+
+/// Routes a frame from newer firmware without decoding its content. Such frames addressed to
+/// this node cannot be understood and are dropped.
+async fn relay_opaque(
+    msg: OpaqueMessage,
+    duplicates: &mut DuplicateFilter,
+    link: &'static ActiveLink,
+    tree: &'static asynchronous::Mutex<Tree>,
+    state: &'static asynchronous::Mutex<MeshState>,
+    role: NodeRole,
+) -> Result<(), MeshError> {
+    let final_source = msg.final_source();
+    if duplicates.is_duplicate(final_source, msg.sequence()) {
+        return Ok(());
+    }
+    if msg.is_final_destination() {
+        return Err(MeshError::ReceiveMessageError(
+            ReceiveMessageError::UnsupportedVersionError(msg.version()),
+        ));
+    }
+    if role == NodeRole::Monitor {
+        return Ok(());
+    }
+    let result = forward_opaque(msg, link, tree, state).await;
+    state.lock().await.forwards.record(&result);
+    result
+}
+
+/// Like `forward` for a frame from newer firmware.
+async fn forward_opaque(
+    mut msg: OpaqueMessage,
+    link: &'static ActiveLink,
+    tree: &'static asynchronous::Mutex<Tree>,
+    state: &'static asynchronous::Mutex<MeshState>,
+) -> Result<(), MeshError> {
+    let final_source = msg.final_source();
+    if !msg.consume_hop() {
+        return Err(MeshError::HopLimitExceededError(final_source));
+    }
+    let hop = tree.lock().await.next_hop(msg.final_destination());
+    let next = match hop {
+        Ok(next) => next,
+        Err(e) => {
+            let destination = msg.final_destination();
+            let _ = report_unreachable(link, tree, state, final_source, destination).await;
+            return Err(MeshError::TreeError(e));
+        }
+    };
+    link.try_send(
+        msg.serialize().map_err(MeshError::SerializationError)?,
+        next,
+        msg.priority(),
+    )
+    .map_err(MeshError::LinkError)?;
+    Ok(())
+}
+
+/// Tells `source` that this node has no route to `destination`, so that its sends fail fast
+/// instead of vanishing here. Best effort, the report is dropped if `source` is not routable
+/// either.
+async fn report_unreachable(
+    link: &'static ActiveLink,
+    tree: &'static asynchronous::Mutex<Tree>,
+    state: &'static asynchronous::Mutex<MeshState>,
+    source: Node,
+    destination: Node,
+) -> Result<(), MeshError> {
+    let content = MessageContent::Unreachable(destination);
+    send_without_waiting(link, tree, state, content, source).await
+}
+
+/// Sends `content` to `destination` without waiting for room in the send queue, so the
+/// dispatcher never stalls on an answer of its own.
+async fn send_without_waiting(
+    link: &'static ActiveLink,
+    tree: &'static asynchronous::Mutex<Tree>,
+    state: &'static asynchronous::Mutex<MeshState>,
+    content: MessageContent,
+    destination: Node,
+) -> Result<(), MeshError> {
+    let next = tree
+        .lock()
+        .await
+        .next_hop(destination)
+        .map_err(MeshError::TreeError)?;
+    let (sequence, options) = {
+        let mut state = state.lock().await;
+        state.next_frame()
+    };
+    let msg = SendMessage::new(destination, content, None, sequence);
+    let priority = msg.priority;
+    let data = serialize_signed(msg, link.address(), options)?;
+    link.try_send(data, next, priority)
+        .map_err(MeshError::LinkError)
+}
+
+#[cfg(test)]
+mod tests {
+    use core::time::Duration;
+
+    use super::*;
+    use crate::{
+        logic::{
+            capabilities::Features,
+            config::TxPowerPolicy,
+            consts::{
+                CONGESTION_HOLD_MS, CONGESTION_QUEUE_THRESHOLD, FIRMWARE_VERSION,
+                HEARTBEAT_INTERVAL_MS, MAX_CHILD_LEAFS, MAX_NEWS_INTERVAL_MS,
+                UNREACHABLE_AFTER_FAILURES,
+            },
+            history,
+            link::{
+                ActiveLink, Selector, SplitLink,
+                mock::{MockLink, MockNetwork},
+            },
+            message,
+            placement::StrongestSignal,
+            timeline,
+            udp::UdpLink,
+        },
+        unwrap_print,
+    };
+    use tokio::{task::LocalSet, time::sleep};
+
+    /// RAM the routing statics may take, so a change that grows them shows up in CI instead of
+    /// as a stack overflow on the board. `wide-slots` trades memory for `MAX_LEAFS`.
+    #[cfg(not(feature = "wide-slots"))]
+    const STATIC_BUDGET: (usize, usize) = (3 * 1024, 16 * 1024);
+    #[cfg(feature = "wide-slots")]
+    const STATIC_BUDGET: (usize, usize) = (40 * 1024, 176 * 1024);
+
+    #[test]
+    fn routing_statics_fit_their_budget() {
+        let (tree_budget, state_budget) = STATIC_BUDGET;
+        assert!(size_of::<Tree>() <= tree_budget);
+        assert!(size_of::<MeshState>() <= state_budget);
+    }
+
+    fn setup_mesh(spawner: asynchronous::Spawner, link: &'static MockLink) -> Mesh {
+        setup_mesh_with_config(spawner, link, MeshConfig::default())
+    }
+
+    fn setup_mesh_with_config(
+        spawner: asynchronous::Spawner,
+        link: &'static MockLink,
+        config: MeshConfig,
+    ) -> Mesh {
+        setup_mesh_on(
+            spawner,
+            Box::leak(Box::new(ActiveLink::Radio(link))),
+            config,
+        )
+    }
+
+    fn setup_mesh_on(
+        spawner: asynchronous::Spawner,
+        link: &'static ActiveLink,
+        config: MeshConfig,
+    ) -> Mesh {
+        let mut tree = Tree::new();
+        tree.init().unwrap();
+        let tree: &'static _ = Box::leak(Box::new(asynchronous::Mutex::new(tree)));
+        if let Ok(path) = std::env::var(timeline::TIMELINE_ENV) {
+            let interval = Duration::from_millis(100);
+            tokio::task::spawn_local(timeline::record_tree(link.address(), tree, path, interval));
+        }
+        let state = asynchronous::Mutex::new(MeshState::new());
+        let recv_queue: asynchronous::Channel<Delivery, RECV_QUEUE_SIZE> =
+            asynchronous::Channel::new();
+        let organize_queue: asynchronous::Channel<message::ReceiveMessage, ORGANIZE_QUEUE_SIZE> =
+            asynchronous::Channel::new();
+        let mesh = Mesh::new(
+            spawner,
+            link,
+            tree,
+            Box::leak(Box::new(state)),
+            Box::leak(Box::new(recv_queue)),
+            Box::leak(Box::new(organize_queue)),
+            config,
+        );
+        mesh.init().unwrap();
+        if let Ok(path) = std::env::var(history::HISTORY_ENV) {
+            let interval = Duration::from_millis(100);
+            tokio::task::spawn_local(history::record_history(mesh.handle(), path, interval));
+        }
+        mesh
+    }
+
+    fn news(entries: &[(Node, Option<Node>, i32)]) -> LinearMap<Node, ParentReports, MAX_NEWS> {
+        let mut news = LinearMap::new();
+        for (node, parent, rssi) in entries {
+            news.insert(*node, ParentReports::single(*parent, *rssi))
+                .unwrap();
+        }
+        news
+    }
+
+    #[test]
+    fn admission_rejects_weak_links() {
+        let config = MeshConfig {
+            min_join_rssi: -80,
+            join_grace_rounds: 0,
+            ..MeshConfig::default()
+        };
+        let strong = Node::new([0, 0, 0, 0, 0, 1]);
+        let weak = Node::new([0, 0, 0, 0, 0, 2]);
+        let mut candidates = LinearMap::new();
+
+        let admitted = select_admissions(
+            news(&[(strong, None, -60), (weak, None, -85)]),
+            &mut candidates,
+            &config,
+        );
+
+        assert!(admitted.contains_key(&strong));
+        assert!(!admitted.contains_key(&weak));
+        assert!(candidates.contains_key(&weak));
+    }
+
+    #[test]
+    fn admission_waits_for_stronger_report_during_grace_period() {
+        let config = MeshConfig {
+            min_join_rssi: -90,
+            join_grace_rounds: 1,
+            ..MeshConfig::default()
+        };
+        let new = Node::new([0, 0, 0, 0, 0, 1]);
+        let far = Node::new([0, 0, 0, 0, 0, 2]);
+        let near = Node::new([0, 0, 0, 0, 0, 3]);
+        let mut candidates = LinearMap::new();
+
+        let admitted = select_admissions(news(&[(new, Some(far), -88)]), &mut candidates, &config);
+        assert!(admitted.is_empty());
+
+        let admitted = select_admissions(news(&[(new, Some(near), -50)]), &mut candidates, &config);
+        let reports = admitted.get(&new).unwrap();
+        assert_eq!(reports.strongest(), Some((Some(near), -50)));
+        assert_eq!(reports.iter().count(), 2);
+        assert!(candidates.is_empty());
+    }
+
+    #[test]
+    fn admission_carries_over_silent_candidates() {
+        let config = MeshConfig {
+            min_join_rssi: -90,
+            join_grace_rounds: 2,
+            ..MeshConfig::default()
+        };
+        let new = Node::new([0, 0, 0, 0, 0, 1]);
+        let mut candidates = LinearMap::new();
+
+        select_admissions(news(&[(new, None, -50)]), &mut candidates, &config);
+        let admitted = select_admissions(LinearMap::new(), &mut candidates, &config);
+        assert!(admitted.is_empty());
+        let admitted = select_admissions(LinearMap::new(), &mut candidates, &config);
+        assert_eq!(
+            admitted.get(&new).and_then(ParentReports::strongest),
+            Some((None, -50))
+        );
+    }
+
+    #[test]
+    fn admission_forgets_candidates_silent_for_too_long() {
+        let config = MeshConfig {
+            min_join_rssi: -40,
+            join_grace_rounds: 0,
+            ..MeshConfig::default()
+        };
+        let new = Node::new([0, 0, 0, 0, 0, 1]);
+        let mut candidates = LinearMap::new();
+
+        select_admissions(news(&[(new, None, -50)]), &mut candidates, &config);
+        for _ in 0..=MAX_SILENT_ROUNDS {
+            select_admissions(LinearMap::new(), &mut candidates, &config);
+        }
+
+        assert!(candidates.is_empty());
+    }
+
+    #[test]
+    fn admission_ignores_outdated_firmware() {
+        let config = MeshConfig {
+            min_firmware: 3,
+            ..MeshConfig::default()
+        };
+        let old = Node::new([0, 0, 0, 0, 0, 1]);
+        let new = Node::new([0, 0, 0, 0, 0, 2]);
+        let mut news = news(&[(old, None, -50), (new, None, -50)]);
+        for (node, firmware) in [(old, 2), (new, 3)] {
+            news.get_mut(&node).unwrap().capabilities.firmware = firmware;
+        }
+        let mut candidates = LinearMap::new();
+
+        let admitted = select_admissions(news, &mut candidates, &config);
+
+        assert!(admitted.contains_key(&new));
+        assert!(!admitted.contains_key(&old));
+        assert!(candidates.is_empty());
+    }
+
+    #[test]
+    fn full_mesh_refuses_newcomers_but_not_moves() {
+        let mut tree = Tree::new();
+        tree.init().unwrap();
+        for i in 1..=MAX_CHILD_LEAFS as u8 {
+            tree.upsert_edge(None, Node::new([0, 0, 0, 0, 0, i]))
+                .unwrap();
+        }
+        let child = Node::new([0, 0, 0, 0, 0, 1]);
+        let new = Node::new([0, 0, 0, 0, 1, 0]);
+
+        let state = MeshState::new();
+        let place_below = |tree: &Tree, node, parent| {
+            place(
+                tree,
+                &state,
+                node,
+                &ParentReports::single(parent, -50),
+                &StrongestSignal,
+                &FanOutLimit::UNLIMITED,
+            )
+        };
+
+        assert_eq!(
+            place_below(&tree, new, None),
+            Placement::Refused(None, JoinRejection::NoRoom)
+        );
+        assert_eq!(
+            place_below(&tree, new, Some(child)),
+            Placement::Below(Some(child))
+        );
+        assert_eq!(place_below(&tree, child, None), Placement::Deferred);
+
+        for i in 0..(MESH_FULL_WATERMARK - tree.node_count()) as u8 {
+            let parent = Node::new([0, 0, 0, 0, 0, i % MAX_CHILD_LEAFS as u8 + 1]);
+            tree.upsert_edge(Some(parent), Node::new([0, 0, 0, 0, 2, i]))
+                .unwrap();
+        }
+        assert_eq!(
+            place_below(&tree, new, Some(child)),
+            Placement::Refused(Some(child), JoinRejection::MeshFull)
+        );
+        let moved = Node::new([0, 0, 0, 0, 2, 0]);
+        assert_eq!(
+            place_below(&tree, moved, Some(child)),
+            Placement::Below(Some(child))
+        );
+    }
+
+    /// Spreads newcomers over the members regardless of the signal.
+    #[derive(Debug)]
+    struct LeastLoaded;
+
+    impl ParentSelectionPolicy for LeastLoaded {
+        fn select<'a>(
+            &self,
+            _node: Node,
+            _capabilities: &Capabilities,
+            candidates: &'a [ParentCandidate],
+        ) -> Option<&'a ParentCandidate> {
+            candidates.iter().min_by_key(|candidate| candidate.load)
+        }
+    }
+
+    /// Keeps newcomers low on battery from forwarding for others by attaching them only below
+    /// parents known to be mains powered.
+    #[derive(Debug)]
+    struct SpareBatteries;
+
+    impl ParentSelectionPolicy for SpareBatteries {
+        fn select<'a>(
+            &self,
+            _node: Node,
+            capabilities: &Capabilities,
+            candidates: &'a [ParentCandidate],
+        ) -> Option<&'a ParentCandidate> {
+            let mains = |candidate: &&ParentCandidate| {
+                candidate
+                    .capabilities
+                    .is_some_and(|c| c.features.contains(Features::MAINS_POWERED))
+            };
+            match capabilities.battery {
+                Some(percent) if percent < 20 => candidates.iter().find(mains),
+                _ => candidates.first(),
+            }
+        }
+    }
+
+    #[test]
+    fn placement_follows_custom_policy() {
+        let mut tree = Tree::new();
+        tree.init().unwrap();
+        let busy = Node::new([0, 0, 0, 0, 0, 1]);
+        let idle = Node::new([0, 0, 0, 0, 0, 2]);
+        tree.upsert_edge(None, busy).unwrap();
+        tree.upsert_edge(None, idle).unwrap();
+        tree.upsert_edge(Some(busy), Node::new([0, 0, 0, 0, 0, 3]))
+            .unwrap();
+        let new = Node::new([0, 0, 0, 0, 1, 0]);
+        let mut reports = ParentReports::single(Some(busy), -40);
+        reports.record(Some(idle), -80);
+        let state = MeshState::new();
+
+        assert_eq!(
+            place(
+                &tree,
+                &state,
+                new,
+                &reports,
+                &StrongestSignal,
+                &FanOutLimit::UNLIMITED
+            ),
+            Placement::Below(Some(busy))
+        );
+        assert_eq!(
+            place(
+                &tree,
+                &state,
+                new,
+                &reports,
+                &LeastLoaded,
+                &FanOutLimit::UNLIMITED
+            ),
+            Placement::Below(Some(idle))
+        );
+    }
+
+    #[test]
+    fn placement_sees_advertised_capabilities() {
+        let mut tree = Tree::new();
+        tree.init().unwrap();
+        let tag = Node::new([0, 0, 0, 0, 0, 1]);
+        let base = Node::new([0, 0, 0, 0, 0, 2]);
+        tree.upsert_edge(None, tag).unwrap();
+        tree.upsert_edge(None, base).unwrap();
+        let mut state = MeshState::new();
+        let mains = Capabilities::new().with_features(Features::MAINS_POWERED);
+        state.record_capabilities(&tree, base, mains);
+        let new = Node::new([0, 0, 0, 0, 1, 0]);
+        let mut reports = ParentReports::single(Some(tag), -40);
+        reports.record(Some(base), -80);
+
+        assert_eq!(
+            place(
+                &tree,
+                &state,
+                new,
+                &reports,
+                &SpareBatteries,
+                &FanOutLimit::UNLIMITED
+            ),
+            Placement::Below(Some(tag))
+        );
+        let reports = reports.with_capabilities(Capabilities::new().with_battery(10));
+        assert_eq!(
+            place(
+                &tree,
+                &state,
+                new,
+                &reports,
+                &SpareBatteries,
+                &FanOutLimit::UNLIMITED
+            ),
+            Placement::Below(Some(base))
+        );
+        assert_eq!(
+            place(
+                &tree,
+                &MeshState::new(),
+                new,
+                &reports,
+                &SpareBatteries,
+                &FanOutLimit::UNLIMITED
+            ),
+            Placement::Deferred
+        );
+    }
+
+    #[test]
+    fn placement_spills_past_capped_parent() {
+        let mut tree = Tree::new();
+        tree.init().unwrap();
+        let far = Node::new([0, 0, 0, 0, 0, 1]);
+        let near = Node::new([0, 0, 0, 0, 0, 2]);
+        tree.upsert_edge(None, far).unwrap();
+        tree.upsert_edge(None, near).unwrap();
+        tree.upsert_edge(Some(far), Node::new([0, 0, 0, 0, 0, 3]))
+            .unwrap();
+        tree.upsert_edge(Some(far), Node::new([0, 0, 0, 0, 0, 4]))
+            .unwrap();
+        let mut state = MeshState::new();
+        // `far` hears the leader weakly and took as many children as it may.
+        let weak = Health::new(Some(80), 0, Some(-85));
+        state.child_health.record(far, weak, 0);
+        let new = Node::new([0, 0, 0, 0, 1, 0]);
+        let mut reports = ParentReports::single(Some(far), -40);
+        reports.record(Some(near), -80);
+        let fan_out = FanOutLimit::default();
+
+        assert_eq!(
+            place(&tree, &state, new, &reports, &StrongestSignal, &fan_out),
+            Placement::Below(Some(near))
+        );
+        assert_eq!(
+            place(
+                &tree,
+                &state,
+                new,
+                &reports,
+                &StrongestSignal,
+                &FanOutLimit::UNLIMITED
+            ),
+            Placement::Below(Some(far))
+        );
+        // Without another parent the capped one still takes it.
+        let only_far = ParentReports::single(Some(far), -40);
+        assert_eq!(
+            place(&tree, &state, new, &only_far, &StrongestSignal, &fan_out),
+            Placement::Below(Some(far))
+        );
+    }
+    #[test]
+    fn degraded_uplink_moves_to_stronger_parent() {
+        let leader = Node::new([0, 0, 0, 0, 0, 0x10]);
+        let a = Node::new([0, 0, 0, 0, 0, 1]);
+        let b = Node::new([0, 0, 0, 0, 0, 2]);
+        let c = Node::new([0, 0, 0, 0, 0, 3]);
+        let mut tree = Tree::new();
+        tree.init().unwrap();
+        tree.upsert_edge(None, a).unwrap();
+        tree.upsert_edge(Some(a), b).unwrap();
+        tree.upsert_edge(Some(b), c).unwrap();
+        let report = |heard: &[(Node, i32)]| LinkReport::from_slice(heard).unwrap();
+
+        let fine = report(&[(leader, -40), (b, -70)]);
+        assert_eq!(better_parent(&tree, leader, c, &fine), None);
+        let close_call = report(&[(a, -78), (b, -85)]);
+        assert_eq!(better_parent(&tree, leader, c, &close_call), None);
+        let degraded = report(&[(leader, -50), (a, -55), (b, -85)]);
+        assert_eq!(better_parent(&tree, leader, c, &degraded), Some(None));
+        let below = report(&[(c, -40), (a, -90)]);
+        assert_eq!(better_parent(&tree, leader, b, &below), None);
+        let lost = report(&[(c, -40), (leader, -85)]);
+        assert_eq!(better_parent(&tree, leader, b, &lost), Some(None));
+    }
+
+    #[test]
+    fn orphan_reports_skip_departed_and_own_subtree() {
+        let leader = Node::new([0, 0, 0, 0, 0, 0x10]);
+        let a = Node::new([0, 0, 0, 0, 0, 1]);
+        let b = Node::new([0, 0, 0, 0, 0, 2]);
+        let c = Node::new([0, 0, 0, 0, 0, 3]);
+        let d = Node::new([0, 0, 0, 0, 0, 4]);
+        let mut tree = Tree::new();
+        tree.init().unwrap();
+        tree.upsert_edge(None, a).unwrap();
+        tree.upsert_edge(None, b).unwrap();
+        tree.upsert_edge(Some(a), c).unwrap();
+        tree.upsert_edge(Some(c), d).unwrap();
+        let removed = tree.remove_node(a, OrphanPolicy::Promote).unwrap();
+        let mut orphans = Vec::new();
+        adopt_later(&mut orphans, &removed);
+        assert_eq!(orphans.as_slice(), &[c]);
+
+        let heard = LinkReport::from_slice(&[(a, -30), (d, -40), (b, -50), (leader, -70)]).unwrap();
+        let reports = adoption_reports(&tree, leader, c, &heard);
+        assert_eq!(
+            reports.iter().collect::<Vec<_, 4>>().as_slice(),
+            &[(Some(b), -50), (None, -70)]
+        );
+    }
+
+    #[test]
+    fn full_news_evict_weakest_rssi() {
+        let mut news: LinearMap<Node, i32, 2> = LinearMap::new();
+        let a = Node::new([0, 0, 0, 0, 0, 1]);
+        let b = Node::new([0, 0, 0, 0, 0, 2]);
+        let c = Node::new([0, 0, 0, 0, 0, 3]);
+        let d = Node::new([0, 0, 0, 0, 0, 4]);
+
+        assert_eq!(insert_evicting_weakest(&mut news, a, -70, |r| *r), None);
+        assert_eq!(insert_evicting_weakest(&mut news, b, -50, |r| *r), None);
+        assert_eq!(insert_evicting_weakest(&mut news, c, -60, |r| *r), Some(a));
+        assert_eq!(insert_evicting_weakest(&mut news, d, -80, |r| *r), Some(d));
+
+        assert!(news.contains_key(&b));
+        assert!(news.contains_key(&c));
+    }
+
+    #[test]
+    fn follower_aggregates_repeated_discoveries() {
+        let a = Node::new([0, 0, 0, 0, 0, 1]);
+        let b = Node::new([0, 0, 0, 0, 0, 2]);
+        let mut news = LinearMap::new();
+
+        let charged = Capabilities::new().with_battery(80);
+        let drained = Capabilities::new().with_battery(5);
+
+        record_discovery(&mut news, a, -70, charged);
+        record_discovery(&mut news, a, -50, charged);
+        record_discovery(&mut news, a, -60, drained);
+        record_discovery(&mut news, b, -40, charged);
+
+        assert_eq!(news.len(), 2);
+        let discovered = news.get(&a).unwrap();
+        assert_eq!(discovered.rssi, -50);
+        assert_eq!(discovered.heard, 3);
+        assert_eq!(discovered.capabilities, drained);
+    }
+
+    #[test]
+    fn network_config_version_wraps() {
+        let mut state = MeshState::new();
+        let config = |version| NetworkConfig {
+            version,
+            ..NetworkConfig::new()
+        };
+        for version in [30_000, 60_000, u16::MAX, 0] {
+            assert!(state.apply_network_config(config(version)));
+        }
+        assert!(!state.apply_network_config(config(u16::MAX)));
+        assert!(!state.apply_network_config(config(0)));
+    }
+
+    #[test]
+    fn crash_notice_is_addressed_to_uplink() {
+        let a = Node::new([0, 0, 0, 0, 0, 1]);
+        let b = Node::new([0, 0, 0, 0, 0, 2]);
+        let mut tree = Tree::new();
+        tree.init().unwrap();
+        let mut state = MeshState::new();
+        assert!(crash_notice(&tree, &mut state, b).is_err());
+
+        tree.upsert_edge(None, a).unwrap();
+        tree.set_uplink(a);
+        let (uplink, data) = unwrap_print!(crash_notice(&tree, &mut state, b));
+        assert_eq!(uplink, a);
+
+        let msg = unwrap_print!(ReceiveMessage::new(data, a, b, 0));
+        assert_eq!(msg.final_destination, a);
+        assert!(matches!(msg.data, MessageContent::Crash(n) if n == b));
+    }
+
+    #[test]
+    fn organization_messages_require_valid_tag() {
+        let a = Node::new([0, 0, 0, 0, 0, 1]);
+        let b = Node::new([0, 0, 0, 0, 0, 2]);
+        let key = Some([7; 16]);
+        let content = MessageContent::UpsertEdge((None, Some(b)), 0);
+
+        let options = |key| FrameOptions {
+            network_id: 0,
+            key,
+            timestamps: false,
+            session: 9,
+        };
+
+        let signed = SendMessage::new(b, content.clone(), None, 5);
+        let data = unwrap_print!(serialize_signed(signed, a, options(key)));
+        let msg = unwrap_print!(ReceiveMessage::new(data, b, a, 0));
+        assert_eq!(msg.session, 9);
+        unwrap_print!(verify_organization(&msg, key));
+        assert!(matches!(
+            verify_organization(&msg, Some([8; 16])),
+            Err(MeshError::CryptoError(CryptoError::AuthenticationError))
+        ));
+
+        // A replay cannot pass as a new session of its source.
+        let mut replayed: SendMessage = msg.into();
+        replayed.session = 10;
+        let data = unwrap_print!(replayed.serialize());
+        let msg = unwrap_print!(ReceiveMessage::new(data, b, a, 0));
+        assert!(matches!(
+            verify_organization(&msg, key),
+            Err(MeshError::CryptoError(CryptoError::AuthenticationError))
+        ));
+
+        let forged = SendMessage::new(b, content, None, 5);
+        let data = unwrap_print!(serialize_signed(forged, a, options(None)));
+        let msg = unwrap_print!(ReceiveMessage::new(data, b, a, 0));
+        unwrap_print!(verify_organization(&msg, None));
+        assert!(matches!(
+            verify_organization(&msg, key),
+            Err(MeshError::CryptoError(CryptoError::MissingTagError))
+        ));
+    }
+
+    #[tokio::test(flavor = "current_thread")]
+    async fn mesh_creation_test() {
+        let local = LocalSet::new();
+        let link = Box::leak(Box::new(MockLink::new(Node::new([0, 0, 0, 0, 0, 1]))));
+        local
+            .run_until(async {
+                let _mesh = setup_mesh((), link);
+            })
+            .await;
+    }
+
+    #[tokio::test(flavor = "current_thread")]
+    async fn mesh_send_to_self_returns_error() {
+        let local = LocalSet::new();
+        let self_node = Node::new([0, 0, 0, 0, 0, 1]);
+        let mut link = Box::leak(Box::new(MockLink::new(self_node)));
+        local
+            .run_until(async {
+                let mesh = setup_mesh((), link);
+                let payload = MessageData::from([1, 2, 3]);
+                let result = mesh.send(payload, self_node).await;
+                assert!(result.is_err(), "sending to self must error");
+            })
+            .await;
+    }
+
+    #[tokio::test(flavor = "current_thread")]
+    async fn mesh_send_receive_one() {
+        let local = LocalSet::new();
+
+        let a = Node::new([0, 0, 0, 0, 0, 1]);
+        let b = Node::new([0, 0, 0, 0, 0, 2]);
+        let mut link_a = Box::leak(Box::new(MockLink::new(a)));
+        let mut link_b = Box::leak(Box::new(MockLink::new(b)));
+
+        local
+            .run_until(async {
+                let mesh_a = setup_mesh((), link_a);
+
+                sleep(Duration::from_millis(500)).await;
+                link_a.connect(link_b).await;
+                link_b.connect(link_a).await;
+                let mesh_b = setup_mesh((), link_b);
+
+                sleep(Duration::from_secs(5)).await;
+
+                let payload = MessageData::from([42]);
+                mesh_a.send(payload.clone(), b).await.unwrap();
+
+                sleep(Duration::from_secs(1)).await;
+
+                let (recv, src) = mesh_b.receive().await;
+                assert_eq!(recv, payload);
+                assert_eq!(src, a);
+            })
+            .await;
+    }
+
+    #[tokio::test(flavor = "current_thread")]
+    async fn mesh_send_receive_two() {
+        let local = LocalSet::new();
+
+        let a = Node::new([0, 0, 0, 0, 0, 1]);
+        let b = Node::new([0, 0, 0, 0, 0, 2]);
+        let mut link_a = Box::leak(Box::new(MockLink::new(a)));
+        let mut link_b = Box::leak(Box::new(MockLink::new(b)));
+
+        local
+            .run_until(async {
+                let mesh_a = setup_mesh((), link_a);
+
+                sleep(Duration::from_millis(500)).await;
+                link_a.connect(link_b).await;
+                link_b.connect(link_a).await;
+                let mesh_b = setup_mesh((), link_b);
+
+                sleep(Duration::from_secs(5)).await;
+
+                let payload = MessageData::from([42]);
+                mesh_b.send(payload.clone(), a).await.unwrap();
+
+                sleep(Duration::from_secs(1)).await;
+
+                let (recv, src) = mesh_a.receive().await;
+                assert_eq!(recv, payload);
+                assert_eq!(src, b);
+            })
+            .await;
+    }
+
+    #[tokio::test(flavor = "current_thread")]
+    async fn mesh_fails_fast_for_unreachable_destination() {
+        let local = LocalSet::new();
+
+        let a = Node::new([0, 0, 0, 0, 0, 1]);
+        let unknown = Node::new([0, 0, 0, 0, 0, 9]);
+        let link_a = Box::leak(Box::new(MockLink::new(a)));
+
+        local
+            .run_until(async {
+                let mesh_a = setup_mesh((), link_a);
+
+                for _ in 0..UNREACHABLE_AFTER_FAILURES {
+                    let err = mesh_a.send(MessageData::from([1]), unknown).await;
+                    assert!(matches!(err, Err(MeshError::TreeError(_))));
+                }
+                let err = mesh_a.send(MessageData::from([1]), unknown).await;
+                assert!(matches!(err, Err(MeshError::UnreachableError(n)) if n == unknown));
+
+                let reachability = mesh_a.reachability(unknown).await;
+                assert_eq!(
+                    reachability.map(|r| r.failures),
+                    Some(UNREACHABLE_AFTER_FAILURES as u32)
+                );
+            })
+            .await;
+    }
+
+    #[tokio::test(flavor = "current_thread")]
+    async fn mesh_relay_reports_unroutable_destination() {
+        let local = LocalSet::new();
+
+        let a = Node::new([0, 0, 0, 0, 0, 1]);
+        let b = Node::new([0, 0, 0, 0, 0, 2]);
+        let gone = Node::new([0, 0, 0, 0, 0, 9]);
+        let link_a = Box::leak(Box::new(MockLink::new(a)));
+        let link_b = Box::leak(Box::new(MockLink::new(b)));
+
+        local
+            .run_until(async {
+                let mesh_a = setup_mesh((), link_a);
+
+                sleep(Duration::from_millis(500)).await;
+                link_a.connect(link_b).await;
+                link_b.connect(link_a).await;
+                let _mesh_b = setup_mesh((), link_b);
+
+                sleep(Duration::from_secs(5)).await;
+                mesh_a.tree.lock().await.upsert_edge(Some(b), gone).unwrap();
+
+                mesh_a.send(MessageData::from([1]), gone).await.unwrap();
+                sleep(Duration::from_millis(500)).await;
+
+                let err = mesh_a.send(MessageData::from([1]), gone).await;
+                assert!(matches!(err, Err(MeshError::DestinationUnreachableError(n)) if n == gone));
+                let reachability = mesh_a.reachability(gone).await.unwrap();
+                assert!(reachability.last_report_ms.is_some());
+            })
+            .await;
+    }
+
+    #[tokio::test(flavor = "current_thread")]
+    async fn mesh_full_leader_refuses_joiner() {
+        let local = LocalSet::new();
+
+        let a = Node::new([0, 0, 0, 0, 0, 1]);
+        let b = Node::new([0, 0, 0, 0, 0, 2]);
+        let link_a = Box::leak(Box::new(MockLink::new(a)));
+        let link_b = Box::leak(Box::new(MockLink::new(b)));
+
+        local
+            .run_until(async {
+                let mesh_a = setup_mesh((), link_a);
+                sleep(Duration::from_millis(500)).await;
+                {
+                    let mut tree = mesh_a.tree.lock().await;
+                    for i in 0..MAX_CHILD_LEAFS as u8 {
+                        tree.upsert_edge(None, Node::new([0, 0, 0, 0, 1, i]))
+                            .unwrap();
+                    }
+                }
+
+                link_a.connect(link_b).await;
+                link_b.connect(link_a).await;
+                let mesh_b = setup_mesh((), link_b);
+
+                sleep(Duration::from_secs(10)).await;
+
+                assert!(!mesh_a.tree.lock().await.is_downstream(b));
+                let rejected = |record: &EventRecord| {
+                    matches!(
+                        record.event,
+                        MeshEvent::JoinRejected(node, JoinRejection::NoRoom) if node == b
+                    )
+                };
+                assert!(mesh_a.recent_events().await.iter().any(rejected));
+                let refused = |record: &EventRecord| {
+                    matches!(
+                        record.event,
+                        MeshEvent::JoinRefused(node, JoinRejection::NoRoom) if node == a
+                    )
+                };
+                assert!(mesh_b.recent_events().await.iter().any(refused));
+                assert_eq!(mesh_b.node_count().await, 1);
+            })
+            .await;
+    }
+
+    #[tokio::test(flavor = "current_thread")]
+    async fn mesh_tells_outdated_joiner_why() {
+        let local = LocalSet::new();
+
+        let a = Node::new([0, 0, 0, 0, 0, 1]);
+        let b = Node::new([0, 0, 0, 0, 0, 2]);
+        let link_a = Box::leak(Box::new(MockLink::new(a)));
+        let link_b = Box::leak(Box::new(MockLink::new(b)));
+
+        local
+            .run_until(async {
+                let strict = MeshConfig {
+                    min_firmware: FIRMWARE_VERSION + 1,
+                    ..MeshConfig::default()
+                };
+                let mesh_a = setup_mesh_with_config((), link_a, strict);
+                sleep(Duration::from_millis(500)).await;
+
+                link_a.connect(link_b).await;
+                link_b.connect(link_a).await;
+                let mesh_b = setup_mesh((), link_b);
+
+                sleep(Duration::from_secs(10)).await;
+
+                assert!(!mesh_a.tree.lock().await.is_downstream(b));
+                let refusals = mesh_b
+                    .recent_events()
+                    .await
+                    .iter()
+                    .filter(|record| {
+                        record.event == MeshEvent::JoinRefused(a, JoinRejection::OutdatedFirmware)
+                    })
+                    .count();
+                assert_eq!(refusals, 1);
+            })
+            .await;
+    }
+
+    #[tokio::test(flavor = "current_thread")]
+    async fn mesh_leader_resends_unacknowledged_edges() {
+        let local = LocalSet::new();
+
+        let a = Node::new([0, 0, 0, 0, 0, 1]);
+        let b = Node::new([0, 0, 0, 0, 0, 2]);
+        let c = Node::new([0, 0, 0, 0, 0, 3]);
+        let link_a = Box::leak(Box::new(MockLink::new(a)));
+        let link_b = Box::leak(Box::new(MockLink::new(b)));
+        let link_c = Box::leak(Box::new(MockLink::new(c)));
+
+        local
+            .run_until(async {
+                let mesh_a = setup_mesh((), link_a);
+
+                sleep(Duration::from_millis(500)).await;
+                link_a.connect(link_b).await;
+                link_b.connect(link_a).await;
+                link_a.connect(link_c).await;
+                link_c.connect(link_a).await;
+                let mesh_b = setup_mesh((), link_b);
+                let _mesh_c = setup_mesh((), link_c);
+
+                sleep(Duration::from_secs(5)).await;
+                assert!(
+                    mesh_a
+                        .state
+                        .lock()
+                        .await
+                        .edge_acks
+                        .values()
+                        .all(PendingEdges::is_empty)
+                );
+
+                // Pretend the placement of c never reached b, which b forgot as well.
+                let epoch = mesh_a.tree.lock().await.epoch(c).unwrap();
+                mesh_a.state.lock().await.record_edge_sent(b, epoch);
+                mesh_b.tree.lock().await.upsert_edge(None, c).unwrap();
+
+                sleep(Duration::from_secs(4)).await;
+                assert!(
+                    mesh_a
+                        .state
+                        .lock()
+                        .await
+                        .edge_acks
+                        .values()
+                        .all(PendingEdges::is_empty)
+                );
+                assert_eq!(mesh_b.tree.lock().await.next_hop(c).unwrap(), a);
+            })
+            .await;
+    }
+
+    #[tokio::test(flavor = "current_thread")]
+    async fn mesh_prunes_leaving_node_everywhere() {
+        let local = LocalSet::new();
+
+        let a = Node::new([0, 0, 0, 0, 0, 1]);
+        let b = Node::new([0, 0, 0, 0, 0, 2]);
+        let c = Node::new([0, 0, 0, 0, 0, 3]);
+        let link_a = Box::leak(Box::new(MockLink::new(a)));
+        let link_b = Box::leak(Box::new(MockLink::new(b)));
+        let link_c = Box::leak(Box::new(MockLink::new(c)));
+
+        local
+            .run_until(async {
+                let mesh_a = setup_mesh((), link_a);
+
+                sleep(Duration::from_millis(500)).await;
+                link_a.connect(link_b).await;
+                link_b.connect(link_a).await;
+                link_a.connect(link_c).await;
+                link_c.connect(link_a).await;
+                let mesh_b = setup_mesh((), link_b);
+                let mesh_c = setup_mesh((), link_c);
+
+                sleep(Duration::from_secs(5)).await;
+                assert_eq!(mesh_b.node_count().await, 3);
+                assert!(matches!(
+                    mesh_a.leave().await,
+                    Err(MeshError::TreeError(TreeError::NoUplinkError))
+                ));
+
+                mesh_c.leave().await.unwrap();
+                sleep(Duration::from_millis(500)).await;
+
+                for mesh in [&mesh_a, &mesh_b] {
+                    assert_eq!(mesh.node_count().await, 2);
+                    assert!(
+                        mesh.recent_events()
+                            .await
+                            .iter()
+                            .any(|record| matches!(record.event, MeshEvent::NodeLeft(n) if n == c))
+                    );
+                }
+            })
+            .await;
+    }
+
+    #[tokio::test(flavor = "current_thread")]
+    async fn mesh_ping_measures_round_trip() {
+        let local = LocalSet::new();
+
+        let a = Node::new([0, 0, 0, 0, 0, 1]);
+        let b = Node::new([0, 0, 0, 0, 0, 2]);
+        let link_a = Box::leak(Box::new(MockLink::new(a)));
+        let link_b = Box::leak(Box::new(MockLink::new(b)));
+
+        local
+            .run_until(async {
+                let mesh_a = setup_mesh((), link_a);
+
+                sleep(Duration::from_millis(500)).await;
+                link_a.connect(link_b).await;
+                link_b.connect(link_a).await;
+                let mesh_b = setup_mesh((), link_b);
+
+                sleep(Duration::from_secs(5)).await;
+                let rtt = mesh_b.ping(a).await.unwrap();
+                assert!(rtt < Duration::from_millis(PING_TIMEOUT_MS));
+                mesh_a.ping(b).await.unwrap();
+
+                let gone = Node::new([0, 0, 0, 0, 0, 9]);
+                mesh_a.tree.lock().await.upsert_edge(Some(b), gone).unwrap();
+                let err = mesh_a.ping(gone).await;
+                assert!(matches!(err, Err(MeshError::PingTimeoutError(n)) if n == gone));
+            })
+            .await;
+    }
+
+    #[tokio::test(flavor = "current_thread")]
+    async fn mesh_identify_is_acknowledged_and_expires() {
+        let local = LocalSet::new();
+
+        let a = Node::new([0, 0, 0, 0, 0, 1]);
+        let b = Node::new([0, 0, 0, 0, 0, 2]);
+        let mut network = MockNetwork::new();
+        let link_a = network.add(a);
+        let link_b = network.add(b);
+
+        local
+            .run_until(async {
+                let mesh_a = setup_mesh((), link_a);
+
+                sleep(Duration::from_millis(500)).await;
+                network.connect(a, b).await;
+                let mesh_b = setup_mesh((), link_b);
+
+                sleep(Duration::from_secs(5)).await;
+                assert!(!mesh_b.identifying().await);
+                mesh_a.identify(b, Duration::from_secs(1)).await.unwrap();
+                assert!(mesh_b.identifying().await);
+                assert!(mesh_b.recent_events().await.iter().any(
+                    |record| matches!(record.event, MeshEvent::IdentifyRequested(node) if node == a)
+                ));
+                sleep(Duration::from_secs(1)).await;
+                assert!(!mesh_b.identifying().await);
+
+                network.disconnect(a, b).await;
+                let err = mesh_a.identify(b, Duration::from_secs(1)).await;
+                assert!(err.is_err());
+            })
+            .await;
+    }
+
+    #[tokio::test(flavor = "current_thread")]
+    async fn mesh_leaves_lead_to_eager_node() {
+        let local = LocalSet::new();
+
+        let a = Node::new([0, 0, 0, 0, 0, 1]);
+        let b = Node::new([0, 0, 0, 0, 0, 2]);
+        let link_a = Box::leak(Box::new(MockLink::new(a)));
+        let link_b = Box::leak(Box::new(MockLink::new(b)));
+
+        local
+            .run_until(async {
+                link_a.connect(link_b).await;
+                link_b.connect(link_a).await;
+                let relay = MeshConfig {
+                    lead_after_rounds: u8::MAX,
+                    ..MeshConfig::default()
+                };
+                let mesh_a = setup_mesh_with_config((), link_a, relay);
+                let mesh_b = setup_mesh((), link_b);
+
+                let led = |events: &[EventRecord]| {
+                    events
+                        .iter()
+                        .any(|record| record.event == MeshEvent::BecameLeader)
+                };
+                // a outranks b by its address, so b only leads once it stops deferring to a.
+                for _ in 0..2 * MAX_OUTRANKED_ROUNDS {
+                    if mesh_a.tree.lock().await.uplink().is_some() {
+                        break;
+                    }
+                    sleep(Duration::from_secs(1)).await;
+                }
+                assert!(led(&mesh_b.recent_events().await));
+                assert!(!led(&mesh_a.recent_events().await));
+                assert_eq!(mesh_a.tree.lock().await.uplink(), Some(b));
+            })
+            .await;
+    }
+
+    #[test]
+    fn priority_outranks_address() {
+        let a = Node::new([0, 0, 0, 0, 0, 1]);
+        let b = Node::new([0, 0, 0, 0, 0, 2]);
+        assert!(outranks((0, a), (0, b)));
+        assert!(!outranks((0, b), (0, a)));
+        assert!(outranks((1, b), (0, a)));
+        assert!(!outranks((0, a), (1, b)));
+        assert!(!outranks((0, a), (0, a)));
+    }
+
+    #[tokio::test(flavor = "current_thread")]
+    async fn mesh_elects_node_with_highest_priority() {
+        let local = LocalSet::new();
+
+        let nodes: [Node; 3] = core::array::from_fn(|i| Node::new([0, 0, 0, 0, 0, i as u8 + 1]));
+        let [a, b, c] = nodes;
+        let mut network = MockNetwork::new();
+        let links = nodes.map(|node| network.add(node));
+
+        local
+            .run_until(async {
+                network.connect(a, b).await;
+                network.connect(a, c).await;
+                network.connect(b, c).await;
+                let [link_a, link_b, link_c] = links;
+                let anchor = MeshConfig {
+                    capabilities: Capabilities::new().with_priority(1),
+                    ..MeshConfig::default()
+                };
+                let mesh_a = setup_mesh((), link_a);
+                let mesh_b = setup_mesh_with_config((), link_b, anchor);
+                let mesh_c = setup_mesh((), link_c);
+
+                for _ in 0..10 {
+                    if mesh_a.tree.lock().await.uplink().is_some()
+                        && mesh_c.tree.lock().await.uplink().is_some()
+                    {
+                        break;
+                    }
+                    sleep(Duration::from_secs(1)).await;
+                }
+                assert!(
+                    mesh_b
+                        .recent_events()
+                        .await
+                        .iter()
+                        .any(|record| record.event == MeshEvent::BecameLeader)
+                );
+                assert_eq!(mesh_a.tree.lock().await.uplink(), Some(b));
+                assert_eq!(mesh_c.tree.lock().await.uplink(), Some(b));
+            })
+            .await;
+    }
+
+    #[tokio::test(flavor = "current_thread")]
+    async fn mesh_follows_pinned_leader() {
+        let local = LocalSet::new();
+
+        let nodes: [Node; 3] = core::array::from_fn(|i| Node::new([0, 0, 0, 0, 0, i as u8 + 1]));
+        let [a, b, c] = nodes;
+        let mut network = MockNetwork::new();
+        let links = nodes.map(|node| network.add(node));
+
+        local
+            .run_until(async {
+                let [link_a, link_b, link_c] = links;
+                let never = MeshConfig {
+                    lead: LeadPolicy::Never,
+                    ..MeshConfig::default()
+                };
+                let always = MeshConfig {
+                    lead: LeadPolicy::Always,
+                    ..MeshConfig::default()
+                };
+                let became_leader = async |mesh: &Mesh| {
+                    mesh.recent_events()
+                        .await
+                        .iter()
+                        .any(|record| record.event == MeshEvent::BecameLeader)
+                };
+
+                // Newcomers that hear each other elect a leader, unless neither may lead.
+                network.connect(a, b).await;
+                let mesh_a = setup_mesh_with_config((), link_a, never);
+                let mesh_b = setup_mesh_with_config((), link_b, never);
+                sleep(Duration::from_secs(3)).await;
+                assert!(!became_leader(&mesh_a).await);
+                assert!(!became_leader(&mesh_b).await);
+
+                // A pinned leader leads without hearing anyone.
+                let mesh_c = setup_mesh_with_config((), link_c, always);
+                sleep(Duration::from_millis(100)).await;
+                assert!(became_leader(&mesh_c).await);
+
+                network.connect(b, c).await;
+                for _ in 0..20 {
+                    if mesh_a.tree.lock().await.uplink().is_some() {
+                        break;
+                    }
+                    sleep(Duration::from_secs(1)).await;
+                }
+                assert_eq!(mesh_b.tree.lock().await.uplink(), Some(c));
+                assert_eq!(mesh_a.tree.lock().await.uplink(), Some(b));
+                assert!(!became_leader(&mesh_a).await);
+            })
+            .await;
+    }
+
+    #[tokio::test(flavor = "current_thread")]
+    async fn mesh_role_follows_election() {
+        let local = LocalSet::new();
+
+        let a = Node::new([0, 0, 0, 0, 0, 1]);
+        let b = Node::new([0, 0, 0, 0, 0, 2]);
+        let mut network = MockNetwork::new();
+        let link_a = network.add(a);
+        let link_b = network.add(b);
+
+        local
+            .run_until(async {
+                let always = MeshConfig {
+                    lead: LeadPolicy::Always,
+                    ..MeshConfig::default()
+                };
+                let mesh_b = setup_mesh((), link_b);
+                let mut receiver = mesh_b.role_receiver().await;
+                assert_eq!(mesh_b.role().await, MeshRole::Searching);
+                assert!(!mesh_b.is_connected().await);
+
+                let mesh_a = setup_mesh_with_config((), link_a, always);
+                sleep(Duration::from_millis(100)).await;
+                assert_eq!(mesh_a.role().await, MeshRole::Leader);
+                // Leading alone is no mesh yet.
+                assert!(!mesh_a.is_connected().await);
+
+                network.connect(a, b).await;
+                let role = tokio::time::timeout(Duration::from_secs(10), receiver.changed())
+                    .await
+                    .expect("role did not change");
+                assert_eq!(role, MeshRole::Follower);
+                assert!(mesh_b.is_connected().await);
+                assert!(mesh_a.is_connected().await);
+            })
+            .await;
+    }
+
+    #[tokio::test(flavor = "current_thread")]
+    async fn mesh_leader_keeps_roster_of_members() {
+        let local = LocalSet::new();
+
+        let a = Node::new([0, 0, 0, 0, 0, 1]);
+        let b = Node::new([0, 0, 0, 0, 0, 2]);
+        let link_a = Box::leak(Box::new(MockLink::new(a)));
+        let link_b = Box::leak(Box::new(MockLink::new(b)));
+
+        local
+            .run_until(async {
+                let mesh_a = setup_mesh((), link_a);
+
+                sleep(Duration::from_millis(500)).await;
+                link_a.connect(link_b).await;
+                link_b.connect(link_a).await;
+                let named = MeshConfig {
+                    name: "tag-02",
+                    capabilities: Capabilities::new().with_battery(80),
+                    ..MeshConfig::default()
+                };
+                let mesh_b = setup_mesh_with_config((), link_b, named);
+
+                sleep(Duration::from_secs(5)).await;
+                let roster = mesh_a.roster().await;
+                assert_eq!(roster.len(), 1);
+                assert_eq!(roster[0].node, b);
+                assert_eq!(roster[0].name, "tag-02");
+                assert_eq!(roster[0].short_id, "0002");
+                assert_eq!(roster[0].capabilities.and_then(|c| c.battery), Some(80));
+                assert!(mesh_b.roster().await.is_empty());
+
+                mesh_b.leave().await.unwrap();
+                sleep(Duration::from_millis(500)).await;
+                assert!(mesh_a.roster().await.is_empty());
+            })
+            .await;
+    }
+
+    #[tokio::test(flavor = "current_thread")]
+    async fn mesh_confirms_boot_of_updated_firmware() {
+        let local = LocalSet::new();
+
+        let a = Node::new([0, 0, 0, 0, 0, 1]);
+        let b = Node::new([0, 0, 0, 0, 0, 2]);
+        let link_a = Box::leak(Box::new(MockLink::new(a)));
+        let link_b = Box::leak(Box::new(MockLink::new(b)));
+
+        local
+            .run_until(async {
+                let mesh_a = setup_mesh((), link_a);
+
+                sleep(Duration::from_millis(500)).await;
+                link_a.connect(link_b).await;
+                link_b.connect(link_a).await;
+                let updated = MeshConfig {
+                    capabilities: Capabilities::new().with_image(ImageState::PendingVerify),
+                    ..MeshConfig::default()
+                };
+                let mesh_b = setup_mesh_with_config((), link_b, updated);
+
+                unwrap_print!(mesh_b.confirm_boot().await);
+                let own = mesh_b.capabilities(b).await.unwrap();
+                assert_eq!(own.image, ImageState::Confirmed);
+                let seen = mesh_a.capabilities(b).await.unwrap();
+                assert_eq!(seen.image, ImageState::Confirmed);
+                assert!(
+                    mesh_a
+                        .recent_events()
+                        .await
+                        .iter()
+                        .any(|record| record.event == MeshEvent::BootConfirmed(b))
+                );
+                unwrap_print!(mesh_a.confirm_boot().await);
+            })
+            .await;
+    }
+
+    #[cfg(feature = "postcard")]
+    #[derive(Debug, PartialEq, serde::Serialize, serde::Deserialize)]
+    struct Reading {
+        sensor: u8,
+        value: i32,
+    }
+
+    #[cfg(feature = "postcard")]
+    #[tokio::test(flavor = "current_thread")]
+    async fn mesh_sends_typed_payloads() {
+        let local = LocalSet::new();
+
+        let a = Node::new([0, 0, 0, 0, 0, 1]);
+        let b = Node::new([0, 0, 0, 0, 0, 2]);
+        let link_a = Box::leak(Box::new(MockLink::new(a)));
+        let link_b = Box::leak(Box::new(MockLink::new(b)));
+
+        local
+            .run_until(async {
+                let mesh_a = setup_mesh((), link_a);
+
+                sleep(Duration::from_millis(500)).await;
+                link_a.connect(link_b).await;
+                link_b.connect(link_a).await;
+                let mesh_b = setup_mesh((), link_b);
+
+                sleep(Duration::from_secs(5)).await;
+
+                let reading = Reading {
+                    sensor: 3,
+                    value: -42,
+                };
+                unwrap_print!(mesh_a.send_typed(&reading, b).await);
+
+                let (received, source) = unwrap_print!(mesh_b.receive_typed::<Reading>().await);
+                assert_eq!(received, reading);
+                assert_eq!(source, a);
+            })
+            .await;
+    }
+
+    #[tokio::test(flavor = "current_thread")]
+    async fn mesh_keeps_recent_events() {
+        let local = LocalSet::new();
+
+        let a = Node::new([0, 0, 0, 0, 0, 1]);
+        let b = Node::new([0, 0, 0, 0, 0, 2]);
+        let link_a = Box::leak(Box::new(MockLink::new(a)));
+        let link_b = Box::leak(Box::new(MockLink::new(b)));
+
+        local
+            .run_until(async {
+                let mesh_a = setup_mesh((), link_a);
+
+                sleep(Duration::from_millis(500)).await;
+                link_a.connect(link_b).await;
+                link_b.connect(link_a).await;
+                let mesh_b = setup_mesh((), link_b);
+
+                sleep(Duration::from_secs(5)).await;
+
+                let leader_events: std::vec::Vec<_> = mesh_a
+                    .recent_events()
+                    .await
+                    .iter()
+                    .map(|record| record.event)
+                    .collect();
+                assert_eq!(
+                    leader_events,
+                    [MeshEvent::BecameLeader, MeshEvent::NodeAttached(b)]
+                );
+                let follower_events = mesh_b.recent_events().await;
+                assert_eq!(follower_events[0].event, MeshEvent::Joined(a));
+            })
+            .await;
+    }
+
+    #[tokio::test(flavor = "current_thread")]
+    async fn mesh_run_app_dispatches_messages() {
+        let local = LocalSet::new();
+
+        let a = Node::new([0, 0, 0, 0, 0, 1]);
+        let b = Node::new([0, 0, 0, 0, 0, 2]);
+        let link_a = Box::leak(Box::new(MockLink::new(a)));
+        let link_b = Box::leak(Box::new(MockLink::new(b)));
+
+        local
+            .run_until(async {
+                let mesh_a = setup_mesh((), link_a);
+
+                sleep(Duration::from_millis(500)).await;
+                link_a.connect(link_b).await;
+                link_b.connect(link_a).await;
+                let mesh_b = setup_mesh((), link_b);
+
+                sleep(Duration::from_secs(5)).await;
+
+                mesh_a.send(MessageData::from([1]), b).await.unwrap();
+                mesh_a.send(MessageData::from([2]), b).await.unwrap();
+
+                let mut received = std::vec::Vec::new();
+                let _ = tokio::time::timeout(
+                    Duration::from_secs(1),
+                    mesh_b.run_app(|data, source| received.push((data, source))),
+                )
+                .await;
+                assert_eq!(
+                    received,
+                    [(MessageData::from([1]), a), (MessageData::from([2]), a)]
+                );
+            })
+            .await;
+    }
+
+    #[tokio::test(flavor = "current_thread")]
+    async fn mesh_piggyback_rides_on_next_heartbeat() {
+        let local = LocalSet::new();
+
+        let a = Node::new([0, 0, 0, 0, 0, 1]);
+        let b = Node::new([0, 0, 0, 0, 0, 2]);
+        let link_a = Box::leak(Box::new(MockLink::new(a)));
+        let link_b = Box::leak(Box::new(MockLink::new(b)));
+
+        local
+            .run_until(async {
+                let mesh_a = setup_mesh((), link_a);
+
+                sleep(Duration::from_millis(500)).await;
+                link_a.connect(link_b).await;
+                link_b.connect(link_a).await;
+                let mesh_b = setup_mesh((), link_b);
+
+                sleep(Duration::from_secs(5)).await;
+
+                let payload = MessageData::from([7]);
+                mesh_a
+                    .send_with_class(
+                        payload.clone(),
+                        &Metadata::new(),
+                        TrafficClass::Piggyback,
+                        b,
+                    )
+                    .await
+                    .unwrap();
+                assert_eq!(mesh_a.state.lock().await.piggyback.len(), 1);
+
+                let interval_ms = NetworkConfig::new().keepalive_interval_ms as u64;
+                let (recv, src) = unwrap_print!(
+                    tokio::time::timeout(Duration::from_millis(2 * interval_ms), mesh_b.receive())
+                        .await
+                );
+                assert_eq!(recv, payload);
+                assert_eq!(src, a);
+                assert!(mesh_a.state.lock().await.piggyback.is_empty());
+            })
+            .await;
+    }
+
+    #[tokio::test(flavor = "current_thread")]
+    async fn mesh_network_config_reaches_followers() {
+        let local = LocalSet::new();
+
+        let a = Node::new([0, 0, 0, 0, 0, 1]);
+        let b = Node::new([0, 0, 0, 0, 0, 2]);
+        let link_a = Box::leak(Box::new(MockLink::new(a)));
+        let link_b = Box::leak(Box::new(MockLink::new(b)));
+
+        local
+            .run_until(async {
+                let mesh_a = setup_mesh((), link_a);
+
+                sleep(Duration::from_millis(500)).await;
+                link_a.connect(link_b).await;
+                link_b.connect(link_a).await;
+                let mesh_b = setup_mesh((), link_b);
+
+                sleep(Duration::from_secs(5)).await;
+
+                let config = NetworkConfig {
+                    keepalive_interval_ms: 500,
+                    tx_power: TxPowerPolicy::LowPower,
+                    ..NetworkConfig::new()
+                };
+                mesh_a.set_network_config(config).await;
+
+                sleep(Duration::from_secs(4)).await;
+
+                let received = mesh_b.network_config().await;
+                assert_eq!(received.version, 1);
+                assert_eq!(received.keepalive_interval_ms, 500);
+                assert!(mesh_a.state.lock().await.has_acked_config(b));
+                assert_eq!(link_a.tx_power(), TxPowerPolicy::LowPower);
+                assert_eq!(link_b.tx_power(), TxPowerPolicy::LowPower);
+
+                // b beats its uplink four times as often now.
+                let before = link_b.energy().tx_frames;
+                sleep(Duration::from_millis(2 * HEARTBEAT_INTERVAL_MS)).await;
+                assert!(link_b.energy().tx_frames - before >= 7);
+            })
+            .await;
+    }
+
+    #[tokio::test(flavor = "current_thread")]
+    async fn mesh_monitor_receives_but_never_becomes_parent() {
+        let local = LocalSet::new();
+
+        let a = Node::new([0, 0, 0, 0, 0, 1]);
+        let m = Node::new([0, 0, 0, 0, 0, 2]);
+        let c = Node::new([0, 0, 0, 0, 0, 3]);
+        let link_a = Box::leak(Box::new(MockLink::new(a)));
+        let link_m = Box::leak(Box::new(MockLink::new(m)));
+        let link_c = Box::leak(Box::new(MockLink::new(c)));
+        let monitor = MeshConfig {
+            role: NodeRole::Monitor,
+            ..MeshConfig::default()
+        };
+
+        local
+            .run_until(async {
+                let mesh_a = setup_mesh((), link_a);
+
+                sleep(Duration::from_millis(500)).await;
+                link_a.connect(link_m).await;
+                link_m.connect(link_a).await;
+                let mesh_m = setup_mesh_with_config((), link_m, monitor);
+
+                sleep(Duration::from_secs(5)).await;
+
+                let payload = MessageData::from([42]);
+                mesh_a.send(payload.clone(), m).await.unwrap();
+                let (recv, src) = mesh_m.receive().await;
+                assert_eq!(recv, payload);
+                assert_eq!(src, a);
+
+                link_m.connect(link_c).await;
+                link_c.connect(link_m).await;
+                let _mesh_c = setup_mesh((), link_c);
+
+                sleep(Duration::from_secs(7)).await;
+
+                assert!(mesh_a.tree.lock().await.next_hop(c).is_err());
+            })
+            .await;
+    }
+
+    /// Frame from `x` to `a` with the given sequence, tagged with `key` if there is one.
+    fn ping_frame(x: Node, a: Node, sequence: u16, key: Option<NetworkKey>) -> MessageData {
+        let options = FrameOptions {
+            network_id: 0,
+            key,
+            timestamps: false,
+            session: 1,
+        };
+        let msg = SendMessage::new(a, MessageContent::Ping(1), None, sequence);
+        unwrap_print!(serialize_signed(msg, x, options))
+    }
+
+    #[tokio::test(flavor = "current_thread", start_paused = true)]
+    async fn forged_frames_leave_no_sign_of_life() {
+        let local = LocalSet::new();
+
+        let a = Node::new([0, 0, 0, 0, 0, 1]);
+        let x = Node::new([0, 0, 0, 0, 0, 9]);
+        let key = Some([7; 16]);
+        let link_a = Box::leak(Box::new(MockLink::new(a)));
+        let link_x = Box::leak(Box::new(MockLink::new(x)));
+        let keyed = MeshConfig {
+            network_key: key,
+            ..MeshConfig::default()
+        };
+
+        local
+            .run_until(async {
+                let mesh_a = setup_mesh_with_config((), link_a, keyed);
+                link_a.connect(link_x).await;
+                link_x.connect(link_a).await;
+                {
+                    let mut t = mesh_a.tree.lock().await;
+                    unwrap_print!(t.upsert_edge(None, x));
+                    t.mark_suspect(x);
+                }
+
+                unwrap_print!(link_x.send(ping_frame(x, a, 1, Some([8; 16])), a).await);
+                unwrap_print!(link_x.send(ping_frame(x, a, 2, None), a).await);
+                sleep(Duration::from_millis(100)).await;
+                assert_eq!(mesh_a.last_seen(x).await, None);
+                assert!(mesh_a.tree.lock().await.is_suspect(x));
+                assert!(
+                    !mesh_a
+                        .state
+                        .lock()
+                        .await
+                        .shortcuts
+                        .is_shortcut(x, asynchronous::now_ms())
+                );
+
+                // The forged frame did not use up the sequence of the genuine one.
+                unwrap_print!(link_x.send(ping_frame(x, a, 1, key), a).await);
+                sleep(Duration::from_millis(100)).await;
+                assert!(mesh_a.last_seen(x).await.is_some());
+                assert!(!mesh_a.tree.lock().await.is_suspect(x));
+                assert!(
+                    mesh_a
+                        .state
+                        .lock()
+                        .await
+                        .shortcuts
+                        .is_shortcut(x, asynchronous::now_ms())
+                );
+            })
+            .await;
+    }
+
+    #[tokio::test(flavor = "current_thread")]
+    async fn mesh_encrypted_send_receive() {
+        let local = LocalSet::new();
+
+        let a = Node::new([0, 0, 0, 0, 0, 1]);
+        let b = Node::new([0, 0, 0, 0, 0, 2]);
+        let link_a = Box::leak(Box::new(MockLink::new(a)));
+        let link_b = Box::leak(Box::new(MockLink::new(b)));
+        let keyed = MeshConfig {
+            network_key: Some([7; 16]),
+            ..MeshConfig::default()
+        };
+
+        local
+            .run_until(async {
+                let mesh_a = setup_mesh_with_config((), link_a, keyed);
+
+                sleep(Duration::from_millis(500)).await;
+                link_a.connect(link_b).await;
+                link_b.connect(link_a).await;
+                let mesh_b = setup_mesh_with_config((), link_b, keyed);
+
+                sleep(Duration::from_secs(5)).await;
+
+                let payload = MessageData::from([42, 43, 44]);
+                mesh_a.send(payload.clone(), b).await.unwrap();
+                mesh_b.send(payload.clone(), a).await.unwrap();
+
+                let (recv, src) = mesh_b.receive().await;
+                assert_eq!(recv, payload);
+                assert_eq!(src, a);
+                let (recv, src) = mesh_a.receive().await;
+                assert_eq!(recv, payload);
+                assert_eq!(src, b);
+            })
+            .await;
+    }
+
+    #[tokio::test(flavor = "current_thread")]
+    async fn mesh_metadata_reaches_receiver() {
+        let local = LocalSet::new();
+
+        let a = Node::new([0, 0, 0, 0, 0, 1]);
+        let b = Node::new([0, 0, 0, 0, 0, 2]);
+        let link_a = Box::leak(Box::new(MockLink::new(a)));
+        let link_b = Box::leak(Box::new(MockLink::new(b)));
+        let keyed = MeshConfig {
+            network_key: Some([7; 16]),
+            ..MeshConfig::default()
+        };
+
+        local
+            .run_until(async {
+                let mesh_a = setup_mesh_with_config((), link_a, keyed);
+
+                sleep(Duration::from_millis(500)).await;
+                link_a.connect(link_b).await;
+                link_b.connect(link_a).await;
+                let mesh_b = setup_mesh_with_config((), link_b, keyed);
+
+                sleep(Duration::from_secs(5)).await;
+
+                let payload = MessageData::from([42]);
+                let mut metadata = Metadata::for_port(5);
+                unwrap_print!(metadata.insert(1, &[3]));
+                unwrap_print!(
+                    mesh_a
+                        .send_with_metadata(payload.clone(), &metadata, b)
+                        .await
+                );
+
+                let (recv, recv_metadata, src) = mesh_b.receive_with_metadata().await;
+                assert_eq!(recv, payload);
+                assert_eq!(recv_metadata.get(1), Some(&[3][..]));
+                assert_eq!(recv_metadata.port(), Some(5));
+                assert_eq!(src, a);
+
+                unwrap_print!(mesh_a.send(payload.clone(), b).await);
+                mesh_b.receive().await;
+                let traffic = mesh_b.port_traffic().await;
+                assert_eq!(traffic.len(), 2);
+                assert!(traffic.iter().all(|t| t.peer == a && t.frames == 1));
+                assert_eq!(traffic[0].port, Some(5));
+                assert!(traffic[1].bytes > payload.len() as u32);
+                assert!(mesh_a.port_traffic().await.is_empty());
+            })
+            .await;
+    }
+
+    #[tokio::test(flavor = "current_thread")]
+    async fn mesh_realtime_skips_tree_to_direct_neighbor() {
+        let local = LocalSet::new();
+
+        let a = Node::new([0, 0, 0, 0, 0, 1]);
+        let b = Node::new([0, 0, 0, 0, 0, 2]);
+        let c = Node::new([0, 0, 0, 0, 0, 3]);
+        let link_a = Box::leak(Box::new(MockLink::new(a)));
+        let link_b = Box::leak(Box::new(MockLink::new(b)));
+        let link_c = Box::leak(Box::new(MockLink::new(c)));
+
+        local
+            .run_until(async {
+                let mesh_a = setup_mesh((), link_a);
+
+                sleep(Duration::from_millis(100)).await;
+                link_a.connect(link_b).await;
+                link_b.connect(link_a).await;
+                let _mesh_b = setup_mesh((), link_b);
+                link_a.connect(link_c).await;
+                link_c.connect(link_a).await;
+                let mesh_c = setup_mesh((), link_c);
+
+                sleep(Duration::from_secs(6)).await;
+                // The tree now routes through b, which cannot reach c.
+                unwrap_print!(mesh_a.tree.lock().await.upsert_edge(Some(b), c));
+                assert_eq!(mesh_a.tree.lock().await.next_hop(c).unwrap(), b);
+
+                let payload = MessageData::from([7]);
+                let metadata = Metadata::new();
+                mesh_a
+                    .send_with_class(payload.clone(), &metadata, TrafficClass::Realtime, c)
+                    .await
+                    .unwrap();
+                let (recv, src) = mesh_c.receive().await;
+                assert_eq!(recv, payload);
+                assert_eq!(src, a);
+            })
+            .await;
+    }
+
+    #[tokio::test(flavor = "current_thread")]
+    async fn mesh_shortcuts_carry_every_class_when_enabled() {
+        let local = LocalSet::new();
+
+        let a = Node::new([0, 0, 0, 0, 0, 1]);
+        let b = Node::new([0, 0, 0, 0, 0, 2]);
+        let c = Node::new([0, 0, 0, 0, 0, 3]);
+        let link_a = Box::leak(Box::new(MockLink::new(a)));
+        let link_b = Box::leak(Box::new(MockLink::new(b)));
+        let link_c = Box::leak(Box::new(MockLink::new(c)));
+        let config = MeshConfig {
+            shortcuts: true,
+            ..MeshConfig::default()
+        };
+
+        local
+            .run_until(async {
+                let mesh_a = setup_mesh_with_config((), link_a, config);
+
+                sleep(Duration::from_millis(100)).await;
+                link_a.connect(link_b).await;
+                link_b.connect(link_a).await;
+                let _mesh_b = setup_mesh_with_config((), link_b, config);
+                link_a.connect(link_c).await;
+                link_c.connect(link_a).await;
+                let mesh_c = setup_mesh_with_config((), link_c, config);
+
+                sleep(Duration::from_secs(6)).await;
+                unwrap_print!(mesh_a.tree.lock().await.upsert_edge(Some(b), c));
+
+                let payload = MessageData::from([8]);
+                mesh_a.send(payload.clone(), c).await.unwrap();
+                let (recv, src) = mesh_c.receive().await;
+                assert_eq!(recv, payload);
+                assert_eq!(src, a);
+            })
+            .await;
+    }
+
+    #[tokio::test(flavor = "current_thread")]
+    async fn mesh_stats_count_relayed_frames() {
+        let local = LocalSet::new();
+
+        let a = Node::new([0, 0, 0, 0, 0, 1]);
+        let b = Node::new([0, 0, 0, 0, 0, 2]);
+        let c = Node::new([0, 0, 0, 0, 0, 3]);
+        let mut network = MockNetwork::new();
+        let link_a = network.add(a);
+        let link_b = network.add(b);
+        let link_c = network.add(c);
+
+        local
+            .run_until(async {
+                let mesh_a = setup_mesh((), link_a);
+
+                sleep(Duration::from_millis(100)).await;
+                network.connect(a, b).await;
+                let mesh_b = setup_mesh((), link_b);
+                sleep(Duration::from_secs(6)).await;
+                network.connect(b, c).await;
+                let mesh_c = setup_mesh((), link_c);
+                for _ in 0..20 {
+                    if mesh_c.tree.lock().await.contains(a) {
+                        break;
+                    }
+                    sleep(Duration::from_secs(1)).await;
+                }
+
+                let payload = MessageData::from([3]);
+                mesh_c.send(payload.clone(), a).await.unwrap();
+                let (recv, _) = mesh_a.receive().await;
+                assert_eq!(recv, payload);
+
+                let stats = mesh_b.stats().await;
+                assert_eq!(stats.node_count, 3);
+                assert_eq!(stats.subtrees.as_slice(), &[(c, 1)]);
+                assert!(stats.forwards.forwarded >= 1);
+                assert_eq!(stats.forwards.dropped, 0);
+                assert_eq!(stats.receive_queue, 0);
+
+                let stats = mesh_a.stats().await;
+                assert_eq!(stats.height, 3);
+                assert_eq!(stats.subtrees.as_slice(), &[(b, 2)]);
+            })
+            .await;
+    }
+
+    #[tokio::test(flavor = "current_thread")]
+    async fn mesh_relays_through_split_link_of_gateway() {
+        let local = LocalSet::new();
+
+        let a = Node::new([0, 0, 0, 0, 0, 1]);
+        let gateway = Node::new([0, 0, 0, 0, 0, 2]);
+        let b = Node::new([0, 0, 0, 0, 0, 3]);
+        let mut network = MockNetwork::new();
+        let link_a = network.add(a);
+        let split = |node, udp: &'static UdpLink| -> &'static _ {
+            let via_udp: Selector = Box::leak(Box::new(|node| udp.knows(node)));
+            Box::leak(Box::new(SplitLink::new(MockLink::new(node), udp, via_udp)))
+        };
+        let udp_gateway = Box::leak(Box::new(unwrap_print!(
+            UdpLink::bind(gateway, "127.0.0.1:0").await
+        )));
+        // b is a second gateway out of radio range, reached only over UDP.
+        let udp_b = Box::leak(Box::new(unwrap_print!(
+            UdpLink::bind(b, "127.0.0.1:0").await
+        )));
+        let split_gateway = split(gateway, udp_gateway);
+        let split_b = split(b, udp_b);
+
+        local
+            .run_until(async {
+                let mesh_a = setup_mesh((), link_a);
+
+                sleep(Duration::from_millis(500)).await;
+                split_gateway.primary().connect(link_a).await;
+                link_a.connect(split_gateway.primary()).await;
+                let link_gateway = Box::leak(Box::new(ActiveLink::Split(split_gateway)));
+                let _mesh_gateway = setup_mesh_on((), link_gateway, MeshConfig::default());
+
+                sleep(Duration::from_secs(5)).await;
+                // The gateway learns b from its first datagram that passes the checks.
+                udp_b.add_peer(gateway, unwrap_print!(udp_gateway.local_addr()));
+                let link_b = Box::leak(Box::new(ActiveLink::Split(split_b)));
+                let mesh_b = setup_mesh_on((), link_b, MeshConfig::default());
+                for _ in 0..20 {
+                    if mesh_a.tree.lock().await.contains(b) {
+                        break;
+                    }
+                    sleep(Duration::from_secs(1)).await;
+                }
+                assert_eq!(mesh_a.tree.lock().await.next_hop(b).unwrap(), gateway);
+                assert!(udp_gateway.knows(b));
+
+                let payload = MessageData::from([8]);
+                mesh_a.send(payload.clone(), b).await.unwrap();
+                let (recv, src) = mesh_b.receive().await;
+                assert_eq!(recv, payload);
+                assert_eq!(src, a);
+            })
+            .await;
+    }
+
+    #[tokio::test(flavor = "current_thread")]
+    async fn gateway_learns_udp_peer_only_from_trusted_frames() {
+        let local = LocalSet::new();
+
+        let gateway = Node::new([0, 0, 0, 0, 0, 2]);
+        let x = Node::new([0, 0, 0, 0, 0, 9]);
+        let key = Some([7; 16]);
+        let udp_gateway = Box::leak(Box::new(unwrap_print!(
+            UdpLink::bind(gateway, "127.0.0.1:0").await
+        )));
+        let via_udp: Selector = Box::leak(Box::new(|node| udp_gateway.knows(node)));
+        let split_gateway = Box::leak(Box::new(SplitLink::new(
+            MockLink::new(gateway),
+            &*udp_gateway,
+            via_udp,
+        )));
+        let socket = unwrap_print!(tokio::net::UdpSocket::bind("127.0.0.1:0").await);
+        let to_gateway = unwrap_print!(udp_gateway.local_addr());
+        let datagram = |sequence, key| {
+            let options = FrameOptions {
+                network_id: 0,
+                key,
+                timestamps: false,
+                session: 1,
+            };
+            let msg = SendMessage::new(gateway, MessageContent::Ping(1), None, sequence);
+            let frame = unwrap_print!(serialize_signed(msg, x, options));
+            [&x.mac[..], &gateway.mac[..], &frame[..]].concat()
+        };
+
+        local
+            .run_until(async {
+                let config = MeshConfig {
+                    network_key: key,
+                    ..MeshConfig::default()
+                };
+                let link_gateway = Box::leak(Box::new(ActiveLink::Split(split_gateway)));
+                let _mesh_gateway = setup_mesh_on((), link_gateway, config);
+
+                unwrap_print!(
+                    socket
+                        .send_to(&datagram(1, Some([8; 16])), to_gateway)
+                        .await
+                );
+                unwrap_print!(socket.send_to(&datagram(2, None), to_gateway).await);
+                sleep(Duration::from_millis(200)).await;
+                assert!(!udp_gateway.knows(x));
+
+                unwrap_print!(socket.send_to(&datagram(3, key), to_gateway).await);
+                sleep(Duration::from_millis(200)).await;
+                assert!(udp_gateway.knows(x));
+            })
+            .await;
+    }
+
+    #[tokio::test(flavor = "current_thread")]
+    async fn mesh_moves_node_off_degraded_uplink() {
+        let local = LocalSet::new();
+
+        let a = Node::new([0, 0, 0, 0, 0, 1]);
+        let b = Node::new([0, 0, 0, 0, 0, 2]);
+        let c = Node::new([0, 0, 0, 0, 0, 3]);
+        let mut network = MockNetwork::new();
+        let link_a = network.add(a);
+        let link_b = network.add(b);
+        let link_c = network.add(c);
+
+        local
+            .run_until(async {
+                let mesh_a = setup_mesh((), link_a);
+
+                sleep(Duration::from_millis(100)).await;
+                network.connect(a, b).await;
+                let mesh_b = setup_mesh((), link_b);
+
+                sleep(Duration::from_secs(6)).await;
+                network.set_link_quality(b, c, -70, 0.0).await;
+                let mesh_c = setup_mesh((), link_c);
+
+                sleep(Duration::from_secs(5)).await;
+                assert_eq!(mesh_c.tree.lock().await.uplink(), Some(b));
+
+                // c walks away from b towards a.
+                network.set_link_quality(b, c, -95, 0.0).await;
+                network.connect(a, c).await;
+                for _ in 0..40 {
+                    if mesh_c.tree.lock().await.uplink() == Some(a) {
+                        break;
+                    }
+                    sleep(Duration::from_secs(1)).await;
+                }
+                assert_eq!(mesh_c.tree.lock().await.uplink(), Some(a));
+                assert_eq!(mesh_a.tree.lock().await.parent(c).unwrap(), None);
+                sleep(Duration::from_millis(500)).await;
+                assert_eq!(mesh_b.tree.lock().await.next_hop(c).unwrap(), a);
+
+                let payload = MessageData::from([9]);
+                mesh_b.send(payload.clone(), c).await.unwrap();
+                let (recv, src) = mesh_c.receive().await;
+                assert_eq!(recv, payload);
+                assert_eq!(src, b);
+            })
+            .await;
+    }
+
+    #[tokio::test(flavor = "current_thread")]
+    async fn partition_leads_itself_and_merges_back() {
+        let local = LocalSet::new();
+
+        let a = Node::new([0, 0, 0, 0, 0, 1]);
+        let b = Node::new([0, 0, 0, 0, 0, 2]);
+        let c = Node::new([0, 0, 0, 0, 0, 3]);
+        let d = Node::new([0, 0, 0, 0, 0, 4]);
+        let mut network = MockNetwork::new();
+        let link_a = network.add(a);
+        let link_b = network.add(b);
+        let link_c = network.add(c);
+        let link_d = network.add(d);
+
+        local
+            .run_until(async {
+                let mesh_a = setup_mesh((), link_a);
+
+                sleep(Duration::from_millis(100)).await;
+                network.connect(a, b).await;
+                let _mesh_b = setup_mesh((), link_b);
+
+                sleep(Duration::from_secs(6)).await;
+                network.connect(b, c).await;
+                let mesh_c = setup_mesh((), link_c);
+
+                sleep(Duration::from_secs(5)).await;
+                network.connect(c, d).await;
+                let mesh_d = setup_mesh((), link_d);
+
+                sleep(Duration::from_secs(5)).await;
+                assert_eq!(mesh_d.tree.lock().await.uplink(), Some(c));
+
+                network.disconnect(b, c).await;
+                for _ in 0..20 {
+                    if mesh_c.state.lock().await.partitioned_from.is_some() {
+                        break;
+                    }
+                    sleep(Duration::from_secs(1)).await;
+                }
+                assert_eq!(mesh_c.state.lock().await.partitioned_from, Some(b));
+                assert!(mesh_c.recent_events().await.iter().any(
+                    |record| matches!(record.event, MeshEvent::Partitioned(node) if node == b)
+                ));
+                sleep(Duration::from_secs(1)).await;
+                assert!(!mesh_d.tree.lock().await.contains(a));
+                assert_eq!(mesh_d.tree.lock().await.uplink(), Some(c));
+
+                network.connect(b, c).await;
+                for _ in 0..40 {
+                    if mesh_d.tree.lock().await.contains(a) {
+                        break;
+                    }
+                    sleep(Duration::from_secs(1)).await;
+                }
+                assert_eq!(mesh_c.tree.lock().await.uplink(), Some(b));
+                assert_eq!(mesh_d.tree.lock().await.uplink(), Some(c));
+                assert!(mesh_a.tree.lock().await.is_below(c, d));
+                assert!(mesh_c.state.lock().await.partitioned_from.is_none());
+
+                let payload = MessageData::from([7]);
+                mesh_d.send(payload.clone(), a).await.unwrap();
+                let (recv, src) = mesh_a.receive().await;
+                assert_eq!(recv, payload);
+                assert_eq!(src, d);
+            })
+            .await;
+    }
+
+    #[tokio::test(flavor = "current_thread")]
+    async fn detached_follower_keeps_relaying_below_it() {
+        let local = LocalSet::new();
+
+        let nodes: [Node; 5] = core::array::from_fn(|i| Node::new([0, 0, 0, 0, 0, i as u8 + 1]));
+        let [a, b, c, d, e] = nodes;
+        let mut network = MockNetwork::new();
+        let links = nodes.map(|node| network.add(node));
+
+        local
+            .run_until(async {
+                let [link_a, link_b, link_c, link_d, link_e] = links;
+                let never = MeshConfig {
+                    lead: LeadPolicy::Never,
+                    ..MeshConfig::default()
+                };
+                let mesh_a = setup_mesh((), link_a);
+
+                sleep(Duration::from_millis(100)).await;
+                network.connect(a, b).await;
+                let _mesh_b = setup_mesh((), link_b);
+                sleep(Duration::from_secs(6)).await;
+                network.connect(b, c).await;
+                let mesh_c = setup_mesh_with_config((), link_c, never);
+                sleep(Duration::from_secs(5)).await;
+                network.connect(c, d).await;
+                network.connect(c, e).await;
+                let mesh_d = setup_mesh_with_config((), link_d, never);
+                let mesh_e = setup_mesh_with_config((), link_e, never);
+                for _ in 0..20 {
+                    if mesh_d.tree.lock().await.contains(e) && mesh_e.tree.lock().await.contains(d)
+                    {
+                        break;
+                    }
+                    sleep(Duration::from_secs(1)).await;
+                }
+
+                network.disconnect(b, c).await;
+                for _ in 0..20 {
+                    if mesh_c.state.lock().await.detached_from.is_some() {
+                        break;
+                    }
+                    sleep(Duration::from_secs(1)).await;
+                }
+                assert_eq!(mesh_c.role().await, MeshRole::Searching);
+                assert_eq!(mesh_c.tree.lock().await.detached().as_slice(), &[b]);
+                assert!(
+                    mesh_c.recent_events().await.iter().any(
+                        |record| matches!(record.event, MeshEvent::Detached(node) if node == b)
+                    )
+                );
+
+                let payload = MessageData::from([4]);
+                mesh_d.send(payload.clone(), e).await.unwrap();
+                let (recv, src) = mesh_e.receive().await;
+                assert_eq!(recv, payload);
+                assert_eq!(src, d);
+
+                network.connect(b, c).await;
+                for _ in 0..40 {
+                    if mesh_c.role().await == MeshRole::Follower {
+                        break;
+                    }
+                    sleep(Duration::from_secs(1)).await;
+                }
+                assert_eq!(mesh_c.role().await, MeshRole::Follower);
+                for _ in 0..40 {
+                    if mesh_e.tree.lock().await.contains(a) {
+                        break;
+                    }
+                    sleep(Duration::from_secs(1)).await;
+                }
+                assert!(mesh_c.tree.lock().await.detached().is_empty());
+                assert!(mesh_a.tree.lock().await.is_below(c, e));
+
+                mesh_e.send(payload.clone(), a).await.unwrap();
+                let (recv, src) = mesh_a.receive().await;
+                assert_eq!(recv, payload);
+                assert_eq!(src, e);
+            })
+            .await;
+    }
+
+    #[tokio::test(flavor = "current_thread")]
+    async fn send_skips_hop_that_stopped_acknowledging() {
+        let local = LocalSet::new();
+
+        let a = Node::new([0, 0, 0, 0, 0, 1]);
+        let b = Node::new([0, 0, 0, 0, 0, 2]);
+        let c = Node::new([0, 0, 0, 0, 0, 3]);
+        let mut network = MockNetwork::new();
+        let link_a = network.add(a);
+        let link_b = network.add(b);
+        let link_c = network.add(c);
+
+        local
+            .run_until(async {
+                let mesh_a = setup_mesh((), link_a);
+
+                sleep(Duration::from_millis(100)).await;
+                network.connect(a, b).await;
+                let _mesh_b = setup_mesh((), link_b);
+                sleep(Duration::from_secs(6)).await;
+                network.connect(b, c).await;
+                let mesh_c = setup_mesh((), link_c);
+                for _ in 0..20 {
+                    if mesh_c.tree.lock().await.contains(a) {
+                        break;
+                    }
+                    sleep(Duration::from_secs(1)).await;
+                }
+                assert_eq!(mesh_c.tree.lock().await.uplink(), Some(b));
+
+                // c reaches the leader directly, but its uplink no longer answers.
+                network.connect(a, c).await;
+                network.set_link_quality(b, c, -60, 1.0).await;
+                let payload = MessageData::from([5]);
+                mesh_c.send(payload.clone(), a).await.unwrap();
+                let (recv, src) = mesh_a.receive().await;
+                assert_eq!(recv, payload);
+                assert_eq!(src, c);
+                assert!(mesh_c.tree.lock().await.is_suspect(b));
+
+                // Later frames skip the suspect hop right away.
+                assert_eq!(route(mesh_c.tree, mesh_c.state, a, false).await.unwrap(), a);
+                mesh_c.send(payload.clone(), a).await.unwrap();
+                let (recv, _) = mesh_a.receive().await;
+                assert_eq!(recv, payload);
+            })
+            .await;
+    }
+
+    #[tokio::test(flavor = "current_thread")]
+    async fn leader_adopts_children_of_departed_relay() {
+        let local = LocalSet::new();
+
+        let nodes: [Node; 4] = core::array::from_fn(|i| Node::new([0, 0, 0, 0, 0, i as u8 + 1]));
+        let [a, b, c, d] = nodes;
+        let mut network = MockNetwork::new();
+        let links = nodes.map(|node| network.add(node));
+
+        local
+            .run_until(async {
+                let [link_a, link_b, link_c, link_d] = links;
+                let mesh_a = setup_mesh((), link_a);
+
+                sleep(Duration::from_millis(100)).await;
+                network.connect(a, b).await;
+                network.connect(a, d).await;
+                let mesh_b = setup_mesh((), link_b);
+                let _mesh_d = setup_mesh((), link_d);
+                sleep(Duration::from_secs(6)).await;
+                network.connect(b, c).await;
+                let mesh_c = setup_mesh((), link_c);
+                for _ in 0..20 {
+                    if mesh_c.tree.lock().await.contains(a) {
+                        break;
+                    }
+                    sleep(Duration::from_secs(1)).await;
+                }
+                assert_eq!(mesh_c.tree.lock().await.uplink(), Some(b));
+
+                // c hears d, but not the leader, and tells the leader so.
+                network.connect(c, d).await;
+                for _ in 0..30 {
+                    let state = mesh_a.state.lock().await;
+                    let heard = state.link_reports.get(&c);
+                    if heard.is_some_and(|report| report.iter().any(|(n, _)| *n == d)) {
+                        break;
+                    }
+                    drop(state);
+                    sleep(Duration::from_secs(1)).await;
+                }
+
+                let mut receiver = mesh_c.role_receiver().await;
+                mesh_b.leave().await.unwrap();
+                sleep(Duration::from_millis(200)).await;
+                network.disconnect(a, b).await;
+                network.disconnect(b, c).await;
+                for _ in 0..20 {
+                    if mesh_c.tree.lock().await.uplink() == Some(d) {
+                        break;
+                    }
+                    sleep(Duration::from_secs(1)).await;
+                }
+                assert_eq!(mesh_a.tree.lock().await.parent(c).ok(), Some(Some(d)));
+                assert_eq!(mesh_c.tree.lock().await.uplink(), Some(d));
+                // Moved by the leader, not by searching on its own.
+                let changed =
+                    tokio::time::timeout(Duration::from_millis(10), receiver.changed()).await;
+                assert!(changed.is_err());
+
+                let payload = MessageData::from([6]);
+                mesh_c.send(payload.clone(), a).await.unwrap();
+                let (recv, src) = mesh_a.receive().await;
+                assert_eq!(recv, payload);
+                assert_eq!(src, c);
+            })
+            .await;
+    }
+
+    #[tokio::test(flavor = "current_thread")]
+    async fn leader_hands_over_to_member() {
+        let local = LocalSet::new();
+
+        let a = Node::new([0, 0, 0, 0, 0, 1]);
+        let b = Node::new([0, 0, 0, 0, 0, 2]);
+        let c = Node::new([0, 0, 0, 0, 0, 3]);
+        let d = Node::new([0, 0, 0, 0, 0, 4]);
+        let mut network = MockNetwork::new();
+        let link_a = network.add(a);
+        let link_b = network.add(b);
+        let link_c = network.add(c);
+        let link_d = network.add(d);
+
+        local
+            .run_until(async {
+                let mesh_a = setup_mesh((), link_a);
+
+                sleep(Duration::from_millis(100)).await;
+                network.connect(a, b).await;
+                let mesh_b = setup_mesh((), link_b);
+
+                sleep(Duration::from_secs(6)).await;
+                network.connect(b, c).await;
+                let mesh_c = setup_mesh((), link_c);
+
+                for _ in 0..20 {
+                    if mesh_c.state.lock().await.leader.is_some() {
+                        break;
+                    }
+                    sleep(Duration::from_secs(1)).await;
+                }
+                assert_eq!(mesh_c.state.lock().await.leader, Some(a));
+
+                assert!(matches!(
+                    mesh_b.hand_over_leadership(c).await,
+                    Err(MeshError::NotLeaderError)
+                ));
+                assert!(matches!(
+                    mesh_a.hand_over_leadership(d).await,
+                    Err(MeshError::TreeError(TreeError::NodeNotFoundError))
+                ));
+                mesh_a.hand_over_leadership(c).await.unwrap();
+                for _ in 0..10 {
+                    if mesh_c.tree.lock().await.uplink().is_none() {
+                        break;
+                    }
+                    sleep(Duration::from_secs(1)).await;
+                }
+                assert!(mesh_c.tree.lock().await.uplink().is_none());
+                assert_eq!(mesh_b.tree.lock().await.uplink(), Some(c));
+                assert_eq!(mesh_a.tree.lock().await.uplink(), Some(b));
+                assert_eq!(mesh_a.state.lock().await.leader, Some(c));
+                assert_eq!(mesh_b.state.lock().await.leader, Some(c));
+                assert!(mesh_a.recent_events().await.iter().any(
+                    |record| matches!(record.event, MeshEvent::LeaderHandedOver(node) if node == c)
+                ));
+
+                // The new leader places newcomers.
+                network.connect(a, d).await;
+                let mesh_d = setup_mesh((), link_d);
+                for _ in 0..20 {
+                    if mesh_d.tree.lock().await.uplink().is_some() {
+                        break;
+                    }
+                    sleep(Duration::from_secs(1)).await;
+                }
+                assert_eq!(mesh_d.tree.lock().await.uplink(), Some(a));
+                assert!(mesh_c.tree.lock().await.contains(d));
+
+                let payload = MessageData::from([9]);
+                mesh_d.send(payload.clone(), c).await.unwrap();
+                let (recv, src) = mesh_c.receive().await;
+                assert_eq!(recv, payload);
+                assert_eq!(src, d);
+            })
+            .await;
+    }
+
+    #[tokio::test(flavor = "current_thread")]
+    async fn successor_takes_over_from_silent_leader() {
+        let local = LocalSet::new();
+
+        let a = Node::new([0, 0, 0, 0, 0, 1]);
+        let b = Node::new([0, 0, 0, 0, 0, 2]);
+        let c = Node::new([0, 0, 0, 0, 0, 3]);
+        let d = Node::new([0, 0, 0, 0, 0, 4]);
+        let mut network = MockNetwork::new();
+        let link_a = network.add(a);
+        let link_b = network.add(b);
+        let link_c = network.add(c);
+        let link_d = network.add(d);
+
+        local
+            .run_until(async {
+                let _mesh_a = setup_mesh((), link_a);
+
+                sleep(Duration::from_millis(100)).await;
+                network.connect(a, b).await;
+                let mesh_b = setup_mesh((), link_b);
+
+                sleep(Duration::from_secs(6)).await;
+                network.connect(a, c).await;
+                network.connect(b, c).await;
+                let mesh_c = setup_mesh((), link_c);
+
+                sleep(Duration::from_secs(5)).await;
+                network.connect(c, d).await;
+                let mesh_d = setup_mesh((), link_d);
+
+                for _ in 0..20 {
+                    if mesh_d.state.lock().await.leader.is_some() {
+                        break;
+                    }
+                    sleep(Duration::from_secs(1)).await;
+                }
+                assert_eq!(mesh_d.tree.lock().await.uplink(), Some(c));
+                assert_eq!(mesh_d.state.lock().await.leader, Some(a));
+
+                // The leader dies.
+                network.disconnect(a, b).await;
+                network.disconnect(a, c).await;
+                for _ in 0..30 {
+                    if !mesh_d.tree.lock().await.contains(a) {
+                        break;
+                    }
+                    sleep(Duration::from_secs(1)).await;
+                }
+                assert!(!mesh_d.tree.lock().await.contains(a));
+                assert!(mesh_b.recent_events().await.iter().any(
+                    |record| matches!(record.event, MeshEvent::LeaderLost(node) if node == a)
+                ));
+                assert!(mesh_b.tree.lock().await.uplink().is_none());
+                assert_eq!(mesh_c.tree.lock().await.uplink(), Some(b));
+                assert_eq!(mesh_d.state.lock().await.leader, Some(b));
+                assert_eq!(mesh_d.tree.lock().await.next_hop(b).unwrap(), c);
+
+                let payload = MessageData::from([8]);
+                mesh_d.send(payload.clone(), b).await.unwrap();
+                let (recv, src) = mesh_b.receive().await;
+                assert_eq!(recv, payload);
+                assert_eq!(src, d);
+            })
+            .await;
+    }
+
+    #[tokio::test(flavor = "current_thread", start_paused = true)]
+    async fn followers_keep_leader_with_slow_news_rounds() {
+        let local = LocalSet::new();
+
+        let a = Node::new([0, 0, 0, 0, 0, 1]);
+        let b = Node::new([0, 0, 0, 0, 0, 2]);
+        let c = Node::new([0, 0, 0, 0, 0, 3]);
+        let mut network = MockNetwork::new();
+        let link_a = network.add(a);
+        let link_b = network.add(b);
+        let link_c = network.add(c);
+
+        local
+            .run_until(async {
+                let mesh_a = setup_mesh((), link_a);
+
+                sleep(Duration::from_millis(500)).await;
+                network.connect(a, b).await;
+                let _mesh_b = setup_mesh((), link_b);
+
+                sleep(Duration::from_secs(5)).await;
+                network.connect(b, c).await;
+                let mesh_c = setup_mesh((), link_c);
+
+                for _ in 0..20 {
+                    if mesh_c.state.lock().await.leader.is_some() {
+                        break;
+                    }
+                    sleep(Duration::from_secs(1)).await;
+                }
+                assert_eq!(mesh_c.state.lock().await.leader, Some(a));
+
+                let config = NetworkConfig {
+                    news_interval_ms: MAX_NEWS_INTERVAL_MS as u32,
+                    ..NetworkConfig::new()
+                };
+                mesh_a.set_network_config(config).await;
+
+                // c hears a only through what a sends to the whole mesh.
+                sleep(Duration::from_millis(4 * MAX_NEWS_INTERVAL_MS)).await;
+                assert_eq!(
+                    mesh_c.network_config().await.news_interval_ms,
+                    config.news_interval_ms
+                );
+                assert_eq!(mesh_c.state.lock().await.leader, Some(a));
+                assert!(
+                    !mesh_c
+                        .recent_events()
+                        .await
+                        .iter()
+                        .any(|record| matches!(record.event, MeshEvent::LeaderLost(_)))
+                );
+            })
+            .await;
+    }
+
+    #[tokio::test(flavor = "current_thread")]
+    async fn mesh_notifies_source_of_congested_relay() {
+        let local = LocalSet::new();
+
+        let a = Node::new([0, 0, 0, 0, 0, 1]);
+        let b = Node::new([0, 0, 0, 0, 0, 2]);
+        let c = Node::new([0, 0, 0, 0, 0, 3]);
+        let link_a = Box::leak(Box::new(MockLink::new(a)));
+        let link_b = Box::leak(Box::new(MockLink::new(b)));
+        let link_c = Box::leak(Box::new(MockLink::new(c)));
+
+        local
+            .run_until(async {
+                let mesh_a = setup_mesh((), link_a);
+
+                sleep(Duration::from_millis(100)).await;
+                link_a.connect(link_b).await;
+                link_b.connect(link_a).await;
+                let mesh_b = setup_mesh((), link_b);
+
+                sleep(Duration::from_secs(6)).await;
+                link_b.connect(link_c).await;
+                link_c.connect(link_b).await;
+                let mesh_c = setup_mesh((), link_c);
+
+                sleep(Duration::from_secs(5)).await;
+                assert_eq!(mesh_c.tree.lock().await.uplink(), Some(b));
+
+                let payload = MessageData::from([7]);
+                mesh_c.send(payload.clone(), a).await.unwrap();
+                assert_eq!(mesh_a.receive().await, (payload.clone(), c));
+                sleep(Duration::from_millis(200)).await;
+                assert!(!mesh_c.take_congestion().await);
+
+                link_b.set_backlog(CONGESTION_QUEUE_THRESHOLD);
+                for _ in 0..2 {
+                    mesh_c.send(payload.clone(), a).await.unwrap();
+                    assert_eq!(mesh_a.receive().await, (payload.clone(), c));
+                    sleep(Duration::from_millis(CONGESTION_HOLD_MS)).await;
+                }
+                assert!(mesh_c.take_congestion().await);
+                assert!(!mesh_c.take_congestion().await);
+                assert!(!mesh_b.take_congestion().await);
+            })
+            .await;
+    }
+
+    #[tokio::test(flavor = "current_thread")]
+    async fn parent_keeps_health_piggybacked_on_heartbeats() {
+        let local = LocalSet::new();
+
+        let a = Node::new([0, 0, 0, 0, 0, 1]);
+        let b = Node::new([0, 0, 0, 0, 0, 2]);
+        let link_a = Box::leak(Box::new(MockLink::new(a)));
+        let link_b = Box::leak(Box::new(MockLink::new(b)));
+
+        local
+            .run_until(async {
+                let mesh_a = setup_mesh((), link_a);
+                sleep(Duration::from_millis(500)).await;
+                link_a.connect(link_b).await;
+                link_b.connect(link_a).await;
+                let mesh_b = setup_mesh((), link_b);
+                sleep(Duration::from_secs(5)).await;
+
+                mesh_b
+                    .set_capabilities(Capabilities::new().with_battery(40))
+                    .await;
+                link_b.set_backlog(3);
+                sleep(Duration::from_millis(HEARTBEAT_INTERVAL_MS * 3 / 2)).await;
+
+                let report = mesh_a.child_health(b).await.unwrap();
+                assert_eq!(report.health.battery, Some(40));
+                assert_eq!(report.health.queued, 3);
+                assert!(report.health.uplink_rssi.is_some());
+                assert!(mesh_b.child_health(a).await.is_none());
+            })
+            .await;
     }
-}
 
-#[cfg_attr(feature = "hardware", embassy_executor::task)]
-async fn dispatcher_task(
-    link: &'static ActiveLink,
-    tree: &'static asynchronous::Mutex<Tree>,
-    recv_queue: &'static asynchronous::Channel<(MessageData, Node), RECV_QUEUE_SIZE>,
-    organize_queue: &'static asynchronous::Channel<ReceiveMessage, ORGANIZE_QUEUE_SIZE>,
-) {
-    loop {
-        let data = link.receive().await;
-        let result = (|| async {
-            let msg = ReceiveMessage::new(data.data, data.destination, data.source, data.rssi)
-                .map_err(|e| MeshError::ReceiveMessageError(e))?;
-            if !msg.is_final_destination()
-                && !matches!(MessageType::from(&msg.data), MessageType::Discovery)
-            {
-                let send_msg: SendMessage = msg.into();
-                let next = tree
+    #[tokio::test(flavor = "current_thread")]
+    async fn leader_prunes_silent_members() {
+        let local = LocalSet::new();
+
+        let a = Node::new([0, 0, 0, 0, 0, 1]);
+        let b = Node::new([0, 0, 0, 0, 0, 2]);
+        let ghost = Node::new([0, 0, 0, 0, 0, 3]);
+        let link_a = Box::leak(Box::new(MockLink::new(a)));
+        let link_b = Box::leak(Box::new(MockLink::new(b)));
+        let impatient = MeshConfig {
+            stale_after_ms: 2 * HEARTBEAT_INTERVAL_MS,
+            ..MeshConfig::default()
+        };
+
+        local
+            .run_until(async {
+                let mesh_a = setup_mesh_with_config((), link_a, impatient);
+                sleep(Duration::from_millis(500)).await;
+                link_a.connect(link_b).await;
+                link_b.connect(link_a).await;
+                let mesh_b = setup_mesh((), link_b);
+                sleep(Duration::from_secs(5)).await;
+
+                // A member that crashed without a word.
+                mesh_a.tree.lock().await.upsert_edge(None, ghost).unwrap();
+                mesh_b
+                    .tree
                     .lock()
                     .await
-                    .next_hop(send_msg.final_destination)
-                    .map_err(|e| MeshError::TreeError(e))?;
-                link.try_send(
-                    send_msg
-                        .serialize()
-                        .map_err(|e| MeshError::SerializationError(e))?,
-                    next,
-                )
-                .map_err(|e| MeshError::LinkError(e))?;
-                return Ok(());
-            }
-            if msg.is_organization() {
-                organize_queue
-                    .my_try_send(msg)
-                    .map_err(|e| MeshError::OrganizeQueueSendError())?;
-                return Ok(());
-            }
-            match msg.data {
-                MessageContent::Application(d) => recv_queue
-                    .my_try_send((d, msg.final_source))
-                    .map_err(|e| MeshError::ReceiveQueueSendError())?,
-                _ => (),
-            }
-            Ok::<_, MeshError>(())
-        })()
-        .await;
-        if let Err(e) = result {
-            println!("{}", e);
-        }
+                    .upsert_edge(Some(a), ghost)
+                    .unwrap();
+                sleep(Duration::from_secs(10)).await;
+
+                for mesh in [&mesh_a, &mesh_b] {
+                    let t = mesh.tree.lock().await;
+                    assert!(!t.contains(ghost));
+                }
+                assert!(mesh_a.tree.lock().await.contains(b));
+                assert!(
+                    mesh_a
+                        .recent_events()
+                        .await
+                        .iter()
+                        .any(|record| record.event == MeshEvent::NodeSilent(ghost))
+                );
+            })
+            .await;
     }
-}
 
-#[cfg(test)]
-mod tests {
-    use core::time::Duration;
+    #[tokio::test(flavor = "current_thread")]
+    async fn mesh_refuses_to_route_to_broadcast() {
+        let local = LocalSet::new();
 
-    use super::*;
-    use crate::logic::{
-        link::{ActiveLink, mock::MockLink},
-        message,
-    };
-    use tokio::{task::LocalSet, time::sleep};
+        let a = Node::new([0, 0, 0, 0, 0, 1]);
+        let link_a = Box::leak(Box::new(MockLink::new(a)));
 
-    fn setup_mesh(spawner: asynchronous::Spawner, link: &'static ActiveLink) -> Mesh {
-        let mut tree = Tree::new();
-        tree.init().unwrap();
-        let tree = asynchronous::Mutex::new(tree);
-        let recv_queue: asynchronous::Channel<(MessageData, Node), 16> =
-            asynchronous::Channel::new();
-        let organize_queue: asynchronous::Channel<message::ReceiveMessage, 16> =
-            asynchronous::Channel::new();
-        let mesh = Mesh::new(
-            spawner,
-            link,
-            Box::leak(Box::new(tree)),
-            Box::leak(Box::new(recv_queue)),
-            Box::leak(Box::new(organize_queue)),
-        );
-        mesh.init().unwrap();
-        mesh
+        local
+            .run_until(async {
+                let mesh_a = setup_mesh((), link_a);
+                sleep(Duration::from_millis(500)).await;
+
+                assert!(matches!(
+                    mesh_a.send(MessageData::from([1]), Node::BROADCAST).await,
+                    Err(MeshError::NotUnicastError(_))
+                ));
+                assert!(matches!(
+                    mesh_a.ping(Node::BROADCAST).await,
+                    Err(MeshError::NotUnicastError(_))
+                ));
+            })
+            .await;
     }
 
     #[tokio::test(flavor = "current_thread")]
-    async fn mesh_creation_test() {
+    async fn mesh_ignores_echo_of_its_own_frames() {
         let local = LocalSet::new();
-        let link = Box::leak(Box::new(MockLink::new(Node::new([0, 0, 0, 0, 0, 1]))));
+
+        let a = Node::new([0, 0, 0, 0, 0, 1]);
+        let link_a = Box::leak(Box::new(MockLink::new(a)));
+
         local
             .run_until(async {
-                let _mesh = setup_mesh((), link);
+                let mesh_a = setup_mesh((), link_a);
+                link_a.connect(link_a).await;
+                sleep(Duration::from_secs(3)).await;
+
+                let state = mesh_a.state.lock().await;
+                assert_eq!(state.liveness.last_seen(a), None);
+                assert!(state.neighbors.report(asynchronous::now_ms()).is_empty());
             })
             .await;
     }
 
     #[tokio::test(flavor = "current_thread")]
-    async fn mesh_send_to_self_returns_error() {
+    async fn mesh_resyncs_follower_that_missed_a_placement() {
         let local = LocalSet::new();
-        let self_node = Node::new([0, 0, 0, 0, 0, 1]);
-        let mut link = Box::leak(Box::new(MockLink::new(self_node)));
+
+        let a = Node::new([0, 0, 0, 0, 0, 1]);
+        let b = Node::new([0, 0, 0, 0, 0, 2]);
+        let c = Node::new([0, 0, 0, 0, 0, 3]);
+        let d = Node::new([0, 0, 0, 0, 0, 4]);
+        let link_a = Box::leak(Box::new(MockLink::new(a)));
+        let link_b = Box::leak(Box::new(MockLink::new(b)));
+        let link_c = Box::leak(Box::new(MockLink::new(c)));
+        let link_d = Box::leak(Box::new(MockLink::new(d)));
+
         local
             .run_until(async {
-                let mesh = setup_mesh((), link);
-                let payload = MessageData::from([1, 2, 3]);
-                let result = mesh.send(payload, self_node).await;
-                assert!(result.is_err(), "sending to self must error");
+                let mesh_a = setup_mesh((), link_a);
+
+                sleep(Duration::from_millis(500)).await;
+                link_a.connect(link_b).await;
+                link_b.connect(link_a).await;
+                let mesh_b = setup_mesh((), link_b);
+                sleep(Duration::from_secs(5)).await;
+                link_a.connect(link_c).await;
+                link_c.connect(link_a).await;
+                let _mesh_c = setup_mesh((), link_c);
+                sleep(Duration::from_secs(5)).await;
+
+                // Lose the placement of c on b and one epoch in flight.
+                mesh_b
+                    .tree
+                    .lock()
+                    .await
+                    .remove_node(c, OrphanPolicy::Promote)
+                    .unwrap();
+                mesh_a.tree.lock().await.next_version();
+
+                link_a.connect(link_d).await;
+                link_d.connect(link_a).await;
+                let _mesh_d = setup_mesh((), link_d);
+                sleep(Duration::from_secs(5)).await;
+
+                let t = mesh_b.tree.lock().await;
+                assert!(t.contains(c));
+                assert!(t.contains(d));
+                assert_eq!(t.version(), mesh_a.tree.lock().await.version());
             })
             .await;
     }
 
     #[tokio::test(flavor = "current_thread")]
-    async fn mesh_send_receive_one() {
+    async fn mesh_resyncs_follower_whose_topology_hash_diverged() {
         let local = LocalSet::new();
 
         let a = Node::new([0, 0, 0, 0, 0, 1]);
         let b = Node::new([0, 0, 0, 0, 0, 2]);
-        let mut link_a = Box::leak(Box::new(MockLink::new(a)));
-        let mut link_b = Box::leak(Box::new(MockLink::new(b)));
+        let c = Node::new([0, 0, 0, 0, 0, 3]);
+        let link_a = Box::leak(Box::new(MockLink::new(a)));
+        let link_b = Box::leak(Box::new(MockLink::new(b)));
+        let link_c = Box::leak(Box::new(MockLink::new(c)));
 
         local
             .run_until(async {
@@ -504,49 +6356,134 @@ mod tests {
                 link_a.connect(link_b).await;
                 link_b.connect(link_a).await;
                 let mesh_b = setup_mesh((), link_b);
-
+                sleep(Duration::from_secs(5)).await;
+                link_a.connect(link_c).await;
+                link_c.connect(link_a).await;
+                let _mesh_c = setup_mesh((), link_c);
                 sleep(Duration::from_secs(5)).await;
 
-                let payload = MessageData::from([42]);
-                mesh_a.send(payload.clone(), b).await.unwrap();
-
-                sleep(Duration::from_secs(1)).await;
+                // Lose the last placement on b, no later epoch reveals the gap.
+                mesh_b
+                    .tree
+                    .lock()
+                    .await
+                    .remove_node(c, OrphanPolicy::Promote)
+                    .unwrap();
 
-                let (recv, src) = mesh_b.receive().await;
-                assert_eq!(recv, payload);
-                assert_eq!(src, a);
+                for _ in 0..30 {
+                    if mesh_b.tree.lock().await.contains(c) {
+                        break;
+                    }
+                    sleep(Duration::from_secs(1)).await;
+                }
+                let hash = mesh_a.tree.lock().await.topology_hash(a);
+                assert!(mesh_b.tree.lock().await.contains(c));
+                assert_eq!(mesh_b.tree.lock().await.topology_hash(b), hash);
             })
             .await;
     }
 
     #[tokio::test(flavor = "current_thread")]
-    async fn mesh_send_receive_two() {
+    async fn mesh_handles_share_one_mesh_across_tasks() {
         let local = LocalSet::new();
 
         let a = Node::new([0, 0, 0, 0, 0, 1]);
         let b = Node::new([0, 0, 0, 0, 0, 2]);
-        let mut link_a = Box::leak(Box::new(MockLink::new(a)));
-        let mut link_b = Box::leak(Box::new(MockLink::new(b)));
+        let link_a = Box::leak(Box::new(MockLink::new(a)));
+        let link_b = Box::leak(Box::new(MockLink::new(b)));
 
         local
             .run_until(async {
                 let mesh_a = setup_mesh((), link_a);
-
                 sleep(Duration::from_millis(500)).await;
                 link_a.connect(link_b).await;
                 link_b.connect(link_a).await;
                 let mesh_b = setup_mesh((), link_b);
+                sleep(Duration::from_secs(5)).await;
+
+                let sender = mesh_b.handle();
+                let receiver = mesh_a.handle().clone();
+                let sent = tokio::task::spawn_local(async move {
+                    sender.send(MessageData::from([5]), a).await
+                });
+                let received = tokio::task::spawn_local(async move { receiver.receive().await });
+                sent.await.unwrap().unwrap();
+                assert_eq!(received.await.unwrap(), (MessageData::from([5]), b));
+                assert_eq!(mesh_b.handle().node_count().await, 2);
+            })
+            .await;
+    }
+
+    #[tokio::test(flavor = "current_thread")]
+    async fn mesh_stamps_messages_when_enabled() {
+        let local = LocalSet::new();
+
+        let a = Node::new([0, 0, 0, 0, 0, 1]);
+        let b = Node::new([0, 0, 0, 0, 0, 2]);
+        let link_a = Box::leak(Box::new(MockLink::new(a)));
+        let link_b = Box::leak(Box::new(MockLink::new(b)));
+        let stamped = MeshConfig {
+            timestamps: true,
+            ..MeshConfig::default()
+        };
+
+        local
+            .run_until(async {
+                let mesh_a = setup_mesh_with_config((), link_a, stamped);
+
+                sleep(Duration::from_millis(500)).await;
+                link_a.connect(link_b).await;
+                link_b.connect(link_a).await;
+                let mesh_b = setup_mesh_with_config((), link_b, stamped);
 
                 sleep(Duration::from_secs(5)).await;
 
-                let payload = MessageData::from([42]);
-                mesh_b.send(payload.clone(), a).await.unwrap();
+                unwrap_print!(mesh_a.send(MessageData::from([42]), b).await);
 
-                sleep(Duration::from_secs(1)).await;
+                let delivery = mesh_b.receive_delivery().await;
+                assert_eq!(delivery.source, a);
+                assert!(delivery.sent_ms.is_some());
+                assert!(delivery.latency_ms().is_some());
+            })
+            .await;
+    }
 
-                let (recv, src) = mesh_a.receive().await;
-                assert_eq!(recv, payload);
-                assert_eq!(src, b);
+    #[tokio::test(flavor = "current_thread")]
+    async fn mesh_ignores_other_network() {
+        let local = LocalSet::new();
+
+        let a = Node::new([0, 0, 0, 0, 0, 1]);
+        let b = Node::new([0, 0, 0, 0, 0, 2]);
+        let link_a = Box::leak(Box::new(MockLink::new(a)));
+        let link_b = Box::leak(Box::new(MockLink::new(b)));
+
+        local
+            .run_until(async {
+                let mesh_a = setup_mesh_with_config(
+                    (),
+                    link_a,
+                    MeshConfig {
+                        network_id: 1,
+                        ..MeshConfig::default()
+                    },
+                );
+
+                sleep(Duration::from_millis(500)).await;
+                link_a.connect(link_b).await;
+                link_b.connect(link_a).await;
+                let _mesh_b = setup_mesh_with_config(
+                    (),
+                    link_b,
+                    MeshConfig {
+                        network_id: 2,
+                        ..MeshConfig::default()
+                    },
+                );
+
+                sleep(Duration::from_secs(5)).await;
+
+                let result = mesh_a.send(MessageData::from([42]), b).await;
+                assert!(result.is_err(), "b must not have joined the tree of a");
             })
             .await;
     }
@@ -619,4 +6556,144 @@ mod tests {
             })
             .await;
     }
+
+    /// Join, multi-hop delivery and liveness across five nodes, the reference run for the routing
+    /// stack, and delivery around the relay b once it is lost:
+    ///
+    /// ```text
+    /// a - b - d - e        a
+    ///  \               =>   \
+    ///   c                    c - d - e
+    /// ```
+    #[tokio::test(flavor = "current_thread", start_paused = true)]
+    async fn mesh_lifecycle_join_and_multi_hop_delivery() {
+        let local = LocalSet::new();
+
+        let nodes: [Node; 5] = core::array::from_fn(|i| Node::new([0, 0, 0, 0, 0, i as u8 + 1]));
+        let [a, b, c, d, e] = nodes;
+        let mut network = MockNetwork::new();
+        let [link_a, link_b, link_c, link_d, link_e] = nodes.map(|node| network.add(node));
+
+        local
+            .run_until(async {
+                let mesh_a = setup_mesh((), link_a);
+
+                sleep(Duration::from_millis(500)).await;
+                network.connect(a, b).await;
+                network.connect(a, c).await;
+                let mesh_b = setup_mesh((), link_b);
+                let mesh_c = setup_mesh((), link_c);
+
+                sleep(Duration::from_secs(5)).await;
+                network.connect(b, d).await;
+                let mesh_d = setup_mesh((), link_d);
+
+                sleep(Duration::from_secs(5)).await;
+                network.connect(d, e).await;
+                let tag = Capabilities::new()
+                    .with_battery(40)
+                    .with_features(Features::DISPLAY);
+                let config = MeshConfig {
+                    capabilities: tag,
+                    ..MeshConfig::default()
+                };
+                let mesh_e = setup_mesh_with_config((), link_e, config);
+
+                sleep(Duration::from_secs(5)).await;
+
+                assert_eq!(mesh_a.tree.lock().await.next_hop(e).unwrap(), b);
+                assert_eq!(mesh_e.tree.lock().await.next_hop(c).unwrap(), d);
+                assert_eq!(mesh_d.tree.lock().await.next_hop(c).unwrap(), b);
+
+                let payload = MessageData::from([42]);
+                mesh_a.send(payload.clone(), e).await.unwrap();
+                let (recv, src) = mesh_e.receive().await;
+                assert_eq!(recv, payload);
+                assert_eq!(src, a);
+
+                mesh_e.send(payload.clone(), c).await.unwrap();
+                let (recv, src) = mesh_c.receive().await;
+                assert_eq!(recv, payload);
+                assert_eq!(src, e);
+
+                mesh_c.send(payload.clone(), d).await.unwrap();
+                let (recv, src) = mesh_d.receive().await;
+                assert_eq!(recv, payload);
+                assert_eq!(src, c);
+
+                assert_eq!(mesh_e.trace_route(c).await.unwrap(), [d, b, a, c]);
+                assert_eq!(mesh_a.trace_route(b).await.unwrap(), [b]);
+
+                assert_eq!(mesh_a.capabilities(e).await, Some(tag));
+                assert_eq!(mesh_e.capabilities(e).await, Some(tag));
+                assert_eq!(mesh_b.capabilities(e).await, None);
+
+                assert!(mesh_b.recent_events().await.iter().any(
+                    |record| matches!(record.event, MeshEvent::NodeAttached(node) if node == e)
+                ));
+
+                // Every tree link is kept alive by heartbeats in both directions.
+                sleep(Duration::from_millis(HEARTBEAT_INTERVAL_MS)).await;
+                let now_ms = asynchronous::now_ms();
+                for (mesh, neighbor) in [
+                    (&mesh_a, b),
+                    (&mesh_a, c),
+                    (&mesh_b, a),
+                    (&mesh_b, d),
+                    (&mesh_c, a),
+                    (&mesh_d, b),
+                    (&mesh_d, e),
+                    (&mesh_e, d),
+                ] {
+                    let last_seen = mesh.last_seen(neighbor).await.unwrap();
+                    assert!(now_ms - last_seen <= HEARTBEAT_INTERVAL_MS + 100);
+                }
+                // Frames that came over relays count as well.
+                assert!(mesh_c.last_seen(e).await.is_some());
+                assert_eq!(mesh_c.last_seen(d).await, None);
+
+                // b goes down while d comes into range of c. The leader prunes b once it went
+                // silent, and d moves below c with e still hanging below it.
+                network.disconnect(a, b).await;
+                network.disconnect(b, d).await;
+                network.connect(c, d).await;
+                for _ in 0..60 {
+                    let t = mesh_a.tree.lock().await;
+                    if !t.contains(b) && t.parent(d).ok() == Some(Some(c)) {
+                        break;
+                    }
+                    drop(t);
+                    sleep(Duration::from_secs(1)).await;
+                }
+                {
+                    let t = mesh_a.tree.lock().await;
+                    assert!(!t.contains(b));
+                    assert_eq!(t.parent(d).unwrap(), Some(c));
+                    assert_eq!(t.parent(e).unwrap(), Some(d));
+                    assert_eq!(t.next_hop(e).unwrap(), c);
+                }
+                assert!(
+                    mesh_a
+                        .recent_events()
+                        .await
+                        .iter()
+                        .any(|record| record.event == MeshEvent::NodeSilent(b))
+                );
+                for _ in 0..10 {
+                    if mesh_d.tree.lock().await.uplink() == Some(c) {
+                        break;
+                    }
+                    sleep(Duration::from_secs(1)).await;
+                }
+                assert_eq!(mesh_d.tree.lock().await.uplink(), Some(c));
+                assert_eq!(mesh_e.tree.lock().await.uplink(), Some(d));
+                assert_eq!(mesh_e.tree.lock().await.next_hop(a).unwrap(), d);
+
+                mesh_a.send(payload.clone(), e).await.unwrap();
+                let (recv, src) = mesh_e.receive().await;
+                assert_eq!(recv, payload);
+                assert_eq!(src, a);
+            })
+            .await;
+    }
 }
@@ -1,31 +1,235 @@
 use crate::logic::{
-    error::{CodecError, MessageTypeError, ReceiveMessageError, SendMessageError},
+    capabilities::Capabilities,
+    config::NetworkConfig,
+    consts::{
+        DEFAULT_HOP_LIMIT, JOIN_REFUSED_BACKOFF_MS, MAX_EXTENSION_LEN, MAX_HOP_LIMIT,
+        MAX_ORGANIZATION_BODY_LEN, MAX_TRACE_HOPS, MESSAGE_SIZE, MESSAGE_TAG_LEN,
+        OUTDATED_FIRMWARE_BACKOFF_MS,
+    },
+    error::{CodecError, CursorError, MessageTypeError, ReceiveMessageError, SendMessageError},
+    health::Health,
+    neighbor::LinkReport,
     node::Node,
-    wire::{Cursor, WireCodec},
+    roster::NodeName,
+    topology::{ChunkBitmap, Edge, TopologyChunk, decode_chunk_bitmap, encode_chunk_bitmap},
+    wire::{Cursor, WireCodec, WriteBytes, crc16, decode_varint, decode_varint_u16, encode_varint},
 };
 use heapless::Vec;
 
-pub const MESSAGE_SIZE: usize = 256;
-
 pub type MessageData = Vec<u8, MESSAGE_SIZE>;
+/// Authenticates an organization message, see `crypto::message_tag`.
+pub type MessageTag = [u8; MESSAGE_TAG_LEN];
+/// Nodes a trace route passed, in order.
+pub type TraceHops = Vec<Node, MAX_TRACE_HOPS>;
+/// Optional fields behind the body as received, see `MessageFlags::EXTENDED`.
+pub type Extension = Vec<u8, MAX_EXTENSION_LEN>;
+
+/// Wire format version written at the start of every frame. Optional fields are added behind
+/// `MessageFlags::EXTENDED` instead, which older firmware skips.
+pub const PROTOCOL_VERSION: u8 = 1;
+/// Oldest version that can still be decoded.
+pub const MIN_PROTOCOL_VERSION: u8 = 1;
+
+/// Order in which queued frames are handed to the radio. Sent in the two low bits of the varint
+/// hop-limit field.
+#[repr(u8)]
+#[derive(Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Priority {
+    Low = 0,
+    Normal = 1,
+    High = 2,
+    Critical = 3,
+}
+
+impl Priority {
+    fn from_bits(bits: u8) -> Self {
+        match bits & 0b11 {
+            0 => Priority::Low,
+            1 => Priority::Normal,
+            2 => Priority::High,
+            _ => Priority::Critical,
+        }
+    }
+}
+
+/// How urgently the application needs a payload delivered, see `Mesh::send_with_class`.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub enum TrafficClass {
+    /// Follows the tree.
+    #[default]
+    Standard,
+    /// Proximity based game events that lose their meaning when late. Every node on the way,
+    /// the sender included, hands the frame straight to the destination when it is a shortcut,
+    /// even if the tree routes it through the parent. See `MeshConfig::shortcuts` for the
+    /// other classes.
+    Realtime,
+    /// Small chatty payloads that can wait up to `NetworkConfig::keepalive_interval_ms`. The frame
+    /// waits for the next heartbeat to its next hop, which carries it if there is room. It is sent
+    /// on its own if the queue is full.
+    Piggyback,
+}
+
+/// Why the leader refused a newcomer, see `MessageContent::JoinRejected`.
+#[repr(u8)]
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum JoinRejection {
+    /// The mesh reached `MESH_FULL_WATERMARK`.
+    MeshFull = 0x00,
+    /// None of the members that heard the newcomer has room for another child.
+    NoRoom = 0x01,
+    /// The newcomer runs firmware older than `MeshConfig::min_firmware`.
+    OutdatedFirmware = 0x02,
+}
+
+impl JoinRejection {
+    /// How long the refused node waits before it searches again.
+    pub const fn backoff_ms(self) -> u64 {
+        match self {
+            Self::MeshFull | Self::NoRoom => JOIN_REFUSED_BACKOFF_MS,
+            Self::OutdatedFirmware => OUTDATED_FIRMWARE_BACKOFF_MS,
+        }
+    }
+}
+
+impl TryFrom<u8> for JoinRejection {
+    type Error = CodecError;
+
+    fn try_from(value: u8) -> Result<Self, Self::Error> {
+        match value {
+            0x00 => Ok(JoinRejection::MeshFull),
+            0x01 => Ok(JoinRejection::NoRoom),
+            0x02 => Ok(JoinRejection::OutdatedFirmware),
+            v => Err(CodecError::InvalidJoinRejectionError(v)),
+        }
+    }
+}
+
+impl<const N: usize> WireCodec<N> for JoinRejection {
+    fn encode(&self, out: &mut Vec<u8, N>) -> Result<(), CodecError> {
+        out.write_u8(*self as u8)
+    }
 
-pub const BROADCAST_NODE: Node = Node::new([0xFF; 6]);
+    fn decode(cursor: &mut Cursor<'_>) -> Result<Self, CodecError> {
+        JoinRejection::try_from(cursor.read_u8().map_err(CodecError::CursorReadError)?)
+    }
+}
 
 #[derive(Clone, Debug)]
 pub enum MessageContent {
     Application(MessageData),
-    Discovery,
+    /// Broadcast by a node searching for a mesh, advertising what it brings along.
+    Discovery(Capabilities),
     Invitation,
     RequestNews,
-    SendNew((Node, i32)),
+    /// A newcomer a member heard, the strongest RSSI it heard it with since the last news round,
+    /// how many of its Discoveries it heard in that time and the capabilities it advertised.
+    SendNew((Node, i32), u16, Capabilities),
     FinSendNew,
-    UpsertEdge((Option<Node>, Option<Node>)),
+    /// An edge and the leader epoch it was placed with.
+    UpsertEdge(Edge, u32),
     RequestInitTopology(Node),
+    Config(NetworkConfig),
+    ConfigAck(u16),
+    /// Last message of a panicking node, relayed up to the leader.
+    Crash(Node),
+    /// Several edges of the initial topology in one frame.
+    Topology(TopologyChunk),
+    /// Bitmap of initial topology chunks a joining node is still missing.
+    RequestTopologyChunks(ChunkBitmap),
+    /// Sent back to the final source by a node that has no route to the given destination.
+    Unreachable(Node),
+    /// Refusal of the given newcomer and why. Sent by the leader to the member that reported
+    /// the newcomer, which passes it on to the newcomer directly.
+    JoinRejected(Node, JoinRejection),
+    /// A member applied the `UpsertEdge` with the given epoch.
+    EdgeAck(u32),
+    /// Sent to every neighbor in the tree periodically, so it knows this node is alive. Carries
+    /// the health of the sender, which its parent keeps, and optionally a serialized application
+    /// frame for the receiver as the next hop, see `TrafficClass::Piggyback`.
+    Heartbeat(Health, Option<MessageData>),
+    /// The given node is about to power down. Relayed up to the leader, which passes it on to
+    /// every member so they all prune the node.
+    Leave(Node),
+    /// Asks the final destination to answer with a `Pong` carrying the same correlation ID.
+    Ping(u16),
+    Pong(u16),
+    /// Collects the nodes on the way to the final destination. Every relay appends itself to
+    /// the hops, the destination appends itself and answers with a `TraceRouteReply`.
+    TraceRoute(u16, TraceHops),
+    TraceRouteReply(u16, TraceHops),
+    /// The given node runs freshly updated firmware and reached the mesh. Relayed up to the
+    /// leader, which records the capabilities and answers with `BootConfirmed`.
+    BootHealthy(Node, Capabilities),
+    BootConfirmed,
+    /// The name the given node was provisioned with. Relayed up to the leader after the node
+    /// joined, which keeps it in its roster.
+    Introduce(Node, NodeName),
+    /// The neighbors the given node hears and how strong, sent periodically and relayed up to
+    /// the leader, which moves the node to a better parent once its uplink degrades.
+    LinkReport(Node, LinkReport),
+    /// Sent back to the final source by a relay whose send queue stayed backed up while it
+    /// forwarded application messages of that source. Carries the destination, so the source
+    /// knows which path is backed up.
+    Congested(Node),
+    /// Asks the leader to send every placement again, after a follower noticed that the epoch
+    /// of an `UpsertEdge` skipped placements it never received, see `Tree::observe_version`.
+    RequestResync,
+    /// Sent by the temporary leader of a partition to each of its members once it heard the rest
+    /// of the mesh again. Members drop their tree and search for the mesh like a new node.
+    Remerge,
+    /// Sent by the leader to every member each `LEADER_HEARTBEAT_INTERVAL_MS`, so followers know
+    /// who leads and notice when it went silent. May carry the `Tree::topology_hash` of the
+    /// leader as an extension, a follower whose tree hashes differently requests a resync.
+    LeaderHeartbeat(Option<u32>),
+    /// Sent by the leader to every member when it hands the lead over to the given member, see
+    /// `Mesh::hand_over_leadership`. Carries the topology version of the leader, which the new
+    /// leader continues to stamp its placements from.
+    LeaderHandover(Node, u32),
+    /// Asks the final destination to show where it is for the given milliseconds, e.g. blink
+    /// and show "HERE" on its display, see `Mesh::identify`. Answered with a `Pong` carrying the
+    /// same correlation ID.
+    Identify(u16, u32),
 }
 
-#[repr(u8)]
-#[derive(Copy, Clone, Debug, PartialEq, Eq)]
-pub enum MessageType {
+/// Declares `MessageType` from a single table of wire discriminants. Every `MessageContent`
+/// variant must appear with the same name, the generated `From` match is exhaustive, so a new
+/// content variant without a discriminant does not compile. A discriminant used twice is
+/// rejected by the `repr(u8)` enum.
+macro_rules! message_types {
+    ($($variant:ident = $value:literal),* $(,)?) => {
+        #[repr(u8)]
+        #[derive(Copy, Clone, Debug, PartialEq, Eq)]
+        pub enum MessageType {
+            $($variant = $value),*
+        }
+
+        impl MessageType {
+            /// Every message type in wire order.
+            pub const ALL: &'static [MessageType] = &[$(MessageType::$variant),*];
+        }
+
+        impl From<&MessageContent> for MessageType {
+            fn from(content: &MessageContent) -> Self {
+                match content {
+                    $(MessageContent::$variant { .. } => MessageType::$variant),*
+                }
+            }
+        }
+
+        impl TryFrom<u8> for MessageType {
+            type Error = MessageTypeError;
+
+            fn try_from(value: u8) -> Result<Self, Self::Error> {
+                match value {
+                    $($value => Ok(MessageType::$variant),)*
+                    v => Err(MessageTypeError::InvalidMessageType(v)),
+                }
+            }
+        }
+    };
+}
+
+message_types! {
     Application = 0x01,
     Discovery = 0x02,
     Invitation = 0x03,
@@ -34,37 +238,44 @@ pub enum MessageType {
     FinSendNew = 0x06,
     UpsertEdge = 0x07,
     RequestInitTopology = 0x08,
+    Config = 0x09,
+    ConfigAck = 0x0A,
+    Crash = 0x0B,
+    Topology = 0x0C,
+    RequestTopologyChunks = 0x0D,
+    Unreachable = 0x0E,
+    JoinRejected = 0x0F,
+    EdgeAck = 0x10,
+    Heartbeat = 0x11,
+    Leave = 0x12,
+    Ping = 0x13,
+    Pong = 0x14,
+    TraceRoute = 0x15,
+    TraceRouteReply = 0x16,
+    BootHealthy = 0x17,
+    BootConfirmed = 0x18,
+    Introduce = 0x19,
+    LinkReport = 0x1A,
+    Congested = 0x1B,
+    RequestResync = 0x1C,
+    Remerge = 0x1D,
+    LeaderHeartbeat = 0x1E,
+    LeaderHandover = 0x1F,
+    Identify = 0x20,
 }
 
-impl From<&MessageContent> for MessageType {
-    fn from(content: &MessageContent) -> Self {
-        match content {
-            MessageContent::Application(_) => MessageType::Application,
-            MessageContent::Discovery => MessageType::Discovery,
-            MessageContent::Invitation => MessageType::Invitation,
-            MessageContent::RequestNews => MessageType::RequestNews,
-            MessageContent::SendNew(_) => MessageType::SendNew,
-            MessageContent::FinSendNew => MessageType::FinSendNew,
-            MessageContent::UpsertEdge(_) => MessageType::UpsertEdge,
-            MessageContent::RequestInitTopology(_) => MessageType::RequestInitTopology,
-        }
+impl MessageContent {
+    pub fn is_organization(&self) -> bool {
+        !matches!(self, MessageContent::Application(_))
     }
-}
-
-impl TryFrom<u8> for MessageType {
-    type Error = MessageTypeError;
 
-    fn try_from(value: u8) -> Result<Self, Self::Error> {
-        match value {
-            0x01 => Ok(MessageType::Application),
-            0x02 => Ok(MessageType::Discovery),
-            0x03 => Ok(MessageType::Invitation),
-            0x04 => Ok(MessageType::RequestNews),
-            0x05 => Ok(MessageType::SendNew),
-            0x06 => Ok(MessageType::FinSendNew),
-            0x07 => Ok(MessageType::UpsertEdge),
-            0x08 => Ok(MessageType::RequestInitTopology),
-            v => Err(MessageTypeError::InvalidMessageType(v)),
+    /// Organization traffic goes before application messages so joins and elections keep
+    /// working under load. Crash notices go first, the node is about to reset.
+    pub fn priority(&self) -> Priority {
+        match self {
+            MessageContent::Application(_) => Priority::Normal,
+            MessageContent::Crash(_) => Priority::Critical,
+            _ => Priority::High,
         }
     }
 }
@@ -73,112 +284,567 @@ impl WireCodec<MESSAGE_SIZE> for MessageContent {
     fn encode(&self, out: &mut MessageData) -> Result<(), CodecError> {
         out.push(MessageType::from(self) as u8)
             .map_err(|e| CodecError::BufferOverflowError(e))?;
+        self.encode_body(out)
+    }
+
+    fn decode(cursor: &mut Cursor<'_>) -> Result<Self, CodecError> {
+        let msg_type = MessageType::decode(cursor)?;
+        Self::decode_body(msg_type, cursor)
+    }
+}
+
+impl MessageType {
+    fn decode(cursor: &mut Cursor<'_>) -> Result<Self, CodecError> {
+        let type_byte = cursor.read_u8().map_err(CodecError::CursorReadError)?;
+        MessageType::try_from(type_byte).map_err(|e| CodecError::MessageTypeError(e))
+    }
+}
+
+impl MessageContent {
+    /// Number of bytes the content takes behind the type and flags.
+    pub fn body_len(&self) -> Result<usize, CodecError> {
+        let mut out = MessageData::new();
+        self.encode_body(&mut out)?;
+        Ok(out.len())
+    }
+
+    /// Returns true if the body stays within `MAX_ORGANIZATION_BODY_LEN`, so the message fits
+    /// into one frame whatever header it is sent with.
+    pub fn fits_frame(&self) -> bool {
+        self.body_len()
+            .is_ok_and(|len| len <= MAX_ORGANIZATION_BODY_LEN)
+    }
+
+    /// Encodes the content without its message type.
+    fn encode_body(&self, out: &mut MessageData) -> Result<(), CodecError> {
+        self.encode_fields(out)?;
+        self.encode_extension(out)
+    }
+
+    /// Encodes the fields every firmware knows, without the optional ones behind them.
+    fn encode_fields(&self, out: &mut MessageData) -> Result<(), CodecError> {
         match self {
             Self::Application(d) => {
-                out.push(d.len() as u8)
-                    .map_err(|e| CodecError::BufferOverflowError(e))?;
-                out.extend_from_slice(d)
-                    .map_err(|e| CodecError::BufferCapacityError(e))?;
+                d.encode(out)?;
+            }
+            Self::Discovery(capabilities) => {
+                capabilities.encode(out)?;
             }
-            Self::Discovery => {}
             Self::Invitation => {}
             Self::RequestNews => {}
-            Self::SendNew((n, rssi)) => {
-                n.encode(out).map_err(|_| CodecError::CodecError)?;
-                out.extend_from_slice(&rssi.to_le_bytes())
-                    .map_err(|e| CodecError::BufferCapacityError(e))?;
+            Self::SendNew(news, heard, capabilities) => {
+                news.encode(out)?;
+                encode_varint(*heard as u32, out)?;
+                capabilities.encode(out)?;
             }
             Self::FinSendNew => {}
-            Self::UpsertEdge((n, p)) => {
-                n.encode(out).map_err(|_| CodecError::CodecError)?;
-                p.encode(out).map_err(|_| CodecError::CodecError)?;
+            Self::UpsertEdge(edge, epoch) => {
+                edge.encode(out)?;
+                encode_varint(*epoch, out)?;
             }
             Self::RequestInitTopology(n) => {
-                n.encode(out).map_err(|_| CodecError::CodecError)?;
+                n.encode(out)?;
+            }
+            Self::Config(c) => {
+                c.encode(out)?;
+            }
+            Self::ConfigAck(v) => {
+                encode_varint(*v as u32, out)?;
+            }
+            Self::Crash(n) => {
+                n.encode(out)?;
+            }
+            Self::Topology(chunk) => {
+                chunk.encode(out)?;
+            }
+            Self::RequestTopologyChunks(missing) => {
+                encode_chunk_bitmap(*missing, out)?;
+            }
+            Self::Unreachable(n) => {
+                n.encode(out)?;
+            }
+            Self::JoinRejected(n, reason) => {
+                n.encode(out)?;
+                reason.encode(out)?;
+            }
+            Self::EdgeAck(epoch) => {
+                encode_varint(*epoch, out)?;
+            }
+            Self::Heartbeat(health, bundle) => {
+                health.encode(out)?;
+                bundle.encode(out)?;
+            }
+            Self::Leave(n) => {
+                n.encode(out)?;
+            }
+            Self::Ping(id) | Self::Pong(id) => {
+                encode_varint(*id as u32, out)?;
+            }
+            Self::TraceRoute(id, hops) | Self::TraceRouteReply(id, hops) => {
+                encode_varint(*id as u32, out)?;
+                hops.encode(out)?;
+            }
+            Self::BootHealthy(n, capabilities) => {
+                n.encode(out)?;
+                capabilities.encode(out)?;
+            }
+            Self::BootConfirmed => {}
+            Self::Introduce(n, name) => {
+                n.encode(out)?;
+                name.encode(out)?;
+            }
+            Self::LinkReport(n, report) => {
+                n.encode(out)?;
+                report.encode(out)?;
+            }
+            Self::Congested(n) => {
+                n.encode(out)?;
+            }
+            Self::RequestResync | Self::Remerge | Self::LeaderHeartbeat(_) => {}
+            Self::LeaderHandover(n, epoch) => {
+                n.encode(out)?;
+                encode_varint(*epoch, out)?;
+            }
+            Self::Identify(id, duration_ms) => {
+                encode_varint(*id as u32, out)?;
+                encode_varint(*duration_ms, out)?;
             }
         }
         Ok(())
     }
 
-    fn decode(cursor: &mut Cursor<'_>) -> Result<Self, CodecError> {
-        let type_byte = cursor.take(1).map_err(|e| CodecError::CursorReadError(e))?[0];
-        let msg_type =
-            MessageType::try_from(type_byte).map_err(|e| CodecError::MessageTypeError(e))?;
+    fn decode_body(msg_type: MessageType, cursor: &mut Cursor<'_>) -> Result<Self, CodecError> {
         match msg_type {
             MessageType::Application => {
-                let len_byte = cursor.take(1).map_err(|e| CodecError::CursorReadError(e))?[0];
-                let mut d = MessageData::new();
-                d.extend_from_slice(
-                    cursor
-                        .take(len_byte as usize)
-                        .map_err(|e| CodecError::CursorReadError(e))?,
-                )
-                .map_err(|e| CodecError::BufferCapacityError(e))?;
-                Ok(MessageContent::Application(d))
-            }
-            MessageType::Discovery => Ok(MessageContent::Discovery),
+                let data = <MessageData as WireCodec<MESSAGE_SIZE>>::decode(cursor)?;
+                Ok(MessageContent::Application(data))
+            }
+            MessageType::Discovery => Ok(MessageContent::Discovery(Capabilities::decode(cursor)?)),
             MessageType::Invitation => Ok(MessageContent::Invitation),
             MessageType::RequestNews => Ok(MessageContent::RequestNews),
             MessageType::SendNew => {
-                let n = Node::decode(cursor).map_err(|_| CodecError::CodecError)?;
-                let rssi_bytes = cursor.take(4).map_err(|e| CodecError::CursorReadError(e))?;
-                let rssi =
-                    i32::from_le_bytes(rssi_bytes.try_into().map_err(|_| CodecError::CodecError)?);
-                Ok(MessageContent::SendNew((n, rssi)))
+                let news = WireCodec::decode(cursor)?;
+                let heard = decode_varint_u16(cursor)?;
+                Ok(MessageContent::SendNew(
+                    news,
+                    heard,
+                    Capabilities::decode(cursor)?,
+                ))
             }
             MessageType::FinSendNew => Ok(MessageContent::FinSendNew),
             MessageType::UpsertEdge => {
-                let n = Option::<Node>::decode(cursor).map_err(|_| CodecError::CodecError)?;
-                let p = Option::<Node>::decode(cursor).map_err(|_| CodecError::CodecError)?;
-                Ok(MessageContent::UpsertEdge((n, p)))
+                let edge = Edge::decode(cursor)?;
+                let epoch = decode_varint(cursor)?;
+                Ok(MessageContent::UpsertEdge(edge, epoch))
             }
             MessageType::RequestInitTopology => {
-                let n = Node::decode(cursor).map_err(|_| CodecError::CodecError)?;
-                Ok(MessageContent::RequestInitTopology(n))
+                Ok(MessageContent::RequestInitTopology(Node::decode(cursor)?))
+            }
+            MessageType::Config => Ok(MessageContent::Config(NetworkConfig::decode(cursor)?)),
+            MessageType::ConfigAck => Ok(MessageContent::ConfigAck(decode_varint_u16(cursor)?)),
+            MessageType::Crash => Ok(MessageContent::Crash(Node::decode(cursor)?)),
+            MessageType::Topology => Ok(MessageContent::Topology(TopologyChunk::decode(cursor)?)),
+            MessageType::RequestTopologyChunks => Ok(MessageContent::RequestTopologyChunks(
+                decode_chunk_bitmap(cursor)?,
+            )),
+            MessageType::Unreachable => Ok(MessageContent::Unreachable(Node::decode(cursor)?)),
+            MessageType::JoinRejected => {
+                let node = Node::decode(cursor)?;
+                Ok(MessageContent::JoinRejected(
+                    node,
+                    <JoinRejection as WireCodec<MESSAGE_SIZE>>::decode(cursor)?,
+                ))
+            }
+            MessageType::EdgeAck => Ok(MessageContent::EdgeAck(decode_varint(cursor)?)),
+            MessageType::Heartbeat => {
+                let health = Health::decode(cursor)?;
+                let bundle = <Option<MessageData> as WireCodec<MESSAGE_SIZE>>::decode(cursor)?;
+                Ok(MessageContent::Heartbeat(health, bundle))
+            }
+            MessageType::Leave => Ok(MessageContent::Leave(Node::decode(cursor)?)),
+            MessageType::Ping => Ok(MessageContent::Ping(decode_varint_u16(cursor)?)),
+            MessageType::Pong => Ok(MessageContent::Pong(decode_varint_u16(cursor)?)),
+            MessageType::TraceRoute => {
+                let id = decode_varint_u16(cursor)?;
+                let hops = <TraceHops as WireCodec<MESSAGE_SIZE>>::decode(cursor)?;
+                Ok(MessageContent::TraceRoute(id, hops))
+            }
+            MessageType::TraceRouteReply => {
+                let id = decode_varint_u16(cursor)?;
+                let hops = <TraceHops as WireCodec<MESSAGE_SIZE>>::decode(cursor)?;
+                Ok(MessageContent::TraceRouteReply(id, hops))
+            }
+            MessageType::BootHealthy => {
+                let node = Node::decode(cursor)?;
+                Ok(MessageContent::BootHealthy(
+                    node,
+                    Capabilities::decode(cursor)?,
+                ))
+            }
+            MessageType::BootConfirmed => Ok(MessageContent::BootConfirmed),
+            MessageType::Introduce => {
+                let node = Node::decode(cursor)?;
+                let name = <NodeName as WireCodec<MESSAGE_SIZE>>::decode(cursor)?;
+                Ok(MessageContent::Introduce(node, name))
+            }
+            MessageType::LinkReport => {
+                let node = Node::decode(cursor)?;
+                let report = <LinkReport as WireCodec<MESSAGE_SIZE>>::decode(cursor)?;
+                Ok(MessageContent::LinkReport(node, report))
+            }
+            MessageType::Congested => Ok(MessageContent::Congested(Node::decode(cursor)?)),
+            MessageType::RequestResync => Ok(MessageContent::RequestResync),
+            MessageType::Remerge => Ok(MessageContent::Remerge),
+            MessageType::LeaderHeartbeat => Ok(MessageContent::LeaderHeartbeat(None)),
+            MessageType::LeaderHandover => {
+                let node = Node::decode(cursor)?;
+                Ok(MessageContent::LeaderHandover(node, decode_varint(cursor)?))
+            }
+            MessageType::Identify => {
+                let id = decode_varint_u16(cursor)?;
+                Ok(MessageContent::Identify(id, decode_varint(cursor)?))
             }
         }
     }
+
+    /// Returns true if optional fields follow the body, see `MessageFlags::EXTENDED`.
+    pub fn has_extension(&self) -> bool {
+        matches!(self, Self::LeaderHeartbeat(Some(_)))
+    }
+
+    /// Encodes the optional fields behind the body that are set.
+    fn encode_extension(&self, out: &mut MessageData) -> Result<(), CodecError> {
+        if let Self::LeaderHeartbeat(Some(hash)) = self {
+            hash.encode(out)?;
+        }
+        Ok(())
+    }
+
+    /// Reads the optional fields behind the body that this firmware knows. Fields added by
+    /// newer firmware are left in `cursor`, relays pass them on with `ReceiveMessage::extension`.
+    fn decode_extension(&mut self, cursor: &mut Cursor<'_>) -> Result<(), CodecError> {
+        if let Self::LeaderHeartbeat(hash) = self
+            && !cursor.remaining().is_empty()
+        {
+            *hash = Some(<u32 as WireCodec<MESSAGE_SIZE>>::decode(cursor)?);
+        }
+        Ok(())
+    }
+}
+
+/// Flags sent in the byte right after the message type. The low nibble holds hints a receiver
+/// may ignore. Flags in the high nibble change how the content has to be read, so a receiver
+/// rejects frames carrying one it does not support.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub struct MessageFlags(u8);
+
+impl MessageFlags {
+    /// The sender raised the priority above the default of the content.
+    pub const PRIORITY: Self = Self(0x01);
+    /// The payload belongs to `TrafficClass::Realtime`.
+    pub const REALTIME: Self = Self(0x02);
+    /// Optional fields follow the body. A receiver reads those it knows and skips the rest, so
+    /// new ones need no protocol version.
+    pub const EXTENDED: Self = Self(0x08);
+    /// The application payload is sealed with the network key.
+    pub const ENCRYPTED: Self = Self(0x10);
+    /// Reserved for compressed content.
+    pub const COMPRESSED: Self = Self(0x20);
+    /// Reserved for content split across several frames.
+    pub const FRAGMENTED: Self = Self(0x40);
+    /// The content is followed by the session of the final source and a `MESSAGE_TAG_LEN` byte
+    /// tag.
+    pub const TAGGED: Self = Self(0x80);
+
+    const MANDATORY: u8 = 0xF0;
+    const SUPPORTED: u8 = Self::ENCRYPTED.0 | Self::TAGGED.0;
+
+    pub const fn empty() -> Self {
+        Self(0)
+    }
+
+    pub const fn from_bits(bits: u8) -> Self {
+        Self(bits)
+    }
+
+    pub const fn bits(self) -> u8 {
+        self.0
+    }
+
+    pub const fn contains(self, other: Self) -> bool {
+        self.0 & other.0 == other.0
+    }
+
+    pub fn insert(&mut self, other: Self) {
+        self.0 |= other.0;
+    }
+
+    pub fn remove(&mut self, other: Self) {
+        self.0 &= !other.0;
+    }
+
+    /// The flags a tag covers. `TAGGED` only says whether a tag follows.
+    pub const fn authenticated(self) -> Self {
+        Self(self.0 & !Self::TAGGED.0)
+    }
+
+    /// Mandatory flags this firmware cannot handle, zero if the content can be decoded.
+    pub const fn unsupported(self) -> u8 {
+        self.0 & Self::MANDATORY & !Self::SUPPORTED
+    }
 }
 
 #[derive(Debug)]
 pub struct SendMessage {
     data: MessageContent,
+    pub network_id: u16,
     pub final_destination: Node,
     pub final_source: Option<Node>,
+    pub sequence: u16,
+    pub hop_limit: u16,
+    pub priority: Priority,
+    /// Time of the final source in milliseconds when it sent the message.
+    pub timestamp: Option<u32>,
+    /// `MessageFlags::PRIORITY`, `MessageFlags::EXTENDED` and `MessageFlags::TAGGED` are added
+    /// by `serialize` when needed.
+    pub flags: MessageFlags,
+    /// Session of the final source, covered by the tag and sent in front of it, see
+    /// `ReplayFilter`.
+    pub session: u32,
+    tag: Option<MessageTag>,
+    /// Optional fields of a relayed frame, sent instead of those of the content so fields only
+    /// newer firmware knows reach the final destination unchanged.
+    extension: Option<Extension>,
 }
 
 impl SendMessage {
-    pub fn new(final_destination: Node, data: MessageContent, final_source: Option<Node>) -> Self {
+    pub fn new(
+        final_destination: Node,
+        data: MessageContent,
+        final_source: Option<Node>,
+        sequence: u16,
+    ) -> Self {
         return SendMessage {
+            priority: data.priority(),
             data,
+            network_id: 0,
             final_destination,
             final_source,
+            sequence,
+            hop_limit: DEFAULT_HOP_LIMIT,
+            timestamp: None,
+            flags: MessageFlags::empty(),
+            session: 0,
+            tag: None,
+            extension: None,
         };
     }
 
+    pub fn is_organization(&self) -> bool {
+        self.data.is_organization()
+    }
+
+    /// Bytes covered by the tag of an organization message. `source` is the final source the
+    /// receiver will see, which is the sending node itself when `final_source` is `None`.
+    pub fn authenticated_data(&self, source: Node) -> Result<MessageData, CodecError> {
+        authenticated_data(
+            self.network_id,
+            self.final_destination,
+            self.final_source.unwrap_or(source),
+            self.sequence,
+            self.session,
+            self.wire_flags(),
+            &self.data,
+            self.extension.as_deref(),
+        )
+    }
+
+    /// Flags as sent, with those derived from the priority, the optional fields and the tag.
+    fn wire_flags(&self) -> MessageFlags {
+        let mut flags = self.flags;
+        if self.priority > self.data.priority() {
+            flags.insert(MessageFlags::PRIORITY);
+        }
+        flags.remove(MessageFlags::EXTENDED);
+        if self.extension.is_some() || self.data.has_extension() {
+            flags.insert(MessageFlags::EXTENDED);
+        }
+        flags.remove(MessageFlags::TAGGED);
+        if self.tag.is_some() {
+            flags.insert(MessageFlags::TAGGED);
+        }
+        flags
+    }
+
+    /// Attaches a tag, which is sent after the content and the session.
+    pub fn set_tag(&mut self, tag: MessageTag) {
+        self.tag = Some(tag);
+    }
+
+    /// Uses up one hop before the message is forwarded. Returns false once no hops are left,
+    /// in which case the message must be dropped.
+    pub fn consume_hop(&mut self) -> bool {
+        consume_hop(&mut self.hop_limit)
+    }
+
     pub fn serialize(&self) -> Result<MessageData, SendMessageError> {
         let mut out = MessageData::new();
-        self.data
-            .encode(&mut out)
+        let header = Header {
+            version: PROTOCOL_VERSION,
+            network_id: self.network_id,
+            final_destination: self.final_destination,
+            final_source: self.final_source,
+            sequence: self.sequence,
+            hop_limit: self.hop_limit,
+            priority: self.priority,
+            timestamp: self.timestamp,
+        };
+        header.encode(&mut out)?;
+        out.write_u8(MessageType::from(&self.data) as u8)
+            .map_err(SendMessageError::MessageTypeEncodeError)?;
+        out.write_u8(self.wire_flags().bits())
+            .map_err(SendMessageError::FlagsEncodeError)?;
+        encode_content(&self.data, self.extension.as_deref(), &mut out)
             .map_err(|e| SendMessageError::MessageTypeEncodeError(e))?;
+        if let Some(tag) = &self.tag {
+            out.extend_from_slice(&self.session.to_le_bytes())
+                .map_err(SendMessageError::MessageTooLargeError)?;
+            out.extend_from_slice(tag)
+                .map_err(SendMessageError::MessageTooLargeError)?;
+        }
+        append_checksum(&mut out)?;
+        Ok(out)
+    }
+}
+
+/// Routing fields at the start of every frame. Their layout must stay the same across protocol
+/// versions so that frames from newer firmware can still be relayed. Network ID, sequence
+/// number and the hop limit, which carries the priority in its two lowest bits, are varints, so
+/// small values take fewer bytes.
+#[derive(Copy, Clone, Debug)]
+struct Header {
+    version: u8,
+    /// Keeps co-located deployments on the same channel apart.
+    network_id: u16,
+    final_destination: Node,
+    final_source: Option<Node>,
+    sequence: u16,
+    hop_limit: u16,
+    priority: Priority,
+    /// Set by sources that stamp their frames, relays pass it on unchanged.
+    timestamp: Option<u32>,
+}
+
+impl Header {
+    fn encode(&self, out: &mut MessageData) -> Result<(), SendMessageError> {
+        out.write_u8(self.version)
+            .map_err(SendMessageError::VersionEncodeError)?;
+        encode_varint(self.network_id as u32, out)
+            .map_err(SendMessageError::NetworkIdEncodeError)?;
         self.final_destination
-            .encode(&mut out)
+            .encode(out)
             .map_err(|e| SendMessageError::FinalDestinationEncodeError(e))?;
         self.final_source
-            .encode(&mut out)
+            .encode(out)
             .map_err(|e| SendMessageError::FinalSourceEncodeError(e))?;
-        Ok(out)
+        encode_varint(self.sequence as u32, out).map_err(SendMessageError::SequenceEncodeError)?;
+        let hop_limit = self.hop_limit.min(MAX_HOP_LIMIT) as u32;
+        encode_varint(hop_limit << 2 | self.priority as u32, out)
+            .map_err(SendMessageError::HopLimitEncodeError)?;
+        self.timestamp
+            .encode(out)
+            .map_err(SendMessageError::TimestampEncodeError)?;
+        Ok(())
+    }
+
+    fn decode(cursor: &mut Cursor<'_>) -> Result<Self, ReceiveMessageError> {
+        let version = cursor
+            .read_u8()
+            .map_err(|e| ReceiveMessageError::VersionDecodeError(CodecError::CursorReadError(e)))?;
+        if version < MIN_PROTOCOL_VERSION {
+            return Err(ReceiveMessageError::UnsupportedVersionError(version));
+        }
+        let network_id =
+            decode_varint_u16(cursor).map_err(ReceiveMessageError::NetworkIdDecodeError)?;
+        let final_destination = Node::decode(cursor)
+            .map_err(|e| ReceiveMessageError::FinalDestinationDecodeError(e))?;
+        let final_source = Option::<Node>::decode(cursor)
+            .map_err(|e| ReceiveMessageError::FinalSourceDecodeError(e))?;
+        let sequence =
+            decode_varint_u16(cursor).map_err(ReceiveMessageError::SequenceDecodeError)?;
+        let hop_field =
+            decode_varint_u16(cursor).map_err(ReceiveMessageError::HopLimitDecodeError)?;
+        let timestamp = <Option<u32> as WireCodec<MESSAGE_SIZE>>::decode(cursor)
+            .map_err(ReceiveMessageError::TimestampDecodeError)?;
+        Ok(Header {
+            version,
+            network_id,
+            final_destination,
+            final_source,
+            sequence,
+            hop_limit: hop_field >> 2,
+            priority: Priority::from_bits(hop_field as u8),
+            timestamp,
+        })
+    }
+}
+
+/// A received frame. Frames from older firmware down to `MIN_PROTOCOL_VERSION` are decoded with
+/// the current codec, since those versions only ever append message types. Optional fields
+/// behind the body are flagged with `MessageFlags::EXTENDED`. The ones this firmware does not
+/// know are skipped, and relays forward them unchanged under the tag. Frames from newer firmware
+/// are kept opaque so they can still be routed.
+#[derive(Debug)]
+pub enum Frame {
+    Message(ReceiveMessage),
+    Opaque(OpaqueMessage),
+}
+
+impl Frame {
+    pub fn parse(
+        payload: MessageData,
+        destination: Node,
+        source: Node,
+        rssi: i32,
+    ) -> Result<Self, ReceiveMessageError> {
+        match payload.first() {
+            Some(&version) if version > PROTOCOL_VERSION => Ok(Frame::Opaque(OpaqueMessage::new(
+                payload,
+                destination,
+                source,
+            )?)),
+            _ => Ok(Frame::Message(ReceiveMessage::new(
+                payload,
+                destination,
+                source,
+                rssi,
+            )?)),
+        }
     }
 }
 
 #[derive(Debug)]
 pub struct ReceiveMessage {
     pub data: MessageContent,
+    /// The optional fields behind the body as received, known to this firmware or not. They
+    /// are covered by the tag and forwarded unchanged.
+    pub extension: Option<Extension>,
+    pub tag: Option<MessageTag>,
+    /// Session of the final source, zero on frames without tag.
+    pub session: u32,
+    pub network_id: u16,
     pub final_destination: Node,
     pub destination: Node,
     pub source: Node,
     pub final_source: Node,
+    pub sequence: u16,
+    pub hop_limit: u16,
+    pub priority: Priority,
+    pub timestamp: Option<u32>,
+    pub flags: MessageFlags,
     pub rssi: i32,
+    /// Local time in milliseconds when the frame arrived, set by the dispatcher.
+    pub received_ms: u64,
 }
 
 impl ReceiveMessage {
@@ -188,24 +854,65 @@ impl ReceiveMessage {
         source: Node,
         rssi: i32,
     ) -> Result<Self, ReceiveMessageError> {
-        let mut cursor = Cursor::new(&payload);
-        let data = MessageContent::decode(&mut cursor)
+        let body = verify_checksum(&payload).map_err(ReceiveMessageError::ChecksumDecodeError)?;
+        let mut cursor = Cursor::new(body);
+        let header = Header::decode(&mut cursor)?;
+        if header.version > PROTOCOL_VERSION {
+            return Err(ReceiveMessageError::UnsupportedVersionError(header.version));
+        }
+        let msg_type = MessageType::decode(&mut cursor)
+            .map_err(ReceiveMessageError::MessageTypeDecodeError)?;
+        let flags =
+            MessageFlags::from_bits(cursor.read_u8().map_err(|e| {
+                ReceiveMessageError::FlagsDecodeError(CodecError::CursorReadError(e))
+            })?);
+        if flags.unsupported() != 0 {
+            return Err(ReceiveMessageError::UnsupportedFlagsError(flags.bits()));
+        }
+        let mut content = cursor.remaining();
+        let (mut session, mut tag) = (0, None);
+        if flags.contains(MessageFlags::TAGGED) {
+            let split = content.len().saturating_sub(4 + MESSAGE_TAG_LEN);
+            let mut signature = Cursor::new(&content[split..]);
+            let read_error =
+                |e| ReceiveMessageError::TagDecodeError(CodecError::CursorReadError(e));
+            session = signature.read_u32_le().map_err(read_error)?;
+            tag = Some(signature.read_array().map_err(read_error)?);
+            content = &content[..split];
+        }
+        let mut cursor = Cursor::new(content);
+        let mut data = MessageContent::decode_body(msg_type, &mut cursor)
             .map_err(|e| ReceiveMessageError::MessageTypeDecodeError(e))?;
-        let final_destination = Node::decode(&mut cursor)
-            .map_err(|e| ReceiveMessageError::FinalDestinationDecodeError(e))?;
-        let final_source = match Option::<Node>::decode(&mut cursor)
-            .map_err(|e| ReceiveMessageError::FinalSourceDecodeError(e))?
-        {
-            Some(val) => val,
-            None => source,
-        };
+        let mut extension = None;
+        if flags.contains(MessageFlags::EXTENDED) {
+            extension = Some(
+                Extension::from_slice(cursor.remaining())
+                    .map_err(ReceiveMessageError::BufferOverflowError)?,
+            );
+            data.decode_extension(&mut cursor)
+                .map_err(ReceiveMessageError::MessageTypeDecodeError)?;
+        } else if !cursor.remaining().is_empty() {
+            return Err(ReceiveMessageError::TrailingBytesError(
+                cursor.remaining().len(),
+            ));
+        }
         Ok(ReceiveMessage {
             data,
+            extension,
+            tag,
+            session,
+            network_id: header.network_id,
             destination,
             source: source,
-            final_destination,
-            final_source,
+            final_destination: header.final_destination,
+            final_source: header.final_source.unwrap_or(source),
+            sequence: header.sequence,
+            hop_limit: header.hop_limit,
+            priority: header.priority,
+            timestamp: header.timestamp,
+            flags,
             rssi,
+            received_ms: 0,
         })
     }
 
@@ -214,32 +921,202 @@ impl ReceiveMessage {
     }
 
     pub fn is_organization(&self) -> bool {
-        match MessageType::from(&self.data) {
-            MessageType::Discovery => true,
-            MessageType::SendNew => true,
-            MessageType::Invitation => true,
-            MessageType::FinSendNew => true,
-            MessageType::RequestNews => true,
-            MessageType::UpsertEdge => true,
-            MessageType::RequestInitTopology => true,
-            _ => false,
+        self.data.is_organization()
+    }
+
+    /// Bytes covered by the tag of an organization message.
+    pub fn authenticated_data(&self) -> Result<MessageData, CodecError> {
+        authenticated_data(
+            self.network_id,
+            self.final_destination,
+            self.final_source,
+            self.sequence,
+            self.session,
+            self.flags,
+            &self.data,
+            self.extension.as_deref(),
+        )
+    }
+}
+
+/// A frame from newer firmware. Only its routing header is understood, the content is relayed
+/// unchanged.
+#[derive(Debug)]
+pub struct OpaqueMessage {
+    header: Header,
+    content: MessageData,
+    destination: Node,
+    final_source: Node,
+}
+
+impl OpaqueMessage {
+    pub fn new(
+        payload: MessageData,
+        destination: Node,
+        source: Node,
+    ) -> Result<Self, ReceiveMessageError> {
+        let body = verify_checksum(&payload).map_err(ReceiveMessageError::ChecksumDecodeError)?;
+        let mut cursor = Cursor::new(body);
+        let mut header = Header::decode(&mut cursor)?;
+        let final_source = header.final_source.unwrap_or(source);
+        header.final_source = Some(final_source);
+        let mut content = MessageData::new();
+        content
+            .extend_from_slice(cursor.remaining())
+            .map_err(ReceiveMessageError::BufferOverflowError)?;
+        Ok(OpaqueMessage {
+            header,
+            content,
+            destination,
+            final_source,
+        })
+    }
+
+    pub fn version(&self) -> u8 {
+        self.header.version
+    }
+
+    pub fn network_id(&self) -> u16 {
+        self.header.network_id
+    }
+
+    pub fn final_destination(&self) -> Node {
+        self.header.final_destination
+    }
+
+    pub fn final_source(&self) -> Node {
+        self.final_source
+    }
+
+    pub fn sequence(&self) -> u16 {
+        self.header.sequence
+    }
+
+    pub fn priority(&self) -> Priority {
+        self.header.priority
+    }
+
+    pub fn is_final_destination(&self) -> bool {
+        self.header.final_destination == self.destination
+    }
+
+    pub fn consume_hop(&mut self) -> bool {
+        consume_hop(&mut self.header.hop_limit)
+    }
+
+    pub fn serialize(&self) -> Result<MessageData, SendMessageError> {
+        let mut out = MessageData::new();
+        self.header.encode(&mut out)?;
+        out.extend_from_slice(&self.content)
+            .map_err(SendMessageError::MessageTooLargeError)?;
+        append_checksum(&mut out)?;
+        Ok(out)
+    }
+}
+
+fn consume_hop(hop_limit: &mut u16) -> bool {
+    match hop_limit.checked_sub(1) {
+        Some(remaining) => {
+            *hop_limit = remaining;
+            true
         }
+        None => false,
     }
 }
 
+fn append_checksum(out: &mut MessageData) -> Result<(), SendMessageError> {
+    let checksum = crc16(out);
+    out.write_u16_le(checksum)
+        .map_err(SendMessageError::ChecksumEncodeError)
+}
+
+/// Splits off the trailing CRC-16 and checks it against the rest of the frame.
+fn verify_checksum(payload: &[u8]) -> Result<&[u8], CodecError> {
+    if payload.len() < 2 {
+        return Err(CodecError::CursorReadError(
+            CursorError::BufferUnderflowError,
+        ));
+    }
+    let (body, checksum_bytes) = payload.split_at(payload.len() - 2);
+    let expected = u16::from_le_bytes([checksum_bytes[0], checksum_bytes[1]]);
+    let actual = crc16(body);
+    if expected != actual {
+        return Err(CodecError::ChecksumMismatch(expected, actual));
+    }
+    Ok(body)
+}
+
 impl Into<SendMessage> for ReceiveMessage {
     fn into(self) -> SendMessage {
         SendMessage {
+            network_id: self.network_id,
             final_destination: self.final_destination,
             final_source: Some(self.final_source),
+            sequence: self.sequence,
+            hop_limit: self.hop_limit,
+            priority: self.priority,
+            timestamp: self.timestamp,
+            flags: self.flags,
             data: self.data,
+            session: self.session,
+            tag: self.tag,
+            extension: self.extension,
         }
     }
 }
 
+/// Encodes the body of `content` followed by `extension` if given, else by the optional fields
+/// of `content`.
+fn encode_content(
+    content: &MessageContent,
+    extension: Option<&[u8]>,
+    out: &mut MessageData,
+) -> Result<(), CodecError> {
+    content.encode_fields(out)?;
+    match extension {
+        Some(extension) => out.write_bytes(extension),
+        None => content.encode_extension(out),
+    }
+}
+
+/// Routing fields that stay the same on every hop and the session of the final source, followed
+/// by the encoded content with its flags and optional fields as sent. The hop limit, the hops of
+/// a trace route and the flags relays set are left out since relays change them.
+#[allow(clippy::too_many_arguments)]
+fn authenticated_data(
+    network_id: u16,
+    final_destination: Node,
+    final_source: Node,
+    sequence: u16,
+    session: u32,
+    flags: MessageFlags,
+    content: &MessageContent,
+    extension: Option<&[u8]>,
+) -> Result<MessageData, CodecError> {
+    let mut out = MessageData::new();
+    out.write_u16_le(network_id)?;
+    final_destination.encode(&mut out)?;
+    final_source.encode(&mut out)?;
+    out.write_u16_le(sequence)?;
+    out.write_bytes(&session.to_le_bytes())?;
+    let content = match content {
+        MessageContent::TraceRoute(id, _) => &MessageContent::TraceRoute(*id, TraceHops::new()),
+        content => content,
+    };
+    out.write_u8(MessageType::from(content) as u8)?;
+    out.write_u8(flags.authenticated().bits())?;
+    encode_content(content, extension, &mut out)?;
+    Ok(out)
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::logic::{
+        capabilities::{Features, ImageState},
+        consts::MAX_FRAME_OVERHEAD,
+        crypto,
+    };
     use crate::unwrap_print;
 
     #[test]
@@ -253,9 +1130,23 @@ mod tests {
         assert_eq!(MessageType::from(&decoded), MessageType::Application);
     }
 
+    #[test]
+    fn test_message_type_discriminants_round_trip() {
+        for (i, message_type) in MessageType::ALL.iter().enumerate() {
+            let byte = *message_type as u8;
+            assert_eq!(unwrap_print!(MessageType::try_from(byte)), *message_type);
+            assert!(
+                MessageType::ALL[..i]
+                    .iter()
+                    .all(|other| *other as u8 != byte)
+            );
+        }
+        assert!(MessageType::try_from(0x00).is_err());
+    }
+
     #[test]
     fn test_upsert_message_type_encode_decode() {
-        let msg_content = MessageContent::UpsertEdge((None, Some(BROADCAST_NODE)));
+        let msg_content = MessageContent::UpsertEdge((None, Some(Node::BROADCAST)), 300);
         let mut out = MessageData::new();
         unwrap_print!(msg_content.encode(&mut out));
 
@@ -263,21 +1154,70 @@ mod tests {
         let decoded = unwrap_print!(MessageContent::decode(&mut cursor));
         assert_eq!(MessageType::from(&decoded), MessageType::UpsertEdge);
         match decoded {
-            MessageContent::UpsertEdge((n, p)) => {
+            MessageContent::UpsertEdge((n, p), epoch) => {
                 assert_eq!(n, None);
-                assert_eq!(p, Some(BROADCAST_NODE));
+                assert_eq!(p, Some(Node::BROADCAST));
+                assert_eq!(epoch, 300);
             }
             _ => panic!("decoded message is not UpsertEdge"),
         }
     }
 
+    #[test]
+    fn test_config_message_encode_decode() {
+        let config = NetworkConfig {
+            version: 2,
+            ..NetworkConfig::new()
+        };
+        let msg_content = MessageContent::Config(config);
+        let mut out = MessageData::new();
+        unwrap_print!(msg_content.encode(&mut out));
+
+        let mut cursor = Cursor::new(&out);
+        let decoded = unwrap_print!(MessageContent::decode(&mut cursor));
+        match decoded {
+            MessageContent::Config(c) => assert_eq!(c, config),
+            _ => panic!("decoded message is not Config"),
+        }
+    }
+
+    #[test]
+    fn test_heartbeat_bundle_round_trip() {
+        let node = Node::new([10, 20, 30, 40, 50, 60]);
+        let bundle = unwrap_print!(
+            SendMessage::new(
+                node,
+                MessageContent::Application(MessageData::from([1, 2])),
+                None,
+                3
+            )
+            .serialize()
+        );
+        let health = Health::new(Some(80), 2, Some(-60));
+        let content = MessageContent::Heartbeat(health, Some(bundle.clone()));
+        let serialized = unwrap_print!(SendMessage::new(node, content, None, 7).serialize());
+        let receive_msg = unwrap_print!(ReceiveMessage::new(serialized, node, node, 0));
+        assert!(matches!(
+            receive_msg.data,
+            MessageContent::Heartbeat(h, Some(b)) if h == health && b == bundle
+        ));
+
+        let content = MessageContent::Heartbeat(health, None);
+        let serialized = unwrap_print!(SendMessage::new(node, content, None, 7).serialize());
+        let receive_msg = unwrap_print!(ReceiveMessage::new(serialized, node, node, 0));
+        assert!(matches!(
+            receive_msg.data,
+            MessageContent::Heartbeat(_, None)
+        ));
+    }
+
     #[test]
     fn test_send_message_to_receive_message() {
         let final_destination = Node::new([10, 20, 30, 40, 50, 60]);
         let destination = Node::new([10, 20, 30, 40, 50, 60]);
         let source = Node::new([10, 20, 30, 40, 50, 60]);
         let data = MessageContent::Application(MessageData::new());
-        let send_msg = SendMessage::new(final_destination.clone(), data.clone(), None);
+        let send_msg = SendMessage::new(final_destination.clone(), data.clone(), None, 7);
 
         let serialized = unwrap_print!(send_msg.serialize());
 
@@ -288,6 +1228,314 @@ mod tests {
             MessageType::from(&receive_msg.data)
         );
         assert_eq!(final_destination, receive_msg.final_destination);
+        assert_eq!(receive_msg.sequence, 7);
+    }
+
+    #[test]
+    fn test_topology_chunk_round_trip() {
+        let node = Node::new([10, 20, 30, 40, 50, 60]);
+        let chunk = TopologyChunk {
+            index: 3,
+            total: 5,
+            edges: unwrap_print!(Vec::from_slice(&[(Some(node), None), (None, Some(node))])),
+        };
+        let content = MessageContent::Topology(chunk.clone());
+        let send_msg = SendMessage::new(node, content, None, 7);
+
+        let serialized = unwrap_print!(send_msg.serialize());
+        let receive_msg = unwrap_print!(ReceiveMessage::new(serialized, node, node, 0));
+
+        assert!(matches!(receive_msg.data, MessageContent::Topology(c) if c == chunk));
+    }
+
+    #[test]
+    fn test_flags_round_trip() {
+        let node = Node::new([10, 20, 30, 40, 50, 60]);
+        let content = MessageContent::Application(MessageData::from([1, 2, 3]));
+        let mut send_msg = SendMessage::new(node, content, None, 7);
+        send_msg.flags.insert(MessageFlags::ENCRYPTED);
+        send_msg.priority = Priority::Critical;
+
+        let serialized = unwrap_print!(send_msg.serialize());
+        let receive_msg = unwrap_print!(ReceiveMessage::new(serialized, node, node, 0));
+
+        assert!(receive_msg.flags.contains(MessageFlags::ENCRYPTED));
+        assert!(receive_msg.flags.contains(MessageFlags::PRIORITY));
+        assert!(!receive_msg.flags.contains(MessageFlags::COMPRESSED));
+    }
+
+    #[test]
+    fn test_unknown_mandatory_flags_are_rejected() {
+        let node = Node::new([10, 20, 30, 40, 50, 60]);
+        let frame = |flags| {
+            let mut send_msg = SendMessage::new(
+                node,
+                MessageContent::Discovery(Capabilities::new()),
+                None,
+                7,
+            );
+            send_msg.flags = MessageFlags::from_bits(flags);
+            unwrap_print!(send_msg.serialize())
+        };
+
+        assert!(ReceiveMessage::new(frame(0x08), node, node, 0).is_ok());
+        for flags in [
+            MessageFlags::COMPRESSED.bits(),
+            MessageFlags::FRAGMENTED.bits(),
+        ] {
+            assert!(matches!(
+                ReceiveMessage::new(frame(flags), node, node, 0),
+                Err(ReceiveMessageError::UnsupportedFlagsError(f)) if f == flags
+            ));
+        }
+    }
+
+    #[test]
+    fn test_bytes_after_content_are_rejected() {
+        let node = Node::new([10, 20, 30, 40, 50, 60]);
+        let content = || MessageContent::Discovery(Capabilities::new());
+        let untagged = unwrap_print!(SendMessage::new(node, content(), None, 7).serialize());
+        let mut tagged = SendMessage::new(node, content(), None, 7);
+        tagged.set_tag([0; MESSAGE_TAG_LEN]);
+        let tagged = unwrap_print!(tagged.serialize());
+        let receive = |frame: &MessageData| ReceiveMessage::new(frame.clone(), node, node, 0);
+
+        assert!(unwrap_print!(receive(&untagged)).tag.is_none());
+        assert!(unwrap_print!(receive(&tagged)).tag.is_some());
+
+        // A tag-sized tail without the flag is leftover content, not a tag.
+        let mut padded = untagged.clone();
+        padded.truncate(padded.len() - 2);
+        unwrap_print!(padded.extend_from_slice(&[0; MESSAGE_TAG_LEN]));
+        unwrap_print!(append_checksum(&mut padded));
+        assert!(matches!(
+            receive(&padded),
+            Err(ReceiveMessageError::TrailingBytesError(MESSAGE_TAG_LEN))
+        ));
+    }
+
+    #[test]
+    fn test_leader_heartbeat_hash_is_optional() {
+        let node = Node::new([10, 20, 30, 40, 50, 60]);
+        for hash in [Some(0xdead_beef), None] {
+            let content = MessageContent::LeaderHeartbeat(hash);
+            let serialized = unwrap_print!(SendMessage::new(node, content, None, 7).serialize());
+            let receive_msg = unwrap_print!(ReceiveMessage::new(serialized, node, node, 0));
+
+            assert_eq!(
+                receive_msg.flags.contains(MessageFlags::EXTENDED),
+                hash.is_some()
+            );
+            assert!(matches!(receive_msg.data, MessageContent::LeaderHeartbeat(h) if h == hash));
+        }
+    }
+
+    #[test]
+    fn test_unknown_extension_is_skipped() {
+        let node = Node::new([10, 20, 30, 40, 50, 60]);
+        let msg = SendMessage::new(node, MessageContent::Pong(3), None, 7);
+        let mut frame = unwrap_print!(msg.serialize());
+        // The flags sit right in front of the one byte body.
+        frame.truncate(frame.len() - 2);
+        let flags_at = frame.len() - 2;
+        frame[flags_at] |= MessageFlags::EXTENDED.bits();
+        unwrap_print!(frame.extend_from_slice(&[0xAB, 0xCD]));
+        unwrap_print!(append_checksum(&mut frame));
+
+        let receive_msg = unwrap_print!(ReceiveMessage::new(frame, node, node, 0));
+        assert!(matches!(receive_msg.data, MessageContent::Pong(3)));
+    }
+
+    #[test]
+    fn test_relay_keeps_unknown_extension_under_tag() {
+        let key = [7; 16];
+        let source = Node::new([10, 20, 30, 40, 50, 60]);
+        let relay = Node::new([1, 2, 3, 4, 5, 6]);
+        let destination = Node::new([6, 5, 4, 3, 2, 1]);
+        // A field only newer firmware knows, sent and tagged by such a source.
+        let mut msg = SendMessage::new(destination, MessageContent::Pong(3), None, 7);
+        msg.extension = Some(unwrap_print!(Extension::from_slice(&[0xAB, 0xCD])));
+        msg.session = 9;
+        let data = unwrap_print!(msg.authenticated_data(source));
+        msg.set_tag(crypto::message_tag(&key, &data));
+        let frame = unwrap_print!(msg.serialize());
+
+        let received = unwrap_print!(ReceiveMessage::new(frame, relay, source, 0));
+        let mut forwarded: SendMessage = received.into();
+        assert!(forwarded.consume_hop());
+        let frame = unwrap_print!(forwarded.serialize());
+
+        let received = unwrap_print!(ReceiveMessage::new(frame, destination, relay, 0));
+        assert!(matches!(received.data, MessageContent::Pong(3)));
+        assert_eq!(received.extension.as_deref(), Some(&[0xAB, 0xCD][..]));
+        let data = unwrap_print!(received.authenticated_data());
+        assert!(crypto::verify_message_tag(&key, &data, &received.tag.unwrap()).is_ok());
+    }
+
+    #[test]
+    fn test_flags_are_authenticated() {
+        let key = [7; 16];
+        let source = Node::new([10, 20, 30, 40, 50, 60]);
+        let destination = Node::new([6, 5, 4, 3, 2, 1]);
+        let mut msg = SendMessage::new(destination, MessageContent::Pong(3), None, 7);
+        msg.priority = Priority::High;
+        let data = unwrap_print!(msg.authenticated_data(source));
+        msg.set_tag(crypto::message_tag(&key, &data));
+        let frame = unwrap_print!(msg.serialize());
+        let verify = |toggled: MessageFlags| {
+            let frame = frame.clone();
+            let mut received = unwrap_print!(ReceiveMessage::new(frame, destination, source, 0));
+            received.flags = MessageFlags::from_bits(received.flags.bits() ^ toggled.bits());
+            let data = unwrap_print!(received.authenticated_data());
+            crypto::verify_message_tag(&key, &data, &received.tag.unwrap())
+        };
+        assert!(verify(MessageFlags::empty()).is_ok());
+        assert!(verify(MessageFlags::TAGGED).is_ok());
+        assert!(verify(MessageFlags::PRIORITY).is_err());
+        assert!(verify(MessageFlags::REALTIME).is_err());
+    }
+
+    #[test]
+    fn test_full_topology_chunk_fits_frame() {
+        let node = Node::new([10, 20, 30, 40, 50, 60]);
+        let mut edges = Vec::new();
+        while edges.push((Some(node), Some(node))).is_ok() {}
+        let chunk = TopologyChunk {
+            index: 0,
+            total: 1,
+            edges,
+        };
+        let mut send_msg = SendMessage::new(node, MessageContent::Topology(chunk), Some(node), 7);
+        send_msg.network_id = u16::MAX;
+        send_msg.timestamp = Some(u32::MAX);
+        send_msg.set_tag([0; MESSAGE_TAG_LEN]);
+
+        assert!(send_msg.serialize().is_ok());
+    }
+
+    /// Largest content of each organization message type, `None` for application payloads,
+    /// which are sized by the application.
+    fn largest_content(message_type: MessageType) -> Option<MessageContent> {
+        let node = Node::new([0xFF; 6]);
+        let mut edges = Vec::new();
+        while edges.push((Some(node), Some(node))).is_ok() {}
+        let mut hops = TraceHops::new();
+        while hops.push(node).is_ok() {}
+        let capabilities = Capabilities {
+            firmware: u16::MAX,
+            image: ImageState::RolledBack,
+            battery: Some(100),
+            features: Features::from_bits(0xFF),
+            priority: u8::MAX,
+        };
+        let content = match message_type {
+            MessageType::Application => return None,
+            MessageType::Discovery => MessageContent::Discovery(capabilities),
+            MessageType::Invitation => MessageContent::Invitation,
+            MessageType::RequestNews => MessageContent::RequestNews,
+            MessageType::SendNew => {
+                MessageContent::SendNew((node, i32::MIN), u16::MAX, capabilities)
+            }
+            MessageType::FinSendNew => MessageContent::FinSendNew,
+            MessageType::UpsertEdge => {
+                MessageContent::UpsertEdge((Some(node), Some(node)), u32::MAX)
+            }
+            MessageType::RequestInitTopology => MessageContent::RequestInitTopology(node),
+            MessageType::Config => MessageContent::Config(NetworkConfig::new()),
+            MessageType::ConfigAck => MessageContent::ConfigAck(u16::MAX),
+            MessageType::Crash => MessageContent::Crash(node),
+            MessageType::Topology => MessageContent::Topology(TopologyChunk {
+                index: u8::MAX,
+                total: u8::MAX,
+                edges,
+            }),
+            MessageType::RequestTopologyChunks => {
+                MessageContent::RequestTopologyChunks(ChunkBitmap::MAX)
+            }
+            MessageType::Unreachable => MessageContent::Unreachable(node),
+            MessageType::JoinRejected => {
+                MessageContent::JoinRejected(node, JoinRejection::OutdatedFirmware)
+            }
+            MessageType::EdgeAck => MessageContent::EdgeAck(u32::MAX),
+            // A carried frame is sized by the application, like an application payload.
+            MessageType::Heartbeat => {
+                MessageContent::Heartbeat(Health::new(Some(100), 0, Some(-1)), None)
+            }
+            MessageType::Leave => MessageContent::Leave(node),
+            MessageType::Ping => MessageContent::Ping(u16::MAX),
+            MessageType::Pong => MessageContent::Pong(u16::MAX),
+            MessageType::TraceRoute => MessageContent::TraceRoute(u16::MAX, hops.clone()),
+            MessageType::TraceRouteReply => MessageContent::TraceRouteReply(u16::MAX, hops.clone()),
+            MessageType::BootHealthy => MessageContent::BootHealthy(node, capabilities),
+            MessageType::BootConfirmed => MessageContent::BootConfirmed,
+            MessageType::Introduce => {
+                let mut name = NodeName::new();
+                while name.push('x').is_ok() {}
+                MessageContent::Introduce(node, name)
+            }
+            MessageType::LinkReport => {
+                let mut report = LinkReport::new();
+                while report.push((node, i32::MIN)).is_ok() {}
+                MessageContent::LinkReport(node, report)
+            }
+            MessageType::Congested => MessageContent::Congested(node),
+            MessageType::RequestResync => MessageContent::RequestResync,
+            MessageType::Remerge => MessageContent::Remerge,
+            MessageType::LeaderHeartbeat => MessageContent::LeaderHeartbeat(Some(u32::MAX)),
+            MessageType::LeaderHandover => MessageContent::LeaderHandover(node, u32::MAX),
+            MessageType::Identify => MessageContent::Identify(u16::MAX, u32::MAX),
+        };
+        Some(content)
+    }
+
+    fn longest_header(content: MessageContent) -> SendMessage {
+        let node = Node::new([0xFF; 6]);
+        let mut msg = SendMessage::new(node, content, Some(node), u16::MAX);
+        msg.network_id = u16::MAX;
+        msg.hop_limit = MAX_HOP_LIMIT;
+        msg.timestamp = Some(u32::MAX);
+        msg.set_tag([0; MESSAGE_TAG_LEN]);
+        msg
+    }
+
+    #[test]
+    fn test_trace_route_hops_are_not_authenticated() {
+        let node = Node::new([10, 20, 30, 40, 50, 60]);
+        let trace = |hops: &[Node]| {
+            let content = MessageContent::TraceRoute(3, unwrap_print!(Vec::from_slice(hops)));
+            let msg = SendMessage::new(node, content, None, 7);
+            unwrap_print!(msg.authenticated_data(node))
+        };
+        assert_eq!(trace(&[]), trace(&[node, node]));
+
+        let reply = |hops: &[Node]| {
+            let content = MessageContent::TraceRouteReply(3, unwrap_print!(Vec::from_slice(hops)));
+            let msg = SendMessage::new(node, content, None, 7);
+            unwrap_print!(msg.authenticated_data(node))
+        };
+        assert_ne!(reply(&[]), reply(&[node]));
+    }
+
+    #[test]
+    fn test_frame_overhead_matches_longest_header() {
+        let serialized = unwrap_print!(longest_header(MessageContent::RequestResync).serialize());
+        assert_eq!(serialized.len(), MAX_FRAME_OVERHEAD);
+    }
+
+    #[test]
+    fn test_every_organization_message_fits_frame() {
+        for message_type in MessageType::ALL {
+            let Some(content) = largest_content(*message_type) else {
+                continue;
+            };
+            assert_eq!(MessageType::from(&content), *message_type);
+            assert!(
+                content.fits_frame(),
+                "{:?} exceeds the body budget",
+                message_type
+            );
+            assert!(longest_header(content).serialize().is_ok());
+        }
     }
 
     #[test]
@@ -296,7 +1544,7 @@ mod tests {
         let destination = Node::new([10, 20, 30, 40, 50, 60]);
         let source = Node::new([10, 20, 30, 40, 50, 60]);
         let data = MessageContent::Application(MessageData::new());
-        let tmpl_msg = SendMessage::new(final_destination.clone(), data.clone(), None);
+        let tmpl_msg = SendMessage::new(final_destination.clone(), data.clone(), None, 7);
 
         let serialized = unwrap_print!(tmpl_msg.serialize());
         let receive_msg = unwrap_print!(ReceiveMessage::new(serialized, destination, source, 0));
@@ -304,5 +1552,176 @@ mod tests {
 
         assert_eq!(MessageType::from(&data), MessageType::from(&send_msg.data));
         assert_eq!(final_destination, send_msg.final_destination);
+        assert_eq!(send_msg.sequence, 7);
+    }
+
+    #[test]
+    fn test_hop_limit_survives_forwarding() {
+        let node = Node::new([10, 20, 30, 40, 50, 60]);
+        let data = MessageContent::Application(MessageData::new());
+        let mut tmpl_msg = SendMessage::new(node, data, None, 1);
+        tmpl_msg.hop_limit = 5;
+
+        let serialized = unwrap_print!(tmpl_msg.serialize());
+        let receive_msg = unwrap_print!(ReceiveMessage::new(serialized, node, node, 0));
+        assert_eq!(receive_msg.hop_limit, 5);
+
+        let mut send_msg: SendMessage = receive_msg.into();
+        assert!(send_msg.consume_hop());
+        assert_eq!(send_msg.hop_limit, 4);
+    }
+
+    #[test]
+    fn test_priority_shares_varint_with_hop_limit() {
+        let node = Node::new([10, 20, 30, 40, 50, 60]);
+        let mut msg = SendMessage::new(node, MessageContent::Crash(node), None, 1);
+        assert_eq!(msg.priority, Priority::Critical);
+        msg.hop_limit = MAX_HOP_LIMIT;
+
+        let serialized = unwrap_print!(msg.serialize());
+        let receive_msg = unwrap_print!(ReceiveMessage::new(serialized, node, node, 0));
+        assert_eq!(receive_msg.priority, Priority::Critical);
+        assert_eq!(receive_msg.hop_limit, MAX_HOP_LIMIT);
+    }
+
+    #[test]
+    fn test_consume_hop_fails_when_exhausted() {
+        let node = Node::new([10, 20, 30, 40, 50, 60]);
+        let data = MessageContent::Application(MessageData::new());
+        let mut msg = SendMessage::new(node, data, None, 1);
+        msg.hop_limit = 1;
+
+        assert!(msg.consume_hop());
+        assert!(!msg.consume_hop());
+        assert_eq!(msg.hop_limit, 0);
+    }
+
+    #[test]
+    fn test_corrupted_message_is_rejected() {
+        let node = Node::new([10, 20, 30, 40, 50, 60]);
+        let data = MessageContent::UpsertEdge((Some(node), None), 1);
+        let msg = SendMessage::new(node, data, None, 1);
+
+        let mut serialized = unwrap_print!(msg.serialize());
+        serialized[1] ^= 0x40;
+        match ReceiveMessage::new(serialized, node, node, 0) {
+            Err(ReceiveMessageError::ChecksumDecodeError(CodecError::ChecksumMismatch(_, _))) => {}
+            other => panic!("Expected checksum mismatch, got {:?}", other),
+        }
+    }
+
+    fn with_version(mut frame: MessageData, version: u8) -> MessageData {
+        frame.truncate(frame.len() - 2);
+        frame[0] = version;
+        unwrap_print!(append_checksum(&mut frame));
+        frame
+    }
+
+    #[test]
+    fn test_frame_starts_with_protocol_version() {
+        let node = Node::new([10, 20, 30, 40, 50, 60]);
+        let msg = SendMessage::new(
+            node,
+            MessageContent::Discovery(Capabilities::new()),
+            None,
+            1,
+        );
+
+        let serialized = unwrap_print!(msg.serialize());
+        assert_eq!(serialized[0], PROTOCOL_VERSION);
+    }
+
+    #[test]
+    fn test_oldest_supported_version_is_decoded() {
+        let node = Node::new([10, 20, 30, 40, 50, 60]);
+        let msg = SendMessage::new(node, MessageContent::RequestInitTopology(node), None, 1);
+
+        let serialized = with_version(unwrap_print!(msg.serialize()), MIN_PROTOCOL_VERSION);
+        let receive_msg = unwrap_print!(ReceiveMessage::new(serialized, node, node, 0));
+        assert_eq!(
+            MessageType::from(&receive_msg.data),
+            MessageType::RequestInitTopology
+        );
+    }
+
+    #[test]
+    fn test_version_below_minimum_is_rejected() {
+        let node = Node::new([10, 20, 30, 40, 50, 60]);
+        let msg = SendMessage::new(
+            node,
+            MessageContent::Discovery(Capabilities::new()),
+            None,
+            1,
+        );
+
+        let serialized = with_version(unwrap_print!(msg.serialize()), MIN_PROTOCOL_VERSION - 1);
+        match Frame::parse(serialized, node, node, 0) {
+            Err(ReceiveMessageError::UnsupportedVersionError(v)) => {
+                assert_eq!(v, MIN_PROTOCOL_VERSION - 1)
+            }
+            other => panic!("Expected unsupported version, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_network_id_round_trip() {
+        let node = Node::new([10, 20, 30, 40, 50, 60]);
+        let mut msg = SendMessage::new(
+            node,
+            MessageContent::Discovery(Capabilities::new()),
+            None,
+            1,
+        );
+        msg.network_id = 0xBEEF;
+
+        let serialized = unwrap_print!(msg.serialize());
+        let receive_msg = unwrap_print!(ReceiveMessage::new(serialized, node, node, 0));
+        assert_eq!(receive_msg.network_id, 0xBEEF);
+    }
+
+    #[test]
+    fn test_timestamp_round_trip() {
+        let node = Node::new([10, 20, 30, 40, 50, 60]);
+        let mut msg = SendMessage::new(
+            node,
+            MessageContent::Discovery(Capabilities::new()),
+            None,
+            1,
+        );
+        let unstamped = unwrap_print!(msg.serialize());
+        msg.timestamp = Some(123_456);
+
+        let serialized = unwrap_print!(msg.serialize());
+        assert_eq!(serialized.len(), unstamped.len() + 4);
+        let receive_msg = unwrap_print!(ReceiveMessage::new(serialized, node, node, 0));
+        assert_eq!(receive_msg.timestamp, Some(123_456));
+    }
+
+    #[test]
+    fn test_newer_version_is_relayed_unchanged() {
+        let source = Node::new([1, 1, 1, 1, 1, 1]);
+        let relay = Node::new([2, 2, 2, 2, 2, 2]);
+        let destination = Node::new([3, 3, 3, 3, 3, 3]);
+        let data = MessageContent::Application(MessageData::from([1, 2, 3]));
+        let msg = SendMessage::new(destination, data, None, 7);
+        let serialized = with_version(unwrap_print!(msg.serialize()), PROTOCOL_VERSION + 1);
+
+        assert!(ReceiveMessage::new(serialized.clone(), relay, source, 0).is_err());
+        let mut opaque = match unwrap_print!(Frame::parse(serialized.clone(), relay, source, 0)) {
+            Frame::Opaque(opaque) => opaque,
+            Frame::Message(_) => panic!("Expected an opaque frame"),
+        };
+        assert!(!opaque.is_final_destination());
+        assert_eq!(opaque.final_destination(), destination);
+        assert_eq!(opaque.final_source(), source);
+        assert!(opaque.consume_hop());
+
+        let relayed = unwrap_print!(opaque.serialize());
+        let content_start = serialized.len() - 2 - 5;
+        assert_eq!(relayed[0], PROTOCOL_VERSION + 1);
+        assert_eq!(
+            relayed[relayed.len() - 2 - 5..relayed.len() - 2],
+            serialized[content_start..serialized.len() - 2]
+        );
     }
 }
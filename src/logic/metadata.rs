@@ -0,0 +1,196 @@
+use crate::logic::{
+    consts::{MAX_METADATA_LEN, MESSAGE_SIZE},
+    error::CodecError,
+    message::MessageData,
+    wire::{Cursor, WireCodec, WriteBytes, decode_varint, encode_varint},
+};
+use heapless::Vec;
+
+/// Kind of the entry holding the port a payload is sent to, so receivers can tell application
+/// features apart, e.g. game state from chat. Reserved, applications use the other kinds.
+pub const PORT_KIND: u8 = 0xFF;
+
+/// Application-defined type-length-value entries that travel next to an application payload,
+/// e.g. a team ID or a sensor type. They are sealed together with the payload, so relays never
+/// see them.
+///
+/// Layout: section length (varint) | (kind | value length | value)*
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Metadata {
+    entries: Vec<u8, MAX_METADATA_LEN>,
+}
+
+impl Metadata {
+    pub const fn new() -> Self {
+        Self {
+            entries: Vec::new(),
+        }
+    }
+
+    /// Appends an entry, leaving the metadata unchanged if it does not fit. An existing entry
+    /// of the same kind is kept, `get` returns the first one.
+    pub fn insert(&mut self, kind: u8, value: &[u8]) -> Result<(), CodecError> {
+        let mut entries = self.entries.clone();
+        entries
+            .push(kind)
+            .map_err(CodecError::BufferOverflowError)?;
+        entries
+            .push(value.len() as u8)
+            .map_err(CodecError::BufferOverflowError)?;
+        entries
+            .extend_from_slice(value)
+            .map_err(CodecError::BufferCapacityError)?;
+        self.entries = entries;
+        Ok(())
+    }
+
+    /// Metadata holding only `port`, see `PORT_KIND`.
+    pub fn for_port(port: u8) -> Self {
+        let mut metadata = Self::new();
+        // A single one byte entry always fits.
+        let _ = metadata.insert(PORT_KIND, &[port]);
+        metadata
+    }
+
+    pub fn port(&self) -> Option<u8> {
+        match self.get(PORT_KIND)? {
+            [port] => Some(*port),
+            _ => None,
+        }
+    }
+
+    pub fn get(&self, kind: u8) -> Option<&[u8]> {
+        self.iter()
+            .find(|(k, _)| *k == kind)
+            .map(|(_, value)| value)
+    }
+
+    pub fn iter(&self) -> MetadataIter<'_> {
+        MetadataIter {
+            cursor: Cursor::new(&self.entries),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+}
+
+pub struct MetadataIter<'a> {
+    cursor: Cursor<'a>,
+}
+
+impl<'a> Iterator for MetadataIter<'a> {
+    type Item = (u8, &'a [u8]);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let header = self.cursor.take(2).ok()?;
+        let value = self.cursor.take(header[1] as usize).ok()?;
+        Some((header[0], value))
+    }
+}
+
+impl WireCodec<MESSAGE_SIZE> for Metadata {
+    fn encode(&self, out: &mut MessageData) -> Result<(), CodecError> {
+        encode_varint(self.entries.len() as u32, out)?;
+        out.write_bytes(&self.entries)
+    }
+
+    fn decode(cursor: &mut Cursor<'_>) -> Result<Self, CodecError> {
+        let len = decode_varint(cursor)? as usize;
+        let section = cursor.take(len).map_err(CodecError::CursorReadError)?;
+        let mut entries = Cursor::new(section);
+        while !entries.remaining().is_empty() {
+            let header = entries
+                .take(2)
+                .map_err(|_| CodecError::InvalidMetadataError)?;
+            entries
+                .take(header[1] as usize)
+                .map_err(|_| CodecError::InvalidMetadataError)?;
+        }
+        let mut metadata = Metadata::new();
+        metadata
+            .entries
+            .extend_from_slice(section)
+            .map_err(CodecError::BufferCapacityError)?;
+        Ok(metadata)
+    }
+}
+
+/// Puts the encoded `metadata` in front of `payload`, giving the application data that is
+/// sealed and sent.
+pub fn attach(metadata: &Metadata, payload: &[u8]) -> Result<MessageData, CodecError> {
+    let mut out = MessageData::new();
+    metadata.encode(&mut out)?;
+    out.write_bytes(payload)?;
+    Ok(out)
+}
+
+/// Splits received application data into its metadata and the payload behind it.
+pub fn detach(data: &[u8]) -> Result<(Metadata, MessageData), CodecError> {
+    let mut cursor = Cursor::new(data);
+    let metadata = Metadata::decode(&mut cursor)?;
+    let mut payload = MessageData::new();
+    payload
+        .extend_from_slice(cursor.remaining())
+        .map_err(CodecError::BufferCapacityError)?;
+    Ok((metadata, payload))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::unwrap_print;
+
+    #[test]
+    fn metadata_entries_are_looked_up_by_kind() {
+        let mut metadata = Metadata::new();
+        unwrap_print!(metadata.insert(1, &[7]));
+        unwrap_print!(metadata.insert(2, b"temp"));
+
+        assert_eq!(metadata.get(1), Some(&[7][..]));
+        assert_eq!(metadata.get(2), Some(&b"temp"[..]));
+        assert_eq!(metadata.get(3), None);
+        assert_eq!(metadata.iter().count(), 2);
+    }
+
+    #[test]
+    fn port_is_a_reserved_entry() {
+        let mut metadata = Metadata::for_port(4);
+        unwrap_print!(metadata.insert(1, &[7]));
+        assert_eq!(metadata.port(), Some(4));
+
+        assert_eq!(Metadata::new().port(), None);
+        let mut malformed = Metadata::new();
+        unwrap_print!(malformed.insert(PORT_KIND, &[4, 5]));
+        assert_eq!(malformed.port(), None);
+    }
+
+    #[test]
+    fn metadata_insert_rejects_overflow() {
+        let mut metadata = Metadata::new();
+        unwrap_print!(metadata.insert(1, &[0; MAX_METADATA_LEN - 2]));
+
+        assert!(metadata.insert(2, &[]).is_err());
+        assert_eq!(metadata.iter().count(), 1);
+    }
+
+    #[test]
+    fn attach_detach_round_trip() {
+        let mut metadata = Metadata::new();
+        unwrap_print!(metadata.insert(0x10, &[3]));
+        let data = unwrap_print!(attach(&metadata, &[42, 43]));
+
+        let (decoded, payload) = unwrap_print!(detach(&data));
+        assert_eq!(decoded, metadata);
+        assert_eq!(payload, MessageData::from([42, 43]));
+    }
+
+    #[test]
+    fn detach_rejects_truncated_entry() {
+        let data = [3, 0x10, 5, 1, 42];
+
+        let err = detach(&data).unwrap_err();
+        assert!(matches!(err, CodecError::InvalidMetadataError));
+    }
+}
@@ -1,10 +1,43 @@
 pub mod arena;
 pub mod asynchronous;
+pub mod capabilities;
+pub mod config;
+pub mod congestion;
+pub mod consts;
+pub mod crash;
+pub mod crypto;
+pub mod dedup;
 pub mod error;
+pub mod event;
+pub mod health;
+pub mod history;
 pub mod link;
+pub mod liveness;
+// The mesh runs on the tokio or the embassy executor, the protocol code builds without either.
+#[cfg(any(feature = "std", feature = "hardware"))]
 pub mod mesh;
 pub mod message;
+pub mod metadata;
+pub mod neighbor;
 pub mod node;
+pub mod ping;
+pub mod placement;
+pub mod profile;
+pub mod provisioning;
+pub mod reachability;
+pub mod rig;
+pub mod roster;
+pub mod shortcut;
+pub mod smoothing;
+pub mod stats;
+pub mod telemetry;
+pub mod text;
+pub mod timeline;
+pub mod topology;
+pub mod traffic;
 pub mod tree;
+pub mod udp;
 pub mod util;
+pub mod vectors;
+pub mod watch;
 pub mod wire;
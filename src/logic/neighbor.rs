@@ -0,0 +1,114 @@
+use crate::logic::{
+    consts::{MAX_LEAFS, MAX_LINK_REPORT, NEIGHBOR_MAX_AGE_MS, NEIGHBOR_RSSI_SHIFT},
+    node::Node,
+    smoothing::Ewma,
+};
+use heapless::{LinearMap, Vec};
+
+/// Neighbors and the RSSI this node hears them with, strongest first, see
+/// `MessageContent::LinkReport`.
+pub type LinkReport = Vec<(Node, i32), MAX_LINK_REPORT>;
+
+struct Neighbor {
+    rssi: Ewma,
+    heard_ms: u64,
+}
+
+/// RSSI every node in radio range is heard with, smoothed over the frames by an `Ewma` so a
+/// single outlier does not count fully. Once full, the node heard from least recently is forgotten.
+pub struct NeighborTable {
+    neighbors: LinearMap<Node, Neighbor, MAX_LEAFS>,
+}
+
+impl NeighborTable {
+    pub const fn new() -> Self {
+        Self {
+            neighbors: LinearMap::new(),
+        }
+    }
+
+    pub fn record(&mut self, node: Node, rssi: i32, now_ms: u64) {
+        if let Some(neighbor) = self.neighbors.get_mut(&node) {
+            neighbor.rssi.update(rssi);
+            neighbor.heard_ms = now_ms;
+            return;
+        }
+        if self.neighbors.is_full() {
+            let stalest = self
+                .neighbors
+                .iter()
+                .min_by_key(|(_, neighbor)| neighbor.heard_ms)
+                .map(|(node, _)| *node);
+            if let Some(stalest) = stalest {
+                self.neighbors.remove(&stalest);
+            }
+        }
+        let mut neighbor = Neighbor {
+            rssi: Ewma::new(NEIGHBOR_RSSI_SHIFT),
+            heard_ms: now_ms,
+        };
+        neighbor.rssi.update(rssi);
+        let _ = self.neighbors.insert(node, neighbor);
+    }
+
+    /// Smoothed RSSI `node` is heard with.
+    pub fn rssi(&self, node: Node) -> Option<i32> {
+        self.neighbors
+            .get(&node)
+            .and_then(|neighbor| neighbor.rssi.value())
+    }
+
+    /// The strongest neighbors heard within `NEIGHBOR_MAX_AGE_MS`.
+    pub fn report(&self, now_ms: u64) -> LinkReport {
+        let mut heard = self
+            .neighbors
+            .iter()
+            .filter(|(_, neighbor)| now_ms.saturating_sub(neighbor.heard_ms) <= NEIGHBOR_MAX_AGE_MS)
+            .filter_map(|(node, neighbor)| Some((*node, neighbor.rssi.value()?)))
+            .collect::<Vec<_, MAX_LEAFS>>();
+        heard.sort_unstable_by_key(|(_, rssi)| -rssi);
+        heard.into_iter().take(MAX_LINK_REPORT).collect()
+    }
+}
+
+impl Default for NeighborTable {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn n(id: u16) -> Node {
+        let [high, low] = id.to_be_bytes();
+        Node::new([0, 0, 0, 0, high, low])
+    }
+
+    #[test]
+    fn report_lists_fresh_neighbors_strongest_first() {
+        let mut table = NeighborTable::new();
+        table.record(n(1), -60, 0);
+        table.record(n(1), -81, 100);
+        table.record(n(2), -50, 100);
+        table.record(n(3), -40, 0);
+
+        let report = table.report(NEIGHBOR_MAX_AGE_MS + 50);
+        assert_eq!(report.as_slice(), &[(n(2), -50), (n(1), -71)]);
+    }
+
+    #[test]
+    fn report_keeps_the_strongest_neighbors() {
+        let mut table = NeighborTable::new();
+        for i in 0..MAX_LEAFS as u16 {
+            table.record(n(i), -90 + i as i32, 0);
+        }
+        let report = table.report(0);
+        assert_eq!(report.len(), MAX_LINK_REPORT);
+        assert_eq!(
+            report[0],
+            (n(MAX_LEAFS as u16 - 1), -90 + MAX_LEAFS as i32 - 1)
+        );
+    }
+}
@@ -1,54 +1,52 @@
 use crate::logic::{
+    consts::MESSAGE_SIZE,
     error::CodecError,
-    message::MESSAGE_SIZE,
+    text,
     wire::{Cursor, WireCodec},
 };
-use core::cmp::PartialEq;
 use core::fmt;
 
 use heapless::Vec;
 
-#[cfg_attr(feature = "std", derive(Hash))]
-#[derive(Eq, Copy, Clone, Debug)]
+#[derive(PartialEq, Eq, Hash, Copy, Clone, Debug)]
 pub struct Node {
     pub mac: [u8; 6],
 }
 
 impl Node {
+    /// Address every node in radio range receives a frame sent to.
+    pub const BROADCAST: Node = Node::new([0xFF; 6]);
+
     pub const fn new(mac: [u8; 6]) -> Self {
         return Node { mac };
     }
+
+    pub fn is_broadcast(&self) -> bool {
+        *self == Self::BROADCAST
+    }
+
+    /// Returns true if the address belongs to a single node, i.e. the group bit of the first
+    /// octet is clear. Broadcast and multicast addresses are not unicast.
+    pub fn is_unicast(&self) -> bool {
+        self.mac[0] & 0x01 == 0
+    }
 }
 
 impl WireCodec<MESSAGE_SIZE> for Node {
     fn encode(&self, out: &mut Vec<u8, MESSAGE_SIZE>) -> Result<(), CodecError> {
-        out.extend_from_slice(&self.mac)
-            .map_err(|e| CodecError::BufferCapacityError(e))
+        self.mac.encode(out)
     }
 
     fn decode(cursor: &mut Cursor<'_>) -> Result<Self, CodecError> {
-        let bytes = cursor.take(6).map_err(|e| CodecError::CursorReadError(e))?;
-        let mut mac = [0u8; 6];
-        mac.copy_from_slice(bytes);
-        Ok(Node { mac })
-    }
-}
-
-impl PartialEq for Node {
-    fn eq(&self, other: &Self) -> bool {
-        self.mac.iter().zip(other.mac.iter()).all(|(a, b)| a == b)
+        Ok(Node {
+            mac: <[u8; 6] as WireCodec<MESSAGE_SIZE>>::decode(cursor)?,
+        })
     }
 }
 
 impl fmt::Display for Node {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        for (i, byte) in self.mac.iter().enumerate() {
-            if i != 0 {
-                write!(f, ":")?;
-            }
-            write!(f, "{:02x}", byte)?;
-        }
-        Ok(())
+        f.write_str(&text::mac(*self))
     }
 }
 
@@ -68,4 +66,15 @@ mod tests {
         let decoded = unwrap_print!(Node::decode(&mut cursor));
         assert_eq!(decoded, node);
     }
+
+    #[test]
+    fn only_single_node_addresses_are_unicast() {
+        assert!(Node::BROADCAST.is_broadcast());
+        assert!(!Node::BROADCAST.is_unicast());
+        assert!(!Node::new([0x01, 0, 0x5E, 0, 0, 1]).is_unicast());
+
+        let node = Node::new([0x40, 0x4C, 0xCA, 0, 0, 1]);
+        assert!(node.is_unicast());
+        assert!(!node.is_broadcast());
+    }
 }
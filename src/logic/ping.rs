@@ -0,0 +1,192 @@
+use crate::logic::{consts::MAX_PENDING_PINGS, message::TraceHops, node::Node};
+use core::task::Waker;
+use heapless::LinearMap;
+
+/// Pings and trace routes that wait for their answer, keyed by correlation ID. The dispatcher
+/// stamps an answer with the time its frame arrived, so the round trip does not include how
+/// long `Mesh::ping` takes to notice, and wakes the task waiting for it. Once full, the oldest
+/// ping is given up on.
+pub struct PingTracker {
+    next_id: u16,
+    pending: LinearMap<u16, PendingPing, MAX_PENDING_PINGS>,
+}
+
+struct PendingPing {
+    target: Node,
+    sent_ms: u64,
+    answered_ms: Option<u64>,
+    route: Option<TraceHops>,
+    waker: Option<Waker>,
+}
+
+impl PingTracker {
+    pub const fn new() -> Self {
+        Self {
+            next_id: 0,
+            pending: LinearMap::new(),
+        }
+    }
+
+    /// Registers a ping to `target` sent at `now_ms` and returns its correlation ID.
+    pub fn start(&mut self, target: Node, now_ms: u64) -> u16 {
+        let id = self.next_id;
+        self.next_id = self.next_id.wrapping_add(1);
+        if self.pending.is_full() {
+            let oldest = self
+                .pending
+                .iter()
+                .min_by_key(|(_, ping)| ping.sent_ms)
+                .map(|(id, _)| *id);
+            if let Some(oldest) = oldest {
+                self.pending.remove(&oldest);
+            }
+        }
+        let ping = PendingPing {
+            target,
+            sent_ms: now_ms,
+            answered_ms: None,
+            route: None,
+            waker: None,
+        };
+        let _ = self.pending.insert(id, ping);
+        id
+    }
+
+    /// Records the pong with `id` that arrived from `source` at `received_ms`. Pongs from a
+    /// node other than the pinged one are ignored.
+    pub fn record_pong(&mut self, source: Node, id: u16, received_ms: u64) {
+        if let Some(ping) = self.pending.get_mut(&id)
+            && ping.target == source
+        {
+            ping.answered_ms.get_or_insert(received_ms);
+            if let Some(waker) = ping.waker.take() {
+                waker.wake();
+            }
+        }
+    }
+
+    /// Records the reply to the trace route with `id` that `source` sent with `hops`.
+    pub fn record_route(&mut self, source: Node, id: u16, received_ms: u64, hops: TraceHops) {
+        if let Some(ping) = self.pending.get_mut(&id)
+            && ping.target == source
+        {
+            ping.route.get_or_insert(hops);
+        }
+        self.record_pong(source, id, received_ms);
+    }
+
+    /// Wakes `waker` once the ping with `id` is answered.
+    pub fn wake_on_answer(&mut self, id: u16, waker: &Waker) {
+        if let Some(ping) = self.pending.get_mut(&id) {
+            match &ping.waker {
+                Some(known) if known.will_wake(waker) => {}
+                _ => ping.waker = Some(waker.clone()),
+            }
+        }
+    }
+
+    /// Hops the trace route with `id` passed, `None` while it is not answered.
+    pub fn route(&self, id: u16) -> Option<TraceHops> {
+        self.pending.get(&id)?.route.clone()
+    }
+
+    /// Round trip of the ping with `id` in milliseconds, `None` while it is not answered.
+    pub fn rtt_ms(&self, id: u16) -> Option<u64> {
+        let ping = self.pending.get(&id)?;
+        ping.answered_ms
+            .map(|answered_ms| answered_ms.saturating_sub(ping.sent_ms))
+    }
+
+    pub fn finish(&mut self, id: u16) {
+        self.pending.remove(&id);
+    }
+}
+
+impl Default for PingTracker {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::{
+        sync::{
+            Arc,
+            atomic::{AtomicBool, Ordering},
+        },
+        task::Wake,
+    };
+
+    fn n(mac_last_byte: u8) -> Node {
+        Node::new([0, 0, 0, 0, 0, mac_last_byte])
+    }
+
+    #[test]
+    fn pong_completes_matching_ping_only() {
+        let mut pings = PingTracker::new();
+        let first = pings.start(n(1), 100);
+        let second = pings.start(n(2), 110);
+        assert_ne!(first, second);
+
+        pings.record_pong(n(2), first, 120);
+        assert_eq!(pings.rtt_ms(first), None);
+
+        pings.record_pong(n(1), first, 140);
+        pings.record_pong(n(1), first, 180);
+        assert_eq!(pings.rtt_ms(first), Some(40));
+        assert_eq!(pings.rtt_ms(second), None);
+
+        pings.finish(first);
+        assert_eq!(pings.rtt_ms(first), None);
+    }
+
+    #[test]
+    fn pong_wakes_waiting_task() {
+        struct Flag(AtomicBool);
+        impl Wake for Flag {
+            fn wake(self: Arc<Self>) {
+                self.0.store(true, Ordering::Relaxed);
+            }
+        }
+        let flag = Arc::new(Flag(AtomicBool::new(false)));
+        let mut pings = PingTracker::new();
+        let id = pings.start(n(1), 0);
+        pings.wake_on_answer(id, &Waker::from(flag.clone()));
+
+        pings.record_pong(n(2), id, 10);
+        assert!(!flag.0.load(Ordering::Relaxed));
+        pings.record_pong(n(1), id, 20);
+        assert!(flag.0.load(Ordering::Relaxed));
+    }
+
+    #[test]
+    fn trace_route_keeps_first_reply() {
+        let mut pings = PingTracker::new();
+        let id = pings.start(n(3), 0);
+        let hops = |nodes: &[Node]| TraceHops::from_slice(nodes).unwrap();
+
+        pings.record_route(n(2), id, 10, hops(&[n(2)]));
+        assert_eq!(pings.route(id), None);
+        pings.record_route(n(3), id, 20, hops(&[n(2), n(3)]));
+        pings.record_route(n(3), id, 30, hops(&[n(3)]));
+        assert_eq!(pings.route(id), Some(hops(&[n(2), n(3)])));
+        assert_eq!(pings.rtt_ms(id), Some(20));
+    }
+
+    #[test]
+    fn full_tracker_gives_up_oldest_ping() {
+        let mut pings = PingTracker::new();
+        let oldest = pings.start(n(1), 0);
+        for i in 1..MAX_PENDING_PINGS as u64 {
+            pings.start(n(1), i);
+        }
+        let newest = pings.start(n(1), 100);
+
+        pings.record_pong(n(1), oldest, 200);
+        pings.record_pong(n(1), newest, 200);
+        assert_eq!(pings.rtt_ms(oldest), None);
+        assert_eq!(pings.rtt_ms(newest), Some(100));
+    }
+}
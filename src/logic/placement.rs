@@ -0,0 +1,335 @@
+use crate::logic::{
+    capabilities::Capabilities,
+    consts::{
+        FAN_OUT_LOW_BATTERY, FAN_OUT_LOW_BATTERY_CHILDREN, FAN_OUT_WEAK_UPLINK_CHILDREN,
+        FAN_OUT_WEAK_UPLINK_RSSI, MAX_CHILD_LEAFS, MAX_PARENT_REPORTS, ROUTE_COST_PER_HOP_DB,
+    },
+    node::Node,
+};
+use core::{cmp::Reverse, fmt::Debug};
+use heapless::Vec;
+
+/// A member that heard a newcomer and could become its parent, as offered to a
+/// `ParentSelectionPolicy`.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct ParentCandidate {
+    /// `None` stands for the leader itself.
+    pub parent: Option<Node>,
+    /// Strongest RSSI the parent heard the newcomer with.
+    pub rssi: i32,
+    /// Nodes already below the parent.
+    pub load: usize,
+    /// Hops between the leader and the parent.
+    pub depth: usize,
+    /// Discoveries of the newcomer the parent heard, a parent on a flaky link misses some.
+    pub heard: u16,
+    /// What the parent advertised when it joined, `None` if the leader does not know.
+    pub capabilities: Option<Capabilities>,
+}
+
+/// Decides below which member the leader attaches a newcomer. Only candidates with room for
+/// another child are offered, and never an empty list. Returning `None` passes on all of them,
+/// the newcomer is then considered again once members report it in a later round.
+pub trait ParentSelectionPolicy: Debug + Sync {
+    fn select<'a>(
+        &self,
+        node: Node,
+        capabilities: &Capabilities,
+        candidates: &'a [ParentCandidate],
+    ) -> Option<&'a ParentCandidate>;
+}
+
+/// Attaches a newcomer below the member that heard it the loudest. On equal RSSI it prefers the
+/// parent that heard more of its Discoveries, then the shallower one.
+#[derive(Copy, Clone, Debug, Default)]
+pub struct StrongestSignal;
+
+impl ParentSelectionPolicy for StrongestSignal {
+    fn select<'a>(
+        &self,
+        _node: Node,
+        _capabilities: &Capabilities,
+        candidates: &'a [ParentCandidate],
+    ) -> Option<&'a ParentCandidate> {
+        candidates.iter().max_by(|a, b| {
+            a.rssi
+                .cmp(&b.rssi)
+                .then(a.heard.cmp(&b.heard))
+                .then(b.depth.cmp(&a.depth))
+        })
+    }
+}
+
+/// Attaches a newcomer where the route to the leader is cheapest. Each hop costs as much as
+/// `rssi_per_hop` dB of signal, so a weak parent next to the leader can beat a strong one at the
+/// end of a long chain. Equal costs prefer the parent that heard more of the newcomer's
+/// Discoveries, then the shallower one.
+#[derive(Copy, Clone, Debug)]
+pub struct RouteCost {
+    pub rssi_per_hop: i32,
+}
+
+impl RouteCost {
+    pub const fn new(rssi_per_hop: i32) -> Self {
+        Self { rssi_per_hop }
+    }
+
+    /// Cost of the route through `candidate`, lower is better.
+    pub fn cost(&self, candidate: &ParentCandidate) -> i64 {
+        let hops = candidate.depth as i64 + 1;
+        hops * self.rssi_per_hop as i64 - candidate.rssi as i64
+    }
+}
+
+impl Default for RouteCost {
+    fn default() -> Self {
+        Self::new(ROUTE_COST_PER_HOP_DB)
+    }
+}
+
+impl ParentSelectionPolicy for RouteCost {
+    fn select<'a>(
+        &self,
+        _node: Node,
+        _capabilities: &Capabilities,
+        candidates: &'a [ParentCandidate],
+    ) -> Option<&'a ParentCandidate> {
+        candidates.iter().min_by_key(|candidate| {
+            (
+                self.cost(candidate),
+                Reverse(candidate.heard),
+                candidate.depth,
+            )
+        })
+    }
+}
+
+/// Caps the children the leader gives one member, so a parent with a weak uplink or a drained
+/// battery does not take everything within its earshot. Newcomers beyond the cap go to the
+/// next-best parent, a capped parent is only offered once no other one is left.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct FanOutLimit {
+    /// Children of a parent with a strong uplink and a charged or unknown battery.
+    pub max_children: usize,
+    /// Uplink RSSI below which a parent gets at most `weak_uplink_children`.
+    pub weak_uplink_rssi: i32,
+    pub weak_uplink_children: usize,
+    /// Battery in percent below which a parent gets at most `low_battery_children`.
+    pub low_battery: u8,
+    pub low_battery_children: usize,
+}
+
+impl FanOutLimit {
+    /// Only `MAX_CHILD_LEAFS` limits the children of a parent.
+    pub const UNLIMITED: Self = Self {
+        max_children: MAX_CHILD_LEAFS,
+        weak_uplink_rssi: i32::MIN,
+        weak_uplink_children: MAX_CHILD_LEAFS,
+        low_battery: 0,
+        low_battery_children: MAX_CHILD_LEAFS,
+    };
+
+    /// Children a parent may have that hears its own uplink with `uplink_rssi` and has
+    /// `battery` left, either `None` if the leader does not know.
+    pub fn max_children(&self, uplink_rssi: Option<i32>, battery: Option<u8>) -> usize {
+        let mut limit = self.max_children;
+        if uplink_rssi.is_some_and(|rssi| rssi < self.weak_uplink_rssi) {
+            limit = limit.min(self.weak_uplink_children);
+        }
+        if battery.is_some_and(|battery| battery < self.low_battery) {
+            limit = limit.min(self.low_battery_children);
+        }
+        limit
+    }
+}
+
+impl Default for FanOutLimit {
+    fn default() -> Self {
+        Self {
+            max_children: MAX_CHILD_LEAFS,
+            weak_uplink_rssi: FAN_OUT_WEAK_UPLINK_RSSI,
+            weak_uplink_children: FAN_OUT_WEAK_UPLINK_CHILDREN,
+            low_battery: FAN_OUT_LOW_BATTERY,
+            low_battery_children: FAN_OUT_LOW_BATTERY_CHILDREN,
+        }
+    }
+}
+
+/// Members that reported one newcomer, each with the strongest RSSI it heard the newcomer with
+/// and how many of its Discoveries it heard. Once full, the weakest report makes room for a
+/// stronger one.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct ParentReports {
+    reports: Vec<(Option<Node>, i32, u16), MAX_PARENT_REPORTS>,
+    /// What the newcomer advertised in its latest reported Discovery.
+    pub capabilities: Capabilities,
+}
+
+impl ParentReports {
+    pub const fn new() -> Self {
+        Self {
+            reports: Vec::new(),
+            capabilities: Capabilities::new(),
+        }
+    }
+
+    pub fn single(parent: Option<Node>, rssi: i32) -> Self {
+        let mut reports = Self::new();
+        reports.record(parent, rssi);
+        reports
+    }
+
+    pub fn record(&mut self, parent: Option<Node>, rssi: i32) {
+        self.record_heard(parent, rssi, 1);
+    }
+
+    /// Records that `parent` heard `heard` Discoveries of the newcomer, the strongest with
+    /// `rssi`.
+    pub fn record_heard(&mut self, parent: Option<Node>, rssi: i32, heard: u16) {
+        if let Some((_, known, count)) = self.reports.iter_mut().find(|(p, _, _)| *p == parent) {
+            *known = (*known).max(rssi);
+            *count = count.saturating_add(heard);
+            return;
+        }
+        if let Err(report) = self.reports.push((parent, rssi, heard))
+            && let Some(weakest) = self.reports.iter_mut().min_by_key(|(_, r, _)| *r)
+            && weakest.1 < rssi
+        {
+            *weakest = report;
+        }
+    }
+
+    pub fn with_capabilities(self, capabilities: Capabilities) -> Self {
+        Self {
+            capabilities,
+            ..self
+        }
+    }
+
+    /// Adds the reports of a later round, whose capabilities replace the known ones.
+    pub fn merge(&mut self, other: &ParentReports) {
+        self.capabilities = other.capabilities;
+        for &(parent, rssi, heard) in &other.reports {
+            self.record_heard(parent, rssi, heard);
+        }
+    }
+
+    /// Discoveries of the newcomer `parent` heard, 0 if it did not report it.
+    pub fn heard(&self, parent: Option<Node>) -> u16 {
+        self.reports
+            .iter()
+            .find(|(p, _, _)| *p == parent)
+            .map_or(0, |(_, _, heard)| *heard)
+    }
+
+    pub fn strongest(&self) -> Option<(Option<Node>, i32)> {
+        self.iter().max_by_key(|(_, rssi)| *rssi)
+    }
+
+    /// RSSI of the strongest report, `i32::MIN` without any.
+    pub fn strongest_rssi(&self) -> i32 {
+        self.strongest().map_or(i32::MIN, |(_, rssi)| rssi)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (Option<Node>, i32)> + '_ {
+        self.reports.iter().map(|&(parent, rssi, _)| (parent, rssi))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn n(mac_last_byte: u8) -> Node {
+        Node::new([0, 0, 0, 0, 0, mac_last_byte])
+    }
+
+    fn candidate(parent: u8, rssi: i32, depth: usize) -> ParentCandidate {
+        ParentCandidate {
+            parent: Some(n(parent)),
+            rssi,
+            load: 0,
+            depth,
+            heard: 1,
+            capabilities: None,
+        }
+    }
+
+    #[test]
+    fn strongest_signal_prefers_steady_then_shallow_parent_on_ties() {
+        let mut candidates = [
+            candidate(1, -70, 1),
+            candidate(2, -60, 3),
+            candidate(3, -60, 2),
+        ];
+        let newcomer = Capabilities::new();
+        let chosen = StrongestSignal.select(n(9), &newcomer, &candidates);
+        assert_eq!(chosen.and_then(|c| c.parent), Some(n(3)));
+        assert_eq!(StrongestSignal.select(n(9), &newcomer, &[]), None);
+
+        candidates[1].heard = 4;
+        let chosen = StrongestSignal.select(n(9), &newcomer, &candidates);
+        assert_eq!(chosen.and_then(|c| c.parent), Some(n(2)));
+    }
+
+    #[test]
+    fn route_cost_trades_signal_for_hops() {
+        let candidates = [candidate(1, -80, 0), candidate(2, -60, 4)];
+        let newcomer = Capabilities::new();
+        let chosen = RouteCost::new(6).select(n(9), &newcomer, &candidates);
+        assert_eq!(chosen.and_then(|c| c.parent), Some(n(1)));
+        let chosen = RouteCost::new(4).select(n(9), &newcomer, &candidates);
+        assert_eq!(chosen.and_then(|c| c.parent), Some(n(2)));
+
+        let mut tied = [candidate(1, -60, 1), candidate(2, -66, 0)];
+        let chosen = RouteCost::new(6).select(n(9), &newcomer, &tied);
+        assert_eq!(chosen.and_then(|c| c.parent), Some(n(2)));
+        tied[0].heard = 3;
+        let chosen = RouteCost::new(6).select(n(9), &newcomer, &tied);
+        assert_eq!(chosen.and_then(|c| c.parent), Some(n(1)));
+    }
+
+    #[test]
+    fn reports_keep_strongest_per_parent() {
+        let mut reports = ParentReports::single(Some(n(1)), -80);
+        reports.record(Some(n(1)), -70);
+        reports.record(Some(n(1)), -90);
+        reports.record(None, -75);
+        assert_eq!(reports.strongest(), Some((Some(n(1)), -70)));
+        assert_eq!(reports.heard(Some(n(1))), 3);
+
+        let mut later = ParentReports::new();
+        later.record_heard(None, -90, 4);
+        reports.merge(&later);
+        assert_eq!(reports.heard(None), 5);
+        assert_eq!(reports.heard(Some(n(2))), 0);
+
+        for i in 2..=MAX_PARENT_REPORTS as u8 {
+            reports.record(Some(n(i)), -85);
+        }
+        reports.record(Some(n(100)), -60);
+        reports.record(Some(n(101)), -95);
+        assert_eq!(reports.iter().count(), MAX_PARENT_REPORTS);
+        assert_eq!(reports.strongest(), Some((Some(n(100)), -60)));
+        assert!(reports.iter().all(|(parent, _)| parent != Some(n(101))));
+    }
+
+    #[test]
+    fn fan_out_shrinks_with_weak_uplink_and_battery() {
+        let limit = FanOutLimit::default();
+        assert_eq!(limit.max_children(None, None), MAX_CHILD_LEAFS);
+        assert_eq!(limit.max_children(Some(-60), Some(80)), MAX_CHILD_LEAFS);
+        assert_eq!(
+            limit.max_children(Some(-85), Some(80)),
+            FAN_OUT_WEAK_UPLINK_CHILDREN
+        );
+        assert_eq!(
+            limit.max_children(Some(-85), Some(5)),
+            FAN_OUT_LOW_BATTERY_CHILDREN
+        );
+        assert_eq!(
+            FanOutLimit::UNLIMITED.max_children(Some(-100), Some(0)),
+            MAX_CHILD_LEAFS
+        );
+    }
+}
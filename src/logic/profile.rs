@@ -0,0 +1,136 @@
+use crate::logic::{
+    capabilities::Features,
+    config::{MeshConfig, NodeRole},
+    error::CodecError,
+};
+
+/// Device type a tag starts up as, so one firmware image serves every device of a deployment.
+/// Selected during provisioning, see `Provisioning::profile`.
+#[repr(u8)]
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum StartupProfile {
+    /// Mains powered node placed to lead the mesh, taking the lead as soon as it hears another.
+    LeaderAnchor = 0x00,
+    /// Battery powered tag carried around. Opts out of relaying, so nothing is attached below
+    /// a node that may walk out of range.
+    MobileTag = 0x01,
+    /// Mains powered node that extends the range. Leaves the lead to anchors and gateways.
+    Relay = 0x02,
+    /// Mains powered node bridging the mesh to the outside. Leads when no anchor is around.
+    Gateway = 0x03,
+}
+
+/// Settings a `StartupProfile` stands for.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct ProfileSettings {
+    pub role: NodeRole,
+    /// See `MeshConfig::lead_after_rounds`.
+    pub lead_after_rounds: u8,
+    /// See `Capabilities::priority`.
+    pub priority: u8,
+    /// Advertises `Features::MAINS_POWERED`, so placement policies may prefer it as a parent.
+    pub mains_powered: bool,
+    /// Interval the application reports the state of the node at.
+    pub telemetry_interval_ms: u32,
+}
+
+impl StartupProfile {
+    pub const fn settings(self) -> ProfileSettings {
+        match self {
+            Self::LeaderAnchor => ProfileSettings {
+                role: NodeRole::Member,
+                lead_after_rounds: 0,
+                priority: 3,
+                mains_powered: true,
+                telemetry_interval_ms: 10_000,
+            },
+            Self::MobileTag => ProfileSettings {
+                role: NodeRole::Monitor,
+                lead_after_rounds: 0,
+                priority: 0,
+                mains_powered: false,
+                telemetry_interval_ms: 2000,
+            },
+            Self::Relay => ProfileSettings {
+                role: NodeRole::Member,
+                lead_after_rounds: 3,
+                priority: 1,
+                mains_powered: true,
+                telemetry_interval_ms: 30_000,
+            },
+            Self::Gateway => ProfileSettings {
+                role: NodeRole::Member,
+                lead_after_rounds: 1,
+                priority: 2,
+                mains_powered: true,
+                telemetry_interval_ms: 5000,
+            },
+        }
+    }
+
+    /// Applies the settings of this profile on top of `config`.
+    pub fn apply(self, config: MeshConfig) -> MeshConfig {
+        let settings = self.settings();
+        let mut features = config.capabilities.features;
+        if settings.mains_powered {
+            features.insert(Features::MAINS_POWERED);
+        }
+        MeshConfig {
+            role: settings.role,
+            lead_after_rounds: settings.lead_after_rounds,
+            capabilities: config
+                .capabilities
+                .with_features(features)
+                .with_priority(settings.priority),
+            ..config
+        }
+    }
+}
+
+impl TryFrom<u8> for StartupProfile {
+    type Error = CodecError;
+
+    fn try_from(value: u8) -> Result<Self, Self::Error> {
+        match value {
+            0x00 => Ok(StartupProfile::LeaderAnchor),
+            0x01 => Ok(StartupProfile::MobileTag),
+            0x02 => Ok(StartupProfile::Relay),
+            0x03 => Ok(StartupProfile::Gateway),
+            v => Err(CodecError::InvalidStartupProfileError(v)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn profile_applies_its_settings() {
+        let tag = StartupProfile::MobileTag.apply(MeshConfig::default());
+        assert_eq!(tag.role, NodeRole::Monitor);
+        assert!(!tag.capabilities.features.contains(Features::MAINS_POWERED));
+
+        let config = MeshConfig {
+            min_join_rssi: -70,
+            ..MeshConfig::default()
+        };
+        let relay = StartupProfile::Relay.apply(config);
+        assert_eq!(relay.role, NodeRole::Member);
+        assert_eq!(relay.lead_after_rounds, 3);
+        assert!(
+            relay.capabilities.priority
+                < StartupProfile::LeaderAnchor
+                    .apply(config)
+                    .capabilities
+                    .priority
+        );
+        assert_eq!(relay.min_join_rssi, -70);
+        assert!(
+            relay
+                .capabilities
+                .features
+                .contains(Features::MAINS_POWERED)
+        );
+    }
+}
@@ -0,0 +1,146 @@
+use crate::logic::{
+    consts::{MAX_NAME_LEN, NETWORK_KEY_LEN, PROVISIONING_BLOB_SIZE},
+    error::CodecError,
+    profile::StartupProfile,
+    wire::{Cursor, WireCodec, WriteBytes, crc16},
+};
+use heapless::{String, Vec};
+
+/// Marks the start of a provisioning blob so an erased or foreign partition is not mistaken
+/// for one.
+pub const PROVISIONING_MAGIC: [u8; 4] = *b"ETAG";
+/// Layout version of the provisioning blob.
+pub const PROVISIONING_FORMAT: u8 = 2;
+/// Offset of the `nvs` partition in the default ESP-IDF partition table. The firmware does not
+/// use NVS otherwise, so the blob is stored at its start.
+pub const PROVISIONING_OFFSET: u32 = 0x9000;
+
+pub type ProvisioningData = Vec<u8, PROVISIONING_BLOB_SIZE>;
+
+/// Hardware variant a tag is assembled as.
+#[repr(u8)]
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum BoardPreset {
+    /// ESP32-C3 with the 128x32 SSD1306 display on I2C.
+    Display = 0x00,
+    /// ESP32-C3 without a display.
+    Headless = 0x01,
+}
+
+impl TryFrom<u8> for BoardPreset {
+    type Error = CodecError;
+
+    fn try_from(value: u8) -> Result<Self, Self::Error> {
+        match value {
+            0x00 => Ok(BoardPreset::Display),
+            0x01 => Ok(BoardPreset::Headless),
+            v => Err(CodecError::InvalidBoardPresetError(v)),
+        }
+    }
+}
+
+/// Per-device settings written on the production bench, so a batch of tags can be configured
+/// without code edits.
+///
+/// Layout: magic | format | board | profile | network key | name length | name | CRC-16 (LE)
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Provisioning {
+    pub network_key: [u8; NETWORK_KEY_LEN],
+    pub name: String<MAX_NAME_LEN>,
+    pub board: BoardPreset,
+    pub profile: StartupProfile,
+}
+
+impl WireCodec<PROVISIONING_BLOB_SIZE> for Provisioning {
+    fn encode(&self, out: &mut ProvisioningData) -> Result<(), CodecError> {
+        let start = out.len();
+        out.write_bytes(&PROVISIONING_MAGIC)?;
+        out.write_u8(PROVISIONING_FORMAT)?;
+        out.write_u8(self.board as u8)?;
+        out.write_u8(self.profile as u8)?;
+        out.write_bytes(&self.network_key)?;
+        out.write_u8(self.name.len() as u8)?;
+        out.write_bytes(self.name.as_bytes())?;
+        let checksum = crc16(&out[start..]);
+        out.write_u16_le(checksum)
+    }
+
+    fn decode(cursor: &mut Cursor<'_>) -> Result<Self, CodecError> {
+        let blob = cursor.remaining();
+        let magic: [u8; 4] = cursor.read_array().map_err(CodecError::CursorReadError)?;
+        if magic != PROVISIONING_MAGIC {
+            return Err(CodecError::InvalidMagicError);
+        }
+        let format = cursor.read_u8().map_err(CodecError::CursorReadError)?;
+        if format != PROVISIONING_FORMAT {
+            return Err(CodecError::UnsupportedFormatError(format));
+        }
+        let board = BoardPreset::try_from(cursor.read_u8().map_err(CodecError::CursorReadError)?)?;
+        let profile =
+            StartupProfile::try_from(cursor.read_u8().map_err(CodecError::CursorReadError)?)?;
+        let network_key = cursor.read_array().map_err(CodecError::CursorReadError)?;
+        let name_len = cursor.read_u8().map_err(CodecError::CursorReadError)? as usize;
+        let name_bytes = cursor.take(name_len).map_err(CodecError::CursorReadError)?;
+        let body_len = blob.len() - cursor.remaining().len();
+        let expected = cursor.read_u16_le().map_err(CodecError::CursorReadError)?;
+        let actual = crc16(&blob[..body_len]);
+        if expected != actual {
+            return Err(CodecError::ChecksumMismatch(expected, actual));
+        }
+        let mut name = String::new();
+        name.push_str(core::str::from_utf8(name_bytes).map_err(|_| CodecError::CodecError)?)
+            .map_err(CodecError::BufferCapacityError)?;
+        Ok(Provisioning {
+            network_key,
+            name,
+            board,
+            profile,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::unwrap_print;
+
+    fn provisioning() -> Provisioning {
+        let mut name = String::new();
+        name.push_str("tag-07").unwrap();
+        Provisioning {
+            network_key: [0xA5; NETWORK_KEY_LEN],
+            name,
+            board: BoardPreset::Headless,
+            profile: StartupProfile::Gateway,
+        }
+    }
+
+    #[test]
+    fn provisioning_encode_decode() {
+        let mut out = ProvisioningData::new();
+        unwrap_print!(provisioning().encode(&mut out));
+
+        let mut cursor = Cursor::new(&out);
+        let decoded = unwrap_print!(Provisioning::decode(&mut cursor));
+        assert_eq!(decoded, provisioning());
+    }
+
+    #[test]
+    fn provisioning_rejects_erased_flash() {
+        let erased = [0xFF; PROVISIONING_BLOB_SIZE];
+        let mut cursor = Cursor::new(&erased);
+        let err = Provisioning::decode(&mut cursor).unwrap_err();
+        assert!(matches!(err, CodecError::InvalidMagicError));
+    }
+
+    #[test]
+    fn provisioning_rejects_corrupted_blob() {
+        let mut out = ProvisioningData::new();
+        unwrap_print!(provisioning().encode(&mut out));
+        out[8] ^= 0x01;
+
+        let mut cursor = Cursor::new(&out);
+        let err = Provisioning::decode(&mut cursor).unwrap_err();
+        assert!(matches!(err, CodecError::ChecksumMismatch(_, _)));
+    }
+}
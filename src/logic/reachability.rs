@@ -0,0 +1,185 @@
+use crate::logic::{
+    consts::{MAX_LEAFS, UNREACHABLE_AFTER_FAILURES, UNREACHABLE_HOLD_MS},
+    node::Node,
+};
+use heapless::LinearMap;
+
+/// Outcome of the sends to one destination. A send succeeds once the next hop acknowledged
+/// the frame, so a success says the route was intact at the first hop.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub struct Reachability {
+    pub successes: u32,
+    pub failures: u32,
+    /// Failures since the last success.
+    pub consecutive_failures: u8,
+    pub last_success_ms: Option<u64>,
+    pub last_failure_ms: Option<u64>,
+    /// Last time a node on the route reported that it has no route to the destination.
+    pub last_report_ms: Option<u64>,
+}
+
+impl Reachability {
+    /// True while the destination failed `UNREACHABLE_AFTER_FAILURES` times in a row and the
+    /// last failure is less than `UNREACHABLE_HOLD_MS` old. Afterwards one send is let through
+    /// again to probe the route.
+    pub fn is_unreachable(&self, now_ms: u64) -> bool {
+        self.consecutive_failures >= UNREACHABLE_AFTER_FAILURES
+            && self
+                .last_failure_ms
+                .is_some_and(|at| now_ms.saturating_sub(at) < UNREACHABLE_HOLD_MS)
+    }
+
+    /// True while a report of a node on the route is less than `UNREACHABLE_HOLD_MS` old and
+    /// no send succeeded since.
+    pub fn is_reported_unreachable(&self, now_ms: u64) -> bool {
+        self.last_report_ms.is_some_and(|at| {
+            now_ms.saturating_sub(at) < UNREACHABLE_HOLD_MS
+                && self.last_success_ms.is_none_or(|success| success <= at)
+        })
+    }
+
+    fn last_activity_ms(&self) -> u64 {
+        self.last_success_ms
+            .max(self.last_failure_ms)
+            .max(self.last_report_ms)
+            .unwrap_or_default()
+    }
+}
+
+/// Send statistics per destination. Once full, the destination that was sent to least recently
+/// is forgotten.
+pub struct ReachabilityCache {
+    destinations: LinearMap<Node, Reachability, MAX_LEAFS>,
+}
+
+impl ReachabilityCache {
+    pub const fn new() -> Self {
+        Self {
+            destinations: LinearMap::new(),
+        }
+    }
+
+    pub fn get(&self, destination: Node) -> Option<Reachability> {
+        self.destinations.get(&destination).copied()
+    }
+
+    pub fn is_unreachable(&self, destination: Node, now_ms: u64) -> bool {
+        self.destinations
+            .get(&destination)
+            .is_some_and(|reachability| reachability.is_unreachable(now_ms))
+    }
+
+    pub fn is_reported_unreachable(&self, destination: Node, now_ms: u64) -> bool {
+        self.destinations
+            .get(&destination)
+            .is_some_and(|reachability| reachability.is_reported_unreachable(now_ms))
+    }
+
+    pub fn record(&mut self, destination: Node, delivered: bool, now_ms: u64) {
+        let mut reachability = self.get(destination).unwrap_or_default();
+        if delivered {
+            reachability.successes = reachability.successes.saturating_add(1);
+            reachability.consecutive_failures = 0;
+            reachability.last_success_ms = Some(now_ms);
+        } else {
+            reachability.failures = reachability.failures.saturating_add(1);
+            reachability.consecutive_failures = reachability.consecutive_failures.saturating_add(1);
+            reachability.last_failure_ms = Some(now_ms);
+        }
+        self.insert(destination, reachability);
+    }
+
+    /// Records that a node on the route to `destination` could not forward to it.
+    pub fn record_report(&mut self, destination: Node, now_ms: u64) {
+        let mut reachability = self.get(destination).unwrap_or_default();
+        reachability.last_report_ms = Some(now_ms);
+        self.insert(destination, reachability);
+    }
+
+    fn insert(&mut self, destination: Node, reachability: Reachability) {
+        if !self.destinations.contains_key(&destination) && self.destinations.is_full() {
+            let stalest = self
+                .destinations
+                .iter()
+                .min_by_key(|(_, reachability)| reachability.last_activity_ms())
+                .map(|(node, _)| *node);
+            if let Some(stalest) = stalest {
+                self.destinations.remove(&stalest);
+            }
+        }
+        let _ = self.destinations.insert(destination, reachability);
+    }
+}
+
+impl Default for ReachabilityCache {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn n(id: u16) -> Node {
+        let [high, low] = id.to_be_bytes();
+        Node::new([0, 0, 0, 0, high, low])
+    }
+
+    #[test]
+    fn destination_is_unreachable_after_repeated_failures() {
+        let mut cache = ReachabilityCache::new();
+        for at in 0..UNREACHABLE_AFTER_FAILURES as u64 {
+            assert!(!cache.is_unreachable(n(1), at));
+            cache.record(n(1), false, at);
+        }
+
+        assert!(cache.is_unreachable(n(1), UNREACHABLE_HOLD_MS));
+        assert!(!cache.is_unreachable(n(1), 2 * UNREACHABLE_HOLD_MS));
+        assert!(!cache.is_unreachable(n(2), 0));
+    }
+
+    #[test]
+    fn success_resets_failure_streak() {
+        let mut cache = ReachabilityCache::new();
+        for _ in 0..UNREACHABLE_AFTER_FAILURES {
+            cache.record(n(1), false, 10);
+        }
+        cache.record(n(1), true, 20);
+
+        let reachability = cache.get(n(1)).expect("destination was recorded");
+        assert!(!reachability.is_unreachable(20));
+        assert_eq!(reachability.successes, 1);
+        assert_eq!(reachability.failures, UNREACHABLE_AFTER_FAILURES as u32);
+        assert_eq!(reachability.last_success_ms, Some(20));
+    }
+
+    #[test]
+    fn report_holds_until_next_success() {
+        let mut cache = ReachabilityCache::new();
+        cache.record(n(1), true, 10);
+        cache.record_report(n(1), 20);
+
+        assert!(cache.is_reported_unreachable(n(1), 20 + UNREACHABLE_HOLD_MS - 1));
+        assert!(!cache.is_reported_unreachable(n(1), 20 + UNREACHABLE_HOLD_MS));
+        assert!(!cache.is_unreachable(n(1), 20));
+
+        cache.record(n(1), true, 30);
+        assert!(!cache.is_reported_unreachable(n(1), 30));
+    }
+
+    #[test]
+    fn full_cache_forgets_stalest_destination() {
+        let mut cache = ReachabilityCache::new();
+        for i in 0..MAX_LEAFS as u16 {
+            cache.record(n(i), true, 100 + i as u64);
+        }
+        cache.record(n(0), true, 500);
+
+        cache.record(n(MAX_LEAFS as u16), false, 600);
+
+        assert!(cache.get(n(1)).is_none());
+        assert!(cache.get(n(0)).is_some());
+        assert!(cache.get(n(MAX_LEAFS as u16)).is_some());
+    }
+}
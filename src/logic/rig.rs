@@ -0,0 +1,82 @@
+#![cfg(any(feature = "std", feature = "rig"))]
+//! Test Anything Protocol output of the on-device checks, so a CI rig reading the serial port
+//! of a board can tell passed from failed steps without anyone watching:
+//!
+//! ```text
+//! TAP version 13
+//! 1..2
+//! ok 1 - join mesh
+//! # 2 nodes
+//! not ok 2 - ping neighbor
+//! # Failed to ...
+//! ```
+
+use core::fmt::{self, Display, Write};
+
+pub struct Tap<W: Write> {
+    out: W,
+    /// Number of the next test.
+    next: u16,
+}
+
+impl<W: Write> Tap<W> {
+    /// Starts a report announcing `planned` tests.
+    pub fn new(mut out: W, planned: u16) -> Result<Self, fmt::Error> {
+        writeln!(out, "TAP version 13")?;
+        writeln!(out, "1..{}", planned)?;
+        Ok(Self { out, next: 1 })
+    }
+
+    pub fn pass(&mut self, description: &str) -> fmt::Result {
+        let number = self.advance();
+        writeln!(self.out, "ok {} - {}", number, description)
+    }
+
+    /// Reports a failed test followed by `reason` as a diagnostic.
+    pub fn fail(&mut self, description: &str, reason: impl Display) -> fmt::Result {
+        let number = self.advance();
+        writeln!(self.out, "not ok {} - {}", number, description)?;
+        self.diagnostic(reason)
+    }
+
+    /// Reports a test that could not run, which does not count as a failure.
+    pub fn skip(&mut self, description: &str, reason: impl Display) -> fmt::Result {
+        let number = self.advance();
+        writeln!(
+            self.out,
+            "ok {} - {} # SKIP {}",
+            number, description, reason
+        )
+    }
+
+    /// Adds a line the rig shows but does not interpret.
+    pub fn diagnostic(&mut self, text: impl Display) -> fmt::Result {
+        writeln!(self.out, "# {}", text)
+    }
+
+    fn advance(&mut self) -> u16 {
+        let number = self.next;
+        self.next += 1;
+        number
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn tap_numbers_results_and_marks_skips() {
+        let mut out = String::new();
+        let mut tap = Tap::new(&mut out, 3).unwrap();
+        tap.pass("join mesh").unwrap();
+        tap.diagnostic(format_args!("{} nodes", 2)).unwrap();
+        tap.fail("ping neighbor", "timed out").unwrap();
+        tap.skip("trace route", "no neighbor").unwrap();
+        assert_eq!(
+            out,
+            "TAP version 13\n1..3\nok 1 - join mesh\n# 2 nodes\nnot ok 2 - ping neighbor\n\
+             # timed out\nok 3 - trace route # SKIP no neighbor\n"
+        );
+    }
+}
@@ -0,0 +1,141 @@
+use crate::logic::{
+    capabilities::Capabilities,
+    consts::{MAX_LEAFS, MAX_NAME_LEN},
+    node::Node,
+    text::{self, SHORT_MAC_LEN},
+    tree::Tree,
+};
+use heapless::{LinearMap, String, Vec};
+
+/// Name a node was provisioned with.
+pub type NodeName = String<MAX_NAME_LEN>;
+
+/// A member of the mesh as the leader admitted it, see `Mesh::roster`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct RosterEntry {
+    pub node: Node,
+    /// Last two bytes of the MAC, as shown on the display.
+    pub short_id: String<SHORT_MAC_LEN>,
+    /// Empty until the node introduced itself.
+    pub name: NodeName,
+    /// Local time in milliseconds the leader first attached the node. Moving to another parent
+    /// keeps it.
+    pub joined_ms: u64,
+    pub capabilities: Option<Capabilities>,
+}
+
+struct Admission {
+    joined_ms: u64,
+    name: NodeName,
+}
+
+/// Admission log of the leader, with when each member joined and what it calls itself. Only
+/// nodes still in the tree count as joined. Once full, the others make room.
+pub struct Roster {
+    admissions: LinearMap<Node, Admission, MAX_LEAFS>,
+}
+
+impl Roster {
+    pub const fn new() -> Self {
+        Self {
+            admissions: LinearMap::new(),
+        }
+    }
+
+    /// Records that `node` was attached at `now_ms`, unless it already joined earlier.
+    pub fn record_join(&mut self, tree: &Tree, node: Node, now_ms: u64) {
+        self.admission(tree, node, now_ms);
+    }
+
+    /// Records the name `node` introduced itself with.
+    pub fn record_name(&mut self, tree: &Tree, node: Node, name: NodeName, now_ms: u64) {
+        if let Some(admission) = self.admission(tree, node, now_ms) {
+            admission.name = name;
+        }
+    }
+
+    fn admission(&mut self, tree: &Tree, node: Node, now_ms: u64) -> Option<&mut Admission> {
+        if !self.admissions.contains_key(&node) {
+            if self.admissions.is_full() {
+                self.admissions
+                    .retain(|member, _| tree.is_downstream(*member));
+            }
+            let admission = Admission {
+                joined_ms: now_ms,
+                name: NodeName::new(),
+            };
+            if self.admissions.insert(node, admission).is_err() {
+                return None;
+            }
+        }
+        self.admissions.get_mut(&node)
+    }
+
+    /// Every node still in `tree`, in the order they joined, together with the capabilities
+    /// `capabilities_of` knows for them.
+    pub fn entries(
+        &self,
+        tree: &Tree,
+        capabilities_of: impl Fn(Node) -> Option<Capabilities>,
+    ) -> Vec<RosterEntry, MAX_LEAFS> {
+        let mut entries = self
+            .admissions
+            .iter()
+            .filter(|(node, _)| tree.is_downstream(**node))
+            .map(|(node, admission)| RosterEntry {
+                node: *node,
+                short_id: text::short_mac(*node),
+                name: admission.name.clone(),
+                joined_ms: admission.joined_ms,
+                capabilities: capabilities_of(*node),
+            })
+            .collect::<Vec<_, MAX_LEAFS>>();
+        entries.sort_unstable_by_key(|entry| entry.joined_ms);
+        entries
+    }
+}
+
+impl Default for Roster {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn n(mac_last_byte: u8) -> Node {
+        Node::new([0, 0, 0, 0, 0, mac_last_byte])
+    }
+
+    fn name(text: &str) -> NodeName {
+        let mut name = NodeName::new();
+        name.push_str(text).unwrap();
+        name
+    }
+
+    #[test]
+    fn roster_lists_joined_nodes_in_join_order() {
+        let mut tree = Tree::new();
+        tree.init().unwrap();
+        tree.upsert_edge(None, n(2)).unwrap();
+        tree.upsert_edge(None, n(1)).unwrap();
+        let mut roster = Roster::new();
+
+        roster.record_join(&tree, n(2), 100);
+        roster.record_join(&tree, n(1), 200);
+        roster.record_join(&tree, n(2), 300);
+        roster.record_name(&tree, n(1), name("tag-01"), 400);
+        roster.record_join(&tree, n(3), 500);
+
+        let entries = roster.entries(&tree, |node| (node == n(1)).then(Capabilities::new));
+        assert_eq!(entries.len(), 2);
+        assert_eq!((entries[0].node, entries[0].joined_ms), (n(2), 100));
+        assert_eq!(entries[0].name, "");
+        assert_eq!(entries[0].capabilities, None);
+        assert_eq!(entries[1].name, "tag-01");
+        assert_eq!(entries[1].short_id, "0001");
+        assert_eq!(entries[1].capabilities, Some(Capabilities::new()));
+    }
+}
@@ -0,0 +1,117 @@
+use crate::logic::{
+    consts::{MAX_SHORTCUTS, SHORTCUT_DROP_RSSI, SHORTCUT_MAX_AGE_MS, SHORTCUT_MIN_RSSI},
+    node::Node,
+};
+use heapless::LinearMap;
+
+struct Shortcut {
+    rssi: i32,
+    heard_ms: u64,
+}
+
+/// Destinations this node hears directly with a strong signal, so frames to them can skip the
+/// tree. A node becomes a shortcut once heard at `SHORTCUT_MIN_RSSI` or better and stays one
+/// until it drops below `SHORTCUT_DROP_RSSI` or is silent for `SHORTCUT_MAX_AGE_MS`, so a link
+/// at the edge does not flip between both routes. Once full, the weakest entry makes room.
+pub struct ShortcutTable {
+    entries: LinearMap<Node, Shortcut, MAX_SHORTCUTS>,
+}
+
+impl ShortcutTable {
+    pub const fn new() -> Self {
+        Self {
+            entries: LinearMap::new(),
+        }
+    }
+
+    /// Records a frame received directly from `node`.
+    pub fn record(&mut self, node: Node, rssi: i32, now_ms: u64) {
+        if let Some(shortcut) = self.entries.get_mut(&node) {
+            if rssi < SHORTCUT_DROP_RSSI {
+                self.entries.remove(&node);
+            } else {
+                *shortcut = Shortcut {
+                    rssi,
+                    heard_ms: now_ms,
+                };
+            }
+            return;
+        }
+        if rssi < SHORTCUT_MIN_RSSI {
+            return;
+        }
+        if self.entries.is_full() {
+            let weakest = self
+                .entries
+                .iter()
+                .min_by_key(|(_, shortcut)| shortcut.rssi)
+                .filter(|(_, shortcut)| shortcut.rssi < rssi)
+                .map(|(node, _)| *node);
+            match weakest {
+                Some(weakest) => {
+                    self.entries.remove(&weakest);
+                }
+                None => return,
+            }
+        }
+        let shortcut = Shortcut {
+            rssi,
+            heard_ms: now_ms,
+        };
+        let _ = self.entries.insert(node, shortcut);
+    }
+
+    /// Returns true if frames to `node` may go to it directly.
+    pub fn is_shortcut(&self, node: Node, now_ms: u64) -> bool {
+        self.entries
+            .get(&node)
+            .is_some_and(|shortcut| now_ms.saturating_sub(shortcut.heard_ms) <= SHORTCUT_MAX_AGE_MS)
+    }
+}
+
+impl Default for ShortcutTable {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn n(mac_last_byte: u8) -> Node {
+        Node::new([0, 0, 0, 0, 0, mac_last_byte])
+    }
+
+    #[test]
+    fn shortcut_needs_strong_signal_and_keeps_it_until_degraded() {
+        let mut table = ShortcutTable::new();
+        table.record(n(1), SHORTCUT_MIN_RSSI - 1, 0);
+        assert!(!table.is_shortcut(n(1), 0));
+
+        table.record(n(1), SHORTCUT_MIN_RSSI, 100);
+        assert!(table.is_shortcut(n(1), 100));
+        table.record(n(1), SHORTCUT_DROP_RSSI, 200);
+        assert!(table.is_shortcut(n(1), 200));
+        assert!(!table.is_shortcut(n(1), 201 + SHORTCUT_MAX_AGE_MS));
+
+        table.record(n(1), SHORTCUT_DROP_RSSI - 1, 300);
+        assert!(!table.is_shortcut(n(1), 300));
+        table.record(n(1), SHORTCUT_DROP_RSSI, 400);
+        assert!(!table.is_shortcut(n(1), 400));
+    }
+
+    #[test]
+    fn full_table_replaces_weakest_shortcut() {
+        let mut table = ShortcutTable::new();
+        for i in 0..MAX_SHORTCUTS as u8 {
+            table.record(n(i), SHORTCUT_MIN_RSSI + i as i32, 0);
+        }
+        table.record(n(100), SHORTCUT_MIN_RSSI, 0);
+        assert!(!table.is_shortcut(n(100), 0));
+
+        table.record(n(101), SHORTCUT_MIN_RSSI + 1, 0);
+        assert!(table.is_shortcut(n(101), 0));
+        assert!(!table.is_shortcut(n(0), 0));
+    }
+}
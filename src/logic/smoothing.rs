@@ -0,0 +1,111 @@
+use heapless::{HistoryBuf, Vec};
+
+/// Fractional bits of the fixed point state of `Ewma`.
+const FRACTION_BITS: u32 = 8;
+
+/// Exponentially weighted moving average of signal strengths in fixed point, so it needs no
+/// floating point math. Every sample moves the average by `1 / 2^shift` of its distance, a shift
+/// of 1 averages it with the previous value. The first sample is taken as is.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct Ewma {
+    /// Average in 1/256, `None` before the first sample.
+    state: Option<i32>,
+    shift: u8,
+}
+
+impl Ewma {
+    pub const fn new(shift: u8) -> Self {
+        Self { state: None, shift }
+    }
+
+    /// Adds `sample` and returns the new average.
+    pub fn update(&mut self, sample: i32) -> i32 {
+        let scaled = sample.saturating_mul(1 << FRACTION_BITS);
+        let state = match self.state {
+            Some(state) => state + ((scaled - state) >> self.shift.min(31)),
+            None => scaled,
+        };
+        self.state = Some(state);
+        state >> FRACTION_BITS
+    }
+
+    /// The average rounded down, `None` before the first sample.
+    pub fn value(&self) -> Option<i32> {
+        self.state.map(|state| state >> FRACTION_BITS)
+    }
+}
+
+/// Median of the last `N` samples, so single outliers are ignored completely instead of only
+/// weighted down like in `Ewma`.
+pub struct MedianFilter<const N: usize> {
+    samples: HistoryBuf<i32, N>,
+}
+
+impl<const N: usize> MedianFilter<N> {
+    pub const fn new() -> Self {
+        Self {
+            samples: HistoryBuf::new(),
+        }
+    }
+
+    /// Adds `sample`, replacing the oldest one once `N` are kept, and returns the new median.
+    pub fn update(&mut self, sample: i32) -> i32 {
+        self.samples.write(sample);
+        self.value().unwrap_or(sample)
+    }
+
+    /// Median of the kept samples, the mean of both middle ones rounded down for an even count.
+    /// `None` before the first sample.
+    pub fn value(&self) -> Option<i32> {
+        let mut sorted = self.samples.iter().copied().collect::<Vec<_, N>>();
+        sorted.sort_unstable();
+        let upper = *sorted.get(sorted.len() / 2)?;
+        if sorted.len() % 2 == 1 {
+            return Some(upper);
+        }
+        let lower = sorted[sorted.len() / 2 - 1];
+        Some((lower + upper).div_euclid(2))
+    }
+}
+
+impl<const N: usize> Default for MedianFilter<N> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn ewma_moves_by_a_fraction_of_the_distance() {
+        let mut half = Ewma::new(1);
+        assert_eq!(half.value(), None);
+        assert_eq!(half.update(-60), -60);
+        assert_eq!(half.update(-81), -71);
+        assert_eq!(half.update(-81), -76);
+
+        let mut slow = Ewma::new(3);
+        slow.update(-50);
+        assert_eq!(slow.update(-90), -55);
+        for _ in 0..100 {
+            slow.update(-90);
+        }
+        assert_eq!(slow.value(), Some(-90));
+    }
+
+    #[test]
+    fn median_ignores_outliers() {
+        let mut filter = MedianFilter::<5>::new();
+        assert_eq!(filter.value(), None);
+        assert_eq!(filter.update(-70), -70);
+        assert_eq!(filter.update(-71), -71);
+        assert_eq!(filter.update(-20), -70);
+        assert_eq!(filter.update(-72), -71);
+        for sample in [-72, -73, -74] {
+            filter.update(sample);
+        }
+        assert_eq!(filter.value(), Some(-72));
+    }
+}
@@ -0,0 +1,64 @@
+use crate::logic::{consts::MAX_CHILD_LEAFS, node::Node};
+use heapless::Vec;
+
+/// Snapshot of the tree, queues and relay counters of a node, see `Mesh::stats`. Plain data, so
+/// the display status page and the simulator read it alike.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct MeshStats {
+    /// Nodes in the tree, this node included.
+    pub node_count: usize,
+    /// Levels of the tree rooted at this node, 1 while it is alone.
+    pub height: usize,
+    /// Each child of this node with the number of nodes in its subtree, the child included.
+    /// The uplink is no child, the nodes beyond it are the ones missing from the sum.
+    pub subtrees: Vec<(Node, usize), MAX_CHILD_LEAFS>,
+    /// Frames waiting in the send queue of the link.
+    pub send_queue: usize,
+    /// Application messages waiting for `Mesh::receive`.
+    pub receive_queue: usize,
+    /// Organization messages waiting for the role tasks.
+    pub organize_queue: usize,
+    pub forwards: ForwardCounters,
+}
+
+/// Frames this node relayed for others since it started. Counts wrap around.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub struct ForwardCounters {
+    /// Frames handed to the link towards their next hop.
+    pub forwarded: u32,
+    /// Frames that ran out of hops, had no route or found the send queue full.
+    pub dropped: u32,
+}
+
+impl ForwardCounters {
+    pub const fn new() -> Self {
+        Self {
+            forwarded: 0,
+            dropped: 0,
+        }
+    }
+
+    /// Counts the outcome of forwarding one frame.
+    pub fn record<E>(&mut self, result: &Result<(), E>) {
+        match result {
+            Ok(()) => self.forwarded = self.forwarded.wrapping_add(1),
+            Err(_) => self.dropped = self.dropped.wrapping_add(1),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn forward_counters_count_outcomes() {
+        let mut counters = ForwardCounters::new();
+        counters.record::<()>(&Ok(()));
+        counters.record::<()>(&Ok(()));
+        counters.record(&Err(()));
+
+        assert_eq!(counters.forwarded, 2);
+        assert_eq!(counters.dropped, 1);
+    }
+}
@@ -0,0 +1,53 @@
+use crate::logic::consts::TELEMETRY_MAX_BACKOFF;
+
+/// Interval between two telemetry reports of this node. Every congestion notice of the mesh
+/// doubles the interval up to `TELEMETRY_MAX_BACKOFF` times the interval of the profile, and
+/// every report without one brings it a step back, so sources behind a backed up relay slow
+/// down quickly and recover gradually.
+pub struct TelemetryRate {
+    base_ms: u32,
+    factor: u32,
+}
+
+impl TelemetryRate {
+    pub const fn new(base_ms: u32) -> Self {
+        Self { base_ms, factor: 1 }
+    }
+
+    pub fn interval_ms(&self) -> u32 {
+        self.base_ms.saturating_mul(self.factor)
+    }
+
+    /// Adapts the interval after a report, `congested` telling whether a congestion notice
+    /// arrived since the previous one.
+    pub fn adapt(&mut self, congested: bool) {
+        self.factor = if congested {
+            (self.factor * 2).min(TELEMETRY_MAX_BACKOFF)
+        } else {
+            (self.factor - 1).max(1)
+        };
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn congestion_backs_off_and_recovers_step_by_step() {
+        let mut rate = TelemetryRate::new(1000);
+        rate.adapt(true);
+        assert_eq!(rate.interval_ms(), 2000);
+        for _ in 0..10 {
+            rate.adapt(true);
+        }
+        assert_eq!(rate.interval_ms(), 1000 * TELEMETRY_MAX_BACKOFF);
+
+        rate.adapt(false);
+        assert_eq!(rate.interval_ms(), 1000 * (TELEMETRY_MAX_BACKOFF - 1));
+        for _ in 0..10 {
+            rate.adapt(false);
+        }
+        assert_eq!(rate.interval_ms(), 1000);
+    }
+}
@@ -0,0 +1,122 @@
+//! Formatting of diagnostics for the display and the serial console into fixed size strings,
+//! without `core::fmt`. Every buffer is sized for the longest possible value, so nothing is
+//! ever truncated.
+
+use crate::logic::node::Node;
+use heapless::String;
+
+/// `aa:bb:cc:dd:ee:ff`
+pub const MAC_LEN: usize = 17;
+/// Last two bytes of the MAC, `eeff`. Enough to tell the nodes of one mesh apart on the display.
+pub const SHORT_MAC_LEN: usize = 4;
+/// `-128dBm`
+pub const RSSI_LEN: usize = 8;
+/// `4294967295`
+pub const COUNT_LEN: usize = 10;
+/// `213503982334d 23:59:59` for the longest `u64` uptime.
+pub const UPTIME_LEN: usize = 22;
+
+const HEX: &[u8; 16] = b"0123456789abcdef";
+
+pub fn mac(node: Node) -> String<MAC_LEN> {
+    let mut out = String::new();
+    for (i, byte) in node.mac.iter().enumerate() {
+        if i != 0 {
+            let _ = out.push(':');
+        }
+        push_hex(&mut out, *byte);
+    }
+    out
+}
+
+pub fn short_mac(node: Node) -> String<SHORT_MAC_LEN> {
+    let mut out = String::new();
+    push_hex(&mut out, node.mac[4]);
+    push_hex(&mut out, node.mac[5]);
+    out
+}
+
+pub fn rssi(dbm: i32) -> String<RSSI_LEN> {
+    let mut out = String::new();
+    let dbm = dbm.clamp(i8::MIN as i32, i8::MAX as i32);
+    if dbm < 0 {
+        let _ = out.push('-');
+    }
+    push_decimal(&mut out, dbm.unsigned_abs() as u64, 1);
+    let _ = out.push_str("dBm");
+    out
+}
+
+pub fn count(value: u32) -> String<COUNT_LEN> {
+    let mut out = String::new();
+    push_decimal(&mut out, value as u64, 1);
+    out
+}
+
+/// `hh:mm:ss`, prefixed with the days once the node ran for a day, e.g. `3d 04:05:06`.
+pub fn uptime(ms: u64) -> String<UPTIME_LEN> {
+    let mut out = String::new();
+    let seconds = ms / 1000;
+    let days = seconds / 86_400;
+    if days > 0 {
+        push_decimal(&mut out, days, 1);
+        let _ = out.push_str("d ");
+    }
+    push_decimal(&mut out, seconds / 3600 % 24, 2);
+    let _ = out.push(':');
+    push_decimal(&mut out, seconds / 60 % 60, 2);
+    let _ = out.push(':');
+    push_decimal(&mut out, seconds % 60, 2);
+    out
+}
+
+fn push_hex<const N: usize>(out: &mut String<N>, byte: u8) {
+    let _ = out.push(HEX[(byte >> 4) as usize] as char);
+    let _ = out.push(HEX[(byte & 0x0F) as usize] as char);
+}
+
+/// Appends `value` in decimal, padded with leading zeros to at least `width` digits.
+fn push_decimal<const N: usize>(out: &mut String<N>, mut value: u64, width: usize) {
+    let mut digits = [0u8; 20];
+    let mut len = 0;
+    while value > 0 || len < width {
+        digits[len] = b'0' + (value % 10) as u8;
+        value /= 10;
+        len += 1;
+    }
+    for digit in digits[..len].iter().rev() {
+        let _ = out.push(*digit as char);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn formats_node_addresses() {
+        let node = Node::new([0x00, 0x1a, 0x2b, 0x3c, 0x4d, 0xff]);
+        assert_eq!(mac(node), "00:1a:2b:3c:4d:ff");
+        assert_eq!(short_mac(node), "4dff");
+    }
+
+    #[test]
+    fn formats_numbers_without_truncation() {
+        assert_eq!(rssi(-67), "-67dBm");
+        assert_eq!(rssi(0), "0dBm");
+        assert_eq!(rssi(i32::MIN), "-128dBm");
+        assert_eq!(count(0), "0");
+        assert_eq!(count(u32::MAX), "4294967295");
+    }
+
+    #[test]
+    fn formats_uptime() {
+        assert_eq!(uptime(0), "00:00:00");
+        assert_eq!(uptime(3_723_999), "01:02:03");
+        assert_eq!(
+            uptime((3 * 86_400 + 4 * 3600 + 5 * 60 + 6) * 1000),
+            "3d 04:05:06"
+        );
+        assert_eq!(uptime(u64::MAX), "213503982334d 14:25:51");
+    }
+}
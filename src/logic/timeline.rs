@@ -0,0 +1,180 @@
+#![cfg(feature = "std")]
+//! Timeline of topology snapshots taken in simulated runs, so the way a mesh converges can be
+//! inspected while tuning timers. A timeline file holds one snapshot per line as JSON:
+//!
+//! `{"at_ms":1500,"node":"00:00:00:00:00:02","edges":[["00:00:00:00:00:01","00:00:00:00:00:02"]]}`
+//!
+//! Every edge is a node and its parent in the tree of `node`. The `timeline` binary plots a
+//! file. The simulated meshes of the tests record one when `TIMELINE_ENV` names a path.
+
+use std::{
+    fs::{File, OpenOptions},
+    io::{self, Write},
+    path::Path,
+};
+
+use crate::logic::{asynchronous, node::Node, tree::Tree};
+
+/// Environment variable holding the path simulated meshes append their snapshots to.
+pub const TIMELINE_ENV: &str = "ESP_TAG_TIMELINE";
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Snapshot {
+    /// Local time of the snapshotting node in milliseconds.
+    pub at_ms: u64,
+    pub node: Node,
+    /// Every other node of the tree of `node` together with its parent.
+    pub edges: Vec<(Node, Node)>,
+}
+
+impl Snapshot {
+    pub fn of(at_ms: u64, node: Node, tree: &Tree) -> Self {
+        let edges = tree
+            .into_iter()
+            .map(|(child, parent)| (child, parent.unwrap_or(node)))
+            .collect();
+        Self { at_ms, node, edges }
+    }
+
+    pub fn to_json(&self) -> String {
+        let edges = self
+            .edges
+            .iter()
+            .map(|(child, parent)| format!("[\"{}\",\"{}\"]", child, parent))
+            .collect::<Vec<_>>()
+            .join(",");
+        format!(
+            "{{\"at_ms\":{},\"node\":\"{}\",\"edges\":[{}]}}",
+            self.at_ms, self.node, edges
+        )
+    }
+
+    /// Reads a line written by `to_json`, `None` for anything else.
+    pub fn parse(line: &str) -> Option<Self> {
+        let (at_ms, rest) = line.strip_prefix("{\"at_ms\":")?.split_once(',')?;
+        let (node, rest) = rest.strip_prefix("\"node\":\"")?.split_once('"')?;
+        let edges = rest.strip_prefix(",\"edges\":[")?.strip_suffix("]}")?;
+        let macs = edges
+            .split('"')
+            .filter(|part| part.contains(':'))
+            .map(parse_mac)
+            .collect::<Option<Vec<_>>>()?;
+        if macs.len() % 2 != 0 {
+            return None;
+        }
+        Some(Self {
+            at_ms: at_ms.parse().ok()?,
+            node: parse_mac(node)?,
+            edges: macs.chunks(2).map(|pair| (pair[0], pair[1])).collect(),
+        })
+    }
+
+    /// Links of the tree regardless of direction, sorted. Every node sees the tree from its own
+    /// root, so two snapshots of the same tree only compare equal this way.
+    pub fn links(&self) -> Vec<([u8; 6], [u8; 6])> {
+        let mut links = self
+            .edges
+            .iter()
+            .map(|(a, b)| (a.mac.min(b.mac), a.mac.max(b.mac)))
+            .collect::<Vec<_>>();
+        links.sort();
+        links
+    }
+}
+
+/// Returns true if the latest snapshots of all nodes, one per node, describe the same tree.
+pub fn converged(latest: &[Snapshot]) -> bool {
+    latest
+        .windows(2)
+        .all(|pair| pair[0].links() == pair[1].links())
+}
+
+fn parse_mac(text: &str) -> Option<Node> {
+    let mut mac = [0; 6];
+    let mut parts = text.split(':');
+    for byte in mac.iter_mut() {
+        *byte = u8::from_str_radix(parts.next()?, 16).ok()?;
+    }
+    parts.next().is_none().then_some(Node::new(mac))
+}
+
+/// Timeline file that snapshots are appended to.
+pub struct Timeline {
+    file: File,
+}
+
+impl Timeline {
+    pub fn append(path: impl AsRef<Path>) -> io::Result<Self> {
+        let file = OpenOptions::new().create(true).append(true).open(path)?;
+        Ok(Self { file })
+    }
+
+    /// Writes `snapshot` as one line with a single write, so nodes sharing a file never
+    /// interleave their lines.
+    pub fn record(&mut self, snapshot: &Snapshot) -> io::Result<()> {
+        self.file
+            .write_all(format!("{}\n", snapshot.to_json()).as_bytes())
+    }
+}
+
+/// Appends a snapshot of the tree of `node` to the timeline at `path` every `interval`.
+pub async fn record_tree(
+    node: Node,
+    tree: &'static asynchronous::Mutex<Tree>,
+    path: String,
+    interval: asynchronous::Duration,
+) {
+    let mut timeline = match Timeline::append(&path) {
+        Ok(timeline) => timeline,
+        Err(e) => {
+            println!("Failed to open timeline {}: {}", path, e);
+            return;
+        }
+    };
+    let mut ticker = asynchronous::Ticker::every(interval);
+    loop {
+        ticker.next().await;
+        let snapshot = Snapshot::of(asynchronous::now_ms(), node, &*tree.lock().await);
+        if let Err(e) = timeline.record(&snapshot) {
+            println!("Failed to write timeline {}: {}", path, e);
+            return;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn n(mac_last_byte: u8) -> Node {
+        Node::new([0, 0, 0, 0, 0xab, mac_last_byte])
+    }
+
+    #[test]
+    fn snapshot_survives_json_line() {
+        let mut tree = Tree::new();
+        tree.init().unwrap();
+        tree.upsert_edge(None, n(2)).unwrap();
+        tree.upsert_edge(Some(n(2)), n(3)).unwrap();
+        let snapshot = Snapshot::of(1500, n(1), &tree);
+
+        assert_eq!(snapshot.edges, [(n(2), n(1)), (n(3), n(2))]);
+        assert_eq!(Snapshot::parse(&snapshot.to_json()), Some(snapshot));
+        assert_eq!(Snapshot::parse("{\"at_ms\":1}"), None);
+    }
+
+    #[test]
+    fn views_of_one_tree_have_converged() {
+        let view = |node, edges: &[(Node, Node)]| Snapshot {
+            at_ms: 0,
+            node,
+            edges: edges.to_vec(),
+        };
+        let leader = view(n(1), &[(n(2), n(1)), (n(3), n(2))]);
+        let member = view(n(3), &[(n(2), n(3)), (n(1), n(2))]);
+        let stale = view(n(2), &[(n(1), n(2))]);
+
+        assert!(converged(&[leader.clone(), member.clone()]));
+        assert!(!converged(&[leader, member, stale]));
+    }
+}
@@ -0,0 +1,419 @@
+use crate::logic::{
+    consts::{
+        INIT_TOPOLOGY_COOLDOWN_MS, MAX_CHILD_LEAFS, MAX_ORGANIZATION_BODY_LEN,
+        MAX_TOPOLOGY_CHUNK_EDGES, MESSAGE_SIZE, TOPOLOGY_REQUEST_RETRIES,
+    },
+    error::CodecError,
+    node::Node,
+    wire::{Cursor, WriteBytes, encoded_len},
+};
+use crate::wire_codec;
+use heapless::{LinearMap, Vec};
+
+/// A node and its parent, see `MessageContent::UpsertEdge`.
+pub type Edge = (Option<Node>, Option<Node>);
+
+/// Part of the initial topology a parent sends to a newly attached child, holding as many edges
+/// as fit into one frame. The first edge of chunk 0 attaches the sender itself, the rest
+/// describe the tree of the sender. The receiver applies the edges of a chunk all at once.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct TopologyChunk {
+    pub index: u8,
+    pub total: u8,
+    pub edges: Vec<Edge, MAX_TOPOLOGY_CHUNK_EDGES>,
+}
+
+wire_codec! {
+    TopologyChunk: MESSAGE_SIZE {
+        index: u8,
+        total: u8,
+        edges: Vec<Edge, MAX_TOPOLOGY_CHUNK_EDGES>,
+    }
+    round_trip_test topology_chunk_round_trip = TopologyChunk {
+        index: 2,
+        total: 5,
+        edges: Vec::from_slice(&[
+            (Some(Node::new([1, 2, 3, 4, 5, 6])), None),
+            (None, Some(Node::new([6, 5, 4, 3, 2, 1]))),
+        ])
+        .unwrap(),
+    };
+}
+
+/// Splits the edges of an initial topology into chunks, packing as many edges into each as fit
+/// into one frame next to the longest header. The split only depends on the edges, so a chunk
+/// requested again has the same index as before.
+#[derive(Clone)]
+pub struct TopologyChunks<'a> {
+    edges: &'a [Edge],
+    index: u8,
+    total: u8,
+}
+
+impl<'a> TopologyChunks<'a> {
+    pub fn new(edges: &'a [Edge]) -> Self {
+        let chunks = Self {
+            edges,
+            index: 0,
+            total: 0,
+        };
+        let total = chunks.clone().count() as u8;
+        Self { total, ..chunks }
+    }
+
+    pub fn total(&self) -> u8 {
+        self.total
+    }
+}
+
+impl Iterator for TopologyChunks<'_> {
+    type Item = TopologyChunk;
+
+    fn next(&mut self) -> Option<TopologyChunk> {
+        if self.edges.is_empty() {
+            return None;
+        }
+        let mut chunk = TopologyChunk {
+            index: self.index,
+            total: self.total,
+            edges: Vec::new(),
+        };
+        let mut len = encoded_len::<MESSAGE_SIZE>(&chunk).unwrap_or(0);
+        for edge in self.edges {
+            let edge_len = encoded_len::<MESSAGE_SIZE>(edge).unwrap_or(MESSAGE_SIZE);
+            // A full edge always fits into an empty chunk, see the assertions in `consts`.
+            if len + edge_len > MAX_ORGANIZATION_BODY_LEN || chunk.edges.push(*edge).is_err() {
+                break;
+            }
+            len += edge_len;
+        }
+        self.edges = &self.edges[chunk.edges.len()..];
+        self.index = self.index.saturating_add(1);
+        Some(chunk)
+    }
+}
+
+/// One bit per chunk of the initial topology, wide enough for the chunks of the largest tree.
+#[cfg(not(feature = "wide-slots"))]
+pub type ChunkBitmap = u32;
+#[cfg(feature = "wide-slots")]
+pub type ChunkBitmap = u128;
+
+/// Bitmap with one bit set for each of `total` chunks.
+pub fn chunk_mask(total: u8) -> ChunkBitmap {
+    match total {
+        0 => 0,
+        t if t as u32 >= ChunkBitmap::BITS => ChunkBitmap::MAX,
+        t => (1 << t) - 1,
+    }
+}
+
+/// Writes `bitmap` as a length and its little endian bytes up to the highest one set, so a
+/// bitmap encodes the same in every build whatever the width of `ChunkBitmap`.
+pub fn encode_chunk_bitmap<const N: usize>(
+    bitmap: ChunkBitmap,
+    out: &mut Vec<u8, N>,
+) -> Result<(), CodecError> {
+    let len = (ChunkBitmap::BITS - bitmap.leading_zeros()).div_ceil(8) as usize;
+    out.write_u8(len as u8)?;
+    out.write_bytes(&bitmap.to_le_bytes()[..len])
+}
+
+pub fn decode_chunk_bitmap(cursor: &mut Cursor<'_>) -> Result<ChunkBitmap, CodecError> {
+    let len = cursor.read_u8().map_err(CodecError::CursorReadError)? as usize;
+    let mut bytes = [0; size_of::<ChunkBitmap>()];
+    if len > bytes.len() {
+        return Err(CodecError::LengthExceededError(len as u32));
+    }
+    bytes[..len].copy_from_slice(cursor.take(len).map_err(CodecError::CursorReadError)?);
+    Ok(ChunkBitmap::from_le_bytes(bytes))
+}
+
+/// Tracks which chunks of the initial topology a joining node has received, so the missing
+/// ones can be requested again.
+pub struct TopologyTransfer {
+    source: Node,
+    total: u8,
+    received: ChunkBitmap,
+    requests: u8,
+}
+
+impl TopologyTransfer {
+    pub const fn new(source: Node) -> Self {
+        Self {
+            source,
+            total: 0,
+            received: 0,
+            requests: 0,
+        }
+    }
+
+    /// Node that sends the chunks and answers requests for missing ones.
+    pub fn source(&self) -> Node {
+        self.source
+    }
+
+    /// Marks chunk `index` as received. A different `total` means the sender resent a newer
+    /// snapshot of its tree, so chunks received before are no longer counted.
+    pub fn record(&mut self, index: u8, total: u8) {
+        if total != self.total {
+            self.total = total;
+            self.received = 0;
+        }
+        if (index as u32) < ChunkBitmap::BITS {
+            self.received |= 1 << index;
+        }
+    }
+
+    pub fn missing(&self) -> ChunkBitmap {
+        chunk_mask(self.total) & !self.received
+    }
+
+    pub fn is_complete(&self) -> bool {
+        self.missing() == 0
+    }
+
+    /// Returns the chunks to request next, or `None` once the transfer is complete or the
+    /// sender did not answer `TOPOLOGY_REQUEST_RETRIES` requests.
+    pub fn next_request(&mut self) -> Option<ChunkBitmap> {
+        if self.is_complete() || self.requests >= TOPOLOGY_REQUEST_RETRIES {
+            return None;
+        }
+        self.requests += 1;
+        Some(self.missing())
+    }
+}
+
+/// Placements the leader sent to one member that the member has not acknowledged yet, one bit
+/// per epoch starting at `base`. Once a member falls more than 32 epochs behind, its oldest
+/// placements are given up on, which bounds the memory per member.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub struct PendingEdges {
+    base: u32,
+    missing: u32,
+}
+
+impl PendingEdges {
+    pub const fn new() -> Self {
+        Self {
+            base: 0,
+            missing: 0,
+        }
+    }
+
+    /// Marks the placement with `epoch` as sent but not acknowledged.
+    pub fn sent(&mut self, epoch: u32) {
+        if self.missing == 0 {
+            self.base = epoch;
+        }
+        let Some(offset) = epoch.checked_sub(self.base) else {
+            return;
+        };
+        if offset >= u32::BITS {
+            let shift = offset - (u32::BITS - 1);
+            self.missing = self.missing.checked_shr(shift).unwrap_or(0);
+            self.base += shift;
+        }
+        self.missing |= 1 << (epoch - self.base);
+    }
+
+    pub fn acked(&mut self, epoch: u32) {
+        if let Some(offset) = epoch.checked_sub(self.base)
+            && offset < u32::BITS
+        {
+            self.missing &= !(1 << offset);
+        }
+    }
+
+    /// Epochs of the placements that were not acknowledged, oldest first.
+    pub fn missing(&self) -> impl Iterator<Item = u32> + '_ {
+        (0..u32::BITS)
+            .filter(|offset| self.missing & (1 << offset) != 0)
+            .map(|offset| self.base + offset)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.missing == 0
+    }
+}
+
+/// Remembers when the full initial topology was last sent to each child, so a repeated or
+/// replayed `RequestInitTopology` cannot make a parent flood the mesh.
+pub struct TopologyRateLimit {
+    sent: LinearMap<Node, u64, MAX_CHILD_LEAFS>,
+}
+
+impl TopologyRateLimit {
+    pub const fn new() -> Self {
+        Self {
+            sent: LinearMap::new(),
+        }
+    }
+
+    /// Returns true and records the send if the initial topology may be sent to `child` at
+    /// `now_ms`.
+    pub fn allow(&mut self, child: Node, now_ms: u64) -> bool {
+        if let Some(&sent_ms) = self.sent.get(&child)
+            && now_ms.saturating_sub(sent_ms) < INIT_TOPOLOGY_COOLDOWN_MS
+        {
+            return false;
+        }
+        if let Err((child, now_ms)) = self.sent.insert(child, now_ms) {
+            let oldest = self
+                .sent
+                .iter()
+                .min_by_key(|(_, sent_ms)| **sent_ms)
+                .map(|(node, _)| *node);
+            if let Some(oldest) = oldest {
+                self.sent.remove(&oldest);
+            }
+            let _ = self.sent.insert(child, now_ms);
+        }
+        true
+    }
+}
+
+impl Default for TopologyRateLimit {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn n(mac_last_byte: u8) -> Node {
+        Node::new([0, 0, 0, 0, 0, mac_last_byte])
+    }
+
+    #[test]
+    fn chunk_mask_covers_all_chunks() {
+        assert_eq!(chunk_mask(0), 0);
+        assert_eq!(chunk_mask(3), 0b111);
+        assert_eq!(chunk_mask(32).count_ones(), 32);
+    }
+
+    #[test]
+    fn chunk_bitmap_encodes_up_to_its_highest_byte() {
+        let mut out: Vec<u8, MESSAGE_SIZE> = Vec::new();
+        encode_chunk_bitmap(0x0102, &mut out).unwrap();
+        encode_chunk_bitmap(0, &mut out).unwrap();
+        assert_eq!(out, [2, 0x02, 0x01, 0]);
+
+        let mut cursor = Cursor::new(&out);
+        assert_eq!(decode_chunk_bitmap(&mut cursor).unwrap(), 0x0102);
+        assert_eq!(decode_chunk_bitmap(&mut cursor).unwrap(), 0);
+
+        let too_long = [size_of::<ChunkBitmap>() as u8 + 1];
+        assert!(matches!(
+            decode_chunk_bitmap(&mut Cursor::new(&too_long)),
+            Err(CodecError::LengthExceededError(_))
+        ));
+    }
+
+    #[test]
+    fn chunks_hold_every_edge_once_within_the_budget() {
+        let mut edges: Vec<Edge, 40> = Vec::new();
+        let _ = edges.push((None, Some(n(0))));
+        for i in 1..40 {
+            let _ = edges.push((Some(n(i)), Some(n(i - 1))));
+        }
+        let chunks = TopologyChunks::new(&edges);
+        assert_eq!(
+            chunks.total() as usize,
+            edges.len().div_ceil(MAX_TOPOLOGY_CHUNK_EDGES)
+        );
+
+        let mut rebuilt: Vec<Edge, 40> = Vec::new();
+        for (i, chunk) in chunks.clone().enumerate() {
+            assert_eq!((chunk.index as usize, chunk.total), (i, chunks.total()));
+            assert!(encoded_len::<MESSAGE_SIZE>(&chunk).unwrap() <= MAX_ORGANIZATION_BODY_LEN);
+            rebuilt.extend(chunk.edges);
+        }
+        assert_eq!(rebuilt, edges);
+        assert_eq!(TopologyChunks::new(&[]).total(), 0);
+    }
+
+    #[test]
+    fn transfer_reports_missing_chunks() {
+        let mut transfer = TopologyTransfer::new(n(1));
+        transfer.record(0, 4);
+        transfer.record(2, 4);
+
+        assert_eq!(transfer.missing(), 0b1010);
+        assert_eq!(transfer.next_request(), Some(0b1010));
+
+        transfer.record(1, 4);
+        transfer.record(3, 4);
+        assert!(transfer.is_complete());
+        assert_eq!(transfer.next_request(), None);
+    }
+
+    #[test]
+    fn transfer_restarts_on_new_snapshot() {
+        let mut transfer = TopologyTransfer::new(n(1));
+        transfer.record(0, 2);
+        transfer.record(1, 3);
+
+        assert_eq!(transfer.missing(), 0b101);
+    }
+
+    #[test]
+    fn transfer_gives_up_after_retries() {
+        let mut transfer = TopologyTransfer::new(n(1));
+        transfer.record(0, 2);
+        for _ in 0..TOPOLOGY_REQUEST_RETRIES {
+            assert_eq!(transfer.next_request(), Some(0b10));
+        }
+        assert_eq!(transfer.next_request(), None);
+    }
+
+    #[test]
+    fn pending_edges_track_unacknowledged_epochs() {
+        let mut pending = PendingEdges::new();
+        pending.sent(7);
+        pending.sent(8);
+        pending.sent(10);
+        pending.acked(8);
+        pending.acked(3);
+
+        assert_eq!(pending.missing().collect::<Vec<_, 4>>(), [7, 10]);
+        pending.acked(7);
+        pending.acked(10);
+        assert!(pending.is_empty());
+    }
+
+    #[test]
+    fn pending_edges_give_up_on_oldest_epochs() {
+        let mut pending = PendingEdges::new();
+        pending.sent(1);
+        pending.sent(2);
+        pending.sent(u32::BITS + 1);
+
+        assert_eq!(pending.missing().collect::<Vec<_, 4>>(), [2, u32::BITS + 1]);
+        pending.sent(200);
+        assert_eq!(pending.missing().collect::<Vec<_, 4>>(), [200]);
+    }
+
+    #[test]
+    fn rate_limit_blocks_repeated_sends() {
+        let mut limit = TopologyRateLimit::new();
+
+        assert!(limit.allow(n(1), 0));
+        assert!(!limit.allow(n(1), INIT_TOPOLOGY_COOLDOWN_MS - 1));
+        assert!(limit.allow(n(2), INIT_TOPOLOGY_COOLDOWN_MS - 1));
+        assert!(limit.allow(n(1), INIT_TOPOLOGY_COOLDOWN_MS));
+    }
+
+    #[test]
+    fn rate_limit_forgets_oldest_child_when_full() {
+        let mut limit = TopologyRateLimit::new();
+        for i in 0..=MAX_CHILD_LEAFS as u8 {
+            assert!(limit.allow(n(i), i as u64));
+        }
+
+        assert!(limit.allow(n(0), MAX_CHILD_LEAFS as u64 + 1));
+        assert!(!limit.allow(n(2), MAX_CHILD_LEAFS as u64 + 1));
+    }
+}
@@ -0,0 +1,131 @@
+use crate::logic::{consts::MAX_TRAFFIC_ENTRIES, node::Node};
+use core::cmp::Reverse;
+use heapless::{LinearMap, Vec};
+
+/// Application traffic received from one peer on one port.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct PortTraffic {
+    pub peer: Node,
+    /// Port the payloads were sent to, `None` for payloads without one, see `Metadata::port`.
+    pub port: Option<u8>,
+    pub frames: u32,
+    /// Bytes of application content as it travelled over the air, so metadata and encryption
+    /// overhead count as well.
+    pub bytes: u32,
+    /// Local time in milliseconds the first counted frame arrived.
+    pub first_ms: u64,
+    pub last_ms: u64,
+}
+
+impl PortTraffic {
+    /// Average bytes per second since the first counted frame, `None` until a full millisecond
+    /// has passed.
+    pub fn bytes_per_second(&self, now_ms: u64) -> Option<u32> {
+        let elapsed_ms = now_ms.checked_sub(self.first_ms).filter(|ms| *ms > 0)?;
+        Some((self.bytes as u64 * 1000 / elapsed_ms).min(u32::MAX as u64) as u32)
+    }
+}
+
+/// Counts frames and bytes of received application messages per peer and port, so it is easy
+/// to see which application feature uses up the bandwidth. Once full, the entry updated least
+/// recently is forgotten.
+pub struct TrafficTable {
+    entries: LinearMap<(Node, Option<u8>), PortTraffic, MAX_TRAFFIC_ENTRIES>,
+}
+
+impl TrafficTable {
+    pub const fn new() -> Self {
+        Self {
+            entries: LinearMap::new(),
+        }
+    }
+
+    pub fn record(&mut self, peer: Node, port: Option<u8>, bytes: usize, now_ms: u64) {
+        let bytes = bytes.min(u32::MAX as usize) as u32;
+        if let Some(traffic) = self.entries.get_mut(&(peer, port)) {
+            traffic.frames = traffic.frames.saturating_add(1);
+            traffic.bytes = traffic.bytes.saturating_add(bytes);
+            traffic.last_ms = now_ms;
+            return;
+        }
+        if self.entries.is_full() {
+            let stalest = self
+                .entries
+                .iter()
+                .min_by_key(|(_, traffic)| traffic.last_ms)
+                .map(|(key, _)| *key);
+            if let Some(stalest) = stalest {
+                self.entries.remove(&stalest);
+            }
+        }
+        let traffic = PortTraffic {
+            peer,
+            port,
+            frames: 1,
+            bytes,
+            first_ms: now_ms,
+            last_ms: now_ms,
+        };
+        let _ = self.entries.insert((peer, port), traffic);
+    }
+
+    pub fn get(&self, peer: Node, port: Option<u8>) -> Option<PortTraffic> {
+        self.entries.get(&(peer, port)).copied()
+    }
+
+    /// Every entry, the most bytes first.
+    pub fn entries(&self) -> Vec<PortTraffic, MAX_TRAFFIC_ENTRIES> {
+        let mut entries = self.entries.values().copied().collect::<Vec<_, _>>();
+        entries.sort_unstable_by_key(|traffic| Reverse(traffic.bytes));
+        entries
+    }
+}
+
+impl Default for TrafficTable {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn n(mac_last_byte: u8) -> Node {
+        Node::new([0, 0, 0, 0, 0, mac_last_byte])
+    }
+
+    #[test]
+    fn counts_per_peer_and_port() {
+        let mut table = TrafficTable::new();
+        table.record(n(1), Some(7), 40, 1000);
+        table.record(n(1), Some(7), 60, 1500);
+        table.record(n(1), None, 10, 1500);
+        table.record(n(2), Some(7), 200, 1800);
+
+        let game = table.get(n(1), Some(7)).unwrap();
+        assert_eq!((game.frames, game.bytes), (2, 100));
+        assert_eq!(game.bytes_per_second(3000), Some(50));
+        assert_eq!(game.bytes_per_second(1000), None);
+        assert_eq!(table.get(n(1), None).map(|t| t.bytes), Some(10));
+        assert_eq!(table.get(n(2), None), None);
+
+        let entries = table.entries();
+        assert_eq!(entries.len(), 3);
+        assert_eq!(entries[0].peer, n(2));
+    }
+
+    #[test]
+    fn full_table_forgets_stalest_entry() {
+        let mut table = TrafficTable::new();
+        for i in 0..MAX_TRAFFIC_ENTRIES as u8 {
+            table.record(n(i), None, 1, i as u64);
+        }
+        table.record(n(0), None, 1, 100);
+
+        table.record(n(200), Some(1), 1, 200);
+        assert!(table.get(n(0), None).is_some());
+        assert_eq!(table.get(n(1), None), None);
+        assert!(table.get(n(200), Some(1)).is_some());
+    }
+}
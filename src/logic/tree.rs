@@ -1,19 +1,82 @@
 use crate::logic::{
     arena::{Arena, SlotId},
-    error::TreeError,
+    consts::{MAX_CHILD_LEAFS, MAX_LEAFS},
+    error::{CodecError, TreeError},
     node::Node,
+    text,
+    wire::{Cursor, WireCodec, decode_varint, encode_varint},
 };
 use core::fmt::{self, Display, Formatter};
 use core::{option::Option, result::Result};
-use heapless::{Vec, spsc::Queue};
+use heapless::{String, Vec, index_map::FnvIndexMap, spsc::Queue};
 
-pub const MAX_LEAFS: usize = 32;
-const MAX_CHILD_LEAFS: usize = 8;
-const MAX_PREFIX: usize = 32;
+const MAX_PREFIX: usize = MAX_LEAFS;
 
 pub struct Tree {
     leafs: Arena<Leaf, MAX_LEAFS>,
     root_id: Option<SlotId>,
+    uplink: Option<Node>,
+    /// First hop towards every known node, rebuilt once at the end of every change so forwarding
+    /// a frame never walks the tree.
+    routes: FnvIndexMap<Node, Node, MAX_LEAFS>,
+    /// Roots of the subtrees cut off from this node with `detach`. They keep their leafs but no
+    /// routes until a placement brings them back, see `upsert_edge`.
+    detached: Vec<SlotId, MAX_CHILD_LEAFS>,
+    /// Neighbors whose edge to this node is suspect, see `mark_suspect`.
+    suspects: Vec<Node, MAX_CHILD_LEAFS>,
+    /// Newest leader epoch this tree caught up with, see `observe_version`.
+    version: u32,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum LinkDirection {
+    Upstream,
+    Downstream,
+}
+
+/// What `Tree::remove_node` does with the children of the removed node.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum OrphanPolicy {
+    /// Attaches them to the parent of the removed node, so their subtrees stay routable until
+    /// the leader places them again. Children that no longer fit are dropped like `Rediscover`.
+    Promote,
+    /// Drops them together with their subtrees, so they are placed again once they send a
+    /// Discovery.
+    Rediscover,
+}
+
+/// How `Tree::render_into` lays out the tree. Nodes are shown by `text::short_mac`, this node
+/// as `self`.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum TreeLayout {
+    /// A single line, the children of a node in parentheses after it, e.g.
+    /// `self(0002(0004) 0003)`.
+    Compact,
+    /// One node per line, indented by two spaces per level.
+    Lines,
+}
+
+/// Edges that hung below a removed node, each parent before its children. The
+/// direct children of the removed node have no parent.
+pub type Orphans = Vec<(Node, Option<Node>), MAX_LEAFS>;
+
+/// The topology version after `version`, skipping 0 since it stands for no version.
+fn next_epoch(version: u32) -> u32 {
+    match version.wrapping_add(1) {
+        0 => 1,
+        next => next,
+    }
+}
+
+/// FNV-1a of both ends of an edge, the lower address first.
+fn edge_hash(a: Node, b: Node) -> u32 {
+    let (low, high) = if a.mac <= b.mac { (a, b) } else { (b, a) };
+    low.mac
+        .iter()
+        .chain(high.mac.iter())
+        .fold(0x811C_9DC5, |hash, &byte| {
+            (hash ^ u32::from(byte)).wrapping_mul(0x0100_0193)
+        })
 }
 
 impl Tree {
@@ -22,6 +85,11 @@ impl Tree {
         Tree {
             leafs,
             root_id: None,
+            uplink: None,
+            routes: FnvIndexMap::new(),
+            detached: Vec::new(),
+            suspects: Vec::new(),
+            version: 0,
         }
     }
 
@@ -35,102 +103,695 @@ impl Tree {
         Ok(())
     }
 
+    /// Forgets every other node and starts over with only this node, like `new` followed by
+    /// `init` but without building a second tree on the stack.
+    pub fn reset(&mut self) -> Result<(), TreeError> {
+        self.leafs.clear();
+        self.root_id = None;
+        self.uplink = None;
+        self.routes.clear();
+        self.detached.clear();
+        self.suspects.clear();
+        self.version = 0;
+        self.init()
+    }
+
+    /// Attaches `to` below `from`, or below this node if `from` is `None`. A known `to` moves
+    /// with its subtree, even out of a detached subtree. If `from` hangs below `to` here, e.g.
+    /// because this node is part of the subtree that moves, the path between both is reversed
+    /// instead. The epoch `to` was last placed with is kept.
     pub fn upsert_edge(&mut self, from: Option<Node>, to: Node) -> Result<(), TreeError> {
-        let leaf_id =
-            match self.remove_node_helper(to, self.root_id.ok_or(TreeError::UninitializedError)?) {
-                Some(id) => id,
-                None => self
-                    .leafs
+        let result = self.move_node(from, to);
+        self.rebuild_routes();
+        result.map(|_| ())
+    }
+
+    /// Like `upsert_edge`, but for a placement the leader stamped with `epoch`. A node reported
+    /// by two parents may reach members in either order, so a placement older than the one the
+    /// node already has is rejected with `StaleEdgeError` and the newest epoch wins everywhere.
+    pub fn upsert_edge_with_epoch(
+        &mut self,
+        from: Option<Node>,
+        to: Node,
+        epoch: u32,
+    ) -> Result<(), TreeError> {
+        let result = self.place(from, to, epoch);
+        self.rebuild_routes();
+        result
+    }
+
+    /// `upsert_edge_with_epoch` without updating the routes. Epochs are compared as serial
+    /// numbers, so they may wrap around.
+    fn place(&mut self, from: Option<Node>, to: Node, epoch: u32) -> Result<(), TreeError> {
+        if self
+            .epoch(to)
+            .is_some_and(|known| known != 0 && known.wrapping_sub(epoch) as i32 > 0)
+        {
+            return Err(TreeError::StaleEdgeError(to));
+        }
+        let leaf_id = self.move_node(from, to)?;
+        if let Leaf::Foreign { epoch: e, .. } = &mut *self
+            .leafs
+            .get(leaf_id)
+            .map_err(TreeError::LeafNotFoundError)?
+            .borrow_mut()
+        {
+            *e = epoch;
+        }
+        Ok(())
+    }
+
+    /// Topology version of this tree, the newest epoch the leader stamped a placement with that
+    /// arrived here. 0 before the first one.
+    pub fn version(&self) -> u32 {
+        self.version
+    }
+
+    /// Moves to the next topology version and returns it, for the leader to stamp its next
+    /// placement with. Wraps around past 0, which stands for no version.
+    pub fn next_version(&mut self) -> u32 {
+        self.version = next_epoch(self.version);
+        self.version
+    }
+
+    /// Records that a placement stamped with `epoch` arrived. Returns false if placements
+    /// between the current version and `epoch` never arrived, in which case this tree may have
+    /// diverged from the leader's and needs a full resync. Older epochs are resends and leave
+    /// the version alone, compared as serial numbers so the version may wrap around. The first
+    /// epoch a node sees becomes its baseline.
+    pub fn observe_version(&mut self, epoch: u32) -> bool {
+        if self.version != 0 && epoch.wrapping_sub(self.version) as i32 <= 0 {
+            return true;
+        }
+        let contiguous = self.version == 0 || epoch == next_epoch(self.version);
+        self.version = epoch;
+        contiguous
+    }
+
+    /// Epoch the leader last placed `node` with, 0 if it was only placed locally.
+    pub fn epoch(&self, node: Node) -> Option<u32> {
+        let id = self.find_leaf_helper(Some(node), self.root_id?)?;
+        match *self.leafs.get(id).ok()?.borrow() {
+            Leaf::Own { .. } => None,
+            Leaf::Foreign { epoch, .. } => Some(epoch),
+        }
+    }
+
+    fn move_node(&mut self, from: Option<Node>, to: Node) -> Result<SlotId, TreeError> {
+        let root_id = self.root_id.ok_or(TreeError::UninitializedError)?;
+        if let Some(from) = from
+            && self.is_below(to, from)
+        {
+            self.pull_up(Some(to), from)?;
+            return self
+                .find_leaf_helper(Some(to), root_id)
+                .ok_or(TreeError::NodeNotFoundError);
+        }
+        self.relocate(from, to)
+    }
+
+    /// Moves this node below `parent`, which becomes its uplink. The path to `parent` is
+    /// reversed, so the old uplink and everything behind it now hang below `parent`.
+    pub fn attach_below(&mut self, parent: Node) -> Result<(), TreeError> {
+        let result = self.pull_up(None, parent);
+        self.rebuild_routes();
+        result
+    }
+
+    /// Brings `node`, which hangs somewhere below `anchor`, directly below it by reversing the
+    /// path in between. The nodes on the path keep their other children.
+    fn pull_up(&mut self, anchor: Option<Node>, node: Node) -> Result<(), TreeError> {
+        let root_id = self.root_id.ok_or(TreeError::UninitializedError)?;
+        let anchor_id = self
+            .find_leaf_helper(anchor, root_id)
+            .ok_or(TreeError::NodeNotFoundError)?;
+        let mut path = Vec::<Node, MAX_LEAFS>::new();
+        if !self.path_helper(node, anchor_id, &mut path) {
+            return Err(TreeError::NodeNotFoundError);
+        }
+        self.relocate(anchor, node)?;
+        for pair in path.windows(2).rev() {
+            self.relocate(Some(pair[1]), pair[0])?;
+        }
+        if anchor.is_none() && self.uplink == path.first().copied() {
+            self.uplink = Some(node);
+        }
+        Ok(())
+    }
+
+    /// Collects the nodes from just below `current_id` down to `node` into `path`. Returns
+    /// false if `node` does not hang below `current_id`.
+    fn path_helper(&self, node: Node, current_id: SlotId, path: &mut Vec<Node, MAX_LEAFS>) -> bool {
+        let Ok(current) = self.leafs.get(current_id) else {
+            return false;
+        };
+        for &next_id in current.borrow().get_nexts() {
+            let Some(next) = self
+                .leafs
+                .get(next_id)
+                .ok()
+                .and_then(|l| l.borrow().get_node())
+            else {
+                continue;
+            };
+            if path.push(next).is_err() {
+                return false;
+            }
+            if next == node || self.path_helper(node, next_id, path) {
+                return true;
+            }
+            path.pop();
+        }
+        false
+    }
+
+    /// Returns true if `node` hangs somewhere below `ancestor`.
+    pub fn is_below(&self, ancestor: Node, node: Node) -> bool {
+        let mut path = Vec::new();
+        self.root_id
+            .and_then(|root_id| self.find_leaf_helper(Some(ancestor), root_id))
+            .is_some_and(|id| self.path_helper(node, id, &mut path))
+    }
+
+    /// The node `node` hangs below, `None` for this node.
+    pub fn parent(&self, node: Node) -> Result<Option<Node>, TreeError> {
+        self.parent_helper(node, self.root_id.ok_or(TreeError::UninitializedError)?)
+            .ok_or(TreeError::NodeNotFoundError)
+    }
+
+    /// Nodes attached directly below `node`, or below this node if `node` is `None`, which
+    /// includes the uplink.
+    pub fn children(&self, node: Option<Node>) -> Result<Vec<Node, MAX_CHILD_LEAFS>, TreeError> {
+        let id = self
+            .find_leaf_helper(node, self.root_id.ok_or(TreeError::UninitializedError)?)
+            .ok_or(TreeError::NodeNotFoundError)?;
+        let leaf = self
+            .leafs
+            .get(id)
+            .map_err(TreeError::LeafNotFoundError)?
+            .borrow();
+        Ok(leaf
+            .get_nexts()
+            .iter()
+            .filter_map(|next_id| self.leafs.get(*next_id).ok())
+            .filter_map(|next| next.borrow().get_node())
+            .collect())
+    }
+
+    fn relocate(&mut self, from: Option<Node>, to: Node) -> Result<SlotId, TreeError> {
+        let root_id = self.root_id.ok_or(TreeError::UninitializedError)?;
+        let found = self
+            .remove_node_helper(to, root_id)
+            .or_else(|| self.take_detached(to));
+        let (leaf_id, size, allocated) = match found {
+            Some((leaf_id, size)) => (leaf_id, size, false),
+            None => (
+                self.leafs
                     .alloc(Leaf::new_foreign(to))
                     .ok_or(TreeError::LeafAllocationError)?,
+                1,
+                true,
+            ),
+        };
+        if self
+            .insert_node_helper(from, root_id, leaf_id, size)
+            .is_none()
+        {
+            // A parent that is unknown or has no room left must not cost a leaf.
+            if allocated {
+                let _ = self.leafs.remove(leaf_id);
+            }
+            return Err(TreeError::NodeNotFoundError);
+        }
+        Ok(leaf_id)
+    }
+
+    /// Removes `node` and handles its children according to `policy`. Returns the subtree that
+    /// hung below `node`, so the leader can place the orphans again. Those still in the tree
+    /// were promoted, the others have to be reintegrated.
+    pub fn remove_node(&mut self, node: Node, policy: OrphanPolicy) -> Result<Orphans, TreeError> {
+        let result = self.unlink(node, policy);
+        self.rebuild_routes();
+        result
+    }
+
+    /// `remove_node` without updating the routes.
+    fn unlink(&mut self, node: Node, policy: OrphanPolicy) -> Result<Orphans, TreeError> {
+        let root_id = self.root_id.ok_or(TreeError::UninitializedError)?;
+        let parent = self
+            .parent_helper(node, root_id)
+            .ok_or(TreeError::NodeNotFoundError)?;
+        let (leaf_id, _) = self
+            .remove_node_helper(node, root_id)
+            .ok_or(TreeError::NodeNotFoundError)?;
+        let leaf = self
+            .leafs
+            .remove(leaf_id)
+            .map_err(TreeError::LeafNotFoundError)?
+            .into_inner();
+        let mut orphans = Orphans::new();
+        for &child_id in leaf.get_nexts() {
+            self.collect_subtree(child_id, None, &mut orphans);
+        }
+        for &child_id in leaf.get_nexts() {
+            if policy == OrphanPolicy::Rediscover {
+                self.free_subtree(child_id);
+                continue;
+            }
+            let size = self
+                .leafs
+                .get(child_id)
+                .map_err(TreeError::LeafNotFoundError)?
+                .borrow()
+                .descendants()
+                + 1;
+            if self
+                .insert_node_helper(parent, root_id, child_id, size)
+                .is_none()
+            {
+                self.free_subtree(child_id);
+            }
+        }
+        if self.uplink == Some(node) {
+            self.uplink = None;
+        }
+        Ok(orphans)
+    }
+
+    /// Cuts `node` and its subtree off this node but keeps them, e.g. the rest of the mesh
+    /// behind a lost uplink while this node searches for it again. Detached nodes have no route
+    /// and are not counted, but a placement of any of them brings it back together with what
+    /// still hangs below it. Fails with `LeafAllocationError` once `MAX_CHILD_LEAFS` subtrees
+    /// are detached.
+    pub fn detach(&mut self, node: Node) -> Result<(), TreeError> {
+        let root_id = self.root_id.ok_or(TreeError::UninitializedError)?;
+        if self.detached.is_full() {
+            return Err(TreeError::LeafAllocationError);
+        }
+        let (leaf_id, _) = self
+            .remove_node_helper(node, root_id)
+            .ok_or(TreeError::NodeNotFoundError)?;
+        let _ = self.detached.push(leaf_id);
+        if self.uplink == Some(node) {
+            self.uplink = None;
+        }
+        self.rebuild_routes();
+        Ok(())
+    }
+
+    /// Roots of the subtrees cut off with `detach` that no placement brought back yet.
+    pub fn detached(&self) -> Vec<Node, MAX_CHILD_LEAFS> {
+        self.detached
+            .iter()
+            .filter_map(|id| self.leafs.get(*id).ok())
+            .filter_map(|leaf| leaf.borrow().get_node())
+            .collect()
+    }
+
+    /// Forgets every detached subtree and frees its leafs.
+    pub fn drop_detached(&mut self) {
+        while let Some(id) = self.detached.pop() {
+            self.free_subtree(id);
+        }
+    }
+
+    /// Takes the leaf of `address` with its subtree out of the detached subtrees, like
+    /// `remove_node_helper`.
+    fn take_detached(&mut self, address: Node) -> Option<(SlotId, SlotId)> {
+        for (pos, &root_id) in self.detached.iter().enumerate() {
+            let Ok(root) = self.leafs.get(root_id) else {
+                continue;
             };
-        self.insert_node_helper(
-            from,
-            self.root_id.ok_or(TreeError::UninitializedError)?,
-            leaf_id,
-        )
-        .ok_or(TreeError::NodeNotFoundError)
+            let (node, size) = {
+                let root = root.borrow();
+                (root.get_node(), root.descendants() + 1)
+            };
+            if node == Some(address) {
+                self.detached.remove(pos);
+                return Some((root_id, size));
+            }
+            if let Some(found) = self.remove_node_helper(address, root_id) {
+                return Some(found);
+            }
+        }
+        None
     }
 
-    fn remove_node_helper(&self, address: Node, current_id: SlotId) -> Option<SlotId> {
-        let mut current = self.leafs.get(current_id).ok()?.borrow_mut();
-        if let Some(pos) = current
+    /// Elects the node that takes over from the silent `leader`: its neighbor with the lowest
+    /// address, `None` if that is this node. `own` is the address of this node, which the tree
+    /// does not know itself. Members sharing the leader's tree all elect the same node.
+    pub fn successor(&self, leader: Node, own: Node) -> Result<Option<Node>, TreeError> {
+        let mut lowest = self.parent(leader)?.unwrap_or(own);
+        for child in self.children(Some(leader))? {
+            if child.mac < lowest.mac {
+                lowest = child;
+            }
+        }
+        Ok((lowest != own).then_some(lowest))
+    }
+
+    /// Removes the silent `leader` and attaches its other neighbors below `successor`, or below
+    /// this node if it is `None`, as if `successor` had been the root. The rest of the tree stays
+    /// as it is. If the leader was the uplink, `successor` becomes the new one.
+    pub fn hand_over(&mut self, leader: Node, successor: Option<Node>) -> Result<(), TreeError> {
+        let parent = self.parent(leader)?;
+        let children = self.children(Some(leader))?;
+        if successor != parent && !successor.is_some_and(|s| children.contains(&s)) {
+            return Err(TreeError::NodeNotFoundError);
+        }
+        let was_uplink = self.uplink == Some(leader);
+        let result = self.unlink(leader, OrphanPolicy::Promote).and_then(|_| {
+            let Some(successor) = successor else {
+                return Ok(());
+            };
+            if parent != Some(successor) {
+                for &child in children.iter().filter(|&&child| child != successor) {
+                    self.move_node(Some(successor), child)?;
+                }
+            }
+            if was_uplink {
+                self.uplink = Some(successor);
+            }
+            Ok(())
+        });
+        self.rebuild_routes();
+        result
+    }
+
+    /// Makes the neighbor on the path to the new `leader` the uplink, or none if this node leads
+    /// now, after the lead was handed over in a running mesh. Only the nodes on the path between
+    /// the old and the new leader get another uplink, the tree itself stays as it is.
+    pub fn turn_towards(&mut self, leader: Option<Node>) -> Result<(), TreeError> {
+        self.uplink = match leader {
+            Some(leader) => Some(self.next_hop(leader)?),
+            None => None,
+        };
+        Ok(())
+    }
+
+    fn collect_subtree(&self, leaf_id: SlotId, parent: Option<Node>, out: &mut Orphans) {
+        let Ok(leaf) = self.leafs.get(leaf_id) else {
+            return;
+        };
+        let leaf = leaf.borrow();
+        let Some(node) = leaf.get_node() else {
+            return;
+        };
+        let _ = out.push((node, parent));
+        for &child_id in leaf.get_nexts() {
+            self.collect_subtree(child_id, Some(node), out);
+        }
+    }
+
+    /// Returns the node whose leaf holds `address` directly, `Some(None)` for this node.
+    fn parent_helper(&self, address: Node, current_id: SlotId) -> Option<Option<Node>> {
+        let current = self.leafs.get(current_id).ok()?.borrow();
+        for &next_id in current.get_nexts() {
+            if self.leafs.get(next_id).ok()?.borrow().get_node() == Some(address) {
+                return Some(current.get_node());
+            }
+        }
+        current
             .get_nexts()
             .iter()
-            .enumerate()
-            .find_map(|(idx, next_id)| {
-                let next = self.leafs.get(*next_id).ok()?.borrow();
-                match *next {
-                    Leaf::Own { nexts: _ } => None,
-                    Leaf::Foreign { nexts: _, node } if node == address => Some(idx),
-                    Leaf::Foreign { nexts: _, node: _ } => None,
-                }
-            })
+            .find_map(|next_id| self.parent_helper(address, *next_id))
+    }
+
+    fn free_subtree(&mut self, leaf_id: SlotId) {
+        if let Ok(leaf) = self.leafs.remove(leaf_id) {
+            for &child_id in leaf.into_inner().get_nexts() {
+                self.free_subtree(child_id);
+            }
+        }
+    }
+
+    /// Detaches the leaf of `address` together with its subtree. Returns its id and the number
+    /// of leafs that were detached, which is subtracted from every ancestor on the way back up.
+    fn remove_node_helper(&self, address: Node, current_id: SlotId) -> Option<(SlotId, SlotId)> {
+        let mut current = self.leafs.get(current_id).ok()?.borrow_mut();
+        if let Some((pos, size)) =
+            current
+                .get_nexts()
+                .iter()
+                .enumerate()
+                .find_map(|(idx, next_id)| {
+                    let next = self.leafs.get(*next_id).ok()?.borrow();
+                    match *next {
+                        Leaf::Own { .. } => None,
+                        Leaf::Foreign { node, .. } if node == address => {
+                            Some((idx, next.descendants() + 1))
+                        }
+                        Leaf::Foreign { .. } => None,
+                    }
+                })
         {
-            return Some(current.get_nexts_mut().remove(pos));
+            *current.descendants_mut() -= size;
+            return Some((current.get_nexts_mut().remove(pos), size));
         }
-        if let Some(res) = current
+        if let Some((id, size)) = current
             .get_nexts()
             .iter()
             .find_map(|next_id| self.remove_node_helper(address, *next_id))
         {
-            return Some(res);
+            *current.descendants_mut() -= size;
+            return Some((id, size));
         }
         None
     }
 
+    /// Attaches the subtree of `leaf_id`, which holds `size` leafs, below `parent_address` and
+    /// adds them to every ancestor.
     fn insert_node_helper(
         &self,
         parent_address: Option<Node>,
         current_id: SlotId,
         leaf_id: SlotId,
+        size: SlotId,
     ) -> Option<()> {
         let mut current = self.leafs.get(current_id).ok()?.borrow_mut();
         if current.get_node() == parent_address {
             current.get_nexts_mut().push(leaf_id).ok()?;
+            *current.descendants_mut() += size;
             return Some(());
         }
         if let Some(res) = current
             .get_nexts()
             .iter()
-            .find_map(|next_id| self.insert_node_helper(parent_address, *next_id, leaf_id))
+            .find_map(|next_id| self.insert_node_helper(parent_address, *next_id, leaf_id, size))
         {
+            *current.descendants_mut() += size;
             return Some(res);
         }
         None
     }
 
-    pub fn next_hop(&self, destination: Node) -> Result<Node, TreeError> {
-        self.next_hop_helper(
-            destination,
-            self.root_id.ok_or(TreeError::UninitializedError)?,
-        )
+    fn find_leaf_helper(&self, node: Option<Node>, current_id: SlotId) -> Option<SlotId> {
+        let current = self.leafs.get(current_id).ok()?.borrow();
+        if current.get_node() == node {
+            return Some(current_id);
+        }
+        current
+            .get_nexts()
+            .iter()
+            .find_map(|next_id| self.find_leaf_helper(node, *next_id))
     }
 
-    fn next_hop_helper(&self, destination: Node, current_id: SlotId) -> Result<Node, TreeError> {
-        let current = self
+    /// Number of nodes below `node` in the tree, or below this node if `node` is `None`. Shows
+    /// how much traffic a relay carries and lets the leader place joining nodes by subtree load.
+    pub fn descendants(&self, node: Option<Node>) -> Result<usize, TreeError> {
+        let id = self
+            .find_leaf_helper(node, self.root_id.ok_or(TreeError::UninitializedError)?)
+            .ok_or(TreeError::NodeNotFoundError)?;
+        let leaf = self
             .leafs
-            .get(current_id)
-            .map_err(|e| TreeError::LeafNotFoundError(e))?
+            .get(id)
+            .map_err(TreeError::LeafNotFoundError)?
             .borrow();
-        match *current {
-            Leaf::Own { nexts: _ } => (),
-            Leaf::Foreign { nexts: _, node } if node == destination => return Ok(node),
-            Leaf::Foreign { nexts: _, node: _ } => (),
+        Ok(leaf.descendants() as usize)
+    }
+
+    /// Hops between this node and `node`, 0 for this node itself if `node` is `None`.
+    pub fn depth(&self, node: Option<Node>) -> Result<usize, TreeError> {
+        self.depth_helper(node, self.root_id.ok_or(TreeError::UninitializedError)?)
+            .ok_or(TreeError::NodeNotFoundError)
+    }
+
+    fn depth_helper(&self, node: Option<Node>, current_id: SlotId) -> Option<usize> {
+        let current = self.leafs.get(current_id).ok()?.borrow();
+        if current.get_node() == node {
+            return Some(0);
         }
-        if let Some(&ret_id) = current
+        current
             .get_nexts()
             .iter()
-            .find(|&&next_id| self.next_hop_helper(destination, next_id).is_ok())
-        {
-            let ret = self
+            .find_map(|next_id| self.depth_helper(node, *next_id))
+            .map(|depth| depth + 1)
+    }
+
+    /// The node right behind `via` on the path to `destination`, i.e. the parent or a child of
+    /// `via`. Frames can try it as relay when the edge to `via` is dead. `None` if `destination`
+    /// is `via` itself or is not routed through it.
+    pub fn hop_beyond(&self, via: Node, destination: Node) -> Option<Node> {
+        let id = self.find_leaf_helper(Some(via), self.root_id?)?;
+        let mut path = Vec::new();
+        self.path_helper(destination, id, &mut path)
+            .then(|| path.first().copied())
+            .flatten()
+    }
+
+    /// Flags the edge between this node and its neighbor `node` as suspect, e.g. after it did
+    /// not acknowledge a frame. Only neighbors in the tree are flagged, until `clear_suspect`
+    /// or until they are no neighbor anymore.
+    pub fn mark_suspect(&mut self, node: Node) {
+        let is_neighbor = self
+            .root_id
+            .and_then(|root_id| self.parent_helper(node, root_id))
+            .is_some_and(|parent| parent.is_none());
+        if is_neighbor && !self.suspects.contains(&node) {
+            let _ = self.suspects.push(node);
+        }
+    }
+
+    /// Clears the flag of `node`, e.g. once a frame from it arrived.
+    pub fn clear_suspect(&mut self, node: Node) {
+        self.suspects.retain(|suspect| *suspect != node);
+    }
+
+    pub fn is_suspect(&self, node: Node) -> bool {
+        self.suspects.contains(&node)
+    }
+
+    pub fn next_hop(&self, destination: Node) -> Result<Node, TreeError> {
+        if self.root_id.is_none() {
+            return Err(TreeError::UninitializedError);
+        }
+        self.routes
+            .get(&destination)
+            .copied()
+            .ok_or(TreeError::NodeNotFoundError)
+    }
+
+    /// Fills `routes` from the leafs, walking each subtree below this node once with an explicit
+    /// stack instead of recursion.
+    fn rebuild_routes(&mut self) {
+        self.routes.clear();
+        let Some(root) = self.root_id.and_then(|id| self.leafs.get(id).ok()) else {
+            return;
+        };
+        let mut pending: Vec<(SlotId, Node), MAX_LEAFS> = Vec::new();
+        for &next_id in root.borrow().get_nexts() {
+            if let Some(node) = self
+                .leafs
+                .get(next_id)
+                .ok()
+                .and_then(|l| l.borrow().get_node())
+            {
+                let _ = pending.push((next_id, node));
+            }
+        }
+        while let Some((id, first_hop)) = pending.pop() {
+            let Ok(leaf) = self.leafs.get(id) else {
+                continue;
+            };
+            let leaf = leaf.borrow();
+            if let Some(node) = leaf.get_node() {
+                let _ = self.routes.insert(node, first_hop);
+            }
+            for &next_id in leaf.get_nexts() {
+                let _ = pending.push((next_id, first_hop));
+            }
+        }
+        let routes = &self.routes;
+        self.suspects
+            .retain(|suspect| routes.get(suspect) == Some(suspect));
+    }
+
+    pub fn set_uplink(&mut self, node: Node) {
+        self.uplink = Some(node);
+    }
+
+    pub fn uplink(&self) -> Option<Node> {
+        self.uplink
+    }
+
+    pub fn neighbors(&self) -> Result<Vec<(Node, LinkDirection), MAX_CHILD_LEAFS>, TreeError> {
+        let root = self
+            .leafs
+            .get(self.root_id.ok_or(TreeError::UninitializedError)?)
+            .map_err(TreeError::LeafNotFoundError)?
+            .borrow();
+        let mut neighbors = Vec::new();
+        for next_id in root.get_nexts() {
+            let next = self
                 .leafs
-                .get(ret_id)
-                .map_err(|e| TreeError::LeafNotFoundError(e))?
+                .get(*next_id)
+                .map_err(TreeError::LeafNotFoundError)?
                 .borrow();
-            return Ok(ret.get_node().ok_or(TreeError::RootIsDestinationError)?);
+            if let Some(node) = next.get_node() {
+                let direction = if self.uplink == Some(node) {
+                    LinkDirection::Upstream
+                } else {
+                    LinkDirection::Downstream
+                };
+                let _ = neighbors.push((node, direction));
+            }
         }
-        Err(TreeError::NodeNotFoundError)
+        Ok(neighbors)
+    }
+
+    /// Returns true if `node` is attached directly below this node.
+    pub fn is_child(&self, node: Node) -> bool {
+        self.uplink != Some(node)
+            && self
+                .neighbors()
+                .is_ok_and(|neighbors| neighbors.iter().any(|(n, _)| *n == node))
+    }
+
+    /// Returns true if `node` is known and hangs somewhere below this node rather than behind
+    /// the uplink.
+    pub fn is_downstream(&self, node: Node) -> bool {
+        self.next_hop(node)
+            .is_ok_and(|hop| self.uplink != Some(hop))
+    }
+
+    /// Returns true if `node` is somewhere in the tree. This node itself is not, as it does not
+    /// know its own address.
+    pub fn contains(&self, node: Node) -> bool {
+        self.routes.contains_key(&node)
+    }
+
+    /// Number of nodes in the tree, including this node.
+    pub fn node_count(&self) -> usize {
+        self.descendants(None)
+            .map_or(0, |descendants| descendants + 1)
+    }
+
+    /// Most nodes the tree can hold, this node included.
+    pub fn capacity(&self) -> usize {
+        self.leafs.capacity()
+    }
+
+    /// Returns true if no further node fits into the tree.
+    pub fn is_full(&self) -> bool {
+        self.leafs.is_full()
+    }
+
+    /// Returns true if `parent`, or this node if `parent` is `None`, is known and can take one
+    /// more child.
+    pub fn has_room_below(&self, parent: Option<Node>) -> bool {
+        self.root_id
+            .and_then(|root_id| self.find_leaf_helper(parent, root_id))
+            .and_then(|id| self.leafs.get(id).ok())
+            .is_some_and(|leaf| !leaf.borrow().get_nexts().is_full())
+    }
+
+    /// Hash of the edge set, for followers to notice a tree that silently diverged from the
+    /// leader's. Edges are hashed without direction and summed, so two trees of the same mesh
+    /// rooted at different nodes hash equal. `own` stands in for this node.
+    pub fn topology_hash(&self, own: Node) -> u32 {
+        self.into_iter()
+            .map(|(node, parent)| edge_hash(node, parent.unwrap_or(own)))
+            .fold(0, u32::wrapping_add)
     }
 
     pub fn height(&self) -> usize {
@@ -154,6 +815,58 @@ impl Tree {
             + 1
     }
 
+    /// Appends the tree to `out` without `core::fmt`, for the display and the serial console.
+    /// Fails with `RenderOverflowError` at the first node that does not fit, `out` keeps the
+    /// nodes before it.
+    pub fn render_into<const N: usize>(
+        &self,
+        out: &mut String<N>,
+        layout: TreeLayout,
+    ) -> Result<(), TreeError> {
+        let root_id = self.root_id.ok_or(TreeError::UninitializedError)?;
+        self.render_leaf(out, root_id, 0, layout)
+    }
+
+    fn render_leaf<const N: usize>(
+        &self,
+        out: &mut String<N>,
+        id: SlotId,
+        depth: usize,
+        layout: TreeLayout,
+    ) -> Result<(), TreeError> {
+        fn overflow<E>(_: E) -> TreeError {
+            TreeError::RenderOverflowError
+        }
+        let current = self
+            .leafs
+            .get(id)
+            .map_err(TreeError::LeafNotFoundError)?
+            .borrow();
+        if layout == TreeLayout::Lines && depth > 0 {
+            out.push('\n').map_err(overflow)?;
+            for _ in 0..depth {
+                out.push_str("  ").map_err(overflow)?;
+            }
+        }
+        match *current {
+            Leaf::Own { .. } => out.push_str("self"),
+            Leaf::Foreign { node, .. } => out.push_str(&text::short_mac(node)),
+        }
+        .map_err(overflow)?;
+        let nexts = current.get_nexts();
+        for (idx, next_id) in nexts.iter().enumerate() {
+            if layout == TreeLayout::Compact {
+                out.push(if idx == 0 { '(' } else { ' ' })
+                    .map_err(overflow)?;
+            }
+            self.render_leaf(out, *next_id, depth + 1, layout)?;
+        }
+        if layout == TreeLayout::Compact && !nexts.is_empty() {
+            out.push(')').map_err(overflow)?;
+        }
+        Ok(())
+    }
+
     fn fmt_leaf(
         &self,
         f: &mut Formatter<'_>,
@@ -171,8 +884,8 @@ impl Tree {
         }
         let current = self.leafs.get(id).map_err(|_| fmt::Error)?.borrow();
         match *current {
-            Leaf::Own { nexts: _ } => write!(f, "self\n")?,
-            Leaf::Foreign { nexts: _, node } => write!(f, "{}\n", node)?,
+            Leaf::Own { .. } => write!(f, "self\n")?,
+            Leaf::Foreign { node, .. } => write!(f, "{}\n", node)?,
         }
 
         for (idx, next_id) in current.get_nexts().iter().enumerate() {
@@ -264,45 +977,125 @@ impl<'a> IntoIterator for &'a Tree {
     }
 }
 
+/// The nodes in the order of `TreeIter`, so every parent comes before its children, followed by
+/// the uplink. A parent is referred to by its position in the list instead of its address,
+/// which keeps a small tree within one frame.
+///
+/// Layout: count (varint) | (mac | parent (varint, 0 for this node) | epoch (varint))* |
+/// uplink (varint, 0 for none)
+impl<const N: usize> WireCodec<N> for Tree {
+    fn encode(&self, out: &mut Vec<u8, N>) -> Result<(), CodecError> {
+        let mut listed = Vec::<Node, MAX_LEAFS>::new();
+        let position = |listed: &Vec<Node, MAX_LEAFS>, node: Option<Node>| {
+            node.and_then(|node| listed.iter().position(|n| *n == node))
+                .map_or(0, |i| i as u32 + 1)
+        };
+        encode_varint(self.node_count().saturating_sub(1) as u32, out)?;
+        for (node, parent) in self {
+            node.mac.encode(out)?;
+            encode_varint(position(&listed, parent), out)?;
+            encode_varint(self.epoch(node).unwrap_or(0), out)?;
+            let _ = listed.push(node);
+        }
+        encode_varint(position(&listed, self.uplink), out)
+    }
+
+    fn decode(cursor: &mut Cursor<'_>) -> Result<Self, CodecError> {
+        let count = decode_varint(cursor)?;
+        if count as usize >= MAX_LEAFS {
+            return Err(CodecError::LengthExceededError(count));
+        }
+        let mut tree = Tree::new();
+        tree.init().map_err(|_| CodecError::InvalidTreeError)?;
+        let mut listed = Vec::<Node, MAX_LEAFS>::new();
+        let lookup = |listed: &Vec<Node, MAX_LEAFS>, position: u32| match position {
+            0 => Ok(None),
+            i => listed
+                .get(i as usize - 1)
+                .copied()
+                .map(Some)
+                .ok_or(CodecError::InvalidTreeError),
+        };
+        for _ in 0..count {
+            let node = Node::new(<[u8; 6] as WireCodec<N>>::decode(cursor)?);
+            let parent = lookup(&listed, decode_varint(cursor)?)?;
+            let epoch = decode_varint(cursor)?;
+            if listed.contains(&node) {
+                return Err(CodecError::InvalidTreeError);
+            }
+            tree.place(parent, node, epoch)
+                .map_err(|_| CodecError::InvalidTreeError)?;
+            let _ = listed.push(node);
+        }
+        tree.uplink = lookup(&listed, decode_varint(cursor)?)?;
+        tree.rebuild_routes();
+        Ok(tree)
+    }
+}
+
+/// `descendants` counts every leaf below this one, kept up to date by `upsert_edge`.
 enum Leaf {
     Own {
         nexts: Vec<SlotId, MAX_CHILD_LEAFS>,
+        descendants: SlotId,
     },
     Foreign {
         nexts: Vec<SlotId, MAX_CHILD_LEAFS>,
+        descendants: SlotId,
         node: Node,
+        /// Leader epoch of the placement, see `Tree::upsert_edge_with_epoch`.
+        epoch: u32,
     },
 }
 
 impl Leaf {
     fn new_own() -> Self {
-        Self::Own { nexts: Vec::new() }
+        Self::Own {
+            nexts: Vec::new(),
+            descendants: 0,
+        }
     }
     fn new_foreign(node: Node) -> Self {
         Self::Foreign {
             nexts: Vec::new(),
+            descendants: 0,
             node,
+            epoch: 0,
         }
     }
 
     fn get_nexts(&self) -> &Vec<SlotId, MAX_CHILD_LEAFS> {
         match self {
-            Self::Own { nexts } => nexts,
-            Self::Foreign { nexts, node: _ } => nexts,
+            Self::Own { nexts, .. } => nexts,
+            Self::Foreign { nexts, .. } => nexts,
         }
     }
 
     fn get_nexts_mut(&mut self) -> &mut Vec<SlotId, MAX_CHILD_LEAFS> {
         match self {
-            Self::Own { nexts } => nexts,
-            Self::Foreign { nexts, node: _ } => nexts,
+            Self::Own { nexts, .. } => nexts,
+            Self::Foreign { nexts, .. } => nexts,
+        }
+    }
+
+    fn descendants(&self) -> SlotId {
+        match self {
+            Self::Own { descendants, .. } => *descendants,
+            Self::Foreign { descendants, .. } => *descendants,
+        }
+    }
+
+    fn descendants_mut(&mut self) -> &mut SlotId {
+        match self {
+            Self::Own { descendants, .. } => descendants,
+            Self::Foreign { descendants, .. } => descendants,
         }
     }
 
     fn get_node(&self) -> Option<Node> {
         match self {
-            Self::Own { nexts: _ } => None,
-            Self::Foreign { nexts: _, node } => Some(node.clone()),
+            Self::Own { .. } => None,
+            Self::Foreign { node, .. } => Some(node.clone()),
         }
     }
 }
@@ -310,11 +1103,14 @@ impl Leaf {
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::unwrap_print;
+    use crate::{
+        logic::{consts::MESSAGE_SIZE, message::MessageData},
+        unwrap_print,
+    };
 
-    fn n(mac_last_byte: u8) -> Node {
+    fn n(id: u16) -> Node {
         let mut mac = [0u8; 6];
-        mac[5] = mac_last_byte;
+        mac[4..].copy_from_slice(&id.to_be_bytes());
         Node::new(mac)
     }
 
@@ -326,6 +1122,22 @@ mod tests {
         assert_eq!(tree.height(), 1);
     }
 
+    #[test]
+    fn reset_forgets_every_other_node() {
+        let mut tree = Tree::new();
+        unwrap_print!(tree.init());
+        unwrap_print!(tree.upsert_edge(None, n(1)));
+        unwrap_print!(tree.upsert_edge(Some(n(1)), n(2)));
+
+        unwrap_print!(tree.reset());
+
+        assert_eq!(tree.height(), 1);
+        assert_eq!(tree.node_count(), 1);
+        assert!(tree.next_hop(n(2)).is_err());
+        unwrap_print!(tree.upsert_edge(None, n(2)));
+        assert_eq!(unwrap_print!(tree.next_hop(n(2))), n(2));
+    }
+
     #[test]
     fn insert_single_edge() {
         let mut tree = Tree::new();
@@ -415,4 +1227,517 @@ mod tests {
 
         assert_eq!(tree.height(), 4);
     }
+
+    #[test]
+    fn neighbors_mark_uplink_as_upstream() {
+        let mut tree = Tree::new();
+        unwrap_print!(tree.init());
+
+        unwrap_print!(tree.upsert_edge(None, n(1)));
+        unwrap_print!(tree.upsert_edge(Some(n(1)), n(2)));
+        unwrap_print!(tree.upsert_edge(None, n(3)));
+        tree.set_uplink(n(1));
+
+        let neighbors = unwrap_print!(tree.neighbors());
+        assert_eq!(neighbors.len(), 2);
+        assert!(neighbors.contains(&(n(1), LinkDirection::Upstream)));
+        assert!(neighbors.contains(&(n(3), LinkDirection::Downstream)));
+    }
+
+    #[test]
+    fn is_child_excludes_uplink_and_grandchildren() {
+        let mut tree = Tree::new();
+        unwrap_print!(tree.init());
+
+        unwrap_print!(tree.upsert_edge(None, n(1)));
+        unwrap_print!(tree.upsert_edge(None, n(2)));
+        unwrap_print!(tree.upsert_edge(Some(n(2)), n(3)));
+        tree.set_uplink(n(1));
+
+        assert!(!tree.is_child(n(1)));
+        assert!(tree.is_child(n(2)));
+        assert!(!tree.is_child(n(3)));
+    }
+
+    #[test]
+    fn is_downstream_excludes_nodes_behind_uplink() {
+        let mut tree = Tree::new();
+        unwrap_print!(tree.init());
+
+        unwrap_print!(tree.upsert_edge(None, n(1)));
+        unwrap_print!(tree.upsert_edge(Some(n(1)), n(2)));
+        unwrap_print!(tree.upsert_edge(None, n(3)));
+        unwrap_print!(tree.upsert_edge(Some(n(3)), n(4)));
+        tree.set_uplink(n(1));
+
+        assert!(!tree.is_downstream(n(1)));
+        assert!(!tree.is_downstream(n(2)));
+        assert!(tree.is_downstream(n(3)));
+        assert!(tree.is_downstream(n(4)));
+        assert!(!tree.is_downstream(n(9)));
+    }
+
+    #[test]
+    fn newest_epoch_wins_for_node_reported_by_two_parents() {
+        let mut tree = Tree::new();
+        unwrap_print!(tree.init());
+        unwrap_print!(tree.upsert_edge(None, n(1)));
+        unwrap_print!(tree.upsert_edge(None, n(2)));
+
+        // The leader first placed n(3) below n(1), then moved it below n(2), but the older
+        // update arrives last.
+        unwrap_print!(tree.upsert_edge_with_epoch(Some(n(2)), n(3), 2));
+        let err = tree
+            .upsert_edge_with_epoch(Some(n(1)), n(3), 1)
+            .unwrap_err();
+        assert!(matches!(err, TreeError::StaleEdgeError(node) if node == n(3)));
+
+        assert_eq!(unwrap_print!(tree.next_hop(n(3))), n(2));
+        assert_eq!(tree.epoch(n(3)), Some(2));
+        assert_eq!(unwrap_print!(tree.descendants(Some(n(1)))), 0);
+        assert_eq!(unwrap_print!(tree.descendants(None)), 3);
+
+        unwrap_print!(tree.upsert_edge(Some(n(1)), n(3)));
+        assert_eq!(tree.epoch(n(3)), Some(2));
+        unwrap_print!(tree.upsert_edge_with_epoch(Some(n(2)), n(3), 3));
+        assert_eq!(unwrap_print!(tree.next_hop(n(3))), n(2));
+
+        // Epochs wrap around, the one right after the wrap is still the newer one.
+        unwrap_print!(tree.upsert_edge_with_epoch(Some(n(1)), n(4), u32::MAX));
+        unwrap_print!(tree.upsert_edge_with_epoch(Some(n(2)), n(4), 1));
+        assert_eq!(unwrap_print!(tree.next_hop(n(4))), n(2));
+        assert!(
+            tree.upsert_edge_with_epoch(Some(n(1)), n(4), u32::MAX)
+                .is_err()
+        );
+    }
+
+    #[test]
+    fn rejected_insert_keeps_capacity() {
+        let mut tree = Tree::new();
+        unwrap_print!(tree.init());
+        for i in 1..=MAX_CHILD_LEAFS as u16 {
+            unwrap_print!(tree.upsert_edge(None, n(i)));
+        }
+        assert!(!tree.has_room_below(None));
+        assert!(tree.has_room_below(Some(n(1))));
+        assert!(!tree.has_room_below(Some(n(200))));
+
+        for i in 0..MAX_LEAFS as u16 {
+            assert!(tree.upsert_edge(None, n(100 + i)).is_err());
+        }
+
+        assert_eq!(tree.node_count(), MAX_CHILD_LEAFS + 1);
+        unwrap_print!(tree.upsert_edge(Some(n(1)), n(100)));
+        assert_eq!(tree.node_count(), MAX_CHILD_LEAFS + 2);
+    }
+
+    #[test]
+    fn removed_node_hands_children_to_its_parent() {
+        let mut tree = Tree::new();
+        unwrap_print!(tree.init());
+        unwrap_print!(tree.upsert_edge(None, n(1)));
+        unwrap_print!(tree.upsert_edge(Some(n(1)), n(2)));
+        unwrap_print!(tree.upsert_edge(Some(n(2)), n(3)));
+        unwrap_print!(tree.upsert_edge(Some(n(2)), n(4)));
+        tree.set_uplink(n(1));
+
+        let orphans = unwrap_print!(tree.remove_node(n(2), OrphanPolicy::Promote));
+        assert_eq!(orphans.as_slice(), &[(n(3), None), (n(4), None)]);
+        assert_eq!(tree.node_count(), 4);
+        assert_eq!(unwrap_print!(tree.descendants(Some(n(1)))), 2);
+        assert_eq!(unwrap_print!(tree.next_hop(n(3))), n(1));
+        assert!(tree.next_hop(n(2)).is_err());
+        assert!(matches!(
+            tree.remove_node(n(2), OrphanPolicy::Promote).unwrap_err(),
+            TreeError::NodeNotFoundError
+        ));
+
+        unwrap_print!(tree.remove_node(n(1), OrphanPolicy::Promote));
+        assert_eq!(tree.uplink(), None);
+        assert!(tree.is_child(n(3)));
+        assert_eq!(tree.node_count(), 3);
+    }
+
+    #[test]
+    fn next_hop_follows_changes_along_deep_chain() {
+        let mut tree = Tree::new();
+        unwrap_print!(tree.init());
+        assert!(matches!(
+            tree.next_hop(n(1)).unwrap_err(),
+            TreeError::NodeNotFoundError
+        ));
+        unwrap_print!(tree.upsert_edge(None, n(1)));
+        for i in 2..MAX_LEAFS as u16 {
+            unwrap_print!(tree.upsert_edge(Some(n(i - 1)), n(i)));
+        }
+        let last = n(MAX_LEAFS as u16 - 1);
+        assert_eq!(unwrap_print!(tree.next_hop(last)), n(1));
+
+        unwrap_print!(tree.upsert_edge(None, n(20)));
+        assert_eq!(unwrap_print!(tree.next_hop(last)), n(20));
+        assert_eq!(unwrap_print!(tree.next_hop(n(19))), n(1));
+
+        unwrap_print!(tree.remove_node(n(20), OrphanPolicy::Rediscover));
+        assert!(tree.next_hop(last).is_err());
+        assert!(tree.next_hop(n(20)).is_err());
+    }
+
+    #[test]
+    fn moving_node_below_its_own_descendant_reverses_path() {
+        let mut tree = Tree::new();
+        unwrap_print!(tree.init());
+        unwrap_print!(tree.upsert_edge(None, n(1)));
+        unwrap_print!(tree.upsert_edge(Some(n(1)), n(2)));
+        unwrap_print!(tree.upsert_edge(Some(n(2)), n(3)));
+        unwrap_print!(tree.upsert_edge(Some(n(1)), n(4)));
+
+        unwrap_print!(tree.upsert_edge_with_epoch(Some(n(3)), n(1), 5));
+        assert_eq!(unwrap_print!(tree.parent(n(3))), Some(n(1)));
+        assert_eq!(unwrap_print!(tree.parent(n(2))), Some(n(3)));
+        assert_eq!(unwrap_print!(tree.parent(n(4))), Some(n(1)));
+        assert_eq!(tree.epoch(n(1)), Some(5));
+        assert_eq!(tree.node_count(), 5);
+        assert!(tree.is_below(n(3), n(2)));
+        assert!(!tree.is_below(n(2), n(3)));
+    }
+
+    #[test]
+    fn attach_below_moves_this_node_and_its_uplink() {
+        let mut tree = Tree::new();
+        unwrap_print!(tree.init());
+        unwrap_print!(tree.upsert_edge(None, n(1)));
+        unwrap_print!(tree.upsert_edge(Some(n(1)), n(2)));
+        unwrap_print!(tree.upsert_edge(Some(n(2)), n(3)));
+        unwrap_print!(tree.upsert_edge(None, n(5)));
+        tree.set_uplink(n(1));
+
+        unwrap_print!(tree.attach_below(n(3)));
+        assert_eq!(tree.uplink(), Some(n(3)));
+        assert_eq!(unwrap_print!(tree.parent(n(3))), None);
+        assert_eq!(unwrap_print!(tree.parent(n(1))), Some(n(2)));
+        assert_eq!(unwrap_print!(tree.next_hop(n(1))), n(3));
+        assert!(tree.is_child(n(5)));
+        assert!(tree.attach_below(n(9)).is_err());
+    }
+
+    #[test]
+    fn queries_describe_the_shape_of_the_tree() {
+        let mut tree = Tree::new();
+        assert!(matches!(
+            tree.children(None),
+            Err(TreeError::UninitializedError)
+        ));
+        unwrap_print!(tree.init());
+        unwrap_print!(tree.upsert_edge(None, n(1)));
+        unwrap_print!(tree.upsert_edge(Some(n(1)), n(2)));
+        unwrap_print!(tree.upsert_edge(Some(n(1)), n(3)));
+        unwrap_print!(tree.upsert_edge(Some(n(3)), n(4)));
+
+        assert_eq!(unwrap_print!(tree.children(None)).as_slice(), [n(1)]);
+        assert_eq!(
+            unwrap_print!(tree.children(Some(n(1)))).as_slice(),
+            [n(2), n(3)]
+        );
+        assert!(unwrap_print!(tree.children(Some(n(4)))).is_empty());
+        assert!(matches!(
+            tree.children(Some(n(9))),
+            Err(TreeError::NodeNotFoundError)
+        ));
+        assert_eq!(unwrap_print!(tree.parent(n(4))), Some(n(3)));
+        assert_eq!(unwrap_print!(tree.parent(n(1))), None);
+        assert_eq!(unwrap_print!(tree.depth(Some(n(4)))), 3);
+        assert_eq!(unwrap_print!(tree.depth(None)), 0);
+        assert!(tree.contains(n(4)));
+        assert!(!tree.contains(n(9)));
+        assert_eq!(tree.node_count(), 5);
+
+        unwrap_print!(tree.remove_node(n(3), OrphanPolicy::Rediscover));
+        assert!(!tree.contains(n(4)));
+        assert_eq!(tree.node_count(), 3);
+    }
+
+    #[test]
+    fn tree_round_trips_through_wire_codec() {
+        let mut tree = Tree::new();
+        unwrap_print!(tree.init());
+        unwrap_print!(tree.upsert_edge_with_epoch(None, n(1), 3));
+        unwrap_print!(tree.upsert_edge(Some(n(1)), n(2)));
+        unwrap_print!(tree.upsert_edge(None, n(3)));
+        unwrap_print!(tree.upsert_edge_with_epoch(Some(n(2)), n(4), 300));
+        tree.set_uplink(n(1));
+
+        let mut out = MessageData::new();
+        unwrap_print!(tree.encode(&mut out));
+        // Address, parent and epoch per node, plus count and uplink.
+        assert_eq!(out.len(), 1 + 4 * 8 + 1 + 1);
+        let mut cursor = Cursor::new(&out);
+        let decoded = unwrap_print!(<Tree as WireCodec<MESSAGE_SIZE>>::decode(&mut cursor));
+        assert!(cursor.remaining().is_empty());
+
+        let edges = |tree: &Tree| {
+            let mut edges: std::vec::Vec<_> = tree.into_iter().map(|(n, p)| (n.mac, p)).collect();
+            edges.sort_by_key(|(mac, _)| *mac);
+            edges
+        };
+        assert_eq!(edges(&decoded), edges(&tree));
+        assert_eq!(decoded.uplink(), Some(n(1)));
+        assert_eq!(decoded.epoch(n(4)), Some(300));
+        assert_eq!(unwrap_print!(decoded.next_hop(n(4))), n(1));
+    }
+
+    #[test]
+    fn decoding_rejects_parent_listed_later() {
+        // One node whose parent would be the second entry.
+        let mut data = MessageData::new();
+        data.extend_from_slice(&[1, 0, 0, 0, 0, 0, 1, 2, 0, 0])
+            .unwrap();
+        let mut cursor = Cursor::new(&data);
+        assert!(matches!(
+            <Tree as WireCodec<MESSAGE_SIZE>>::decode(&mut cursor),
+            Err(CodecError::InvalidTreeError)
+        ));
+    }
+
+    #[test]
+    fn version_gap_is_detected_once() {
+        let mut tree = Tree::new();
+        assert!(tree.observe_version(4));
+        assert!(tree.observe_version(5));
+        assert!(tree.observe_version(3));
+        assert!(!tree.observe_version(7));
+        assert_eq!(tree.version(), 7);
+        assert!(tree.observe_version(6));
+        assert!(tree.observe_version(8));
+        assert_eq!(tree.next_version(), 9);
+    }
+
+    #[test]
+    fn version_gap_is_detected_across_the_wrap() {
+        let mut tree = Tree::new();
+        assert!(tree.observe_version(u32::MAX - 1));
+        assert!(tree.observe_version(u32::MAX));
+        assert!(tree.observe_version(1));
+        assert!(tree.observe_version(u32::MAX));
+        assert_eq!(tree.version(), 1);
+        assert!(!tree.observe_version(3));
+        assert_eq!(tree.version(), 3);
+        assert!(tree.observe_version(2));
+        assert_eq!(tree.version(), 3);
+
+        let mut leader = Tree::new();
+        leader.version = u32::MAX;
+        assert_eq!(leader.next_version(), 1);
+    }
+
+    #[test]
+    fn removed_node_leaves_children_for_rediscovery() {
+        let mut tree = Tree::new();
+        unwrap_print!(tree.init());
+        unwrap_print!(tree.upsert_edge(None, n(1)));
+        unwrap_print!(tree.upsert_edge(Some(n(1)), n(2)));
+        unwrap_print!(tree.upsert_edge(Some(n(2)), n(3)));
+        unwrap_print!(tree.upsert_edge(Some(n(3)), n(4)));
+        unwrap_print!(tree.upsert_edge(Some(n(2)), n(5)));
+
+        let orphans = unwrap_print!(tree.remove_node(n(2), OrphanPolicy::Rediscover));
+        assert_eq!(
+            orphans.as_slice(),
+            &[(n(3), None), (n(4), Some(n(3))), (n(5), None)]
+        );
+        assert_eq!(tree.node_count(), 2);
+        assert_eq!(tree.leafs.len(), tree.node_count());
+        assert_eq!(unwrap_print!(tree.descendants(Some(n(1)))), 0);
+        assert!(tree.next_hop(n(4)).is_err());
+
+        for &(node, parent) in &orphans {
+            unwrap_print!(tree.upsert_edge(Some(parent.unwrap_or(n(1))), node));
+        }
+        assert_eq!(tree.node_count(), 5);
+        assert_eq!(unwrap_print!(tree.next_hop(n(4))), n(1));
+    }
+
+    #[test]
+    fn detached_subtree_comes_back_with_a_placement() {
+        let mut tree = Tree::new();
+        unwrap_print!(tree.init());
+        unwrap_print!(tree.upsert_edge(None, n(1)));
+        tree.set_uplink(n(1));
+        unwrap_print!(tree.upsert_edge(Some(n(1)), n(2)));
+        unwrap_print!(tree.upsert_edge(Some(n(2)), n(3)));
+        unwrap_print!(tree.upsert_edge(None, n(4)));
+        unwrap_print!(tree.upsert_edge(Some(n(4)), n(5)));
+
+        unwrap_print!(tree.detach(n(1)));
+        assert_eq!(tree.uplink(), None);
+        assert_eq!(tree.detached().as_slice(), &[n(1)]);
+        assert_eq!(tree.node_count(), 3);
+        assert!(!tree.contains(n(3)));
+        assert_eq!(unwrap_print!(tree.next_hop(n(5))), n(4));
+
+        // The placement of a detached node brings its subtree along.
+        unwrap_print!(tree.upsert_edge(Some(n(5)), n(2)));
+        assert_eq!(tree.node_count(), 5);
+        assert_eq!(unwrap_print!(tree.next_hop(n(3))), n(4));
+        assert_eq!(tree.detached().as_slice(), &[n(1)]);
+
+        tree.drop_detached();
+        assert!(tree.detached().is_empty());
+        assert_eq!(tree.leafs.len(), tree.node_count());
+    }
+
+    #[test]
+    fn suspect_hop_has_a_node_beyond_it() {
+        let mut tree = Tree::new();
+        unwrap_print!(tree.init());
+        unwrap_print!(tree.upsert_edge(None, n(1)));
+        tree.set_uplink(n(1));
+        unwrap_print!(tree.upsert_edge(Some(n(1)), n(2)));
+        unwrap_print!(tree.upsert_edge(None, n(3)));
+        unwrap_print!(tree.upsert_edge(Some(n(3)), n(4)));
+        unwrap_print!(tree.upsert_edge(Some(n(4)), n(5)));
+
+        assert_eq!(tree.hop_beyond(n(1), n(2)), Some(n(2)));
+        assert_eq!(tree.hop_beyond(n(3), n(5)), Some(n(4)));
+        assert_eq!(tree.hop_beyond(n(3), n(3)), None);
+        assert_eq!(tree.hop_beyond(n(3), n(2)), None);
+
+        tree.mark_suspect(n(3));
+        tree.mark_suspect(n(4));
+        assert!(tree.is_suspect(n(3)));
+        assert!(!tree.is_suspect(n(4)));
+        tree.clear_suspect(n(3));
+        assert!(!tree.is_suspect(n(3)));
+
+        tree.mark_suspect(n(3));
+        unwrap_print!(tree.upsert_edge(Some(n(1)), n(3)));
+        assert!(!tree.is_suspect(n(3)));
+    }
+
+    #[test]
+    fn renders_into_fixed_strings() {
+        let mut tree = Tree::new();
+        unwrap_print!(tree.init());
+        unwrap_print!(tree.upsert_edge(None, n(2)));
+        unwrap_print!(tree.upsert_edge(Some(n(2)), n(4)));
+        unwrap_print!(tree.upsert_edge(None, n(3)));
+
+        let mut compact: String<32> = String::new();
+        unwrap_print!(tree.render_into(&mut compact, TreeLayout::Compact));
+        assert_eq!(compact, "self(0002(0004) 0003)");
+
+        let mut lines: String<32> = String::new();
+        unwrap_print!(tree.render_into(&mut lines, TreeLayout::Lines));
+        assert_eq!(lines, "self\n  0002\n    0004\n  0003");
+
+        let mut short: String<8> = String::new();
+        assert!(matches!(
+            tree.render_into(&mut short, TreeLayout::Compact),
+            Err(TreeError::RenderOverflowError)
+        ));
+        assert_eq!(short, "self(");
+    }
+
+    #[test]
+    fn topology_hash_ignores_the_root() {
+        // The leader n(1) with n(2) below it and n(3) below n(2), once seen by the leader and
+        // once by n(3).
+        let mut leader = Tree::new();
+        unwrap_print!(leader.init());
+        unwrap_print!(leader.upsert_edge(None, n(2)));
+        unwrap_print!(leader.upsert_edge(Some(n(2)), n(3)));
+
+        let mut follower = Tree::new();
+        unwrap_print!(follower.init());
+        unwrap_print!(follower.upsert_edge(None, n(2)));
+        follower.set_uplink(n(2));
+        unwrap_print!(follower.upsert_edge(Some(n(2)), n(1)));
+
+        let hash = leader.topology_hash(n(1));
+        assert_eq!(follower.topology_hash(n(3)), hash);
+
+        unwrap_print!(leader.upsert_edge(Some(n(2)), n(4)));
+        assert_ne!(leader.topology_hash(n(1)), hash);
+        assert_ne!(Tree::new().topology_hash(n(1)), hash);
+    }
+
+    #[test]
+    fn successor_adopts_the_neighbors_of_a_silent_leader() {
+        // This node, n(2) and n(3) hang below the leader n(1), n(4) below n(3).
+        let mut tree = Tree::new();
+        unwrap_print!(tree.init());
+        unwrap_print!(tree.upsert_edge(None, n(1)));
+        tree.set_uplink(n(1));
+        unwrap_print!(tree.upsert_edge(Some(n(1)), n(3)));
+        unwrap_print!(tree.upsert_edge(Some(n(1)), n(2)));
+        unwrap_print!(tree.upsert_edge(Some(n(3)), n(4)));
+
+        assert_eq!(unwrap_print!(tree.successor(n(1), n(5))), Some(n(2)));
+        assert_eq!(unwrap_print!(tree.successor(n(1), n(0))), None);
+
+        unwrap_print!(tree.hand_over(n(1), Some(n(2))));
+        assert!(!tree.contains(n(1)));
+        assert_eq!(tree.uplink(), Some(n(2)));
+        assert_eq!(unwrap_print!(tree.parent(n(2))), None);
+        assert_eq!(unwrap_print!(tree.parent(n(3))), Some(n(2)));
+        assert_eq!(unwrap_print!(tree.next_hop(n(4))), n(2));
+
+        let err = tree.hand_over(n(3), Some(n(9))).unwrap_err();
+        assert!(matches!(err, TreeError::NodeNotFoundError));
+        assert!(tree.contains(n(3)));
+    }
+
+    #[test]
+    fn uplink_turns_towards_new_leader() {
+        // The leader n(1) hangs below this node's uplink n(2), n(3) below this node.
+        let mut tree = Tree::new();
+        unwrap_print!(tree.init());
+        unwrap_print!(tree.upsert_edge(None, n(2)));
+        tree.set_uplink(n(2));
+        unwrap_print!(tree.upsert_edge(Some(n(2)), n(1)));
+        unwrap_print!(tree.upsert_edge(None, n(3)));
+
+        unwrap_print!(tree.turn_towards(Some(n(1))));
+        assert_eq!(tree.uplink(), Some(n(2)));
+        unwrap_print!(tree.turn_towards(Some(n(3))));
+        assert_eq!(tree.uplink(), Some(n(3)));
+        assert_eq!(unwrap_print!(tree.next_hop(n(1))), n(2));
+        unwrap_print!(tree.turn_towards(None));
+        assert_eq!(tree.uplink(), None);
+
+        let err = tree.turn_towards(Some(n(9))).unwrap_err();
+        assert!(matches!(err, TreeError::NodeNotFoundError));
+    }
+
+    #[test]
+    fn descendants_follow_upserts() {
+        let mut tree = Tree::new();
+        unwrap_print!(tree.init());
+
+        unwrap_print!(tree.upsert_edge(None, n(1)));
+        unwrap_print!(tree.upsert_edge(Some(n(1)), n(2)));
+        unwrap_print!(tree.upsert_edge(Some(n(2)), n(3)));
+        unwrap_print!(tree.upsert_edge(None, n(4)));
+
+        assert_eq!(unwrap_print!(tree.descendants(None)), 4);
+        assert_eq!(unwrap_print!(tree.descendants(Some(n(1)))), 2);
+        assert_eq!(unwrap_print!(tree.descendants(Some(n(3)))), 0);
+        assert_eq!(unwrap_print!(tree.depth(None)), 0);
+        assert_eq!(unwrap_print!(tree.depth(Some(n(3)))), 3);
+
+        unwrap_print!(tree.upsert_edge(Some(n(4)), n(2)));
+
+        assert_eq!(unwrap_print!(tree.descendants(None)), 4);
+        assert_eq!(unwrap_print!(tree.descendants(Some(n(1)))), 0);
+        assert_eq!(unwrap_print!(tree.descendants(Some(n(4)))), 2);
+    }
+
+    #[test]
+    fn descendants_of_unknown_node() {
+        let mut tree = Tree::new();
+        unwrap_print!(tree.init());
+
+        let err = tree.descendants(Some(n(9))).unwrap_err();
+        assert!(matches!(err, TreeError::NodeNotFoundError));
+    }
 }
@@ -0,0 +1,234 @@
+#![cfg(feature = "std")]
+//! Link over UDP, so a gateway running on a host reaches peers beyond radio range, e.g. other
+//! gateways on the same network. Every datagram holds the source and destination node followed
+//! by the frame. Peers are added with `UdpLink::add_peer` or learned from a datagram once the
+//! mesh trusts its frame, see `Link::trust_source`. Broadcasts go to every known peer.
+//!
+//! The radio next to this link is a `MockLink`, see `ActiveLink::Split`, so gateways built on it
+//! run in simulations only.
+
+use std::{collections::HashMap, io, net::SocketAddr, sync::Mutex};
+
+use tokio::net::{ToSocketAddrs, UdpSocket};
+
+use crate::logic::{
+    asynchronous,
+    config::TxPowerPolicy,
+    consts::MESSAGE_SIZE,
+    error::LinkError,
+    link::{Link, RecvData},
+    message::{MessageData, Priority},
+    node::Node,
+};
+
+/// Source and destination node in front of every frame.
+const HEADER_LEN: usize = 12;
+/// RSSI frames of a UDP link are received with. The network loses no signal, so it is stronger
+/// than any radio neighbor.
+pub const UDP_RSSI: i32 = 0;
+
+pub struct UdpLink {
+    node: Node,
+    socket: UdpSocket,
+    peers: Mutex<HashMap<Node, SocketAddr>>,
+    /// Source of the last accepted datagram and where it came from. The header is not
+    /// authenticated, so the address is only learned once the mesh trusts the frame.
+    last_from: Mutex<Option<(Node, SocketAddr)>>,
+}
+
+impl UdpLink {
+    /// Binds the link of `node` to `address`.
+    pub async fn bind(node: Node, address: impl ToSocketAddrs) -> io::Result<Self> {
+        Ok(Self {
+            node,
+            socket: UdpSocket::bind(address).await?,
+            peers: Mutex::new(HashMap::new()),
+            last_from: Mutex::new(None),
+        })
+    }
+
+    pub fn local_addr(&self) -> io::Result<SocketAddr> {
+        self.socket.local_addr()
+    }
+
+    /// Sends the frames for `node` to `address` from now on.
+    pub fn add_peer(&self, node: Node, address: SocketAddr) {
+        self.peers().insert(node, address);
+    }
+
+    /// Returns true if frames for `node` have an address to go to, which suits the selector of
+    /// a `SplitLink`.
+    pub fn knows(&self, node: Node) -> bool {
+        self.peers().contains_key(&node)
+    }
+
+    fn peers(&self) -> std::sync::MutexGuard<'_, HashMap<Node, SocketAddr>> {
+        self.peers.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// Addresses `data` for `destination` goes to, every known peer for a broadcast.
+    fn route(&self, destination: Node) -> Result<Vec<SocketAddr>, LinkError> {
+        let peers = self.peers();
+        if destination.is_broadcast() {
+            return Ok(peers.values().copied().collect());
+        }
+        peers
+            .get(&destination)
+            .map(|address| vec![*address])
+            .ok_or(LinkError::DeliveryError(destination))
+    }
+
+    fn datagram(&self, data: &MessageData, destination: Node) -> Vec<u8> {
+        let mut datagram = Vec::with_capacity(HEADER_LEN + data.len());
+        datagram.extend_from_slice(&self.node.mac);
+        datagram.extend_from_slice(&destination.mac);
+        datagram.extend_from_slice(data);
+        datagram
+    }
+
+    /// Returns the frame of `datagram` if it is meant for this node, and remembers where it came
+    /// from until `trust_source`.
+    fn accept(&self, datagram: &[u8], from: SocketAddr) -> Option<RecvData> {
+        let (header, frame) = datagram.split_at_checked(HEADER_LEN)?;
+        let (source, destination) = header.split_at(6);
+        let source = Node::new(source.try_into().ok()?);
+        let destination = Node::new(destination.try_into().ok()?);
+        if source == self.node || (destination != self.node && !destination.is_broadcast()) {
+            return None;
+        }
+        let data = MessageData::from_slice(frame).ok()?;
+        *self.last_from.lock().unwrap_or_else(|e| e.into_inner()) = Some((source, from));
+        Some(RecvData {
+            data,
+            source,
+            destination,
+            rssi: UDP_RSSI,
+            received_ms: asynchronous::now_ms(),
+        })
+    }
+}
+
+impl<'a> Link<'a> for UdpLink {
+    /// UDP has no acknowledgements, so a unicast succeeds once the datagram left the socket.
+    async fn send(&'a self, data: MessageData, destination: Node) -> Result<(), LinkError> {
+        let datagram = self.datagram(&data, destination);
+        for address in self.route(destination)? {
+            if let Err(e) = self.socket.send_to(&datagram, address).await {
+                if !destination.is_broadcast() {
+                    return Err(LinkError::DeliveryError(destination));
+                }
+                println!("failed to send broadcast to {}: {}", address, e);
+            }
+        }
+        Ok(())
+    }
+
+    fn try_send(
+        &self,
+        data: MessageData,
+        destination: Node,
+        _priority: Priority,
+    ) -> Result<(), LinkError> {
+        let datagram = self.datagram(&data, destination);
+        for address in self.route(destination)? {
+            if let Err(e) = self.socket.try_send_to(&datagram, address) {
+                if !destination.is_broadcast() {
+                    return Err(LinkError::SenderBusyError);
+                }
+                println!("failed to send broadcast to {}: {}", address, e);
+            }
+        }
+        Ok(())
+    }
+
+    async fn receive(&'a self) -> RecvData {
+        let mut buffer = [0; HEADER_LEN + MESSAGE_SIZE];
+        loop {
+            match self.socket.recv_from(&mut buffer).await {
+                Ok((len, from)) => {
+                    if let Some(recv) = self.accept(&buffer[..len], from) {
+                        return recv;
+                    }
+                }
+                Err(e) => println!("failed to receive datagram: {}", e),
+            }
+        }
+    }
+
+    fn try_receive(&self) -> Result<RecvData, LinkError> {
+        let mut buffer = [0; HEADER_LEN + MESSAGE_SIZE];
+        loop {
+            let (len, from) = self
+                .socket
+                .try_recv_from(&mut buffer)
+                .map_err(|_| LinkError::QueueEmptyError())?;
+            if let Some(recv) = self.accept(&buffer[..len], from) {
+                return Ok(recv);
+            }
+        }
+    }
+
+    /// Datagrams wait in the socket of the host, not in the link.
+    fn queued(&self) -> usize {
+        0
+    }
+
+    fn address(&self) -> Node {
+        self.node
+    }
+
+    /// There is no radio to limit.
+    fn set_tx_power(&self, _policy: TxPowerPolicy) -> Result<(), LinkError> {
+        Ok(())
+    }
+
+    /// Learns where `source` is reached if the last datagram came from it.
+    fn trust_source(&self, source: Node) {
+        let last_from = *self.last_from.lock().unwrap_or_else(|e| e.into_inner());
+        if let Some((node, address)) = last_from
+            && node == source
+        {
+            self.add_peer(node, address);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test(flavor = "current_thread")]
+    async fn udp_link_learns_peer_from_trusted_frames() {
+        let a = Node::new([0, 0, 0, 0, 0, 1]);
+        let b = Node::new([0, 0, 0, 0, 0, 2]);
+        let link_a = UdpLink::bind(a, "127.0.0.1:0").await.unwrap();
+        let link_b = UdpLink::bind(b, "127.0.0.1:0").await.unwrap();
+        link_a.add_peer(b, link_b.local_addr().unwrap());
+        assert!(!link_b.knows(a));
+
+        link_a
+            .send(MessageData::from([1]), Node::BROADCAST)
+            .await
+            .unwrap();
+        let recv = link_b.receive().await;
+        assert_eq!(recv.data, MessageData::from([1]));
+        assert_eq!(recv.source, a);
+        assert_eq!(recv.rssi, UDP_RSSI);
+        assert!(!link_b.knows(a));
+        link_b.trust_source(Node::new([0, 0, 0, 0, 0, 3]));
+        assert!(!link_b.knows(a));
+        link_b.trust_source(a);
+        assert!(link_b.knows(a));
+
+        link_b.send(MessageData::from([2]), a).await.unwrap();
+        let recv = link_a.receive().await;
+        assert_eq!(recv.data, MessageData::from([2]));
+        assert_eq!(recv.destination, a);
+
+        let unknown = Node::new([0, 0, 0, 0, 0, 3]);
+        assert!(matches!(
+            link_a.send(MessageData::from([3]), unknown).await,
+            Err(LinkError::DeliveryError(n)) if n == unknown
+        ));
+    }
+}
@@ -10,3 +10,45 @@ macro_rules! unwrap_print {
         }
     };
 }
+
+/// Maximum deviation of a jittered period in percent of the period.
+pub const JITTER_PERCENT: u64 = 20;
+
+/// Spreads `period_us` uniformly over +-`JITTER_PERCENT` using `random`, so that nodes started
+/// at the same time drift apart instead of transmitting in lockstep.
+pub fn jittered_micros(period_us: u64, random: u32) -> u64 {
+    let spread = period_us * JITTER_PERCENT / 100;
+    period_us - spread + random as u64 % (2 * spread + 1)
+}
+
+/// Random delay before resend `attempt` (0 based), drawn from a window of `base_ms` that
+/// doubles with every attempt so that colliding senders spread out.
+pub fn backoff_ms(base_ms: u64, attempt: u8, random: u32) -> u64 {
+    let window = base_ms << attempt.min(8);
+    random as u64 % (window + 1)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn jitter_stays_within_bounds() {
+        assert_eq!(jittered_micros(1_000_000, 0), 800_000);
+        assert_eq!(jittered_micros(1_000_000, 400_000), 1_200_000);
+        for random in [1, 12_345, 400_001, u32::MAX] {
+            let period = jittered_micros(1_000_000, random);
+            assert!((800_000..=1_200_000).contains(&period));
+        }
+        assert_eq!(jittered_micros(0, 7), 0);
+    }
+
+    #[test]
+    fn backoff_window_doubles_per_attempt() {
+        assert_eq!(backoff_ms(10, 0, 10), 10);
+        assert_eq!(backoff_ms(10, 0, 11), 0);
+        assert_eq!(backoff_ms(10, 1, 20), 20);
+        assert_eq!(backoff_ms(10, 2, u32::MAX), u32::MAX as u64 % 41);
+        assert_eq!(backoff_ms(10, u8::MAX, 2560), 2560);
+    }
+}
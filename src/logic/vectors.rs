@@ -0,0 +1,214 @@
+#![cfg(any(feature = "std", feature = "wire-selftest"))]
+//! Canonical frames every build has to encode to the same bytes, so a codec change that depends
+//! on the target or on a feature is caught. A fixture holds one vector per line, its name and
+//! the frame in hex:
+//!
+//! `heartbeat_tagged 0db424...`
+//!
+//! The `vectors` binary writes the fixture from the std build. `WIRE_VECTORS` embeds the checked
+//! in copy, which the std tests and the firmware built with `wire-selftest` compare against.
+
+use heapless::Vec;
+
+use crate::logic::{
+    capabilities::{Capabilities, Features, ImageState},
+    crypto::{self, AuthenticatedHeader, NetworkKey},
+    error::WireVectorError,
+    health::Health,
+    message::{MessageContent, MessageData, MessageFlags, SendMessage, TraceHops},
+    metadata::{self, Metadata},
+    neighbor::LinkReport,
+    node::Node,
+    topology::TopologyChunk,
+};
+
+/// The checked in fixture, see `check`.
+pub const WIRE_VECTORS: &str = include_str!("../../fixtures/wire_vectors.txt");
+
+pub const MAX_WIRE_VECTORS: usize = 12;
+
+const KEY: NetworkKey = [
+    0x00, 0x11, 0x22, 0x33, 0x44, 0x55, 0x66, 0x77, 0x88, 0x99, 0xaa, 0xbb, 0xcc, 0xdd, 0xee, 0xff,
+];
+const NETWORK_ID: u16 = 0x1234;
+const A: Node = Node::new([0x02, 0, 0, 0, 0, 0x0a]);
+const B: Node = Node::new([0x02, 0, 0, 0, 0, 0x0b]);
+
+/// Encodes every vector with this build. Kept small enough for `small-frames`.
+pub fn wire_vectors() -> Result<Vec<(&'static str, MessageData), MAX_WIRE_VECTORS>, WireVectorError>
+{
+    let mut vectors = Vec::new();
+    let mut add = |name, msg: SendMessage| -> Result<(), WireVectorError> {
+        let frame = msg
+            .serialize()
+            .map_err(WireVectorError::SerializationError)?;
+        vectors
+            .push((name, frame))
+            .map_err(|_| WireVectorError::TooManyVectorsError)
+    };
+
+    add(
+        "application",
+        message(MessageContent::Application(data(&[1, 2, 3]))),
+    )?;
+
+    let payload =
+        metadata::attach(&Metadata::for_port(7), &[4, 5]).map_err(WireVectorError::CodecError)?;
+    let mut msg = message(MessageContent::Application(payload));
+    msg.timestamp = Some(123_456);
+    msg.flags.insert(MessageFlags::REALTIME);
+    add("application_metadata", msg)?;
+
+    let header = AuthenticatedHeader {
+        final_source: A,
+        final_destination: B,
+        sequence: 1,
+    };
+    let sealed = crypto::seal_application(&KEY, &header, 0x0102_0304, b"tag")
+        .map_err(WireVectorError::CryptoError)?;
+    let mut msg = message(MessageContent::Application(sealed));
+    msg.flags.insert(MessageFlags::ENCRYPTED);
+    add("application_sealed", msg)?;
+
+    let capabilities = Capabilities {
+        firmware: 0x0102,
+        image: ImageState::PendingVerify,
+        battery: Some(80),
+        features: Features::from_bits(0x03),
+        priority: 2,
+    };
+    add(
+        "discovery",
+        message(MessageContent::Discovery(capabilities)),
+    )?;
+
+    add(
+        "upsert_edge",
+        message(MessageContent::UpsertEdge((Some(A), Some(B)), 300)),
+    )?;
+
+    let mut edges = Vec::new();
+    let _ = edges.push((None, Some(A)));
+    let _ = edges.push((Some(A), Some(B)));
+    let chunk = TopologyChunk {
+        index: 0,
+        total: 1,
+        edges,
+    };
+    add("topology", message(MessageContent::Topology(chunk)))?;
+
+    let health = Health::new(Some(80), 2, Some(-60));
+    let mut msg = message(MessageContent::Heartbeat(health, None));
+    let authenticated = msg
+        .authenticated_data(A)
+        .map_err(WireVectorError::CodecError)?;
+    msg.set_tag(crypto::message_tag(&KEY, &authenticated));
+    add("heartbeat_tagged", msg)?;
+
+    let bundle = message(MessageContent::Application(data(&[6, 7])))
+        .serialize()
+        .map_err(WireVectorError::SerializationError)?;
+    add(
+        "heartbeat_piggyback",
+        message(MessageContent::Heartbeat(health, Some(bundle))),
+    )?;
+
+    let mut hops = TraceHops::new();
+    let _ = hops.push(A);
+    let mut msg = message(MessageContent::TraceRoute(5, hops));
+    msg.hop_limit = 3;
+    add("trace_route", msg)?;
+
+    let mut report = LinkReport::new();
+    let _ = report.push((B, -60));
+    add(
+        "link_report",
+        message(MessageContent::LinkReport(A, report)),
+    )?;
+
+    add("congested", message(MessageContent::Congested(B)))?;
+    add(
+        "leader_heartbeat_hashed",
+        message(MessageContent::LeaderHeartbeat(Some(0x1234_5678))),
+    )?;
+    Ok(vectors)
+}
+
+fn message(content: MessageContent) -> SendMessage {
+    let mut msg = SendMessage::new(B, content, None, 1);
+    msg.network_id = NETWORK_ID;
+    // The default hop limit grows with `MAX_LEAFS` of the build.
+    msg.hop_limit = 32;
+    msg
+}
+
+fn data(bytes: &[u8]) -> MessageData {
+    // Only called with a few bytes.
+    MessageData::from_slice(bytes).unwrap_or_default()
+}
+
+/// Compares the vectors of this build against `fixture`. Returns how many were checked.
+pub fn check(fixture: &str) -> Result<usize, WireVectorError> {
+    let vectors = wire_vectors()?;
+    for (name, frame) in &vectors {
+        let expected = lookup(fixture, name)?.ok_or(WireVectorError::MissingVectorError(name))?;
+        if expected != *frame {
+            return Err(WireVectorError::MismatchError(name));
+        }
+    }
+    Ok(vectors.len())
+}
+
+/// The frame `fixture` holds for `name`, failing on any line that is not a vector.
+fn lookup(fixture: &str, name: &str) -> Result<Option<MessageData>, WireVectorError> {
+    let mut found = None;
+    for (number, line) in fixture.lines().enumerate() {
+        if line.trim().is_empty() {
+            continue;
+        }
+        let (line_name, hex) = line
+            .trim()
+            .split_once(' ')
+            .ok_or(WireVectorError::MalformedLineError(number + 1))?;
+        let frame = parse_hex(hex).ok_or(WireVectorError::MalformedLineError(number + 1))?;
+        if line_name == name {
+            found = Some(frame);
+        }
+    }
+    Ok(found)
+}
+
+fn parse_hex(hex: &str) -> Option<MessageData> {
+    if !hex.len().is_multiple_of(2) {
+        return None;
+    }
+    let mut out = MessageData::new();
+    for i in (0..hex.len()).step_by(2) {
+        let byte = u8::from_str_radix(hex.get(i..i + 2)?, 16).ok()?;
+        out.push(byte).ok()?;
+    }
+    Some(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn std_build_matches_checked_in_fixture() {
+        assert_eq!(check(WIRE_VECTORS).unwrap(), wire_vectors().unwrap().len());
+    }
+
+    #[test]
+    fn changed_frame_is_reported_by_name() {
+        let fixture = "application 00\n";
+        assert!(matches!(
+            check(fixture),
+            Err(WireVectorError::MismatchError("application"))
+        ));
+        assert!(matches!(
+            check("application zz\n"),
+            Err(WireVectorError::MalformedLineError(1))
+        ));
+    }
+}
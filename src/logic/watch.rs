@@ -0,0 +1,101 @@
+use core::task::{Context, Poll, Waker};
+use heapless::Vec;
+
+/// A value tasks can wait on to change, like `embassy_sync::watch::Watch`, but kept in state
+/// that is already shared behind a mutex instead of another static. Every change moves the
+/// version on, so a receiver that remembers the version it saw last notices a change that
+/// happened while it was busy. Up to `N` tasks wait at the same time.
+pub struct Watched<T, const N: usize> {
+    value: T,
+    version: u32,
+    wakers: Vec<Waker, N>,
+}
+
+impl<T: Copy + PartialEq, const N: usize> Watched<T, N> {
+    pub const fn new(value: T) -> Self {
+        Self {
+            value,
+            version: 0,
+            wakers: Vec::new(),
+        }
+    }
+
+    pub fn get(&self) -> T {
+        self.value
+    }
+
+    pub fn version(&self) -> u32 {
+        self.version
+    }
+
+    /// Replaces the value. Wakes every waiting task if it differs from the current one.
+    pub fn set(&mut self, value: T) {
+        if self.value == value {
+            return;
+        }
+        self.value = value;
+        self.version = self.version.wrapping_add(1);
+        while let Some(waker) = self.wakers.pop() {
+            waker.wake();
+        }
+    }
+
+    /// Ready with the value and its version once the version differs from `seen`. Otherwise
+    /// the task of `cx` is woken on the next change. If `N` tasks wait already, they are woken
+    /// right away and register again.
+    pub fn poll_changed(&mut self, seen: u32, cx: &mut Context<'_>) -> Poll<(T, u32)> {
+        if self.version != seen {
+            return Poll::Ready((self.value, self.version));
+        }
+        if self.wakers.iter().any(|waker| waker.will_wake(cx.waker())) {
+            return Poll::Pending;
+        }
+        if self.wakers.is_full() {
+            while let Some(waker) = self.wakers.pop() {
+                waker.wake();
+            }
+        }
+        let _ = self.wakers.push(cx.waker().clone());
+        Poll::Pending
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::{
+        Arc,
+        atomic::{AtomicUsize, Ordering},
+    };
+    use std::task::Wake;
+
+    struct CountingWaker(AtomicUsize);
+
+    impl Wake for CountingWaker {
+        fn wake(self: Arc<Self>) {
+            self.0.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
+    #[test]
+    fn watched_wakes_waiting_task_on_change() {
+        let counter = Arc::new(CountingWaker(AtomicUsize::new(0)));
+        let waker = Waker::from(counter.clone());
+        let mut cx = Context::from_waker(&waker);
+        let mut watched: Watched<u8, 2> = Watched::new(1);
+        let seen = watched.version();
+
+        assert!(watched.poll_changed(seen, &mut cx).is_pending());
+        watched.set(1);
+        assert_eq!(counter.0.load(Ordering::Relaxed), 0);
+        assert!(watched.poll_changed(seen, &mut cx).is_pending());
+
+        watched.set(2);
+        assert_eq!(counter.0.load(Ordering::Relaxed), 1);
+        let Poll::Ready((value, version)) = watched.poll_changed(seen, &mut cx) else {
+            panic!("change went unnoticed");
+        };
+        assert_eq!(value, 2);
+        assert!(watched.poll_changed(version, &mut cx).is_pending());
+    }
+}
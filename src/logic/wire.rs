@@ -1,5 +1,5 @@
 use crate::logic::error::{CodecError, CursorError};
-use heapless::Vec;
+use heapless::{String, Vec};
 
 pub struct Cursor<'a> {
     buf: &'a [u8],
@@ -23,6 +23,122 @@ impl<'a> Cursor<'a> {
     pub fn remaining(&self) -> &'a [u8] {
         &self.buf[self.pos..]
     }
+
+    /// Returns the next byte without consuming it.
+    pub fn peek(&self) -> Result<u8, CursorError> {
+        self.buf
+            .get(self.pos)
+            .copied()
+            .ok_or(CursorError::BufferUnderflowError)
+    }
+
+    pub fn skip(&mut self, n: usize) -> Result<(), CursorError> {
+        self.take(n).map(|_| ())
+    }
+
+    pub fn read_array<const L: usize>(&mut self) -> Result<[u8; L], CursorError> {
+        let mut array = [0; L];
+        array.copy_from_slice(self.take(L)?);
+        Ok(array)
+    }
+
+    pub fn read_u8(&mut self) -> Result<u8, CursorError> {
+        Ok(self.take(1)?[0])
+    }
+
+    pub fn read_u16_le(&mut self) -> Result<u16, CursorError> {
+        self.read_array().map(u16::from_le_bytes)
+    }
+
+    pub fn read_u32_le(&mut self) -> Result<u32, CursorError> {
+        self.read_array().map(u32::from_le_bytes)
+    }
+
+    pub fn read_i32_le(&mut self) -> Result<i32, CursorError> {
+        self.read_array().map(i32::from_le_bytes)
+    }
+}
+
+/// Write counterparts of the `Cursor` reads, for any byte buffer such as `MessageData`.
+pub trait WriteBytes {
+    fn write_bytes(&mut self, bytes: &[u8]) -> Result<(), CodecError>;
+
+    fn write_u8(&mut self, value: u8) -> Result<(), CodecError>;
+
+    fn write_u16_le(&mut self, value: u16) -> Result<(), CodecError> {
+        self.write_bytes(&value.to_le_bytes())
+    }
+
+    fn write_u32_le(&mut self, value: u32) -> Result<(), CodecError> {
+        self.write_bytes(&value.to_le_bytes())
+    }
+
+    fn write_i32_le(&mut self, value: i32) -> Result<(), CodecError> {
+        self.write_bytes(&value.to_le_bytes())
+    }
+}
+
+impl<const N: usize> WriteBytes for Vec<u8, N> {
+    fn write_bytes(&mut self, bytes: &[u8]) -> Result<(), CodecError> {
+        self.extend_from_slice(bytes)
+            .map_err(CodecError::BufferCapacityError)
+    }
+
+    fn write_u8(&mut self, value: u8) -> Result<(), CodecError> {
+        self.push(value).map_err(CodecError::BufferOverflowError)
+    }
+}
+
+/// CRC-16/CCITT-FALSE (poly 0x1021, init 0xFFFF) over `data`.
+pub fn crc16(data: &[u8]) -> u16 {
+    let mut crc: u16 = 0xFFFF;
+    for byte in data {
+        crc ^= (*byte as u16) << 8;
+        for _ in 0..8 {
+            crc = if crc & 0x8000 != 0 {
+                (crc << 1) ^ 0x1021
+            } else {
+                crc << 1
+            };
+        }
+    }
+    crc
+}
+
+/// Longest varint needed for a `u32`.
+const MAX_VARINT_LEN: usize = 5;
+
+/// Appends `value` as an unsigned LEB128 varint: seven bits per byte, lowest group first, with
+/// the top bit set on every byte but the last. Values below 128 take a single byte.
+pub fn encode_varint<const N: usize>(
+    mut value: u32,
+    out: &mut Vec<u8, N>,
+) -> Result<(), CodecError> {
+    while value >= 0x80 {
+        out.write_u8(value as u8 | 0x80)?;
+        value >>= 7;
+    }
+    out.write_u8(value as u8)
+}
+
+pub fn decode_varint(cursor: &mut Cursor<'_>) -> Result<u32, CodecError> {
+    let mut value: u32 = 0;
+    for i in 0..MAX_VARINT_LEN {
+        let byte = cursor.read_u8().map_err(CodecError::CursorReadError)?;
+        let group = (byte & 0x7F) as u32;
+        if i == MAX_VARINT_LEN - 1 && group > 0x0F {
+            return Err(CodecError::VarintOverflowError);
+        }
+        value |= group << (7 * i);
+        if byte & 0x80 == 0 {
+            return Ok(value);
+        }
+    }
+    Err(CodecError::VarintOverflowError)
+}
+
+pub fn decode_varint_u16(cursor: &mut Cursor<'_>) -> Result<u16, CodecError> {
+    u16::try_from(decode_varint(cursor)?).map_err(|_| CodecError::VarintOverflowError)
 }
 
 pub trait WireCodec<const N: usize>: Sized {
@@ -30,27 +146,29 @@ pub trait WireCodec<const N: usize>: Sized {
     fn decode(cursor: &mut Cursor<'_>) -> Result<Self, CodecError>;
 }
 
+/// Number of bytes `value` takes on the wire. Fails like `encode` if it does not fit into `N`.
+pub fn encoded_len<const N: usize>(value: &impl WireCodec<N>) -> Result<usize, CodecError> {
+    let mut out = Vec::new();
+    value.encode(&mut out)?;
+    Ok(out.len())
+}
+
 impl<T, const N: usize> WireCodec<N> for Option<T>
 where
     T: WireCodec<N>,
 {
     fn encode(&self, out: &mut Vec<u8, N>) -> Result<(), CodecError> {
         match self {
-            None => {
-                out.push(0)
-                    .map_err(|e| CodecError::BufferOverflowError(e))?;
-            }
+            None => out.write_u8(0),
             Some(value) => {
-                out.push(1)
-                    .map_err(|e| CodecError::BufferOverflowError(e))?;
-                value.encode(out)?;
+                out.write_u8(1)?;
+                value.encode(out)
             }
         }
-        Ok(())
     }
 
     fn decode(cursor: &mut Cursor<'_>) -> Result<Self, CodecError> {
-        let flag = cursor.take(1).map_err(|e| CodecError::CursorReadError(e))?[0];
+        let flag = cursor.read_u8().map_err(CodecError::CursorReadError)?;
 
         match flag {
             0 => Ok(None),
@@ -60,6 +178,174 @@ where
     }
 }
 
+impl<A, B, const N: usize> WireCodec<N> for (A, B)
+where
+    A: WireCodec<N>,
+    B: WireCodec<N>,
+{
+    fn encode(&self, out: &mut Vec<u8, N>) -> Result<(), CodecError> {
+        self.0.encode(out)?;
+        self.1.encode(out)
+    }
+
+    fn decode(cursor: &mut Cursor<'_>) -> Result<Self, CodecError> {
+        Ok((A::decode(cursor)?, B::decode(cursor)?))
+    }
+}
+
+/// A varint element count followed by the elements.
+impl<T, const M: usize, const N: usize> WireCodec<N> for Vec<T, M>
+where
+    T: WireCodec<N>,
+{
+    fn encode(&self, out: &mut Vec<u8, N>) -> Result<(), CodecError> {
+        encode_varint(self.len() as u32, out)?;
+        for element in self {
+            element.encode(out)?;
+        }
+        Ok(())
+    }
+
+    fn decode(cursor: &mut Cursor<'_>) -> Result<Self, CodecError> {
+        let len = decode_varint(cursor)?;
+        if len as usize > M {
+            return Err(CodecError::LengthExceededError(len));
+        }
+        let mut elements = Vec::new();
+        for _ in 0..len {
+            let _ = elements.push(T::decode(cursor)?);
+        }
+        Ok(elements)
+    }
+}
+
+/// A varint byte count followed by the UTF-8 bytes.
+impl<const M: usize, const N: usize> WireCodec<N> for String<M> {
+    fn encode(&self, out: &mut Vec<u8, N>) -> Result<(), CodecError> {
+        encode_varint(self.len() as u32, out)?;
+        out.write_bytes(self.as_bytes())
+    }
+
+    fn decode(cursor: &mut Cursor<'_>) -> Result<Self, CodecError> {
+        let len = decode_varint(cursor)?;
+        if len as usize > M {
+            return Err(CodecError::LengthExceededError(len));
+        }
+        let bytes = cursor
+            .take(len as usize)
+            .map_err(CodecError::CursorReadError)?;
+        let mut text = String::new();
+        text.push_str(core::str::from_utf8(bytes).map_err(|_| CodecError::CodecError)?)
+            .map_err(CodecError::BufferCapacityError)?;
+        Ok(text)
+    }
+}
+
+/// Fixed width little endian encoding for integers.
+macro_rules! impl_le_codec {
+    ($($int:ty),*) => {
+        $(
+            impl<const N: usize> WireCodec<N> for $int {
+                fn encode(&self, out: &mut Vec<u8, N>) -> Result<(), CodecError> {
+                    out.write_bytes(&self.to_le_bytes())
+                }
+
+                fn decode(cursor: &mut Cursor<'_>) -> Result<Self, CodecError> {
+                    cursor
+                        .read_array()
+                        .map(<$int>::from_le_bytes)
+                        .map_err(CodecError::CursorReadError)
+                }
+            }
+        )*
+    };
+}
+
+impl_le_codec!(u8, i8, u16, u32, i32);
+
+/// The elements back to back, without a length since it is part of the type.
+impl<T, const L: usize, const N: usize> WireCodec<N> for [T; L]
+where
+    T: WireCodec<N> + Copy + Default,
+{
+    fn encode(&self, out: &mut Vec<u8, N>) -> Result<(), CodecError> {
+        for element in self {
+            element.encode(out)?;
+        }
+        Ok(())
+    }
+
+    fn decode(cursor: &mut Cursor<'_>) -> Result<Self, CodecError> {
+        let mut elements = [T::default(); L];
+        for element in elements.iter_mut() {
+            *element = T::decode(cursor)?;
+        }
+        Ok(elements)
+    }
+}
+
+/// Implements `WireCodec<$size>` for a plain struct by encoding the listed fields one after
+/// another, each with its own `WireCodec`. Encode and decode share the one field list, so their
+/// order cannot drift apart. Every field of the struct must be listed.
+///
+/// An optional `round_trip_test name = value;` adds a test that encodes and decodes `value`.
+///
+/// ```ignore
+/// wire_codec! {
+///     TopologyChunk: MESSAGE_SIZE {
+///         index: u8,
+///         total: u8,
+///         edges: Vec<Edge, MAX_TOPOLOGY_CHUNK_EDGES>,
+///     }
+/// }
+/// ```
+#[macro_export]
+macro_rules! wire_codec {
+    ($name:ident : $size:path { $($field:ident : $ty:ty),* $(,)? }) => {
+        impl $crate::logic::wire::WireCodec<{ $size }> for $name {
+            fn encode(
+                &self,
+                out: &mut heapless::Vec<u8, { $size }>,
+            ) -> Result<(), $crate::logic::error::CodecError> {
+                $(
+                    <$ty as $crate::logic::wire::WireCodec<{ $size }>>::encode(&self.$field, out)?;
+                )*
+                Ok(())
+            }
+
+            fn decode(
+                cursor: &mut $crate::logic::wire::Cursor<'_>,
+            ) -> Result<Self, $crate::logic::error::CodecError> {
+                Ok($name {
+                    $(
+                        $field: <$ty as $crate::logic::wire::WireCodec<{ $size }>>::decode(cursor)?,
+                    )*
+                })
+            }
+        }
+    };
+    (
+        $name:ident : $size:path { $($field:ident : $ty:ty),* $(,)? }
+        round_trip_test $test:ident = $example:expr;
+    ) => {
+        $crate::wire_codec!($name: $size { $($field: $ty),* });
+
+        #[cfg(test)]
+        #[test]
+        fn $test() {
+            use $crate::logic::wire::WireCodec;
+            let value: $name = $example;
+            let mut out = heapless::Vec::<u8, { $size }>::new();
+            $crate::unwrap_print!(value.encode(&mut out));
+            let mut cursor = $crate::logic::wire::Cursor::new(&out);
+            let decoded =
+                $crate::unwrap_print!(<$name as WireCodec<{ $size }>>::decode(&mut cursor));
+            assert_eq!(decoded, value);
+            assert!(cursor.remaining().is_empty());
+        }
+    };
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -110,4 +396,140 @@ mod tests {
 
         assert_eq!(cursor.remaining(), &[]);
     }
+
+    #[test]
+    fn test_cursor_fixed_width_reads() {
+        let mut out = Vec::<u8, 16>::new();
+        unwrap_print!(out.write_u8(7));
+        unwrap_print!(out.write_u16_le(0x1234));
+        unwrap_print!(out.write_u32_le(0xDEAD_BEEF));
+        unwrap_print!(out.write_i32_le(-42));
+        assert_eq!(&out[..3], &[7, 0x34, 0x12]);
+
+        let mut cursor = Cursor::new(&out);
+        assert_eq!(unwrap_print!(cursor.peek()), 7);
+        assert_eq!(unwrap_print!(cursor.read_u8()), 7);
+        assert_eq!(unwrap_print!(cursor.read_u16_le()), 0x1234);
+        assert_eq!(unwrap_print!(cursor.read_u32_le()), 0xDEAD_BEEF);
+        assert_eq!(unwrap_print!(cursor.read_i32_le()), -42);
+        assert!(matches!(
+            cursor.peek(),
+            Err(CursorError::BufferUnderflowError)
+        ));
+    }
+
+    #[test]
+    fn test_cursor_skip_and_failed_read_keep_position() {
+        let mut cursor = Cursor::new(&[1, 2, 3]);
+        unwrap_print!(cursor.skip(1));
+        assert!(cursor.read_u32_le().is_err());
+        assert!(cursor.skip(3).is_err());
+        assert_eq!(unwrap_print!(cursor.read_u16_le()), 0x0302);
+    }
+
+    #[test]
+    fn test_varint_round_trip() {
+        for value in [
+            0,
+            1,
+            127,
+            128,
+            300,
+            16_383,
+            16_384,
+            u16::MAX as u32,
+            u32::MAX,
+        ] {
+            let mut out = Vec::<u8, 8>::new();
+            unwrap_print!(encode_varint(value, &mut out));
+            let mut cursor = Cursor::new(&out);
+            assert_eq!(unwrap_print!(decode_varint(&mut cursor)), value);
+            assert!(cursor.remaining().is_empty());
+        }
+    }
+
+    #[test]
+    fn test_varint_length_grows_with_value() {
+        let len = |value| {
+            let mut out = Vec::<u8, 8>::new();
+            unwrap_print!(encode_varint(value, &mut out));
+            out.len()
+        };
+        assert_eq!(len(127), 1);
+        assert_eq!(len(128), 2);
+        assert_eq!(len(u16::MAX as u32), 3);
+        assert_eq!(len(u32::MAX), MAX_VARINT_LEN);
+    }
+
+    #[test]
+    fn test_varint_rejects_overlong_input() {
+        let mut cursor = Cursor::new(&[0xFF, 0xFF, 0xFF, 0xFF, 0x7F]);
+        assert!(matches!(
+            decode_varint(&mut cursor),
+            Err(CodecError::VarintOverflowError)
+        ));
+
+        let mut cursor = Cursor::new(&[0x80, 0x80, 0x04]);
+        assert!(matches!(
+            decode_varint_u16(&mut cursor),
+            Err(CodecError::VarintOverflowError)
+        ));
+    }
+
+    #[test]
+    fn test_primitive_codecs_round_trip() {
+        type Fields = (Option<[u8; 3]>, (i32, Vec<u16, 4>));
+        let value: Fields = (
+            Some([1, 2, 3]),
+            (-67, unwrap_print!(Vec::from_slice(&[0x0102, 0xFFFF]))),
+        );
+        let mut out = Vec::<u8, 32>::new();
+        unwrap_print!(value.encode(&mut out));
+        assert_eq!(
+            &out[..],
+            &[
+                1, 1, 2, 3, 0xBD, 0xFF, 0xFF, 0xFF, 2, 0x02, 0x01, 0xFF, 0xFF
+            ]
+        );
+
+        let mut cursor = Cursor::new(&out);
+        let decoded = unwrap_print!(<Fields as WireCodec<32>>::decode(&mut cursor));
+        assert_eq!(decoded, value);
+        assert!(cursor.remaining().is_empty());
+    }
+
+    #[test]
+    fn test_vec_codec_rejects_excess_length() {
+        let mut cursor = Cursor::new(&[3, 1, 2, 3]);
+        assert!(matches!(
+            <Vec<u8, 2> as WireCodec<8>>::decode(&mut cursor),
+            Err(CodecError::LengthExceededError(3))
+        ));
+    }
+
+    #[test]
+    fn test_string_codec_checks_length_and_utf8() {
+        let mut out = Vec::<u8, 8>::new();
+        unwrap_print!(String::<4>::try_from("tag").unwrap().encode(&mut out));
+        assert_eq!(&out[..], &[3, b't', b'a', b'g']);
+        let decoded = <String<4> as WireCodec<8>>::decode(&mut Cursor::new(&out));
+        assert_eq!(unwrap_print!(decoded), "tag");
+
+        let mut cursor = Cursor::new(&[3, b't', b'a', b'g']);
+        assert!(matches!(
+            <String<2> as WireCodec<8>>::decode(&mut cursor),
+            Err(CodecError::LengthExceededError(3))
+        ));
+        let mut cursor = Cursor::new(&[1, 0xFF]);
+        assert!(matches!(
+            <String<2> as WireCodec<8>>::decode(&mut cursor),
+            Err(CodecError::CodecError)
+        ));
+    }
+
+    #[test]
+    fn test_crc16_check_value() {
+        assert_eq!(crc16(b"123456789"), 0x29B1);
+        assert_eq!(crc16(&[]), 0xFFFF);
+    }
 }
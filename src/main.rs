@@ -13,45 +13,122 @@ mod logic;
 use crate::{
     hardware::{
         bus::{SharedBus, SharedBusInterface},
+        crash::{arm_last_gasp, send_last_gasp, take_crash_record, write_crash_record},
         display::Display,
         link::ESPNowLink,
+        ota::{boot_image_state, confirm_image, roll_back},
+        provisioning::read_provisioning,
     },
     logic::{
+        capabilities::{Capabilities, Features, ImageState},
+        config::{MeshConfig, NodeRole},
+        consts::{ORGANIZE_QUEUE_SIZE, RECV_QUEUE_SIZE},
+        crash::CrashRecord,
+        crypto::NetworkKey,
         link::{ActiveLink, Link},
-        mesh::{self, Mesh, ORGANIZE_QUEUE_SIZE, RECV_QUEUE_SIZE},
+        mesh::{self, Delivery, Mesh, MeshHandle, MeshState},
         message,
-        node::Node,
+        profile::StartupProfile,
+        provisioning::BoardPreset,
+        roster::NodeName,
+        telemetry::TelemetryRate,
         tree::Tree,
     },
-    message::{MessageData, ReceiveMessage},
+    message::ReceiveMessage,
+};
+use core::{
+    panic::PanicInfo,
+    sync::atomic::{AtomicBool, Ordering},
 };
 use embassy_executor::Spawner;
+use embassy_futures::select::{Either, select};
 use embassy_sync::{blocking_mutex::raw::CriticalSectionRawMutex, channel::Channel, mutex::Mutex};
-use embassy_time::{Duration, Timer};
+use embassy_time::{Duration, Ticker, Timer};
 use esp_alloc as _;
-use esp_backtrace as _;
+use esp_backtrace::Backtrace;
 use esp_hal::{
-    clock::CpuClock, interrupt::software::SoftwareInterruptControl, time::Rate,
+    clock::CpuClock, interrupt::software::SoftwareInterruptControl, rng::TrngSource, time::Rate,
     timer::timg::TimerGroup,
 };
 use esp_println::println;
 use esp_radio::{Controller, esp_now::BROADCAST_ADDRESS};
 use static_cell::StaticCell;
 
-static RECV_QUEUE: Channel<CriticalSectionRawMutex, (MessageData, Node), RECV_QUEUE_SIZE> =
-    Channel::new();
+static RECV_QUEUE: Channel<CriticalSectionRawMutex, Delivery, RECV_QUEUE_SIZE> = Channel::new();
 static ORGANIZE_QUEUE: Channel<CriticalSectionRawMutex, ReceiveMessage, ORGANIZE_QUEUE_SIZE> =
     Channel::new();
+static MESH_STATE: Mutex<CriticalSectionRawMutex, MeshState> = Mutex::new(MeshState::new());
 static ROUTING_TREE: StaticCell<Mutex<CriticalSectionRawMutex, Tree>> = StaticCell::new();
+static RADIO: StaticCell<ESPNowLink> = StaticCell::new();
 static LINK: StaticCell<ActiveLink> = StaticCell::new();
+static PANICKING: AtomicBool = AtomicBool::new(false);
 
 esp_bootloader_esp_idf::esp_app_desc!();
 
+/// Tells the flash and the parent about the crash before printing the backtrace and resetting.
+/// The record goes to flash first, as the last gasp needs locks and the radio that may be what
+/// panicked. A panic while handling a panic resets right away.
+#[panic_handler]
+fn panic(info: &PanicInfo) -> ! {
+    if PANICKING.swap(true, Ordering::Relaxed) {
+        esp_hal::system::software_reset()
+    }
+    if let Err(e) = write_crash_record(&CrashRecord::new(info)) {
+        println!("{}", e);
+    }
+    if let Err(e) = send_last_gasp() {
+        println!("{}", e);
+    }
+    println!("");
+    println!("====================== PANIC ======================");
+    println!("{}", info);
+    println!("");
+    println!("Backtrace:");
+    println!("");
+    for frame in Backtrace::capture().frames() {
+        println!("0x{:x}", frame.program_counter());
+    }
+    esp_hal::system::software_reset()
+}
+
 #[esp_rtos::main]
 async fn main(spawner: Spawner) -> ! {
     esp_println::logger::init_logger_from_env();
     let config = esp_hal::Config::default().with_cpu_clock(CpuClock::max());
     let peripherals = esp_hal::init(config);
+    // Stays enabled for the lifetime of the firmware, so protocol randomness never depends on
+    // the radio being up.
+    mk_static!(
+        TrngSource<'static>,
+        TrngSource::new(peripherals.RNG, peripherals.ADC1)
+    );
+
+    let (board, profile, network_key, name) = match read_provisioning() {
+        Ok(provisioning) => {
+            println!("provisioned as {}", provisioning.name);
+            let name: &'static str = mk_static!(NodeName, provisioning.name).as_str();
+            (
+                provisioning.board,
+                provisioning.profile,
+                Some(provisioning.network_key),
+                name,
+            )
+        }
+        Err(e) => {
+            println!("{}", e);
+            (BoardPreset::Display, StartupProfile::Relay, None, "")
+        }
+    };
+    match take_crash_record() {
+        Ok(Some(record)) => println!("recovered from crash: {}", record.message),
+        Ok(None) => {}
+        Err(e) => println!("{}", e),
+    }
+    #[cfg(feature = "wire-selftest")]
+    match logic::vectors::check(logic::vectors::WIRE_VECTORS) {
+        Ok(checked) => println!("wire selftest passed, {} vectors", checked),
+        Err(e) => println!("wire selftest failed: {}", e),
+    }
 
     esp_alloc::heap_allocator!(size: 72 * 1024);
 
@@ -70,14 +147,62 @@ async fn main(spawner: Spawner) -> ! {
     esp_now.set_channel(11).unwrap();
     esp_println::println!("esp-now version {}", esp_now.version().unwrap());
     let (_, sender, receiver) = esp_now.split();
-    let mut link = ActiveLink::new(spawner, sender, receiver);
-    unwrap_print!(link.init());
-    let link = LINK.init(link);
-    let mut tree = Tree::new();
-    unwrap_print!(tree.init());
-    let routing = ROUTING_TREE.init(Mutex::new(tree));
-    let mesh = Mesh::new(spawner, link, routing, &RECV_QUEUE, &ORGANIZE_QUEUE);
+    let mut radio = ESPNowLink::new(spawner, sender, receiver);
+    unwrap_print!(radio.init());
+    let link = LINK.init(ActiveLink::Radio(RADIO.init(radio)));
+    let routing: &'static _ = ROUTING_TREE.init_with(|| Mutex::new(Tree::new()));
+    unwrap_print!(routing.lock().await.init());
+    arm_last_gasp(routing, &MESH_STATE);
+    let capabilities = match board {
+        BoardPreset::Display => Capabilities::new().with_features(Features::DISPLAY),
+        BoardPreset::Headless => Capabilities::new(),
+    };
+    // Boards flashed without OTA partitions never get updated, so their image counts as confirmed.
+    let image = boot_image_state().unwrap_or_else(|e| {
+        println!("{}", e);
+        ImageState::Confirmed
+    });
+    let capabilities = capabilities.with_image(image);
+    let mesh = Mesh::new(
+        spawner,
+        link,
+        routing,
+        &MESH_STATE,
+        &RECV_QUEUE,
+        &ORGANIZE_QUEUE,
+        profile_config(profile, network_key, name, capabilities),
+    );
     unwrap_print!(mesh.init());
+    if image == ImageState::PendingVerify {
+        match mesh.confirm_boot().await {
+            Ok(()) => {
+                if let Err(e) = confirm_image() {
+                    println!("{}", e);
+                }
+            }
+            Err(e) => {
+                println!("{}", e);
+                roll_back();
+            }
+        }
+    }
+    #[cfg(feature = "rig")]
+    spawner
+        .spawn(hardware::rig::rig_task(mesh.handle(), routing))
+        .unwrap();
+    spawner
+        .spawn(telemetry_task(
+            mesh.handle(),
+            profile.settings().telemetry_interval_ms,
+        ))
+        .unwrap();
+
+    if board == BoardPreset::Headless {
+        loop {
+            let (data, source) = mesh.receive().await;
+            println!("{} sent {:?}", source, data);
+        }
+    }
 
     let i2c_bus = esp_hal::i2c::master::I2c::new(
         peripherals.I2C0,
@@ -102,5 +227,83 @@ async fn main(spawner: Spawner) -> ! {
     Timer::after(Duration::from_millis(50)).await;
     unwrap_print!(display.show_logo().await);
 
-    loop {}
+    let mut ticker = Ticker::every(Duration::from_millis(500));
+    let mut ticks = 0u32;
+    loop {
+        match select(ticker.next(), mesh.receive()).await {
+            Either::First(_) => {
+                ticks = ticks.wrapping_add(1);
+                // Blinks "HERE" while another node looks for this tag, see `Mesh::identify`.
+                if mesh.identifying().await {
+                    if ticks % 2 == 0 {
+                        unwrap_print!(display.show_center_text("HERE").await);
+                    } else {
+                        unwrap_print!(display.clear().await);
+                    }
+                } else if ticks % 4 == 0 && !mesh.is_connected().await {
+                    unwrap_print!(display.show_center_text("ALONE").await);
+                } else if ticks % 4 == 0 {
+                    let neighbors = unwrap_print!(routing.lock().await.neighbors());
+                    unwrap_print!(display.show_neighbors(&neighbors).await);
+                }
+            }
+            Either::Second((data, source)) => println!("{} sent {:?}", source, data),
+        }
+    }
+}
+
+/// Mesh settings of `profile`. The `monitor` feature still forces the monitor role, so a
+/// provisioned tag can be turned into one for debugging.
+fn profile_config(
+    profile: StartupProfile,
+    network_key: Option<NetworkKey>,
+    name: &'static str,
+    capabilities: Capabilities,
+) -> MeshConfig {
+    let config = profile.apply(MeshConfig {
+        network_key,
+        name,
+        capabilities,
+        ..MeshConfig::default()
+    });
+    if cfg!(feature = "monitor") {
+        return MeshConfig {
+            role: NodeRole::Monitor,
+            ..config
+        };
+    }
+    config
+}
+
+/// Reports the status of the mesh at the telemetry interval of the profile, stretched while the
+/// mesh reports congestion, see `TelemetryRate`.
+#[embassy_executor::task]
+async fn telemetry_task(mesh: MeshHandle, interval_ms: u32) -> ! {
+    let mut telemetry = TelemetryRate::new(interval_ms);
+    loop {
+        Timer::after(Duration::from_millis(telemetry.interval_ms() as u64)).await;
+        report_status(&mesh).await;
+        telemetry.adapt(mesh.take_congestion().await);
+    }
+}
+
+/// Prints what this node knows about the mesh. The leader also lists the members it admitted.
+async fn report_status(mesh: &Mesh) {
+    let stats = mesh.stats().await;
+    println!(
+        "telemetry: {:?}, {}/{} nodes, height {}, {} forwarded, {} dropped, {} queued",
+        mesh.role().await,
+        stats.node_count,
+        mesh.capacity().await,
+        stats.height,
+        stats.forwards.forwarded,
+        stats.forwards.dropped,
+        stats.send_queue
+    );
+    for entry in mesh.roster().await {
+        println!(
+            "  {} {} joined at {} ms",
+            entry.short_id, entry.name, entry.joined_ms
+        );
+    }
 }